@@ -23,6 +23,8 @@ fn main() {
         //  .flag("-DSQLITE_OMIT_DISKIO")
         .flag("-DSQLITE_ENABLE_FTS5")
         .flag("-DSQLITE_ENABLE_FTS5_PARENTHESIS")
+        .flag("-DSQLITE_ENABLE_RTREE")
+        .flag("-DSQLITE_ENABLE_GEOPOLY")
         .flag("-DSQLITE_THREADSAFE=0");
 
     // Older versions of visual studio don't support c99 (including isnan), which
@@ -38,6 +40,18 @@ fn main() {
     if cfg!(feature = "preupdate_hook") {
         cfg.flag("-DSQLITE_ENABLE_PREUPDATE_HOOK");
     }
+    if cfg!(feature = "session") {
+        cfg.flag("-DSQLITE_ENABLE_SESSION");
+    }
+    if cfg!(feature = "unlock_notify") {
+        cfg.flag("-DSQLITE_ENABLE_UNLOCK_NOTIFY");
+    }
+    if cfg!(feature = "serialize") {
+        cfg.flag("-DSQLITE_ENABLE_DESERIALIZE");
+    }
+    if cfg!(feature = "column_metadata") {
+        cfg.flag("-DSQLITE_ENABLE_COLUMN_METADATA");
+    }
 
 
     if let Ok(limit) = env::var("SQLITE_MAX_VARIABLE_NUMBER") {
@@ -9,6 +9,9 @@ use crate::ffi;
 use crate::{str_to_cstring, Connection, InnerConnection, Result};
 use wasm_bindgen::__rt::std::panic::AssertUnwindSafe;
 
+#[cfg(feature = "builtin_collations")]
+pub mod builtin;
+
 // FIXME copy/paste from function.rs
 unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
     drop(Box::from_raw(p as *mut T));
@@ -0,0 +1,80 @@
+//! Collations missing from SQLite, written in Rust.
+//!
+//! * `UNICODE_NOCASE`: case-insensitive, with the full Unicode case folding
+//!   (`'Maße' = 'MASSE'`), where the built-in `NOCASE` only folds ASCII
+//!   letters,
+//! * `NATURAL`: runs of ASCII digits compare as numbers
+//!   (`'file2' < 'file10'`), and the rest as `BINARY` does.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! # use rusqlite::collation::builtin;
+//! fn files(conn: &Connection) -> Result<Vec<String>> {
+//!     builtin::load_collations(conn)?;
+//!     let mut stmt = conn.prepare("SELECT name FROM files ORDER BY name COLLATE NATURAL")?;
+//!     let rows = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+//!     rows.collect()
+//! }
+//! ```
+use std::cmp::Ordering;
+
+use unicase::UniCase;
+
+use crate::{Connection, Result};
+
+/// Register all the collations.
+pub fn load_collations(conn: &Connection) -> Result<()> {
+    conn.create_collation("UNICODE_NOCASE", unicode_nocase)?;
+    conn.create_collation("NATURAL", natural)
+}
+
+/// Compare `a` and `b` after Unicode case folding.
+pub fn unicode_nocase(a: &str, b: &str) -> Ordering {
+    UniCase::new(a).cmp(&UniCase::new(b))
+}
+
+/// Compare `a` and `b` in natural order: runs of digits by their value.
+/// Strings that only differ by leading zeros are ordered as `BINARY` does.
+pub fn natural(a: &str, b: &str) -> Ordering {
+    natural_bytes(a.as_bytes(), b.as_bytes()).then_with(|| a.cmp(b))
+}
+
+fn natural_bytes(mut a: &[u8], mut b: &[u8]) -> Ordering {
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, rest_a) = split_number(a);
+                let (y, rest_b) = split_number(b);
+                // Without leading zeros, a longer number is a greater one.
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = rest_a;
+                b = rest_b;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+/// The leading digits of `s`, without leading zeros, and the rest of `s`.
+fn split_number(s: &[u8]) -> (&[u8], &[u8]) {
+    let end = s
+        .iter()
+        .position(|c| !c.is_ascii_digit())
+        .unwrap_or(s.len());
+    let start = s[..end].iter().position(|&c| c != b'0').unwrap_or(end);
+    (&s[start..end], &s[end..])
+}
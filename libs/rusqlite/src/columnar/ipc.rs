@@ -0,0 +1,289 @@
+//! Arrow IPC stream writer.
+//!
+//! The metadata of each message is a flatbuffer (see `Schema.fbs` and
+//! `Message.fbs` in the Arrow format specification). Only the handful of
+//! tables needed here are encoded, by a small front-to-back encoder rather
+//! than the flatbuffers crate.
+
+use std::cmp::Reverse;
+use std::io::{self, Write};
+
+use super::{ColumnData, ColumnType, Field, RecordBatch};
+
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+const METADATA_VERSION_V4: i16 = 3;
+const ENDIANNESS_LITTLE: i16 = 0;
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_BINARY: u8 = 4;
+const TYPE_UTF8: u8 = 5;
+const PRECISION_DOUBLE: i16 = 2;
+
+// A flatbuffer value. Tables list their fields by slot, `None` for absent.
+enum Fb {
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Table(Vec<Option<Fb>>),
+    Str(String),
+    Tables(Vec<Fb>),
+    // A vector of structs made of two longs (`FieldNode` and `Buffer`).
+    Structs(Vec<[i64; 2]>),
+}
+
+impl Fb {
+    fn inline_size(&self) -> usize {
+        match *self {
+            Fb::Bool(_) | Fb::U8(_) => 1,
+            Fb::I16(_) => 2,
+            Fb::I64(_) => 8,
+            // Scalars or offsets to the object.
+            _ => 4,
+        }
+    }
+}
+
+// Objects are laid out front to back: every table is followed by the
+// objects it refers to, so that all offsets point forward as required.
+struct Builder {
+    buf: Vec<u8>,
+}
+
+impl Builder {
+    fn finish(root: &Fb) -> Vec<u8> {
+        let mut builder = Builder { buf: vec![0; 4] };
+        let root = builder.write(root);
+        builder.patch(0, root);
+        builder.buf
+    }
+
+    fn pad_to(&mut self, align: usize) {
+        while self.buf.len() % align != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    // Point the offset at `pos` to `target`.
+    fn patch(&mut self, pos: usize, target: usize) {
+        let offset = (target - pos) as u32;
+        self.buf[pos..pos + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    // Write a non-inline object, returning the position offsets refer to.
+    fn write(&mut self, object: &Fb) -> usize {
+        match *object {
+            Fb::Table(ref fields) => self.write_table(fields),
+            Fb::Str(ref s) => {
+                self.pad_to(4);
+                let pos = self.buf.len();
+                self.buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                self.buf.extend_from_slice(s.as_bytes());
+                self.buf.push(0);
+                pos
+            }
+            Fb::Tables(ref tables) => {
+                self.pad_to(4);
+                let pos = self.buf.len();
+                self.buf
+                    .extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                self.buf.resize(pos + 4 + 4 * tables.len(), 0);
+                for (i, table) in tables.iter().enumerate() {
+                    let target = self.write(table);
+                    self.patch(pos + 4 + 4 * i, target);
+                }
+                pos
+            }
+            Fb::Structs(ref structs) => {
+                // The structs themselves, after the length, are 8-byte aligned.
+                while (self.buf.len() + 4) % 8 != 0 {
+                    self.buf.push(0);
+                }
+                let pos = self.buf.len();
+                self.buf
+                    .extend_from_slice(&(structs.len() as u32).to_le_bytes());
+                for s in structs {
+                    self.buf.extend_from_slice(&s[0].to_le_bytes());
+                    self.buf.extend_from_slice(&s[1].to_le_bytes());
+                }
+                pos
+            }
+            _ => unreachable!("scalars are stored inline"),
+        }
+    }
+
+    fn write_table(&mut self, fields: &[Option<Fb>]) -> usize {
+        // After the offset to the vtable, place fields by decreasing size so
+        // that each one is aligned.
+        let mut order: Vec<usize> = (0..fields.len()).filter(|&i| fields[i].is_some()).collect();
+        order.sort_by_key(|&i| Reverse(fields[i].as_ref().unwrap().inline_size()));
+        let mut offsets = vec![0u16; fields.len()];
+        let mut size = 4;
+        for &i in &order {
+            let field_size = fields[i].as_ref().unwrap().inline_size();
+            size = (size + field_size - 1) / field_size * field_size;
+            offsets[i] = size as u16;
+            size += field_size;
+        }
+
+        self.pad_to(2);
+        let vtable = self.buf.len();
+        let vtable_size = 4 + 2 * fields.len() as u16;
+        self.buf.extend_from_slice(&vtable_size.to_le_bytes());
+        self.buf.extend_from_slice(&(size as u16).to_le_bytes());
+        for offset in &offsets {
+            self.buf.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        self.pad_to(8);
+        let table = self.buf.len();
+        self.buf
+            .extend_from_slice(&((table - vtable) as i32).to_le_bytes());
+        self.buf.resize(table + size, 0);
+        let mut children = Vec::new();
+        for &i in &order {
+            let pos = table + offsets[i] as usize;
+            let field = fields[i].as_ref().unwrap();
+            match *field {
+                Fb::Bool(b) => self.buf[pos] = b as u8,
+                Fb::U8(v) => self.buf[pos] = v,
+                Fb::I16(v) => self.buf[pos..pos + 2].copy_from_slice(&v.to_le_bytes()),
+                Fb::I32(v) => self.buf[pos..pos + 4].copy_from_slice(&v.to_le_bytes()),
+                Fb::I64(v) => self.buf[pos..pos + 8].copy_from_slice(&v.to_le_bytes()),
+                _ => children.push((pos, field)),
+            }
+        }
+        for (pos, child) in children {
+            let target = self.write(child);
+            self.patch(pos, target);
+        }
+        table
+    }
+}
+
+fn message(header_type: u8, header: Fb, body_length: usize) -> Fb {
+    Fb::Table(vec![
+        Some(Fb::I16(METADATA_VERSION_V4)),
+        Some(Fb::U8(header_type)),
+        Some(header),
+        Some(Fb::I64(body_length as i64)),
+    ])
+}
+
+fn field(field: &Field) -> Fb {
+    let (type_type, type_table) = match field.column_type {
+        ColumnType::Int64 => (
+            TYPE_INT,
+            Fb::Table(vec![Some(Fb::I32(64)), Some(Fb::Bool(true))]),
+        ),
+        ColumnType::Float64 => (
+            TYPE_FLOATING_POINT,
+            Fb::Table(vec![Some(Fb::I16(PRECISION_DOUBLE))]),
+        ),
+        ColumnType::Utf8 => (TYPE_UTF8, Fb::Table(vec![])),
+        ColumnType::Binary => (TYPE_BINARY, Fb::Table(vec![])),
+    };
+    Fb::Table(vec![
+        Some(Fb::Str(field.name.clone())),
+        Some(Fb::Bool(true)),
+        Some(Fb::U8(type_type)),
+        Some(type_table),
+        None,
+        // Readers expect the children vector even when it is empty.
+        Some(Fb::Tables(vec![])),
+    ])
+}
+
+// The body of a record batch: every buffer is padded to 8 bytes.
+#[derive(Default)]
+struct Body {
+    bytes: Vec<u8>,
+    buffers: Vec<[i64; 2]>,
+}
+
+impl Body {
+    fn push<I: IntoIterator<Item = u8>>(&mut self, bytes: I) {
+        let offset = self.bytes.len();
+        self.bytes.extend(bytes);
+        self.buffers
+            .push([offset as i64, (self.bytes.len() - offset) as i64]);
+        while self.bytes.len() % 8 != 0 {
+            self.bytes.push(0);
+        }
+    }
+}
+
+fn write_message<W: Write>(w: &mut W, message: &Fb, body: &[u8]) -> io::Result<()> {
+    let mut metadata = Builder::finish(message);
+    // Keep the body 8-byte aligned, counting the 8 byte prefix.
+    while metadata.len() % 8 != 0 {
+        metadata.push(0);
+    }
+    w.write_all(&CONTINUATION.to_le_bytes())?;
+    w.write_all(&(metadata.len() as i32).to_le_bytes())?;
+    w.write_all(&metadata)?;
+    w.write_all(body)
+}
+
+/// Writes `RecordBatch`es as an Arrow IPC stream.
+pub struct ArrowStreamWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> ArrowStreamWriter<W> {
+    /// Write the schema message for `fields`.
+    pub fn new(mut w: W, fields: &[Field]) -> io::Result<ArrowStreamWriter<W>> {
+        let schema = Fb::Table(vec![
+            Some(Fb::I16(ENDIANNESS_LITTLE)),
+            Some(Fb::Tables(fields.iter().map(field).collect())),
+        ]);
+        write_message(&mut w, &message(HEADER_SCHEMA, schema, 0), &[])?;
+        Ok(ArrowStreamWriter { w })
+    }
+
+    /// Write a record batch message. The batch must match the schema.
+    pub fn write(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        let mut nodes = Vec::with_capacity(batch.columns.len());
+        let mut body = Body::default();
+        for column in &batch.columns {
+            nodes.push([batch.num_rows as i64, column.null_count as i64]);
+            body.push(column.validity.iter().cloned());
+            match column.data {
+                ColumnData::Int64(ref v) => {
+                    body.push(v.iter().flat_map(|i| i.to_le_bytes().to_vec()))
+                }
+                ColumnData::Float64(ref v) => {
+                    body.push(v.iter().flat_map(|f| f.to_bits().to_le_bytes().to_vec()))
+                }
+                ColumnData::Utf8 {
+                    ref offsets,
+                    ref data,
+                }
+                | ColumnData::Binary {
+                    ref offsets,
+                    ref data,
+                } => {
+                    body.push(offsets.iter().flat_map(|o| o.to_le_bytes().to_vec()));
+                    body.push(data.iter().cloned());
+                }
+            }
+        }
+        let header = Fb::Table(vec![
+            Some(Fb::I64(batch.num_rows as i64)),
+            Some(Fb::Structs(nodes)),
+            Some(Fb::Structs(body.buffers)),
+        ]);
+        let message = message(HEADER_RECORD_BATCH, header, body.bytes.len());
+        write_message(&mut self.w, &message, &body.bytes)
+    }
+
+    /// Write the end-of-stream marker and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.w.write_all(&CONTINUATION.to_le_bytes())?;
+        self.w.write_all(&0i32.to_le_bytes())?;
+        Ok(self.w)
+    }
+}
@@ -0,0 +1,370 @@
+//! Decode query results column by column into typed vectors, in batches,
+//! and write them as an
+//! [Arrow IPC stream](https://arrow.apache.org/docs/format/Columnar.html#ipc-streaming-format).
+//!
+//! The type of each column is taken from its declared type, following the
+//! SQLite [affinity](https://sqlite.org/datatype3.html#determination_of_column_affinity)
+//! rules, or else from its first non-null value. Values that cannot be
+//! represented in that type without loss make `next_batch` fail with
+//! `Error::InvalidColumnType`; use `CAST` in the query to force a type.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! fn export(conn: &Connection) -> Result<Vec<u8>> {
+//!     let mut stmt = conn.prepare("SELECT x, y FROM points")?;
+//!     let rows = stmt.query_columnar(NO_PARAMS, 65536)?;
+//!     rows.to_arrow_ipc()
+//! }
+//! ```
+
+use crate::types::{ToSql, ValueRef};
+use crate::{Error, Result, Rows, Statement};
+
+mod ipc;
+
+pub use self::ipc::ArrowStreamWriter;
+
+/// The type of a column of a `RecordBatch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    Int64,
+    Float64,
+    Utf8,
+    Binary,
+}
+
+impl ColumnType {
+    fn from_decl_type(decl_type: &str) -> Option<ColumnType> {
+        let t = decl_type.to_ascii_uppercase();
+        if t.contains("INT") {
+            Some(ColumnType::Int64)
+        } else if t.contains("CHAR") || t.contains("CLOB") || t.contains("TEXT") {
+            Some(ColumnType::Utf8)
+        } else if t.contains("BLOB") {
+            Some(ColumnType::Binary)
+        } else if t.contains("REAL") || t.contains("FLOA") || t.contains("DOUB") {
+            Some(ColumnType::Float64)
+        } else {
+            None
+        }
+    }
+
+    fn of(value: ValueRef<'_>) -> ColumnType {
+        match value {
+            ValueRef::Integer(_) => ColumnType::Int64,
+            ValueRef::Real(_) => ColumnType::Float64,
+            ValueRef::Blob(_) => ColumnType::Binary,
+            ValueRef::Null | ValueRef::Text(_) => ColumnType::Utf8,
+        }
+    }
+}
+
+/// The name and type of a column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub column_type: ColumnType,
+}
+
+/// The values of one column of a `RecordBatch`. Null values hold a zero or
+/// empty placeholder.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnData {
+    Int64(Vec<i64>),
+    Float64(Vec<f64>),
+    /// Value `i` is `data[offsets[i]..offsets[i + 1]]`.
+    Utf8 {
+        offsets: Vec<i32>,
+        data: Vec<u8>,
+    },
+    /// Value `i` is `data[offsets[i]..offsets[i + 1]]`.
+    Binary {
+        offsets: Vec<i32>,
+        data: Vec<u8>,
+    },
+}
+
+impl ColumnData {
+    fn with_nulls(column_type: ColumnType, len: usize) -> ColumnData {
+        match column_type {
+            ColumnType::Int64 => ColumnData::Int64(vec![0; len]),
+            ColumnType::Float64 => ColumnData::Float64(vec![0.0; len]),
+            ColumnType::Utf8 => ColumnData::Utf8 {
+                offsets: vec![0; len + 1],
+                data: Vec::new(),
+            },
+            ColumnType::Binary => ColumnData::Binary {
+                offsets: vec![0; len + 1],
+                data: Vec::new(),
+            },
+        }
+    }
+
+    /// Returns the type of the values.
+    pub fn column_type(&self) -> ColumnType {
+        match *self {
+            ColumnData::Int64(_) => ColumnType::Int64,
+            ColumnData::Float64(_) => ColumnType::Float64,
+            ColumnData::Utf8 { .. } => ColumnType::Utf8,
+            ColumnData::Binary { .. } => ColumnType::Binary,
+        }
+    }
+
+    fn push_null(&mut self) {
+        match *self {
+            ColumnData::Int64(ref mut v) => v.push(0),
+            ColumnData::Float64(ref mut v) => v.push(0.0),
+            ColumnData::Utf8 {
+                ref mut offsets, ..
+            }
+            | ColumnData::Binary {
+                ref mut offsets, ..
+            } => {
+                let end = *offsets.last().unwrap();
+                offsets.push(end);
+            }
+        }
+    }
+
+    // Returns `false` if `value` does not fit the column type.
+    fn push(&mut self, value: ValueRef<'_>) -> bool {
+        match (self, value) {
+            (ColumnData::Int64(v), ValueRef::Integer(i)) => v.push(i),
+            (ColumnData::Int64(v), ValueRef::Real(f))
+                if f.fract() == 0.0
+                    && f >= i64::min_value() as f64
+                    && f < i64::max_value() as f64 =>
+            {
+                v.push(f as i64)
+            }
+            (ColumnData::Float64(v), ValueRef::Integer(i)) => v.push(i as f64),
+            (ColumnData::Float64(v), ValueRef::Real(f)) => v.push(f),
+            (ColumnData::Utf8 { offsets, data }, ValueRef::Text(s)) => {
+                data.extend_from_slice(s);
+                offsets.push(data.len() as i32);
+            }
+            (ColumnData::Utf8 { offsets, data }, ValueRef::Integer(i)) => {
+                data.extend_from_slice(i.to_string().as_bytes());
+                offsets.push(data.len() as i32);
+            }
+            (ColumnData::Utf8 { offsets, data }, ValueRef::Real(f)) => {
+                data.extend_from_slice(f.to_string().as_bytes());
+                offsets.push(data.len() as i32);
+            }
+            (ColumnData::Binary { offsets, data }, ValueRef::Blob(b))
+            | (ColumnData::Binary { offsets, data }, ValueRef::Text(b)) => {
+                data.extend_from_slice(b);
+                offsets.push(data.len() as i32);
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// One column of a `RecordBatch`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnArray {
+    pub data: ColumnData,
+    /// Bit `i`, least significant bit first, is set if value `i` is not
+    /// null. Empty when the column has no null.
+    pub validity: Vec<u8>,
+    pub null_count: usize,
+}
+
+impl ColumnArray {
+    /// Returns `true` if value `i` is null.
+    pub fn is_null(&self, i: usize) -> bool {
+        self.null_count > 0 && self.validity[i / 8] & (1 << (i % 8)) == 0
+    }
+}
+
+/// A batch of rows decoded column by column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordBatch {
+    pub num_rows: usize,
+    pub columns: Vec<ColumnArray>,
+}
+
+struct ColumnBuilder {
+    column_type: Option<ColumnType>,
+    // `None` until the type of a column without declared type is known.
+    data: Option<ColumnData>,
+    validity: Vec<u8>,
+    null_count: usize,
+    len: usize,
+}
+
+impl ColumnBuilder {
+    fn new(column_type: Option<ColumnType>) -> ColumnBuilder {
+        ColumnBuilder {
+            column_type,
+            data: column_type.map(|t| ColumnData::with_nulls(t, 0)),
+            validity: Vec::new(),
+            null_count: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, value: ValueRef<'_>) -> bool {
+        if self.len % 8 == 0 {
+            self.validity.push(0);
+        }
+        if let ValueRef::Null = value {
+            if let Some(ref mut data) = self.data {
+                data.push_null();
+            }
+            self.null_count += 1;
+        } else {
+            let len = self.len;
+            let column_type = *self.column_type.get_or_insert(ColumnType::of(value));
+            let data = self
+                .data
+                .get_or_insert_with(|| ColumnData::with_nulls(column_type, len));
+            if !data.push(value) {
+                return false;
+            }
+            self.validity[len / 8] |= 1 << (len % 8);
+        }
+        self.len += 1;
+        true
+    }
+
+    fn finish(self) -> ColumnArray {
+        let len = self.len;
+        let data = self.data.unwrap_or_else(|| {
+            ColumnData::with_nulls(self.column_type.unwrap_or(ColumnType::Utf8), len)
+        });
+        ColumnArray {
+            data,
+            validity: if self.null_count > 0 {
+                self.validity
+            } else {
+                Vec::new()
+            },
+            null_count: self.null_count,
+        }
+    }
+}
+
+/// The batches of rows of a query, returned by `Statement::query_columnar`.
+pub struct ColumnarRows<'stmt> {
+    rows: Rows<'stmt>,
+    names: Vec<String>,
+    // Fixed by the declared types, or else by the first batch.
+    types: Vec<Option<ColumnType>>,
+    batch_size: usize,
+    done: bool,
+}
+
+impl ColumnarRows<'_> {
+    /// Returns the columns of the result. Until the first batch has been
+    /// decoded, columns without declared type are reported as `Utf8`.
+    pub fn fields(&self) -> Vec<Field> {
+        self.names
+            .iter()
+            .zip(&self.types)
+            .map(|(name, t)| Field {
+                name: name.clone(),
+                column_type: t.unwrap_or(ColumnType::Utf8),
+            })
+            .collect()
+    }
+
+    /// Decode up to `batch_size` rows. Returns `None` once the query is
+    /// exhausted.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if stepping the statement fails or a value does not
+    /// fit its column type.
+    pub fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut builders: Vec<ColumnBuilder> =
+            self.types.iter().map(|t| ColumnBuilder::new(*t)).collect();
+        let mut num_rows = 0;
+        while num_rows < self.batch_size {
+            let row = match self.rows.next()? {
+                Some(row) => row,
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+            for (i, builder) in builders.iter_mut().enumerate() {
+                let value = row.get_raw(i);
+                if !builder.push(value) {
+                    return Err(Error::InvalidColumnType(
+                        i,
+                        self.names[i].clone(),
+                        value.data_type(),
+                    ));
+                }
+            }
+            num_rows += 1;
+        }
+        if num_rows == 0 {
+            return Ok(None);
+        }
+        let columns: Vec<ColumnArray> = builders.into_iter().map(ColumnBuilder::finish).collect();
+        for (t, column) in self.types.iter_mut().zip(&columns) {
+            *t = Some(column.data.column_type());
+        }
+        Ok(Some(RecordBatch { num_rows, columns }))
+    }
+
+    /// Decode every remaining row into an Arrow IPC stream.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `next_batch` fails.
+    pub fn to_arrow_ipc(mut self) -> Result<Vec<u8>> {
+        // The schema depends on the first batch.
+        let first = self.next_batch()?;
+        // Writes to a `Vec` cannot fail.
+        let mut writer = ArrowStreamWriter::new(Vec::new(), &self.fields()).unwrap();
+        let mut batch = first;
+        while let Some(b) = batch {
+            writer.write(&b).unwrap();
+            batch = self.next_batch()?;
+        }
+        Ok(writer.finish().unwrap())
+    }
+}
+
+impl Statement<'_> {
+    /// Execute the prepared statement, returning its rows in batches of up
+    /// to `batch_size` rows decoded into one typed vector per column.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    pub fn query_columnar<P>(&mut self, params: P, batch_size: usize) -> Result<ColumnarRows<'_>>
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        let (names, types) = self
+            .columns()
+            .iter()
+            .map(|c| {
+                (
+                    c.name().to_owned(),
+                    c.decl_type().and_then(ColumnType::from_decl_type),
+                )
+            })
+            .unzip();
+        let rows = self.query(params)?;
+        Ok(ColumnarRows {
+            rows,
+            names,
+            types,
+            batch_size: batch_size.max(1),
+            done: false,
+        })
+    }
+}
@@ -1,8 +1,6 @@
 //! Code related to `sqlite3_context` common to `functions` and `vtab` modules.
 
 use std::os::raw::{c_int, c_void};
-#[cfg(feature = "array")]
-use std::rc::Rc;
 
 use crate::ffi;
 use crate::ffi::sqlite3_context;
@@ -25,7 +23,7 @@ pub(crate) unsafe fn set_result(ctx: *mut sqlite3_context, result: &ToSqlOutput<
         ToSqlOutput::Array(ref a) => {
             return ffi::sqlite3_result_pointer(
                 ctx,
-                Rc::into_raw(a.clone()) as *mut c_void,
+                Box::into_raw(Box::new(a.clone())) as *mut c_void,
                 ARRAY_TYPE,
                 Some(free_array),
             );
@@ -0,0 +1,171 @@
+//! Write query results as CSV or TSV.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! # use rusqlite::csv_export::CsvOptions;
+//! fn export(conn: &Connection) -> Result<Vec<u8>> {
+//!     let mut stmt = conn.prepare("SELECT name, email FROM people WHERE active")?;
+//!     let mut csv = Vec::new();
+//!     stmt.write_csv(NO_PARAMS, &mut csv, &CsvOptions::default())?;
+//!     Ok(csv)
+//! }
+//! ```
+
+use std::io;
+
+use crate::types::{ToSql, ValueRef};
+use crate::{Error, Result, Statement};
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// When fields are quoted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// Quote every field.
+    Always,
+    /// Quote fields containing the delimiter, a quote or a line break.
+    Necessary,
+    /// Quote every field that is not a number.
+    NonNumeric,
+    /// Never quote, even if the output becomes ambiguous.
+    Never,
+}
+
+/// How blobs are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobEncoding {
+    /// Upper case hexadecimal, like the `hex()` SQL function.
+    Hex,
+    /// Standard base64, with padding.
+    Base64,
+}
+
+/// Options of `Statement::write_csv`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvOptions {
+    /// Field delimiter. Default `,`.
+    pub delimiter: u8,
+    /// Default `QuoteStyle::Necessary`.
+    pub quote_style: QuoteStyle,
+    /// Whether the first row holds the column names. Default `true`.
+    pub header: bool,
+    /// What NULL is written as. Default empty.
+    pub null: String,
+    /// Default `BlobEncoding::Hex`.
+    pub blob_encoding: BlobEncoding,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            delimiter: b',',
+            quote_style: QuoteStyle::Necessary,
+            header: true,
+            null: String::new(),
+            blob_encoding: BlobEncoding::Hex,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// The default options with tab as delimiter.
+    pub fn tsv() -> CsvOptions {
+        CsvOptions {
+            delimiter: b'\t',
+            ..CsvOptions::default()
+        }
+    }
+}
+
+// Reals keep a decimal point, as SQLite prints them, so that they read back
+// as reals.
+fn push_real(f: f64, out: &mut Vec<u8>) {
+    let s = f.to_string();
+    out.extend_from_slice(s.as_bytes());
+    if f.is_finite() && !s.contains('.') {
+        out.extend_from_slice(b".0");
+    }
+}
+
+fn push_blob(blob: &[u8], encoding: BlobEncoding, out: &mut Vec<u8>) {
+    match encoding {
+        BlobEncoding::Hex => {
+            for b in blob {
+                out.push(HEX[usize::from(b >> 4)]);
+                out.push(HEX[usize::from(b & 0xf)]);
+            }
+        }
+        BlobEncoding::Base64 => {
+            for chunk in blob.chunks(3) {
+                let v = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u32, |v, (i, &b)| v | (u32::from(b) << (16 - 8 * i)));
+                for i in 0..4 {
+                    if i <= chunk.len() {
+                        out.push(BASE64[((v >> (18 - 6 * i)) & 0x3f) as usize]);
+                    } else {
+                        out.push(b'=');
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Statement<'_> {
+    /// Execute the prepared statement and write its rows to `writer` as CSV.
+    /// Returns the number of rows written, not counting the header.
+    ///
+    /// Numbers are written in full, text as is, and blobs encoded as
+    /// `options.blob_encoding` says.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters or stepping the statement
+    /// fails, or `Error::CsvError` if writing fails.
+    pub fn write_csv<P, W>(&mut self, params: P, writer: W, options: &CsvOptions) -> Result<usize>
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+        W: io::Write,
+    {
+        let mut csv = csv::WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .quote_style(match options.quote_style {
+                QuoteStyle::Always => csv::QuoteStyle::Always,
+                QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+                QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+                QuoteStyle::Never => csv::QuoteStyle::Never,
+            })
+            .from_writer(writer);
+        if options.header {
+            csv.write_record(self.column_names())
+                .map_err(Error::CsvError)?;
+        }
+        let column_count = self.column_count();
+        let mut rows = self.query(params)?;
+        let mut count = 0;
+        let mut field = Vec::new();
+        while let Some(row) = rows.next()? {
+            for i in 0..column_count {
+                field.clear();
+                match row.get_raw(i) {
+                    ValueRef::Null => field.extend_from_slice(options.null.as_bytes()),
+                    ValueRef::Integer(n) => field.extend_from_slice(n.to_string().as_bytes()),
+                    ValueRef::Real(f) => push_real(f, &mut field),
+                    ValueRef::Text(t) => field.extend_from_slice(t),
+                    ValueRef::Blob(b) => push_blob(b, options.blob_encoding, &mut field),
+                }
+                csv.write_field(&field).map_err(Error::CsvError)?;
+            }
+            csv.write_record(None::<&[u8]>).map_err(Error::CsvError)?;
+            count += 1;
+        }
+        csv.flush().map_err(|e| Error::CsvError(e.into()))?;
+        Ok(count)
+    }
+}
@@ -103,6 +103,18 @@ pub enum Error {
 
     /// Error when the SQL contains multiple statements.
     MultipleStatement,
+
+    /// Error writing rows with `Statement::write_csv`.
+    #[cfg(feature = "csv_export")]
+    CsvError(csv::Error),
+
+    /// Error writing rows with `Statement::write_ndjson`.
+    #[cfg(feature = "serde_json")]
+    JsonError(serde_json::Error),
+
+    /// Error migrating a database with `migrations::Migrations`.
+    #[cfg(feature = "migrations")]
+    MigrationError(crate::migrations::MigrationError),
 }
 
 impl PartialEq for Error {
@@ -142,6 +154,8 @@ impl PartialEq for Error {
             (Error::UnwindingPanic, Error::UnwindingPanic) => true,
             #[cfg(feature = "functions")]
             (Error::GetAuxWrongType, Error::GetAuxWrongType) => true,
+            #[cfg(feature = "migrations")]
+            (Error::MigrationError(e1), Error::MigrationError(e2)) => e1 == e2,
             (..) => false,
         }
     }
@@ -159,7 +173,7 @@ impl From<::std::ffi::NulError> for Error {
     }
 }
 
-const UNKNOWN_COLUMN: usize = std::usize::MAX;
+pub(crate) const UNKNOWN_COLUMN: usize = std::usize::MAX;
 
 /// The conversion isn't precise, but it's convenient to have it
 /// to allow use of `get_raw(…).as_…()?` in callbacks that take `Error`.
@@ -248,6 +262,12 @@ impl fmt::Display for Error {
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => write!(f, "get_aux called with wrong type"),
             Error::MultipleStatement => write!(f, "Multiple statements provided"),
+            #[cfg(feature = "csv_export")]
+            Error::CsvError(ref err) => err.fmt(f),
+            #[cfg(feature = "serde_json")]
+            Error::JsonError(ref err) => err.fmt(f),
+            #[cfg(feature = "migrations")]
+            Error::MigrationError(ref err) => err.fmt(f),
         }
     }
 }
@@ -291,6 +311,12 @@ impl error::Error for Error {
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => "get_aux called with wrong type",
             Error::MultipleStatement => "multiple statements provided",
+            #[cfg(feature = "csv_export")]
+            Error::CsvError(ref err) => err.description(),
+            #[cfg(feature = "serde_json")]
+            Error::JsonError(ref err) => err.description(),
+            #[cfg(feature = "migrations")]
+            Error::MigrationError(_) => "migration error",
         }
     }
 
@@ -332,6 +358,13 @@ impl error::Error for Error {
 
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => None,
+
+            #[cfg(feature = "csv_export")]
+            Error::CsvError(ref err) => Some(err),
+            #[cfg(feature = "serde_json")]
+            Error::JsonError(ref err) => Some(err),
+            #[cfg(feature = "migrations")]
+            Error::MigrationError(ref err) => Some(err),
         }
     }
 }
@@ -343,6 +376,12 @@ pub fn error_from_sqlite_code(code: c_int, message: Option<String>) -> Error {
 }
 
 pub fn error_from_handle(db: *mut ffi::sqlite3, code: c_int) -> Error {
+    #[cfg(feature = "rtree")]
+    {
+        if let Some(message) = crate::rtree::take_error(db) {
+            return error_from_sqlite_code(code, Some(message));
+        }
+    }
     let message = if db.is_null() {
         None
     } else {
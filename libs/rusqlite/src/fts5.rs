@@ -0,0 +1,757 @@
+//! [FTS5](https://sqlite.org/fts5.html) custom tokenizers and auxiliary
+//! functions.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! # use rusqlite::fts5;
+//! fn search(conn: &Connection) -> Result<Vec<(i64, i64)>> {
+//!     fts5::load_tokenizers(conn)?;
+//!     // The number of matches of each document.
+//!     conn.create_fts5_function("hits", |ctx| ctx.inst_count().map(|n| n as i64))?;
+//!     conn.execute_batch(
+//!         "CREATE VIRTUAL TABLE docs USING fts5(body, tokenize = 'unicode_words')",
+//!     )?;
+//!     let mut stmt = conn.prepare("SELECT rowid, hits(docs) FROM docs WHERE docs MATCH 'tokyo'")?;
+//!     let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+//!     rows.collect()
+//! }
+//! ```
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str;
+
+use crate::context::set_result;
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::functions::{free_boxed_value, report_error};
+use crate::types::{FromSql, FromSqlError, ToSql, ValueRef};
+use crate::{str_to_cstring, Connection, Error, Result};
+
+bitflags::bitflags! {
+    /// Why a text is tokenized.
+    #[repr(C)]
+    pub struct TokenizeFlags: ::std::os::raw::c_int {
+        /// A query string, from a MATCH or a `highlight` like function.
+        const QUERY = ffi::FTS5_TOKENIZE_QUERY;
+        /// A query term followed by `*`.
+        const PREFIX = ffi::FTS5_TOKENIZE_PREFIX;
+        /// A document being inserted or deleted.
+        const DOCUMENT = ffi::FTS5_TOKENIZE_DOCUMENT;
+        /// A document tokenized by an auxiliary function.
+        const AUX = ffi::FTS5_TOKENIZE_AUX;
+    }
+}
+
+type XToken = unsafe extern "C" fn(*mut c_void, c_int, *const c_char, c_int, c_int, c_int) -> c_int;
+
+/// Receives the tokens of a text.
+pub struct TokenSink<'a> {
+    ctx: *mut c_void,
+    x_token: XToken,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl TokenSink<'_> {
+    fn emit(&mut self, token: &str, range: Range<usize>, flags: c_int) -> Result<()> {
+        check(unsafe {
+            (self.x_token)(
+                self.ctx,
+                flags,
+                token.as_ptr() as *const c_char,
+                token.len() as c_int,
+                range.start as c_int,
+                range.end as c_int,
+            )
+        })
+    }
+
+    /// Add `token`, found at the byte range `range` of the text.
+    ///
+    /// # Failure
+    ///
+    /// Returns `Err` when FTS5 does not want more tokens, which must be
+    /// returned by `Tokenizer::tokenize`.
+    pub fn push(&mut self, token: &str, range: Range<usize>) -> Result<()> {
+        self.emit(token, range, 0)
+    }
+
+    /// Add a synonym of the last token, at the same position.
+    ///
+    /// # Failure
+    ///
+    /// Same as `push`.
+    pub fn push_colocated(&mut self, token: &str, range: Range<usize>) -> Result<()> {
+        self.emit(token, range, ffi::FTS5_TOKEN_COLOCATED)
+    }
+}
+
+/// An FTS5 tokenizer. One instance is created by each table using it.
+pub trait Tokenizer: Sized {
+    /// Create a tokenizer with the arguments following its name in the
+    /// `tokenize` option of the table.
+    fn new(args: &[&str]) -> Result<Self>;
+
+    /// Split `text` into tokens.
+    fn tokenize(
+        &mut self,
+        flags: TokenizeFlags,
+        text: &str,
+        sink: &mut TokenSink<'_>,
+    ) -> Result<()>;
+}
+
+fn error_code(err: &Error) -> c_int {
+    match *err {
+        Error::SqliteFailure(ref err, _) => err.extended_code,
+        _ => ffi::SQLITE_ERROR,
+    }
+}
+
+unsafe extern "C" fn x_create<T: Tokenizer>(
+    _: *mut c_void,
+    az_arg: *mut *const c_char,
+    n_arg: c_int,
+    pp_out: *mut *mut ffi::Fts5Tokenizer,
+) -> c_int {
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        let args = if n_arg > 0 {
+            slice::from_raw_parts(az_arg, n_arg as usize)
+                .iter()
+                .map(|&arg| CStr::from_ptr(arg).to_str())
+                .collect::<std::result::Result<Vec<_>, _>>()?
+        } else {
+            Vec::new()
+        };
+        T::new(&args)
+    }));
+    match r {
+        Ok(Ok(tokenizer)) => {
+            *pp_out = Box::into_raw(Box::new(tokenizer)) as *mut ffi::Fts5Tokenizer;
+            ffi::SQLITE_OK
+        }
+        Ok(Err(err)) => error_code(&err),
+        Err(_) => ffi::SQLITE_ERROR,
+    }
+}
+
+unsafe extern "C" fn x_delete<T: Tokenizer>(p: *mut ffi::Fts5Tokenizer) {
+    drop(Box::from_raw(p as *mut T));
+}
+
+unsafe extern "C" fn x_tokenize<T: Tokenizer>(
+    p: *mut ffi::Fts5Tokenizer,
+    ctx: *mut c_void,
+    flags: c_int,
+    p_text: *const c_char,
+    n_text: c_int,
+    x_token: Option<XToken>,
+) -> c_int {
+    let x_token = match x_token {
+        Some(x_token) => x_token,
+        None => return ffi::SQLITE_MISUSE,
+    };
+    let text = if n_text > 0 {
+        slice::from_raw_parts(p_text as *const u8, n_text as usize)
+    } else {
+        &[]
+    };
+    let text = match str::from_utf8(text) {
+        Ok(text) => text,
+        Err(_) => return ffi::SQLITE_MISMATCH,
+    };
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        let tokenizer = &mut *(p as *mut T);
+        let mut sink = TokenSink {
+            ctx,
+            x_token,
+            phantom: PhantomData,
+        };
+        tokenizer.tokenize(TokenizeFlags::from_bits_truncate(flags), text, &mut sink)
+    }));
+    match r {
+        Ok(Ok(())) => ffi::SQLITE_OK,
+        Ok(Err(err)) => error_code(&err),
+        Err(_) => ffi::SQLITE_ERROR,
+    }
+}
+
+/// A phrase match in the current row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instance {
+    /// Index of the phrase in the query.
+    pub phrase: usize,
+    /// Column of the match.
+    pub column: usize,
+    /// Offset of the first token of the match in the column, in tokens.
+    pub offset: usize,
+}
+
+/// Context of an auxiliary function: its arguments, after the table, and
+/// the current row and query.
+pub struct AuxContext<'a> {
+    api: &'a ffi::Fts5ExtensionApi,
+    fts: *mut ffi::Fts5Context,
+    args: &'a [*mut ffi::sqlite3_value],
+}
+
+macro_rules! call {
+    ($ctx:expr, $f:ident $(, $arg:expr)*) => {
+        ($ctx.api.$f.expect(stringify!($f)))($ctx.fts $(, $arg)*)
+    };
+}
+
+fn check(rc: c_int) -> Result<()> {
+    match rc {
+        ffi::SQLITE_OK => Ok(()),
+        rc => Err(error_from_sqlite_code(rc, None)),
+    }
+}
+
+impl AuxContext<'_> {
+    /// Returns the number of arguments to the function, not counting the
+    /// table.
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Returns `true` when there is no argument.
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Returns the `idx`th argument as a `T`.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    ///
+    /// Will return Err if the underlying SQLite type cannot be converted to a
+    /// `T`.
+    pub fn get<T: FromSql>(&self, idx: usize) -> Result<T> {
+        let value = self.get_raw(idx);
+        FromSql::column_result(value).map_err(|err| match err {
+            FromSqlError::InvalidType => {
+                Error::InvalidFunctionParameterType(idx, value.data_type())
+            }
+            FromSqlError::OutOfRange(i) => Error::IntegralValueOutOfRange(idx, i),
+            FromSqlError::Other(err) => {
+                Error::FromSqlConversionFailure(idx, value.data_type(), err)
+            }
+            #[cfg(feature = "i128_blob")]
+            FromSqlError::InvalidI128Size(_) => {
+                Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err))
+            }
+            #[cfg(feature = "uuid")]
+            FromSqlError::InvalidUuidSize(_) => {
+                Error::FromSqlConversionFailure(idx, value.data_type(), Box::new(err))
+            }
+        })
+    }
+
+    /// Returns the `idx`th argument as a `ValueRef`.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    pub fn get_raw(&self, idx: usize) -> ValueRef<'_> {
+        unsafe { ValueRef::from_value(self.args[idx]) }
+    }
+
+    /// Returns the number of columns of the table.
+    pub fn column_count(&self) -> usize {
+        unsafe { call!(self, xColumnCount) as usize }
+    }
+
+    /// Returns the number of rows of the table.
+    pub fn row_count(&self) -> Result<i64> {
+        let mut n = 0;
+        check(unsafe { call!(self, xRowCount, &mut n) })?;
+        Ok(n)
+    }
+
+    /// Returns the number of tokens of column `column` in all rows, or of all
+    /// columns if `column` is `None`.
+    pub fn column_total_size(&self, column: Option<usize>) -> Result<i64> {
+        let mut n = 0;
+        let column = column.map_or(-1, |c| c as c_int);
+        check(unsafe { call!(self, xColumnTotalSize, column, &mut n) })?;
+        Ok(n)
+    }
+
+    /// Returns the number of tokens of column `column` in the current row, or
+    /// of all columns if `column` is `None`.
+    pub fn column_size(&self, column: Option<usize>) -> Result<usize> {
+        let mut n = 0;
+        let column = column.map_or(-1, |c| c as c_int);
+        check(unsafe { call!(self, xColumnSize, column, &mut n) })?;
+        Ok(n as usize)
+    }
+
+    /// Returns the text of column `column` in the current row.
+    pub fn column_text(&self, column: usize) -> Result<&str> {
+        let mut p: *const c_char = ptr::null();
+        let mut n = 0;
+        check(unsafe { call!(self, xColumnText, column as c_int, &mut p, &mut n) })?;
+        if p.is_null() || n <= 0 {
+            return Ok("");
+        }
+        let bytes = unsafe { slice::from_raw_parts(p as *const u8, n as usize) };
+        Ok(str::from_utf8(bytes)?)
+    }
+
+    /// Returns the rowid of the current row.
+    pub fn rowid(&self) -> i64 {
+        unsafe { call!(self, xRowid) }
+    }
+
+    /// Returns the number of phrases of the query.
+    pub fn phrase_count(&self) -> usize {
+        unsafe { call!(self, xPhraseCount) as usize }
+    }
+
+    /// Returns the number of tokens of phrase `phrase`.
+    pub fn phrase_size(&self, phrase: usize) -> usize {
+        unsafe { call!(self, xPhraseSize, phrase as c_int) as usize }
+    }
+
+    /// Returns the number of phrase matches in the current row.
+    pub fn inst_count(&self) -> Result<usize> {
+        let mut n = 0;
+        check(unsafe { call!(self, xInstCount, &mut n) })?;
+        Ok(n as usize)
+    }
+
+    /// Returns the `i`th phrase match in the current row, in the order of
+    /// their position.
+    pub fn inst(&self, i: usize) -> Result<Instance> {
+        let (mut phrase, mut column, mut offset) = (0, 0, 0);
+        check(unsafe {
+            call!(
+                self,
+                xInst,
+                i as c_int,
+                &mut phrase,
+                &mut column,
+                &mut offset
+            )
+        })?;
+        Ok(Instance {
+            phrase: phrase as usize,
+            column: column as usize,
+            offset: offset as usize,
+        })
+    }
+
+    /// Returns the `(column, offset)` of each match of phrase `phrase` in the
+    /// current row.
+    pub fn phrase_positions(&self, phrase: usize) -> Result<Vec<(usize, usize)>> {
+        let mut iter = ffi::Fts5PhraseIter {
+            a: ptr::null(),
+            b: ptr::null(),
+        };
+        let (mut column, mut offset) = (0, 0);
+        check(unsafe {
+            call!(
+                self,
+                xPhraseFirst,
+                phrase as c_int,
+                &mut iter,
+                &mut column,
+                &mut offset
+            )
+        })?;
+        let mut positions = Vec::new();
+        while column >= 0 {
+            positions.push((column as usize, offset as usize));
+            unsafe { call!(self, xPhraseNext, &mut iter, &mut column, &mut offset) };
+        }
+        Ok(positions)
+    }
+
+    /// Tokenize `text` with the tokenizer of the table, calling `f` with
+    /// each token and its byte range.
+    pub fn tokenize<F>(&self, text: &str, mut f: F) -> Result<()>
+    where
+        F: FnMut(&str, Range<usize>) -> Result<()>,
+    {
+        unsafe extern "C" fn x_token<F>(
+            p: *mut c_void,
+            _flags: c_int,
+            p_token: *const c_char,
+            n_token: c_int,
+            start: c_int,
+            end: c_int,
+        ) -> c_int
+        where
+            F: FnMut(&str, Range<usize>) -> Result<()>,
+        {
+            let r = catch_unwind(AssertUnwindSafe(|| {
+                let f = &mut *(p as *mut F);
+                let token = if n_token > 0 {
+                    slice::from_raw_parts(p_token as *const u8, n_token as usize)
+                } else {
+                    &[]
+                };
+                // Tokens need not be valid UTF-8.
+                let token = String::from_utf8_lossy(token);
+                f(&token, start as usize..end as usize)
+            }));
+            match r {
+                Ok(Ok(())) => ffi::SQLITE_OK,
+                Ok(Err(err)) => error_code(&err),
+                Err(_) => ffi::SQLITE_ERROR,
+            }
+        }
+
+        check(unsafe {
+            call!(
+                self,
+                xTokenize,
+                text.as_ptr() as *const c_char,
+                text.len() as c_int,
+                &mut f as *mut F as *mut c_void,
+                Some(x_token::<F>)
+            )
+        })
+    }
+
+    /// Call `f` with the context of each row matching phrase `phrase`,
+    /// for instance to count them.
+    pub fn query_phrase<F>(&self, phrase: usize, mut f: F) -> Result<()>
+    where
+        F: FnMut(&AuxContext<'_>) -> Result<()>,
+    {
+        unsafe extern "C" fn callback<F>(
+            api: *const ffi::Fts5ExtensionApi,
+            fts: *mut ffi::Fts5Context,
+            p: *mut c_void,
+        ) -> c_int
+        where
+            F: FnMut(&AuxContext<'_>) -> Result<()>,
+        {
+            let r = catch_unwind(AssertUnwindSafe(|| {
+                let f = &mut *(p as *mut F);
+                let ctx = AuxContext {
+                    api: &*api,
+                    fts,
+                    args: &[],
+                };
+                f(&ctx)
+            }));
+            match r {
+                Ok(Ok(())) => ffi::SQLITE_OK,
+                Ok(Err(err)) => error_code(&err),
+                Err(_) => ffi::SQLITE_ERROR,
+            }
+        }
+
+        check(unsafe {
+            call!(
+                self,
+                xQueryPhrase,
+                phrase as c_int,
+                &mut f as *mut F as *mut c_void,
+                Some(callback::<F>)
+            )
+        })
+    }
+
+    /// Keep `value` for the following rows of the current query, as with
+    /// `functions::Context::set_aux`.
+    pub fn set_aux<T: 'static>(&self, value: T) -> Result<()> {
+        let boxed = Box::into_raw(Box::new((std::any::TypeId::of::<T>(), value)));
+        check(unsafe {
+            call!(
+                self,
+                xSetAuxdata,
+                boxed as *mut c_void,
+                Some(free_boxed_value::<(std::any::TypeId, T)>)
+            )
+        })
+    }
+
+    /// Returns the value kept by `set_aux` for the current query.
+    pub fn get_aux<T: 'static>(&self) -> Result<Option<&T>> {
+        let p = unsafe { call!(self, xGetAuxdata, 0) as *mut (std::any::TypeId, T) };
+        if p.is_null() {
+            Ok(None)
+        } else {
+            let id_val = unsafe { &*p };
+            if std::any::TypeId::of::<T>() != id_val.0 {
+                Err(Error::GetAuxWrongType)
+            } else {
+                Ok(Some(&id_val.1))
+            }
+        }
+    }
+}
+
+impl Connection {
+    // Retrieve the FTS5 API of the connection, with `SELECT fts5(?1)`.
+    fn fts5_api(&self) -> Result<*mut ffi::fts5_api> {
+        let mut c = self.db.borrow_mut();
+        let mut api: *mut ffi::fts5_api = ptr::null_mut();
+        let mut stmt = ptr::null_mut();
+        let sql = b"SELECT fts5(?1)\0";
+        c.decode_result(unsafe {
+            ffi::sqlite3_prepare_v2(
+                c.db(),
+                sql.as_ptr() as *const c_char,
+                -1,
+                &mut stmt,
+                ptr::null_mut(),
+            )
+        })?;
+        let rc = unsafe {
+            ffi::sqlite3_bind_pointer(
+                stmt,
+                1,
+                &mut api as *mut _ as *mut c_void,
+                b"fts5_api_ptr\0".as_ptr() as *const c_char,
+                None,
+            );
+            ffi::sqlite3_step(stmt);
+            ffi::sqlite3_finalize(stmt)
+        };
+        c.decode_result(rc)?;
+        if api.is_null() {
+            return Err(error_from_sqlite_code(
+                ffi::SQLITE_ERROR,
+                Some("FTS5 is not available".to_owned()),
+            ));
+        }
+        Ok(api)
+    }
+
+    /// Register the tokenizer `T` as `name`, to be used as
+    /// `CREATE VIRTUAL TABLE t USING fts5(..., tokenize = 'name args...')`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if FTS5 is not available or if the tokenizer could
+    /// not be registered.
+    pub fn create_fts5_tokenizer<T: Tokenizer>(&self, name: &str) -> Result<()> {
+        let api = self.fts5_api()?;
+        let c_name = str_to_cstring(name)?;
+        let mut tokenizer = ffi::fts5_tokenizer {
+            xCreate: Some(x_create::<T>),
+            xDelete: Some(x_delete::<T>),
+            xTokenize: Some(x_tokenize::<T>),
+        };
+        // FTS5 copies `tokenizer`.
+        let rc = unsafe {
+            ((*api).xCreateTokenizer.expect("xCreateTokenizer"))(
+                api,
+                c_name.as_ptr(),
+                ptr::null_mut(),
+                &mut tokenizer,
+                None,
+            )
+        };
+        self.db.borrow_mut().decode_result(rc)
+    }
+
+    /// Register the auxiliary function `name`, called from a full-text query
+    /// as `name(table, args...)`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if FTS5 is not available or if the function could
+    /// not be registered.
+    pub fn create_fts5_function<F, T>(&self, name: &str, x_func: F) -> Result<()>
+    where
+        F: FnMut(&AuxContext<'_>) -> Result<T> + 'static,
+        T: ToSql,
+    {
+        unsafe extern "C" fn call_boxed_closure<F, T>(
+            api: *const ffi::Fts5ExtensionApi,
+            fts: *mut ffi::Fts5Context,
+            ctx: *mut ffi::sqlite3_context,
+            n_val: c_int,
+            ap_val: *mut *mut ffi::sqlite3_value,
+        ) where
+            F: FnMut(&AuxContext<'_>) -> Result<T>,
+            T: ToSql,
+        {
+            let r = catch_unwind(AssertUnwindSafe(|| {
+                let api = &*api;
+                let boxed_f = (api.xUserData.expect("xUserData"))(fts) as *mut F;
+                assert!(!boxed_f.is_null(), "Internal error - null function pointer");
+                let args = if n_val > 0 {
+                    slice::from_raw_parts(ap_val, n_val as usize)
+                } else {
+                    &[]
+                };
+                (*boxed_f)(&AuxContext { api, fts, args })
+            }));
+            let t = match r {
+                Err(_) => {
+                    report_error(ctx, &Error::UnwindingPanic);
+                    return;
+                }
+                Ok(r) => r,
+            };
+            let t = t.as_ref().map(|t| ToSql::to_sql(t));
+
+            match t {
+                Ok(Ok(ref value)) => set_result(ctx, value),
+                Ok(Err(err)) => report_error(ctx, &err),
+                Err(err) => report_error(ctx, err),
+            }
+        }
+
+        let api = self.fts5_api()?;
+        let c_name = str_to_cstring(name)?;
+        let boxed_f: *mut F = Box::into_raw(Box::new(x_func));
+        let rc = unsafe {
+            ((*api).xCreateFunction.expect("xCreateFunction"))(
+                api,
+                c_name.as_ptr(),
+                boxed_f as *mut c_void,
+                Some(call_boxed_closure::<F, T>),
+                Some(free_boxed_value::<F>),
+            )
+        };
+        self.db.borrow_mut().decode_result(rc)
+    }
+}
+
+/// Register the "unicode_words" and "ngram" tokenizers.
+pub fn load_tokenizers(conn: &Connection) -> Result<()> {
+    conn.create_fts5_tokenizer::<UnicodeWords>("unicode_words")?;
+    conn.create_fts5_tokenizer::<NGrams>("ngram")
+}
+
+fn no_arguments(name: &str, args: &[&str]) -> Result<()> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        Err(error_from_sqlite_code(
+            ffi::SQLITE_ERROR,
+            Some(format!("{}: unexpected arguments {:?}", name, args)),
+        ))
+    }
+}
+
+// Ideographs, kana and hangul, written without spaces between words.
+fn is_cjk(c: char) -> bool {
+    let c = c as u32;
+    [
+        0x3040..=0x30FF,
+        0x3400..=0x4DBF,
+        0x4E00..=0x9FFF,
+        0xAC00..=0xD7AF,
+        0xF900..=0xFAFF,
+        0x20000..=0x2FFFF,
+    ]
+    .iter()
+    .any(|range| range.contains(&c))
+}
+
+fn push_lowercase(token: &mut String, c: char) {
+    token.extend(c.to_lowercase());
+}
+
+/// Tokenizer splitting text into runs of letters and digits, lower cased.
+/// Each CJK character is a token of its own, so that a word is searched as a
+/// phrase of its characters.
+pub struct UnicodeWords;
+
+impl Tokenizer for UnicodeWords {
+    fn new(args: &[&str]) -> Result<UnicodeWords> {
+        no_arguments("unicode_words", args)?;
+        Ok(UnicodeWords)
+    }
+
+    fn tokenize(&mut self, _: TokenizeFlags, text: &str, sink: &mut TokenSink<'_>) -> Result<()> {
+        let mut token = String::new();
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            let cjk = is_cjk(c);
+            if !c.is_alphanumeric() || cjk {
+                if !token.is_empty() {
+                    sink.push(&token, start..i)?;
+                    token.clear();
+                }
+                if cjk {
+                    push_lowercase(&mut token, c);
+                    sink.push(&token, i..i + c.len_utf8())?;
+                    token.clear();
+                }
+                continue;
+            }
+            if token.is_empty() {
+                start = i;
+            }
+            push_lowercase(&mut token, c);
+        }
+        if !token.is_empty() {
+            sink.push(&token, start..text.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Tokenizer making tokens of every `n` consecutive characters of each
+/// word, lower cased, to search substrings of at least `n` characters, or
+/// text without spaces. `n` is the argument, 3 by default: `ngram 2` suits
+/// CJK text. Words shorter than `n` are a token.
+pub struct NGrams {
+    n: usize,
+}
+
+impl Tokenizer for NGrams {
+    fn new(args: &[&str]) -> Result<NGrams> {
+        let n = match *args {
+            [] => 3,
+            [n] => match n.parse() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    return Err(error_from_sqlite_code(
+                        ffi::SQLITE_ERROR,
+                        Some(format!("ngram: invalid length {}", n)),
+                    ))
+                }
+            },
+            _ => {
+                return Err(error_from_sqlite_code(
+                    ffi::SQLITE_ERROR,
+                    Some(format!("ngram: unexpected arguments {:?}", args)),
+                ))
+            }
+        };
+        Ok(NGrams { n })
+    }
+
+    fn tokenize(&mut self, _: TokenizeFlags, text: &str, sink: &mut TokenSink<'_>) -> Result<()> {
+        let mut token = String::new();
+        // The characters of the current word, with their byte offset.
+        let mut word: Vec<(usize, char)> = Vec::new();
+        // A space ends the last word.
+        for (i, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+            if !c.is_whitespace() {
+                word.push((i, c));
+                continue;
+            }
+            let windows = word.len().saturating_sub(self.n) + 1;
+            for w in 0..windows.min(word.len()) {
+                let chars = &word[w..word.len().min(w + self.n)];
+                token.clear();
+                for &(_, c) in chars {
+                    push_lowercase(&mut token, c);
+                }
+                let end = word.get(w + self.n).map_or(i, |&(next, _)| next);
+                sink.push(&token, chars[0].0..end)?;
+            }
+            word.clear();
+        }
+        Ok(())
+    }
+}
@@ -82,7 +82,12 @@ use crate::types::{FromSql, FromSqlError, ToSql, ValueRef};
 use crate::{str_to_cstring, Connection, Error, InnerConnection, Result};
 use wasm_bindgen::__rt::std::panic::AssertUnwindSafe;
 
-unsafe fn report_error(ctx: *mut sqlite3_context, err: &Error) {
+#[cfg(feature = "builtin_functions")]
+pub mod builtin;
+#[cfg(feature = "stats_functions")]
+pub mod stats;
+
+pub(crate) unsafe fn report_error(ctx: *mut sqlite3_context, err: &Error) {
     // Extended constraint error codes were added in SQLite 3.7.16. We don't have
     // an explicit feature check for that, and this doesn't really warrant one.
     // We'll use the extended code if we're on the bundled version (since it's
@@ -108,7 +113,7 @@ unsafe fn report_error(ctx: *mut sqlite3_context, err: &Error) {
     }
 }
 
-unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
+pub(crate) unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
     drop(Box::from_raw(p as *mut T));
 }
 
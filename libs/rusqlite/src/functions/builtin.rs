@@ -0,0 +1,415 @@
+//! A pack of scalar functions missing from SQLite, written in Rust.
+//!
+//! * Regular expressions, with the syntax of the
+//!   [`regex`](https://docs.rs/regex) crate:
+//!   * `regexp(PATTERN, TEXT)`, which makes `TEXT REGEXP PATTERN` work,
+//!   * `regexp_replace(TEXT, PATTERN, REPLACEMENT)`: all matches replaced,
+//!     with `$1` or `${name}` for captured groups,
+//!   * `regexp_capture(TEXT, PATTERN [, GROUP])`: the text of the first
+//!     match, or of its group `GROUP` (an index or a name), or NULL.
+//!
+//!   Patterns are compiled once per statement when they are constant.
+//! * Math functions, as they are in SQLite 3.35: `acos`, `acosh`, `asin`,
+//!   `asinh`, `atan`, `atan2(Y, X)`, `atanh`, `ceil`, `ceiling`, `cos`,
+//!   `cosh`, `degrees`, `exp`, `floor`, `ln`, `log(X)` (base 10),
+//!   `log(B, X)`, `log10`, `log2`, `mod(X, Y)`, `pi()`, `pow`, `power`,
+//!   `radians`, `sin`, `sinh`, `sqrt`, `tan`, `tanh` and `trunc`. They
+//!   return NULL for NULL or non-numeric arguments, and outside of their
+//!   domain.
+//! * UUIDs, as in SQLite's `uuid` extension:
+//!   * `uuid()`: a random version 4 UUID, as text,
+//!   * `uuid_str(X)`: the UUID X, a blob or text, as canonical text,
+//!   * `uuid_blob(X)`: the UUID X as a 16-byte blob.
+//! * Strings:
+//!   * `lpad(TEXT, N [, FILL])` and `rpad(TEXT, N [, FILL])`: TEXT padded to
+//!     N characters with FILL (a space by default) on the left or the right,
+//!     or truncated to N characters,
+//!   * `split_part(TEXT, DELIMITER, N)`: the field N of TEXT split on
+//!     DELIMITER, counting from 1, or from the end if negative, or `''`,
+//!   * `unaccent(TEXT)`: TEXT without diacritics.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! # use rusqlite::functions::builtin;
+//! fn codes(conn: &Connection) -> Result<Vec<String>> {
+//!     builtin::load_functions(conn)?;
+//!     let mut stmt = conn.prepare(
+//!         "SELECT lpad(regexp_capture(ref, '[0-9]+'), 6, '0') FROM orders
+//!          WHERE ref REGEXP '^[A-Z]{2}-[0-9]+$'",
+//!     )?;
+//!     let rows = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+//!     rows.collect()
+//! }
+//! ```
+use std::f64::consts::PI;
+use std::os::raw::{c_int, c_void};
+use std::str;
+
+use regex::Regex;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::ffi;
+use crate::functions::{Context, FunctionFlags};
+use crate::types::{Value, ValueRef};
+use crate::{Connection, Error, Result};
+
+/// Register all the functions of the pack.
+pub fn load_functions(conn: &Connection) -> Result<()> {
+    load_regexp_functions(conn)?;
+    load_math_functions(conn)?;
+    load_uuid_functions(conn)?;
+    load_string_functions(conn)
+}
+
+fn deterministic() -> FunctionFlags {
+    FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC
+}
+
+/// The argument `idx` as text, converted as `sqlite3_value_text` does.
+fn string(ctx: &Context<'_>, idx: usize) -> Option<String> {
+    match ctx.get_raw(idx) {
+        ValueRef::Null => None,
+        ValueRef::Integer(i) => Some(i.to_string()),
+        ValueRef::Real(r) => Some(real_to_string(r)),
+        ValueRef::Text(t) | ValueRef::Blob(t) => Some(String::from_utf8_lossy(t).into_owned()),
+    }
+}
+
+/// Format `r` like SQLite's `%!.15g`: 15 significant digits, with a
+/// fractional part even when `r` is integral.
+fn real_to_string(r: f64) -> String {
+    if r.is_infinite() {
+        return if r > 0.0 { "Inf" } else { "-Inf" }.to_owned();
+    }
+    if r == 0.0 {
+        return "0.0".to_owned();
+    }
+    let s = format!("{:.14e}", r);
+    let e = s.find('e').unwrap();
+    let exp: i32 = s[e + 1..].parse().unwrap();
+    let sign = if r < 0.0 { "-" } else { "" };
+    let digits: String = s[..e].chars().filter(char::is_ascii_digit).collect();
+    let digits = digits.trim_end_matches('0');
+    if exp < -4 || exp >= 15 {
+        let frac = if digits.len() > 1 { &digits[1..] } else { "0" };
+        let exp_sign = if exp < 0 { '-' } else { '+' };
+        format!(
+            "{}{}.{}e{}{:02}",
+            sign,
+            &digits[..1],
+            frac,
+            exp_sign,
+            exp.abs()
+        )
+    } else if exp < 0 {
+        let zeros = "0".repeat((-exp - 1) as usize);
+        format!("{}0.{}{}", sign, zeros, digits)
+    } else if digits.len() <= exp as usize + 1 {
+        let zeros = "0".repeat(exp as usize + 1 - digits.len());
+        format!("{}{}{}.0", sign, digits, zeros)
+    } else {
+        let (int, frac) = digits.split_at(exp as usize + 1);
+        format!("{}{}.{}", sign, int, frac)
+    }
+}
+
+/// Call `f` with the regex of the argument `arg` of `ctx`, compiled once per
+/// statement if the argument is constant.
+fn with_regex<T, F>(ctx: &Context<'_>, arg: usize, f: F) -> Result<Option<T>>
+where
+    F: FnOnce(&Regex) -> T,
+{
+    if let Some(re) = ctx.get_aux::<Regex>(arg as c_int)? {
+        return Ok(Some(f(re)));
+    }
+    let pattern = match string(ctx, arg) {
+        Some(pattern) => pattern,
+        None => return Ok(None),
+    };
+    let re = Regex::new(&pattern).map_err(|err| Error::UserFunctionError(Box::new(err)))?;
+    let t = f(&re);
+    // SQLite may drop the regex at once, if the pattern is not constant.
+    ctx.set_aux(arg as c_int, re);
+    Ok(Some(t))
+}
+
+/// Register `regexp`, `regexp_replace` and `regexp_capture`.
+pub fn load_regexp_functions(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function("regexp", 2, deterministic(), |ctx| {
+        let text = match string(ctx, 1) {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        with_regex(ctx, 0, |re| re.is_match(&text))
+    })?;
+    conn.create_scalar_function("regexp_replace", 3, deterministic(), |ctx| {
+        let (text, replacement) = match (string(ctx, 0), string(ctx, 2)) {
+            (Some(text), Some(replacement)) => (text, replacement),
+            _ => return Ok(None),
+        };
+        with_regex(ctx, 1, |re| {
+            re.replace_all(&text, replacement.as_str()).into_owned()
+        })
+    })?;
+    for &n_arg in &[2, 3] {
+        conn.create_scalar_function("regexp_capture", n_arg, deterministic(), |ctx| {
+            let text = match string(ctx, 0) {
+                Some(text) => text,
+                None => return Ok(None),
+            };
+            let group = if ctx.len() > 2 {
+                ctx.get::<Value>(2)?
+            } else {
+                Value::Integer(0)
+            };
+            let capture = with_regex(ctx, 1, |re| {
+                let captures = re.captures(&text)?;
+                let capture = match group {
+                    Value::Integer(i) => captures.get(i as usize),
+                    Value::Text(ref name) => captures.name(name),
+                    _ => None,
+                };
+                capture.map(|m| m.as_str().to_owned())
+            })?;
+            Ok(capture.flatten())
+        })?;
+    }
+    Ok(())
+}
+
+/// The argument `idx` as a number, if it is one or text that parses as one.
+fn number(ctx: &Context<'_>, idx: usize) -> Option<f64> {
+    match ctx.get_raw(idx) {
+        ValueRef::Integer(i) => Some(i as f64),
+        ValueRef::Real(r) => Some(r),
+        ValueRef::Text(t) => str::from_utf8(t).ok()?.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn finite(r: f64) -> Option<f64> {
+    if !r.is_finite() {
+        None
+    } else {
+        Some(r)
+    }
+}
+
+fn unary(conn: &Connection, name: &str, f: fn(f64) -> f64) -> Result<()> {
+    conn.create_scalar_function(name, 1, deterministic(), move |ctx| {
+        Ok(number(ctx, 0).map(f).and_then(finite))
+    })
+}
+
+fn binary(conn: &Connection, name: &str, f: fn(f64, f64) -> f64) -> Result<()> {
+    conn.create_scalar_function(name, 2, deterministic(), move |ctx| {
+        Ok(match (number(ctx, 0), number(ctx, 1)) {
+            (Some(x), Some(y)) => finite(f(x, y)),
+            _ => None,
+        })
+    })
+}
+
+// Rounding functions keep integers as they are.
+fn rounding(conn: &Connection, name: &str, f: fn(f64) -> f64) -> Result<()> {
+    conn.create_scalar_function(name, 1, deterministic(), move |ctx| {
+        Ok(match ctx.get_raw(0) {
+            ValueRef::Integer(i) => Some(Value::Integer(i)),
+            _ => number(ctx, 0).map(|r| Value::Real(f(r))),
+        })
+    })
+}
+
+/// Register the math functions.
+pub fn load_math_functions(conn: &Connection) -> Result<()> {
+    let unaries: &[(&str, fn(f64) -> f64)] = &[
+        ("acos", f64::acos),
+        ("acosh", f64::acosh),
+        ("asin", f64::asin),
+        ("asinh", f64::asinh),
+        ("atan", f64::atan),
+        ("atanh", f64::atanh),
+        ("cos", f64::cos),
+        ("cosh", f64::cosh),
+        ("degrees", f64::to_degrees),
+        ("exp", f64::exp),
+        ("ln", f64::ln),
+        ("log", f64::log10),
+        ("log10", f64::log10),
+        ("log2", f64::log2),
+        ("radians", f64::to_radians),
+        ("sin", f64::sin),
+        ("sinh", f64::sinh),
+        ("sqrt", f64::sqrt),
+        ("tan", f64::tan),
+        ("tanh", f64::tanh),
+    ];
+    for &(name, f) in unaries {
+        unary(conn, name, f)?;
+    }
+    let binaries: &[(&str, fn(f64, f64) -> f64)] = &[
+        ("atan2", f64::atan2),
+        ("log", |b, x| x.log(b)),
+        ("mod", |x, y| x % y),
+        ("pow", f64::powf),
+        ("power", f64::powf),
+    ];
+    for &(name, f) in binaries {
+        binary(conn, name, f)?;
+    }
+    rounding(conn, "ceil", f64::ceil)?;
+    rounding(conn, "ceiling", f64::ceil)?;
+    rounding(conn, "floor", f64::floor)?;
+    rounding(conn, "trunc", f64::trunc)?;
+    conn.create_scalar_function("pi", 0, deterministic(), |_| Ok(PI))
+}
+
+fn parse_uuid(value: ValueRef<'_>) -> Option<[u8; 16]> {
+    let mut uuid = [0u8; 16];
+    match value {
+        ValueRef::Blob(b) if b.len() == 16 => uuid.copy_from_slice(b),
+        ValueRef::Text(t) => {
+            // Hyphens and braces are ignored, like the `uuid` extension does.
+            let digits: Vec<u8> = t
+                .iter()
+                .filter(|&&c| c != b'-' && c != b'{' && c != b'}')
+                .map(|&c| (c as char).to_digit(16).map(|d| d as u8))
+                .collect::<Option<_>>()?;
+            if digits.len() != 32 {
+                return None;
+            }
+            for (byte, pair) in uuid.iter_mut().zip(digits.chunks(2)) {
+                *byte = pair[0] << 4 | pair[1];
+            }
+        }
+        _ => return None,
+    }
+    Some(uuid)
+}
+
+fn format_uuid(uuid: &[u8; 16]) -> String {
+    let mut s = String::with_capacity(36);
+    for (i, byte) in uuid.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            s.push('-');
+        }
+        s.push_str(&format!("{:02x}", byte));
+    }
+    s
+}
+
+/// Register `uuid`, `uuid_str` and `uuid_blob`.
+pub fn load_uuid_functions(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function("uuid", 0, FunctionFlags::SQLITE_UTF8, |_| {
+        let mut uuid = [0u8; 16];
+        unsafe { ffi::sqlite3_randomness(16, uuid.as_mut_ptr() as *mut c_void) };
+        // Version 4, variant 1
+        uuid[6] = (uuid[6] & 0x0f) | 0x40;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+        Ok(format_uuid(&uuid))
+    })?;
+    conn.create_scalar_function("uuid_str", 1, deterministic(), |ctx| {
+        Ok(parse_uuid(ctx.get_raw(0)).map(|uuid| format_uuid(&uuid)))
+    })?;
+    conn.create_scalar_function("uuid_blob", 1, deterministic(), |ctx| {
+        Ok(parse_uuid(ctx.get_raw(0)).map(|uuid| uuid.to_vec()))
+    })
+}
+
+fn pad(text: &str, n: i64, fill: &str, left: bool) -> String {
+    let n = n.max(0) as usize;
+    let len = text.chars().count();
+    if len >= n || fill.is_empty() {
+        return text.chars().take(n).collect();
+    }
+    let padding: String = fill.chars().cycle().take(n - len).collect();
+    if left {
+        padding + text
+    } else {
+        text.to_owned() + &padding
+    }
+}
+
+fn split_part(text: &str, delimiter: &str, n: i64) -> String {
+    let field = if delimiter.is_empty() {
+        // The whole text is the only field.
+        if n == 1 || n == -1 {
+            Some(text)
+        } else {
+            None
+        }
+    } else if n > 0 {
+        text.split(delimiter).nth(n as usize - 1)
+    } else if n < 0 {
+        text.rsplit(delimiter).nth((n.unsigned_abs() - 1) as usize)
+    } else {
+        None
+    };
+    field.unwrap_or("").to_owned()
+}
+
+/// Letters without a decomposition, and their unaccented spelling.
+const UNACCENTED: &[(char, &str)] = &[
+    ('Æ', "AE"),
+    ('Ð', "D"),
+    ('Ø', "O"),
+    ('Þ', "TH"),
+    ('ß', "ss"),
+    ('æ', "ae"),
+    ('ð', "d"),
+    ('ø', "o"),
+    ('þ', "th"),
+    ('Đ', "D"),
+    ('đ', "d"),
+    ('Ħ', "H"),
+    ('ħ', "h"),
+    ('ı', "i"),
+    ('Ł', "L"),
+    ('ł', "l"),
+    ('Œ', "OE"),
+    ('œ', "oe"),
+];
+
+fn unaccent(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    for c in text.nfd().filter(|&c| !is_combining_mark(c)) {
+        match UNACCENTED.binary_search_by_key(&c, |&(accented, _)| accented) {
+            Ok(i) => s.push_str(UNACCENTED[i].1),
+            Err(_) => s.push(c),
+        }
+    }
+    s.nfc().collect()
+}
+
+/// Register `lpad`, `rpad`, `split_part` and `unaccent`.
+pub fn load_string_functions(conn: &Connection) -> Result<()> {
+    for &(name, left) in &[("lpad", true), ("rpad", false)] {
+        for &n_arg in &[2, 3] {
+            conn.create_scalar_function(name, n_arg, deterministic(), move |ctx| {
+                let fill = if ctx.len() > 2 {
+                    string(ctx, 2)
+                } else {
+                    Some(" ".to_owned())
+                };
+                Ok(match (string(ctx, 0), ctx.get::<Option<i64>>(1)?, fill) {
+                    (Some(text), Some(n), Some(fill)) => Some(pad(&text, n, &fill, left)),
+                    _ => None,
+                })
+            })?;
+        }
+    }
+    conn.create_scalar_function("split_part", 3, deterministic(), |ctx| {
+        Ok(
+            match (string(ctx, 0), string(ctx, 1), ctx.get::<Option<i64>>(2)?) {
+                (Some(text), Some(delimiter), Some(n)) => Some(split_part(&text, &delimiter, n)),
+                _ => None,
+            },
+        )
+    })?;
+    conn.create_scalar_function("unaccent", 1, deterministic(), |ctx| {
+        Ok(string(ctx, 0).map(|text| unaccent(&text)))
+    })
+}
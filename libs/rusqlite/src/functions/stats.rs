@@ -0,0 +1,635 @@
+//! Statistical aggregate functions, which are window functions too.
+//!
+//! * `median(X)`, `percentile(X, P)` with P from 0 to 100,
+//!   `percentile_cont(X, P)` with P from 0 to 1, interpolating between
+//!   values, and `percentile_disc(X, P)`, the first value whose cumulative
+//!   distribution reaches P,
+//! * `variance(X)` and `var_samp(X)`, `var_pop(X)`, `stddev(X)` and
+//!   `stddev_samp(X)`, `stddev_pop(X)`: the sample or population variance
+//!   and standard deviation,
+//! * `corr(Y, X)`: the Pearson correlation coefficient of pairs,
+//! * `mode(X)`: the most frequent value, the smallest one on ties,
+//! * `approx_quantile(X, Q)` and `approx_median(X)`: the quantile Q, from 0
+//!   to 1, estimated with a [t-digest](https://arxiv.org/abs/1902.04023) of
+//!   bounded size. Removing rows from a window is approximate too.
+//!
+//! NULL and non-numeric values are ignored, except by `mode`, which only
+//! ignores NULLs. The functions return NULL without rows, and the sample
+//! variance and the correlation with less than two.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! # use rusqlite::functions::stats;
+//! fn latencies(conn: &Connection) -> Result<(f64, f64)> {
+//!     stats::load_functions(conn)?;
+//!     conn.query_row(
+//!         "SELECT median(ms), percentile(ms, 99) FROM requests",
+//!         NO_PARAMS,
+//!         |row| Ok((row.get(0)?, row.get(1)?)),
+//!     )
+//! }
+//! ```
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::panic::{AssertUnwindSafe, RefUnwindSafe, UnwindSafe};
+use std::str;
+
+use crate::functions::{Aggregate, Context, FunctionFlags, WindowAggregate};
+use crate::types::{ToSql, Value, ValueRef};
+use crate::{Connection, Error, Result};
+
+/// Register all the statistical aggregates.
+pub fn load_functions(conn: &Connection) -> Result<()> {
+    register(conn, "median", 1, Percentile::Median)?;
+    register(conn, "percentile", 2, Percentile::Percent)?;
+    register(conn, "percentile_cont", 2, Percentile::Cont)?;
+    register(conn, "percentile_disc", 2, Percentile::Disc)?;
+    for &(name, sample, sqrt) in &[
+        ("variance", true, false),
+        ("var_samp", true, false),
+        ("var_pop", false, false),
+        ("stddev", true, true),
+        ("stddev_samp", true, true),
+        ("stddev_pop", false, true),
+    ] {
+        register(conn, name, 1, Variance { sample, sqrt })?;
+    }
+    register(conn, "corr", 2, Correlation)?;
+    register(conn, "mode", 1, Mode)?;
+    register(conn, "approx_quantile", 2, ApproxQuantile { median: false })?;
+    register(conn, "approx_median", 1, ApproxQuantile { median: true })
+}
+
+fn register<A, W, T>(conn: &Connection, name: &str, n_arg: i32, aggregate: W) -> Result<()>
+where
+    A: RefUnwindSafe + UnwindSafe,
+    W: WindowAggregate<A, T>,
+    T: ToSql,
+{
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_window_function(name, n_arg, flags, aggregate)
+}
+
+/// The argument `idx` as a number, if it is one or text that parses as one.
+fn number(ctx: &Context<'_>, idx: usize) -> Option<f64> {
+    match ctx.get_raw(idx) {
+        ValueRef::Integer(i) => Some(i as f64),
+        ValueRef::Real(r) => Some(r),
+        ValueRef::Text(t) => str::from_utf8(t)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+            .filter(|x: &f64| x.is_finite()),
+        _ => None,
+    }
+}
+
+/// The fraction argument `idx`, from 0 to `max`, which must be the same for
+/// all rows.
+fn fraction(ctx: &Context<'_>, idx: usize, max: f64, current: Option<f64>) -> Result<f64> {
+    let p = match number(ctx, idx) {
+        Some(p) if (0.0..=max).contains(&p) => p / max,
+        _ => {
+            return Err(Error::UserFunctionError(
+                format!("the fraction must be a number between 0 and {}", max).into(),
+            ))
+        }
+    };
+    match current {
+        Some(current) if current != p => Err(Error::UserFunctionError(
+            "the fraction must be the same for all rows".into(),
+        )),
+        _ => Ok(p),
+    }
+}
+
+/// Exact percentiles, over the sorted values.
+#[derive(Clone, Copy)]
+enum Percentile {
+    Median,
+    /// From 0 to 100
+    Percent,
+    /// From 0 to 1
+    Cont,
+    /// From 0 to 1, without interpolation
+    Disc,
+}
+
+#[derive(Default)]
+struct Values {
+    values: Vec<f64>,
+    unsorted: bool,
+    /// The percentile, from 0 to 1
+    p: Option<f64>,
+}
+
+impl Values {
+    fn add(&mut self, x: f64) {
+        self.unsorted |= self.values.last().map_or(false, |&last| last > x);
+        self.values.push(x);
+    }
+
+    fn remove(&mut self, x: f64) {
+        if !self.unsorted {
+            if let Ok(i) = self.values.binary_search_by(|v| cmp(*v, x)) {
+                self.values.remove(i);
+            }
+        } else if let Some(i) = self.values.iter().position(|&v| v == x) {
+            self.values.swap_remove(i);
+        }
+    }
+
+    fn sort(&mut self) -> &[f64] {
+        if self.unsorted {
+            self.values.sort_unstable_by(|a, b| cmp(*a, *b));
+            self.unsorted = false;
+        }
+        &self.values
+    }
+}
+
+fn cmp(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+/// Values that `value` sorts in place once, rather than keeping them sorted
+/// on each row.
+type Sample = AssertUnwindSafe<RefCell<Values>>;
+
+impl Percentile {
+    fn update(&self, ctx: &Context<'_>, state: &mut Values) -> Result<Option<f64>> {
+        state.p = Some(match *self {
+            Percentile::Median => 0.5,
+            Percentile::Percent => fraction(ctx, 1, 100.0, state.p)?,
+            Percentile::Cont | Percentile::Disc => fraction(ctx, 1, 1.0, state.p)?,
+        });
+        Ok(number(ctx, 0))
+    }
+}
+
+impl Aggregate<Sample, Option<f64>> for Percentile {
+    fn init(&self) -> Sample {
+        AssertUnwindSafe(RefCell::new(Values::default()))
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, state: &mut Sample) -> Result<()> {
+        let state = state.get_mut();
+        if let Some(x) = self.update(ctx, state)? {
+            state.add(x);
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, state: Option<Sample>) -> Result<Option<f64>> {
+        self.value(state.as_ref())
+    }
+}
+
+impl WindowAggregate<Sample, Option<f64>> for Percentile {
+    fn value(&self, state: Option<&Sample>) -> Result<Option<f64>> {
+        let mut state = match state {
+            Some(state) => state.borrow_mut(),
+            None => return Ok(None),
+        };
+        let p = match state.p {
+            Some(p) if !state.values.is_empty() => p,
+            _ => return Ok(None),
+        };
+        let values = state.sort();
+        let n = values.len();
+        Ok(Some(if let Percentile::Disc = *self {
+            let i = (p * n as f64).ceil() as usize;
+            values[i.max(1).min(n) - 1]
+        } else {
+            let ix = p * (n - 1) as f64;
+            let lo = ix.floor() as usize;
+            match values.get(lo + 1) {
+                Some(&hi) => values[lo] + (hi - values[lo]) * (ix - lo as f64),
+                None => values[lo],
+            }
+        }))
+    }
+
+    fn inverse(&self, ctx: &mut Context<'_>, state: &mut Sample) -> Result<()> {
+        let state = state.get_mut();
+        if let Some(x) = self.update(ctx, state)? {
+            state.remove(x);
+        }
+        Ok(())
+    }
+}
+
+/// Running mean and sum of squared deviations, after Welford.
+#[derive(Default)]
+struct Moments {
+    n: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Moments {
+    fn add(&mut self, x: f64) {
+        self.n += 1.0;
+        let d = x - self.mean;
+        self.mean += d / self.n;
+        self.m2 += d * (x - self.mean);
+    }
+
+    fn remove(&mut self, x: f64) {
+        if self.n <= 1.0 {
+            *self = Moments::default();
+            return;
+        }
+        let d = x - self.mean;
+        self.mean -= d / (self.n - 1.0);
+        self.m2 -= d * (x - self.mean);
+        self.n -= 1.0;
+    }
+}
+
+struct Variance {
+    sample: bool,
+    sqrt: bool,
+}
+
+impl Aggregate<Moments, Option<f64>> for Variance {
+    fn init(&self) -> Moments {
+        Moments::default()
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, state: &mut Moments) -> Result<()> {
+        if let Some(x) = number(ctx, 0) {
+            state.add(x);
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, state: Option<Moments>) -> Result<Option<f64>> {
+        self.value(state.as_ref())
+    }
+}
+
+impl WindowAggregate<Moments, Option<f64>> for Variance {
+    fn value(&self, state: Option<&Moments>) -> Result<Option<f64>> {
+        let state = match state {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        let n = if self.sample { state.n - 1.0 } else { state.n };
+        if n <= 0.0 {
+            return Ok(None);
+        }
+        // Rounding can make the sum of squares slightly negative.
+        let variance = (state.m2 / n).max(0.0);
+        Ok(Some(if self.sqrt { variance.sqrt() } else { variance }))
+    }
+
+    fn inverse(&self, ctx: &mut Context<'_>, state: &mut Moments) -> Result<()> {
+        if let Some(x) = number(ctx, 0) {
+            state.remove(x);
+        }
+        Ok(())
+    }
+}
+
+/// Running means, sums of squared deviations and co-moment of pairs.
+#[derive(Default)]
+struct CoMoments {
+    n: f64,
+    mean_x: f64,
+    mean_y: f64,
+    m2_x: f64,
+    m2_y: f64,
+    c: f64,
+}
+
+impl CoMoments {
+    fn add(&mut self, x: f64, y: f64) {
+        self.n += 1.0;
+        let (dx, dy) = (x - self.mean_x, y - self.mean_y);
+        self.mean_x += dx / self.n;
+        self.mean_y += dy / self.n;
+        self.m2_x += dx * (x - self.mean_x);
+        self.m2_y += dy * (y - self.mean_y);
+        self.c += dx * (y - self.mean_y);
+    }
+
+    fn remove(&mut self, x: f64, y: f64) {
+        if self.n <= 1.0 {
+            *self = CoMoments::default();
+            return;
+        }
+        let (dx, dy) = (x - self.mean_x, y - self.mean_y);
+        self.mean_x -= dx / (self.n - 1.0);
+        self.mean_y -= dy / (self.n - 1.0);
+        self.m2_x -= dx * (x - self.mean_x);
+        self.m2_y -= dy * (y - self.mean_y);
+        self.c -= dx * (y - self.mean_y);
+        self.n -= 1.0;
+    }
+}
+
+struct Correlation;
+
+fn pair(ctx: &Context<'_>) -> Option<(f64, f64)> {
+    Some((number(ctx, 1)?, number(ctx, 0)?))
+}
+
+impl Aggregate<CoMoments, Option<f64>> for Correlation {
+    fn init(&self) -> CoMoments {
+        CoMoments::default()
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, state: &mut CoMoments) -> Result<()> {
+        if let Some((x, y)) = pair(ctx) {
+            state.add(x, y);
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, state: Option<CoMoments>) -> Result<Option<f64>> {
+        self.value(state.as_ref())
+    }
+}
+
+impl WindowAggregate<CoMoments, Option<f64>> for Correlation {
+    fn value(&self, state: Option<&CoMoments>) -> Result<Option<f64>> {
+        Ok(state.and_then(|s| {
+            let d = (s.m2_x * s.m2_y).sqrt();
+            if s.n < 2.0 || d <= 0.0 {
+                None
+            } else {
+                Some((s.c / d).max(-1.0).min(1.0))
+            }
+        }))
+    }
+
+    fn inverse(&self, ctx: &mut Context<'_>, state: &mut CoMoments) -> Result<()> {
+        if let Some((x, y)) = pair(ctx) {
+            state.remove(x, y);
+        }
+        Ok(())
+    }
+}
+
+/// A value, in SQLite's sort order: numbers, then text, then blobs.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    /// The bits of a number, in the order of the numbers, then for an
+    /// integer that a double cannot hold, its difference to that double
+    Number(u64, i64),
+    Text(Vec<u8>),
+    Blob(Vec<u8>),
+}
+
+impl Key {
+    fn from_value(value: ValueRef<'_>) -> Option<Key> {
+        let number = |r: f64, rest: i64| {
+            let bits = r.to_bits();
+            let bits = if bits >> 63 == 1 {
+                !bits
+            } else {
+                bits | 1 << 63
+            };
+            Key::Number(bits, rest)
+        };
+        Some(match value {
+            ValueRef::Null => return None,
+            ValueRef::Integer(i) => {
+                let r = i as f64;
+                number(r, (i128::from(i) - r as i128) as i64)
+            }
+            // 0.0 and -0.0 are the same value.
+            ValueRef::Real(r) => number(r + 0.0, 0),
+            ValueRef::Text(t) => Key::Text(t.to_vec()),
+            ValueRef::Blob(b) => Key::Blob(b.to_vec()),
+        })
+    }
+}
+
+/// The count of each value, and its first occurrence.
+type Counts = BTreeMap<Key, (i64, Value)>;
+
+struct Mode;
+
+impl Aggregate<Counts, Option<Value>> for Mode {
+    fn init(&self) -> Counts {
+        Counts::new()
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, state: &mut Counts) -> Result<()> {
+        let value = ctx.get_raw(0);
+        if let Some(key) = Key::from_value(value) {
+            state.entry(key).or_insert_with(|| (0, value.into())).0 += 1;
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, state: Option<Counts>) -> Result<Option<Value>> {
+        self.value(state.as_ref())
+    }
+}
+
+impl WindowAggregate<Counts, Option<Value>> for Mode {
+    fn value(&self, state: Option<&Counts>) -> Result<Option<Value>> {
+        let mut mode: Option<&(i64, Value)> = None;
+        for entry in state.into_iter().flat_map(BTreeMap::values) {
+            // Keep the smallest value on ties.
+            if mode.map_or(true, |m| entry.0 > m.0) {
+                mode = Some(entry);
+            }
+        }
+        Ok(mode.map(|&(_, ref value)| value.clone()))
+    }
+
+    fn inverse(&self, ctx: &mut Context<'_>, state: &mut Counts) -> Result<()> {
+        if let Some(key) = Key::from_value(ctx.get_raw(0)) {
+            let count = state.get_mut(&key).map_or(0, |entry| {
+                entry.0 -= 1;
+                entry.0
+            });
+            if count <= 0 {
+                state.remove(&key);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accuracy of a t-digest: the number of centroids is about this.
+const COMPRESSION: f64 = 100.0;
+/// Number of values added before they are merged into centroids.
+const BUFFER_SIZE: usize = 500;
+
+#[derive(Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// A merging t-digest, with the k1 scale function: centroids are small near
+/// the extreme quantiles, where estimates need to be precise.
+#[derive(Default)]
+struct TDigest {
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+    /// The quantile, from 0 to 1
+    q: Option<f64>,
+}
+
+fn scale(q: f64) -> f64 {
+    COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin()
+}
+
+fn inverse_scale(k: f64) -> f64 {
+    ((k * 2.0 * PI / COMPRESSION).sin() + 1.0) / 2.0
+}
+
+impl TDigest {
+    fn add(&mut self, x: f64) {
+        self.buffer.push(x);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.compress();
+        }
+    }
+
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = self.centroids.split_off(0);
+        all.extend(
+            self.buffer
+                .drain(..)
+                .map(|mean| Centroid { mean, weight: 1.0 }),
+        );
+        all.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap_or(Ordering::Equal));
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+
+        let mut merged = Vec::new();
+        let mut current = all[0];
+        // Weight of the centroids before `current`
+        let mut before = 0.0;
+        let mut limit = inverse_scale(scale(0.0) + 1.0) * total;
+        for c in all.into_iter().skip(1) {
+            if before + current.weight + c.weight <= limit {
+                current.weight += c.weight;
+                current.mean += (c.mean - current.mean) * c.weight / current.weight;
+            } else {
+                before += current.weight;
+                merged.push(current);
+                limit = inverse_scale(scale(before / total) + 1.0) * total;
+                current = c;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// Remove one value, from the centroid nearest to it.
+    fn remove(&mut self, x: f64) {
+        if let Some(i) = self.buffer.iter().position(|&v| v == x) {
+            self.buffer.swap_remove(i);
+            return;
+        }
+        self.compress();
+        let nearest = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i, (c.mean - x).abs()))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+            .map(|(i, _)| i);
+        if let Some(i) = nearest {
+            let c = &mut self.centroids[i];
+            if c.weight <= 1.0 {
+                self.centroids.remove(i);
+            } else {
+                c.mean = (c.mean * c.weight - x) / (c.weight - 1.0);
+                c.weight -= 1.0;
+            }
+        }
+    }
+
+    /// Estimate the quantile `q`, interpolating between the centres of the
+    /// centroids, once buffered values are compressed.
+    fn quantile(&mut self, q: f64) -> Option<f64> {
+        self.compress();
+        let first = self.centroids.first()?;
+        let total: f64 = self.centroids.iter().map(|c| c.weight).sum();
+        let target = q * total;
+        let mut center = first.weight / 2.0;
+        if target <= center {
+            return Some(first.mean);
+        }
+        for pair in self.centroids.windows(2) {
+            let next = center + (pair[0].weight + pair[1].weight) / 2.0;
+            if target <= next {
+                let t = (target - center) / (next - center);
+                return Some(pair[0].mean + (pair[1].mean - pair[0].mean) * t);
+            }
+            center = next;
+        }
+        self.centroids.last().map(|c| c.mean)
+    }
+}
+
+struct ApproxQuantile {
+    median: bool,
+}
+
+/// A t-digest that `value` compresses in place, rather than a copy on each
+/// row of a window.
+type Digest = AssertUnwindSafe<RefCell<TDigest>>;
+
+impl ApproxQuantile {
+    fn update(&self, ctx: &Context<'_>, state: &mut TDigest) -> Result<Option<f64>> {
+        state.q = Some(if self.median {
+            0.5
+        } else {
+            fraction(ctx, 1, 1.0, state.q)?
+        });
+        Ok(number(ctx, 0))
+    }
+}
+
+impl Aggregate<Digest, Option<f64>> for ApproxQuantile {
+    fn init(&self) -> Digest {
+        AssertUnwindSafe(RefCell::new(TDigest::default()))
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, state: &mut Digest) -> Result<()> {
+        let state = state.get_mut();
+        if let Some(x) = self.update(ctx, state)? {
+            state.add(x);
+        }
+        Ok(())
+    }
+
+    fn finalize(&self, state: Option<Digest>) -> Result<Option<f64>> {
+        self.value(state.as_ref())
+    }
+}
+
+impl WindowAggregate<Digest, Option<f64>> for ApproxQuantile {
+    fn value(&self, state: Option<&Digest>) -> Result<Option<f64>> {
+        Ok(state.and_then(|s| {
+            let mut digest = s.borrow_mut();
+            let q = digest.q?;
+            digest.quantile(q)
+        }))
+    }
+
+    fn inverse(&self, ctx: &mut Context<'_>, state: &mut Digest) -> Result<()> {
+        let state = state.get_mut();
+        if let Some(x) = self.update(ctx, state)? {
+            state.remove(x);
+        }
+        Ok(())
+    }
+}
@@ -7,7 +7,13 @@ use std::ptr;
 
 use crate::ffi;
 
+#[cfg(feature = "preupdate_hook")]
+use crate::error::error_from_sqlite_code;
+#[cfg(feature = "preupdate_hook")]
+use crate::types::ValueRef;
 use crate::{Connection, InnerConnection};
+#[cfg(feature = "preupdate_hook")]
+use crate::{Error, Result};
 use wasm_bindgen::__rt::std::panic::AssertUnwindSafe;
 
 /// Action Codes
@@ -31,6 +37,89 @@ impl From<i32> for Action {
     }
 }
 
+/// The row being changed, as seen by a preupdate hook.
+///
+/// Only valid for the duration of the callback. `old` is available for
+/// `SQLITE_UPDATE` and `SQLITE_DELETE`, `new` for `SQLITE_UPDATE` and
+/// `SQLITE_INSERT`. See [sqlite3_preupdate_old](https://sqlite.org/c3ref/preupdate_count.html).
+#[cfg(feature = "preupdate_hook")]
+pub struct PreUpdateCase {
+    db: *mut ffi::sqlite3,
+    action: Action,
+    old_rowid: i64,
+    new_rowid: i64,
+}
+
+#[cfg(feature = "preupdate_hook")]
+impl PreUpdateCase {
+    /// The type of change (SQLITE_INSERT, SQLITE_UPDATE or SQLITE_DELETE).
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    /// Number of columns in the row being inserted, updated or deleted.
+    pub fn column_count(&self) -> usize {
+        unsafe { ffi::sqlite3_preupdate_count(self.db) as usize }
+    }
+
+    /// 0 for a change made by a top-level statement, 1 for a change made by
+    /// a trigger fired by a top-level statement, and so on.
+    pub fn depth(&self) -> i32 {
+        unsafe { ffi::sqlite3_preupdate_depth(self.db) }
+    }
+
+    /// The rowid of the row before the change (undefined for an insert).
+    pub fn old_rowid(&self) -> i64 {
+        self.old_rowid
+    }
+
+    /// The rowid of the row after the change (undefined for a delete).
+    pub fn new_rowid(&self) -> i64 {
+        self.new_rowid
+    }
+
+    /// Value of column `i` before the change.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `i` is out of range or if the change is an insert.
+    pub fn old(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.value(i, ffi::sqlite3_preupdate_old)
+    }
+
+    /// Value of column `i` after the change.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `i` is out of range or if the change is a delete.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(&self, i: usize) -> Result<ValueRef<'_>> {
+        self.value(i, ffi::sqlite3_preupdate_new)
+    }
+
+    fn value(
+        &self,
+        i: usize,
+        getter: unsafe extern "C" fn(
+            *mut ffi::sqlite3,
+            c_int,
+            *mut *mut ffi::sqlite3_value,
+        ) -> c_int,
+    ) -> Result<ValueRef<'_>> {
+        if i >= self.column_count() {
+            return Err(Error::InvalidColumnIndex(i));
+        }
+        unsafe {
+            let mut value: *mut ffi::sqlite3_value = ptr::null_mut();
+            let rc = getter(self.db, i as c_int, &mut value);
+            if rc != ffi::SQLITE_OK {
+                return Err(error_from_sqlite_code(rc, None));
+            }
+            Ok(ValueRef::from_value(value))
+        }
+    }
+}
+
 impl Connection {
     /// Register a callback function to be invoked whenever a transaction is
     /// committed.
@@ -70,6 +159,39 @@ impl Connection {
     {
         self.db.borrow_mut().update_hook(hook);
     }
+
+    /// Register a callback function to be invoked before a row is updated,
+    /// inserted or deleted in a rowid table.
+    ///
+    /// The callback parameters are:
+    ///
+    /// - the type of database update (SQLITE_INSERT, SQLITE_UPDATE or
+    /// SQLITE_DELETE),
+    /// - the name of the database ("main", "temp", ...),
+    /// - the name of the table that is updated,
+    /// - the old and new values of the row being changed.
+    #[cfg(feature = "preupdate_hook")]
+    pub fn preupdate_hook<F>(&self, hook: Option<F>)
+    where
+        F: FnMut(Action, &str, &str, &PreUpdateCase) + Send + 'static,
+    {
+        self.db.borrow_mut().preupdate_hook(hook);
+    }
+
+    /// Register a query progress callback, invoked periodically during long
+    /// running calls to `step` and `prepare`.
+    ///
+    /// `num_ops` is the approximate number of virtual machine instructions
+    /// evaluated between successive invocations of the callback. If
+    /// `num_ops` is less than one then the progress handler is disabled.
+    ///
+    /// If the callback returns `true`, the operation is interrupted.
+    pub fn progress_handler<F>(&self, num_ops: c_int, handler: Option<F>)
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        self.db.borrow_mut().progress_handler(num_ops, handler);
+    }
 }
 
 impl InnerConnection {
@@ -77,6 +199,50 @@ impl InnerConnection {
         self.update_hook(None::<fn(Action, &str, &str, i64)>);
         self.commit_hook(None::<fn() -> bool>);
         self.rollback_hook(None::<fn()>);
+        #[cfg(feature = "preupdate_hook")]
+        self.preupdate_hook(None::<fn(Action, &str, &str, &PreUpdateCase)>);
+        self.progress_handler(0, None::<fn() -> bool>);
+    }
+
+    fn progress_handler<F>(&mut self, num_ops: c_int, handler: Option<F>)
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(p_arg: *mut c_void) -> c_int
+        where
+            F: FnMut() -> bool,
+        {
+            let r = catch_unwind(AssertUnwindSafe(|| {
+                let boxed_handler: *mut F = p_arg as *mut F;
+                (*boxed_handler)()
+            }));
+            if let Ok(true) = r {
+                1
+            } else {
+                0
+            }
+        }
+
+        // `sqlite3_progress_handler` does not return the previous argument, so
+        // the handler is kept alive by `InnerConnection.progress_handler`.
+        match handler {
+            Some(handler) => {
+                let boxed_handler = Box::new(handler);
+                unsafe {
+                    ffi::sqlite3_progress_handler(
+                        self.db(),
+                        num_ops,
+                        Some(call_boxed_closure::<F>),
+                        &*boxed_handler as *const F as *mut _,
+                    )
+                }
+                self.progress_handler = Some(boxed_handler);
+            }
+            _ => {
+                unsafe { ffi::sqlite3_progress_handler(self.db(), num_ops, None, ptr::null_mut()) }
+                self.progress_handler = None;
+            }
+        }
     }
 
     fn commit_hook<F>(&mut self, hook: Option<F>)
@@ -227,6 +393,74 @@ impl InnerConnection {
         }
         self.free_update_hook = free_update_hook;
     }
+
+    #[cfg(feature = "preupdate_hook")]
+    fn preupdate_hook<F>(&mut self, hook: Option<F>)
+    where
+        F: FnMut(Action, &str, &str, &PreUpdateCase) + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            p_arg: *mut c_void,
+            db: *mut ffi::sqlite3,
+            action_code: c_int,
+            db_str: *const c_char,
+            tbl_str: *const c_char,
+            old_row_id: i64,
+            new_row_id: i64,
+        ) where
+            F: FnMut(Action, &str, &str, &PreUpdateCase),
+        {
+            use std::ffi::CStr;
+            use std::str;
+
+            let action = Action::from(action_code);
+            let db_name = {
+                let c_slice = CStr::from_ptr(db_str).to_bytes();
+                str::from_utf8_unchecked(c_slice)
+            };
+            let tbl_name = {
+                let c_slice = CStr::from_ptr(tbl_str).to_bytes();
+                str::from_utf8_unchecked(c_slice)
+            };
+            let case = PreUpdateCase {
+                db,
+                action,
+                old_rowid: old_row_id,
+                new_rowid: new_row_id,
+            };
+
+            let _ = catch_unwind(AssertUnwindSafe(|| {
+                let boxed_hook: *mut F = p_arg as *mut F;
+                (*boxed_hook)(action, db_name, tbl_name, &case);
+            }));
+        }
+
+        let free_preupdate_hook = if hook.is_some() {
+            Some(free_boxed_hook::<F> as fn(*mut c_void))
+        } else {
+            None
+        };
+
+        let previous_hook = match hook {
+            Some(hook) => {
+                let boxed_hook: *mut F = Box::into_raw(Box::new(hook));
+                unsafe {
+                    ffi::sqlite3_preupdate_hook(
+                        self.db(),
+                        Some(call_boxed_closure::<F>),
+                        boxed_hook as *mut _,
+                    )
+                }
+            }
+            _ => unsafe { ffi::sqlite3_preupdate_hook(self.db(), None, ptr::null_mut()) },
+        };
+        if !previous_hook.is_null() {
+            if let Some(free_boxed_hook) = self.free_preupdate_hook {
+                free_boxed_hook(previous_hook);
+            }
+        }
+        self.free_preupdate_hook = free_preupdate_hook;
+    }
 }
 
 fn free_boxed_hook<F>(p: *mut c_void) {
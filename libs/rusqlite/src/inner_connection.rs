@@ -31,6 +31,12 @@ pub struct InnerConnection {
     pub free_rollback_hook: Option<fn(*mut ::std::os::raw::c_void)>,
     #[cfg(feature = "hooks")]
     pub free_update_hook: Option<fn(*mut ::std::os::raw::c_void)>,
+    #[cfg(feature = "preupdate_hook")]
+    pub free_preupdate_hook: Option<fn(*mut ::std::os::raw::c_void)>,
+    #[cfg(feature = "hooks")]
+    pub progress_handler: Option<Box<dyn FnMut() -> bool + Send>>,
+    #[cfg(feature = "unlock_notify")]
+    pub unlock_notify: Box<unlock_notify::UnlockNotify>,
     owned: bool,
 }
 
@@ -41,6 +47,8 @@ impl InnerConnection {
         InnerConnection {
             db,
             interrupt_lock: Arc::new(Mutex::new(db)),
+            #[cfg(feature = "unlock_notify")]
+            unlock_notify: Box::default(),
             owned,
         }
     }
@@ -54,6 +62,11 @@ impl InnerConnection {
             free_commit_hook: None,
             free_rollback_hook: None,
             free_update_hook: None,
+            #[cfg(feature = "preupdate_hook")]
+            free_preupdate_hook: None,
+            progress_handler: None,
+            #[cfg(feature = "unlock_notify")]
+            unlock_notify: Box::default(),
             owned,
         }
     }
@@ -150,6 +163,10 @@ impl InnerConnection {
             return Ok(());
         }
         self.remove_hooks();
+        #[cfg(feature = "unlock_notify")]
+        unlock_notify::forget(self.db);
+        #[cfg(feature = "rtree")]
+        crate::rtree::take_error(self.db);
         let mut shared_handle = self.interrupt_lock.lock().unwrap();
         assert!(
             !shared_handle.is_null(),
@@ -239,9 +256,26 @@ impl InnerConnection {
         let (c_sql, len, _) = str_for_sqlite(sql.as_bytes())?;
         let mut c_tail = MaybeUninit::uninit();
         let r = unsafe {
-
-
-
+            if cfg!(feature = "unlock_notify") {
+                let mut rc;
+                loop {
+                    rc = ffi::sqlite3_prepare_v2(
+                        self.db(),
+                        c_sql,
+                        len,
+                        c_stmt.as_mut_ptr(),
+                        c_tail.as_mut_ptr(),
+                    );
+                    if !unlock_notify::is_locked(self.db, rc) {
+                        break;
+                    }
+                    rc = unlock_notify::wait_for_unlock_notify(self.db);
+                    if rc != ffi::SQLITE_OK {
+                        break;
+                    }
+                }
+                rc
+            } else {
                 ffi::sqlite3_prepare_v2(
                     self.db(),
                     c_sql,
@@ -249,7 +283,7 @@ impl InnerConnection {
                     c_stmt.as_mut_ptr(),
                     c_tail.as_mut_ptr(),
                 )
-
+            }
         };
         println!("R {:?}",r);
         // If there is an error, *ppStmt is set to NULL.
@@ -81,9 +81,13 @@ pub use crate::error::Error;
 pub use crate::ffi::ErrorCode;
 #[cfg(feature = "hooks")]
 pub use crate::hooks::Action;
+#[cfg(feature = "preupdate_hook")]
+pub use crate::hooks::PreUpdateCase;
 #[cfg(feature = "load_extension")]
 pub use crate::load_extension_guard::LoadExtensionGuard;
 pub use crate::row::{AndThenRows, MappedRows, Row, RowIndex, Rows};
+#[cfg(feature = "serde")]
+pub use crate::serde::{params_from_serialize, NamedParams};
 pub use crate::statement::{Statement, StatementStatus};
 pub use crate::transaction::{DropBehavior, Savepoint, Transaction, TransactionBehavior};
 pub use crate::types::ToSql;
@@ -97,11 +101,17 @@ pub mod blob;
 mod busy;
 mod cache;
 #[cfg(feature = "collation")]
-mod collation;
+pub mod collation;
 mod column;
+#[cfg(feature = "columnar")]
+pub mod columnar;
 pub mod config;
 #[cfg(any(feature = "functions", feature = "vtab"))]
 mod context;
+#[cfg(feature = "csv_export")]
+pub mod csv_export;
+#[cfg(feature = "fts5")]
+pub mod fts5;
 #[cfg(feature = "functions")]
 pub mod functions;
 #[cfg(feature = "hooks")]
@@ -111,12 +121,27 @@ mod inner_connection;
 pub mod limits;
 #[cfg(feature = "load_extension")]
 mod load_extension_guard;
+#[cfg(feature = "migrations")]
+pub mod migrations;
+#[cfg(feature = "serde_json")]
+mod ndjson;
 // public for tests only
 pub mod pragma;
 mod raw_statement;
 mod row;
-
+#[cfg(feature = "rtree")]
+pub mod rtree;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(feature = "serialize")]
+mod serialize;
+#[cfg(feature = "session")]
+pub mod session;
 mod statement;
+#[cfg(feature = "hooks")]
+pub mod subscription;
 #[cfg(feature = "trace")]
 pub mod trace;
 mod transaction;
@@ -287,8 +312,8 @@ pub enum DatabaseName<'a> {
     Attached(&'a str),
 }
 
-// Currently DatabaseName is only used by the backup and blob mods, so hide
-// this (private) impl to avoid dead code warnings.
+// Currently DatabaseName is only used by the backup, blob and serialize mods,
+// so hide this (private) impl to avoid dead code warnings.
 
 impl DatabaseName<'_> {
     fn to_cstring(&self) -> Result<CString> {
@@ -432,6 +457,20 @@ impl Connection {
         Connection::open_with_flags_and_vfs(":memory:", flags, vfs)
     }
 
+    /// Open a new connection to the named in-memory SQLite database in
+    /// [shared-cache](https://sqlite.org/sharedcache.html) mode. Every
+    /// connection opened with the same `name` sees the same database, which
+    /// lives until the last of them is closed.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `name` cannot be converted to a C-compatible
+    /// string or if the underlying SQLite open call fails.
+    pub fn open_shared_in_memory(name: &str) -> Result<Connection> {
+        let flags = OpenFlags::default() | OpenFlags::SQLITE_OPEN_SHARED_CACHE;
+        Connection::open_with_flags(format!("file:{}?mode=memory&cache=shared", name), flags)
+    }
+
     /// Convenience method to run multiple SQL statements (that cannot take any
     /// parameters).
     ///
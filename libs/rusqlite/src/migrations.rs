@@ -0,0 +1,381 @@
+//! Versioned schema migrations.
+//!
+//! A database is at version `n` when the first `n` migrations of a
+//! `Migrations` list are applied. Each migration runs in its own
+//! transaction, which also records the new version, so that a failed
+//! migration leaves the database at the previous version. The transaction
+//! reads the version again first, so that connections migrating the same
+//! database at once apply each migration a single time.
+//!
+//! The version is stored in `PRAGMA user_version`, or, with
+//! `Migrations::with_table`, in a bookkeeping table which also keeps the
+//! name, a checksum and the time of each applied migration. The SQL of an
+//! applied migration must then not change: its checksum is validated before
+//! migrating. `PRAGMA user_version` only holds the version, so without a
+//! bookkeeping table, changes to applied migrations go unnoticed.
+//!
+//! A database newer than the latest migration, written by a newer release
+//! of the application, is refused.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! # use rusqlite::migrations::{Migration, Migrations};
+//! fn open() -> Result<Connection> {
+//!     let conn = Connection::open("app.db")?;
+//!     let migrations = Migrations::new(vec![
+//!         Migration::sql("create notes", "CREATE TABLE notes(id INTEGER PRIMARY KEY, body TEXT)")
+//!             .down_sql("DROP TABLE notes"),
+//!         Migration::sql("add title", "ALTER TABLE notes ADD COLUMN title TEXT"),
+//!         Migration::rust("title notes", |tx| {
+//!             tx.execute("UPDATE notes SET title = substr(body, 1, 20)", NO_PARAMS)?;
+//!             Ok(())
+//!         }),
+//!     ]);
+//!     migrations.to_latest(&conn)?;
+//!     Ok(conn)
+//! }
+//! ```
+use std::error;
+use std::fmt;
+
+use crate::pragma::Sql;
+use crate::{Connection, Error, Result, ToSql, Transaction, TransactionBehavior, NO_PARAMS};
+
+/// Why migrating failed, besides SQLite errors.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MigrationError {
+    /// The database is at `version`, newer than the `latest` migration.
+    DatabaseTooNew { version: usize, latest: usize },
+    /// The SQL of the applied migration `version` changed.
+    ChecksumMismatch { version: usize, name: String },
+    /// The migration `version` has no down migration.
+    Irreversible { version: usize, name: String },
+    /// Migrating to `version`, after the `latest` migration, was requested.
+    InvalidVersion { version: usize, latest: usize },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MigrationError::DatabaseTooNew { version, latest } => write!(
+                f,
+                "database version {} is newer than the latest migration {}",
+                version, latest
+            ),
+            MigrationError::ChecksumMismatch { version, ref name } => write!(
+                f,
+                "migration {} ({}) changed since it was applied",
+                version, name
+            ),
+            MigrationError::Irreversible { version, ref name } => {
+                write!(f, "migration {} ({}) cannot be reverted", version, name)
+            }
+            MigrationError::InvalidVersion { version, latest } => write!(
+                f,
+                "no migration {}: the latest migration is {}",
+                version, latest
+            ),
+        }
+    }
+}
+
+impl error::Error for MigrationError {}
+
+impl From<MigrationError> for Error {
+    fn from(err: MigrationError) -> Error {
+        Error::MigrationError(err)
+    }
+}
+
+enum Step {
+    Sql(String),
+    Rust(Box<dyn Fn(&Transaction<'_>) -> Result<()>>),
+}
+
+impl Step {
+    fn run(&self, tx: &Transaction<'_>) -> Result<()> {
+        match *self {
+            Step::Sql(ref sql) => tx.execute_batch(sql),
+            Step::Rust(ref f) => f(tx),
+        }
+    }
+
+    /// The checksum of SQL steps, as stored in the bookkeeping table.
+    fn checksum(&self) -> Option<i64> {
+        match *self {
+            Step::Sql(ref sql) => Some(fnv1a(sql.as_bytes()) as i64),
+            Step::Rust(_) => None,
+        }
+    }
+}
+
+// 64-bit FNV-1a, stable across releases, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A migration: SQL or a Rust closure, and optionally its reverse.
+pub struct Migration {
+    name: String,
+    up: Step,
+    down: Option<Step>,
+}
+
+impl Migration {
+    /// A migration running the SQL statements `up`.
+    pub fn sql(name: &str, up: &str) -> Migration {
+        Migration {
+            name: name.to_owned(),
+            up: Step::Sql(up.to_owned()),
+            down: None,
+        }
+    }
+
+    /// A migration running `up`.
+    pub fn rust<F>(name: &str, up: F) -> Migration
+    where
+        F: Fn(&Transaction<'_>) -> Result<()> + 'static,
+    {
+        Migration {
+            name: name.to_owned(),
+            up: Step::Rust(Box::new(up)),
+            down: None,
+        }
+    }
+
+    /// Revert the migration by running the SQL statements `down`.
+    pub fn down_sql(mut self, down: &str) -> Migration {
+        self.down = Some(Step::Sql(down.to_owned()));
+        self
+    }
+
+    /// Revert the migration by running `down`.
+    pub fn down_rust<F>(mut self, down: F) -> Migration
+    where
+        F: Fn(&Transaction<'_>) -> Result<()> + 'static,
+    {
+        self.down = Some(Step::Rust(Box::new(down)));
+        self
+    }
+
+    /// The name of the migration.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// An ordered list of migrations.
+pub struct Migrations {
+    migrations: Vec<Migration>,
+    table: Option<String>,
+}
+
+impl Migrations {
+    /// The migrations, from version 1, recorded in `PRAGMA user_version`.
+    pub fn new(migrations: Vec<Migration>) -> Migrations {
+        Migrations {
+            migrations,
+            table: None,
+        }
+    }
+
+    /// Record applied migrations in the table `table`, created if needed,
+    /// rather than in `PRAGMA user_version`.
+    pub fn with_table(mut self, table: &str) -> Migrations {
+        self.table = Some(table.to_owned());
+        self
+    }
+
+    /// The version of the latest migration.
+    pub fn latest(&self) -> usize {
+        self.migrations.len()
+    }
+
+    /// The version of the database, without validating it.
+    pub fn current_version(&self, conn: &Connection) -> Result<usize> {
+        let version: i64 = match self.table {
+            None => conn.pragma_query_value(None, "user_version", |row| row.get(0))?,
+            Some(ref table) => {
+                if !table_exists(conn, table)? {
+                    return Ok(0);
+                }
+                let sql = format!("SELECT ifnull(max(version), 0) FROM {}", quote(table));
+                conn.query_row(&sql, NO_PARAMS, |row| row.get(0))?
+            }
+        };
+        Ok(version as usize)
+    }
+
+    /// The version of the database, after checking that it is not newer
+    /// than the latest migration and, with a bookkeeping table, that no
+    /// applied SQL migration changed.
+    pub fn validate(&self, conn: &Connection) -> Result<usize> {
+        let version = self.current_version(conn)?;
+        if version > self.latest() {
+            return Err(MigrationError::DatabaseTooNew {
+                version,
+                latest: self.latest(),
+            }
+            .into());
+        }
+        if let Some(ref table) = self.table {
+            if !table_exists(conn, table)? {
+                return Ok(version);
+            }
+            let sql = format!("SELECT version, checksum FROM {}", quote(table));
+            let mut stmt = conn.prepare(&sql)?;
+            let mut rows = stmt.query(NO_PARAMS)?;
+            while let Some(row) = rows.next()? {
+                let applied: i64 = row.get(0)?;
+                let checksum: Option<i64> = row.get(1)?;
+                let migration = match (applied as usize).checked_sub(1) {
+                    Some(i) if i < self.migrations.len() => &self.migrations[i],
+                    _ => continue,
+                };
+                if checksum.is_some() && checksum != migration.up.checksum() {
+                    return Err(MigrationError::ChecksumMismatch {
+                        version: applied as usize,
+                        name: migration.name.clone(),
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(version)
+    }
+
+    /// Apply all the migrations not applied yet.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the database fails to validate, or a migration
+    /// fails, leaving the database at the version before it.
+    pub fn to_latest(&self, conn: &Connection) -> Result<()> {
+        self.to_version(conn, self.latest())
+    }
+
+    /// Apply or revert migrations until the database is at `version`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the database fails to validate, a migration
+    /// between the current version and `version` cannot be reverted (before
+    /// any is), or a migration fails, leaving the database at the version
+    /// before it.
+    pub fn to_version(&self, conn: &Connection, version: usize) -> Result<()> {
+        if version > self.latest() {
+            return Err(MigrationError::InvalidVersion {
+                version,
+                latest: self.latest(),
+            }
+            .into());
+        }
+        let mut current = self.validate(conn)?;
+        if version < current {
+            self.check_reversible(version, current)?;
+        }
+        while current != version {
+            current = self.step(conn, version)?;
+        }
+        Ok(())
+    }
+
+    fn check_reversible(&self, version: usize, current: usize) -> Result<()> {
+        for (i, migration) in self.migrations[version..current].iter().enumerate() {
+            if migration.down.is_none() {
+                return Err(MigrationError::Irreversible {
+                    version: version + i + 1,
+                    name: migration.name.clone(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    // Apply or revert one migration towards `version`, from the version read
+    // in the transaction, as another connection may have migrated since.
+    // Returns the version of the database after it.
+    fn step(&self, conn: &Connection, version: usize) -> Result<usize> {
+        let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+        let current = self.validate(&tx)?;
+        let next = if current < version {
+            self.apply(&tx, current + 1)?;
+            current + 1
+        } else if current > version {
+            self.check_reversible(current - 1, current)?;
+            self.revert(&tx, current)?;
+            current - 1
+        } else {
+            return Ok(current);
+        };
+        tx.commit()?;
+        Ok(next)
+    }
+
+    fn apply(&self, tx: &Transaction<'_>, version: usize) -> Result<()> {
+        let migration = &self.migrations[version - 1];
+        migration.up.run(tx)?;
+        match self.table {
+            None => tx.pragma_update(None, "user_version", &(version as i64))?,
+            Some(ref table) => {
+                tx.execute_batch(&format!(
+                    "CREATE TABLE IF NOT EXISTS {}(
+                         version INTEGER PRIMARY KEY,
+                         name TEXT NOT NULL,
+                         checksum INTEGER,
+                         applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                     )",
+                    quote(table)
+                ))?;
+                tx.execute(
+                    &format!(
+                        "INSERT INTO {}(version, name, checksum) VALUES (?, ?, ?)",
+                        quote(table)
+                    ),
+                    &[
+                        &(version as i64) as &dyn ToSql,
+                        &migration.name,
+                        &migration.up.checksum(),
+                    ],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn revert(&self, tx: &Transaction<'_>, version: usize) -> Result<()> {
+        let migration = &self.migrations[version - 1];
+        if let Some(ref down) = migration.down {
+            down.run(tx)?;
+        }
+        match self.table {
+            None => tx.pragma_update(None, "user_version", &(version as i64 - 1))?,
+            Some(ref table) => {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE version = ?", quote(table)),
+                    &[version as i64],
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn quote(table: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(table);
+    sql.as_str().to_owned()
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?",
+        &[table],
+        |row| row.get(0),
+    )
+}
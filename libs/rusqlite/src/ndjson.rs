@@ -0,0 +1,82 @@
+//! Write query results as [JSON Lines](https://jsonlines.org).
+
+use std::io;
+
+use serde_json::Value;
+
+use crate::types::{ToSql, ValueRef};
+use crate::{Error, Result, Statement};
+
+fn json_error(err: io::Error) -> Error {
+    Error::JsonError(serde_json::Error::io(err))
+}
+
+impl Statement<'_> {
+    /// Execute the prepared statement and write its rows to `writer` as
+    /// JSON Lines: one object per row, keyed by column name in column order.
+    /// Returns the number of rows written.
+    ///
+    /// Text in columns declared `JSON` is parsed, other text written as
+    /// strings, and blobs as arrays of bytes.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// fn export(conn: &Connection) -> Result<Vec<u8>> {
+    ///     let mut stmt = conn.prepare("SELECT id, name FROM people")?;
+    ///     let mut ndjson = Vec::new();
+    ///     stmt.write_ndjson(NO_PARAMS, &mut ndjson)?;
+    ///     Ok(ndjson)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters or stepping the statement
+    /// fails, if a `JSON` column holds invalid JSON, or `Error::JsonError`
+    /// if writing fails.
+    pub fn write_ndjson<P, W>(&mut self, params: P, mut writer: W) -> Result<usize>
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+        W: io::Write,
+    {
+        let columns: Vec<(String, bool)> = self
+            .columns()
+            .iter()
+            .map(|c| {
+                let is_json = c
+                    .decl_type()
+                    .map_or(false, |t| t.eq_ignore_ascii_case("JSON"));
+                (c.name().to_owned(), is_json)
+            })
+            .collect();
+        let mut rows = self.query(params)?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            // Written member by member to keep the column order.
+            writer.write_all(b"{").map_err(json_error)?;
+            for (i, &(ref name, is_json)) in columns.iter().enumerate() {
+                if i > 0 {
+                    writer.write_all(b",").map_err(json_error)?;
+                }
+                let value = match row.get_raw(i) {
+                    ValueRef::Null => Value::Null,
+                    ValueRef::Integer(n) => Value::from(n),
+                    ValueRef::Real(f) => Value::from(f),
+                    ValueRef::Text(_) if is_json => row.get::<_, Value>(i)?,
+                    ValueRef::Text(_) => Value::String(row.get(i)?),
+                    ValueRef::Blob(b) => Value::from(b.to_vec()),
+                };
+                serde_json::to_writer(&mut writer, name).map_err(Error::JsonError)?;
+                writer.write_all(b":").map_err(json_error)?;
+                serde_json::to_writer(&mut writer, &value).map_err(Error::JsonError)?;
+            }
+            writer.write_all(b"}\n").map_err(json_error)?;
+            count += 1;
+        }
+        writer.flush().map_err(json_error)?;
+        Ok(count)
+    }
+}
@@ -1,12 +1,17 @@
-//! Pragma helpers
+//! Pragma helpers, and typed pragmas: `Connection::pragma_set` with a
+//! `Pragma` and its value, and `Connection::pragma_get` with a pragma of the
+//! `get` module. Pragmas which only return rows, such as
+//! `get::TableInfo(table)`, are read with `get` and have no `Pragma`.
 
 use std::ops::Deref;
 
 use crate::error::Error;
 use crate::ffi;
-use crate::types::{ToSql, ToSqlOutput, ValueRef};
+use crate::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Value, ValueRef};
 use crate::{Connection, DatabaseName, Result, Row, NO_PARAMS};
 
+pub mod get;
+
 pub struct Sql {
     buf: String,
 }
@@ -283,6 +288,254 @@ impl Connection {
     }
 }
 
+/// The journal mode of a database: `PRAGMA journal_mode`. An in-memory
+/// database only supports `Memory` and `Off`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+impl JournalMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+impl FromSql for JournalMode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()?.to_ascii_uppercase().as_str() {
+            "DELETE" => Ok(JournalMode::Delete),
+            "TRUNCATE" => Ok(JournalMode::Truncate),
+            "PERSIST" => Ok(JournalMode::Persist),
+            "MEMORY" => Ok(JournalMode::Memory),
+            "WAL" => Ok(JournalMode::Wal),
+            "OFF" => Ok(JournalMode::Off),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// The locking mode of a database: `PRAGMA locking_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockingMode {
+    Normal,
+    Exclusive,
+}
+
+impl FromSql for LockingMode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()?.to_ascii_uppercase().as_str() {
+            "NORMAL" => Ok(LockingMode::Normal),
+            "EXCLUSIVE" => Ok(LockingMode::Exclusive),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// When data is synced to storage: `PRAGMA synchronous`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Synchronous {
+    Off = 0,
+    Normal = 1,
+    Full = 2,
+    Extra = 3,
+}
+
+impl FromSql for Synchronous {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_i64()? {
+            0 => Ok(Synchronous::Off),
+            1 => Ok(Synchronous::Normal),
+            2 => Ok(Synchronous::Full),
+            3 => Ok(Synchronous::Extra),
+            i => Err(FromSqlError::OutOfRange(i)),
+        }
+    }
+}
+
+/// Where temporary tables and indices are stored: `PRAGMA temp_store`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TempStore {
+    Default = 0,
+    File = 1,
+    Memory = 2,
+}
+
+impl FromSql for TempStore {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_i64()? {
+            0 => Ok(TempStore::Default),
+            1 => Ok(TempStore::File),
+            2 => Ok(TempStore::Memory),
+            i => Err(FromSqlError::OutOfRange(i)),
+        }
+    }
+}
+
+/// Whether free pages are reclaimed: `PRAGMA auto_vacuum`. Only changes
+/// before the first table is created, or with a `VACUUM`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoVacuum {
+    None = 0,
+    Full = 1,
+    Incremental = 2,
+}
+
+impl FromSql for AutoVacuum {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_i64()? {
+            0 => Ok(AutoVacuum::None),
+            1 => Ok(AutoVacuum::Full),
+            2 => Ok(AutoVacuum::Incremental),
+            i => Err(FromSqlError::OutOfRange(i)),
+        }
+    }
+}
+
+/// The size of the page cache: `PRAGMA cache_size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheSize {
+    /// A number of pages
+    Pages(i64),
+    /// A number of KiB, whatever the page size
+    KiB(i64),
+}
+
+impl FromSql for CacheSize {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let size = value.as_i64()?;
+        Ok(if size < 0 {
+            CacheSize::KiB(-size)
+        } else {
+            CacheSize::Pages(size)
+        })
+    }
+}
+
+/// A pragma and its new value, for `Connection::pragma_set`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pragma {
+    ApplicationId(i32),
+    AutoVacuum(AutoVacuum),
+    AutomaticIndex(bool),
+    CacheSize(CacheSize),
+    CaseSensitiveLike(bool),
+    DeferForeignKeys(bool),
+    ForeignKeys(bool),
+    JournalMode(JournalMode),
+    LockingMode(LockingMode),
+    PageSize(u32),
+    QueryOnly(bool),
+    RecursiveTriggers(bool),
+    ReverseUnorderedSelects(bool),
+    SecureDelete(bool),
+    Synchronous(Synchronous),
+    TempStore(TempStore),
+    UserVersion(i32),
+}
+
+impl Pragma {
+    fn name_and_value(self) -> (&'static str, Value) {
+        let flag = |b: bool| Value::Integer(b as i64);
+        match self {
+            Pragma::ApplicationId(id) => ("application_id", Value::Integer(id.into())),
+            Pragma::AutoVacuum(mode) => ("auto_vacuum", Value::Integer(mode as i64)),
+            Pragma::AutomaticIndex(b) => ("automatic_index", flag(b)),
+            Pragma::CacheSize(CacheSize::Pages(pages)) => ("cache_size", Value::Integer(pages)),
+            Pragma::CacheSize(CacheSize::KiB(kib)) => ("cache_size", Value::Integer(-kib)),
+            Pragma::CaseSensitiveLike(b) => ("case_sensitive_like", flag(b)),
+            Pragma::DeferForeignKeys(b) => ("defer_foreign_keys", flag(b)),
+            Pragma::ForeignKeys(b) => ("foreign_keys", flag(b)),
+            Pragma::JournalMode(mode) => ("journal_mode", Value::Text(mode.as_str().to_owned())),
+            Pragma::LockingMode(LockingMode::Normal) => {
+                ("locking_mode", Value::Text("NORMAL".to_owned()))
+            }
+            Pragma::LockingMode(LockingMode::Exclusive) => {
+                ("locking_mode", Value::Text("EXCLUSIVE".to_owned()))
+            }
+            Pragma::PageSize(size) => ("page_size", Value::Integer(size.into())),
+            Pragma::QueryOnly(b) => ("query_only", flag(b)),
+            Pragma::RecursiveTriggers(b) => ("recursive_triggers", flag(b)),
+            Pragma::ReverseUnorderedSelects(b) => ("reverse_unordered_selects", flag(b)),
+            Pragma::SecureDelete(b) => ("secure_delete", flag(b)),
+            Pragma::Synchronous(mode) => ("synchronous", Value::Integer(mode as i64)),
+            Pragma::TempStore(mode) => ("temp_store", Value::Integer(mode as i64)),
+            Pragma::UserVersion(version) => ("user_version", Value::Integer(version.into())),
+        }
+    }
+}
+
+/// A pragma that can be read, as a `Value`: the types of the `get` module.
+pub trait ReadPragma {
+    /// The value of the pragma
+    type Value;
+
+    /// Query the pragma.
+    fn read(&self, conn: &Connection, schema_name: Option<DatabaseName<'_>>)
+        -> Result<Self::Value>;
+}
+
+impl Connection {
+    /// Set a pragma to a value of the right type.
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// # use rusqlite::pragma::{get, JournalMode, Pragma};
+    /// fn configure(conn: &Connection) -> Result<()> {
+    ///     conn.pragma_set(None, Pragma::JournalMode(JournalMode::Memory))?;
+    ///     conn.pragma_set(None, Pragma::ForeignKeys(true))?;
+    ///     assert!(conn.pragma_get(None, get::ForeignKeys)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the pragma cannot be set, or if SQLite kept
+    /// another journal mode than the requested one.
+    pub fn pragma_set(&self, schema_name: Option<DatabaseName<'_>>, pragma: Pragma) -> Result<()> {
+        let (name, value) = pragma.name_and_value();
+        if let Pragma::JournalMode(mode) = pragma {
+            // The journal mode is left unchanged when it is not supported.
+            let actual: JournalMode =
+                self.pragma_update_and_check(schema_name, name, &value, |row| row.get(0))?;
+            if actual != mode {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_ERROR),
+                    Some(format!(
+                        "cannot change journal_mode to {}: it is {}",
+                        mode.as_str(),
+                        actual.as_str()
+                    )),
+                ));
+            }
+            return Ok(());
+        }
+        self.pragma_update(schema_name, name, &value)
+    }
+
+    /// Read a pragma as a value of the right type. See the `get` module.
+    pub fn pragma_get<P: ReadPragma>(
+        &self,
+        schema_name: Option<DatabaseName<'_>>,
+        pragma: P,
+    ) -> Result<P::Value> {
+        pragma.read(self, schema_name)
+    }
+}
+
 pub fn is_identifier(s: &str) -> bool {
     let chars = s.char_indices();
     for (i, ch) in chars {
@@ -0,0 +1,483 @@
+//! Pragmas to read with `Connection::pragma_get`, each giving a value of
+//! its own type.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! # use rusqlite::pragma::{get, JournalMode};
+//! fn check(conn: &Connection) -> Result<()> {
+//!     assert_eq!(JournalMode::Memory, conn.pragma_get(None, get::JournalMode)?);
+//!     for column in conn.pragma_get(None, get::TableInfo("notes"))? {
+//!         println!("{} {:?}", column.name, column.decl_type);
+//!     }
+//!     assert!(conn.pragma_get(None, get::IntegrityCheck)?.is_empty());
+//!     Ok(())
+//! }
+//! ```
+use super::ReadPragma;
+use crate::{Connection, DatabaseName, Result, Row};
+
+macro_rules! value_pragma {
+    ($(#[$doc:meta])* $key:ident, $name:expr, $value:ty) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug)]
+        pub struct $key;
+
+        impl ReadPragma for $key {
+            type Value = $value;
+
+            fn read(
+                &self,
+                conn: &Connection,
+                schema_name: Option<DatabaseName<'_>>,
+            ) -> Result<$value> {
+                conn.pragma_query_value(schema_name, $name, |row| row.get(0))
+            }
+        }
+    };
+}
+
+value_pragma!(
+    /// `PRAGMA application_id`
+    ApplicationId, "application_id", i32
+);
+value_pragma!(
+    /// `PRAGMA auto_vacuum`
+    AutoVacuum, "auto_vacuum", super::AutoVacuum
+);
+value_pragma!(
+    /// `PRAGMA automatic_index`
+    AutomaticIndex, "automatic_index", bool
+);
+value_pragma!(
+    /// `PRAGMA cache_size`
+    CacheSize, "cache_size", super::CacheSize
+);
+value_pragma!(
+    /// `PRAGMA data_version`: changes when another connection commits
+    DataVersion, "data_version", i64
+);
+value_pragma!(
+    /// `PRAGMA defer_foreign_keys`
+    DeferForeignKeys, "defer_foreign_keys", bool
+);
+value_pragma!(
+    /// `PRAGMA encoding`
+    Encoding, "encoding", String
+);
+value_pragma!(
+    /// `PRAGMA foreign_keys`
+    ForeignKeys, "foreign_keys", bool
+);
+value_pragma!(
+    /// `PRAGMA freelist_count`
+    FreelistCount, "freelist_count", i64
+);
+value_pragma!(
+    /// `PRAGMA journal_mode`
+    JournalMode, "journal_mode", super::JournalMode
+);
+value_pragma!(
+    /// `PRAGMA locking_mode`
+    LockingMode, "locking_mode", super::LockingMode
+);
+value_pragma!(
+    /// `PRAGMA page_count`
+    PageCount, "page_count", i64
+);
+value_pragma!(
+    /// `PRAGMA page_size`
+    PageSize, "page_size", u32
+);
+value_pragma!(
+    /// `PRAGMA query_only`
+    QueryOnly, "query_only", bool
+);
+value_pragma!(
+    /// `PRAGMA recursive_triggers`
+    RecursiveTriggers, "recursive_triggers", bool
+);
+value_pragma!(
+    /// `PRAGMA reverse_unordered_selects`
+    ReverseUnorderedSelects, "reverse_unordered_selects", bool
+);
+value_pragma!(
+    /// `PRAGMA schema_version`
+    SchemaVersion, "schema_version", i64
+);
+value_pragma!(
+    /// `PRAGMA secure_delete`
+    SecureDelete, "secure_delete", bool
+);
+value_pragma!(
+    /// `PRAGMA synchronous`
+    Synchronous, "synchronous", super::Synchronous
+);
+value_pragma!(
+    /// `PRAGMA temp_store`
+    TempStore, "temp_store", super::TempStore
+);
+value_pragma!(
+    /// `PRAGMA user_version`
+    UserVersion, "user_version", i32
+);
+
+/// Query a pragma returning one row per item, read with `f`.
+fn list<T, F>(
+    conn: &Connection,
+    schema_name: Option<DatabaseName<'_>>,
+    name: &str,
+    argument: Option<&str>,
+    f: F,
+) -> Result<Vec<T>>
+where
+    F: Fn(&Row<'_>) -> Result<T>,
+{
+    let mut items = Vec::new();
+    let mut push = |row: &Row<'_>| {
+        items.push(f(row)?);
+        Ok(())
+    };
+    match argument {
+        Some(argument) => conn.pragma(schema_name, name, &argument, push)?,
+        None => conn.pragma_query(schema_name, name, push)?,
+    }
+    Ok(items)
+}
+
+/// `PRAGMA compile_options`: the options SQLite was compiled with, without
+/// their `SQLITE_` prefix
+#[derive(Clone, Copy, Debug)]
+pub struct CompileOptions;
+
+impl ReadPragma for CompileOptions {
+    type Value = Vec<String>;
+
+    fn read(&self, conn: &Connection, _: Option<DatabaseName<'_>>) -> Result<Vec<String>> {
+        list(conn, None, "compile_options", None, |row| row.get(0))
+    }
+}
+
+/// Read the problems reported by `integrity_check` or `quick_check`: none
+/// when the single row is `ok`.
+fn check(
+    conn: &Connection,
+    schema_name: Option<DatabaseName<'_>>,
+    name: &str,
+) -> Result<Vec<String>> {
+    let mut problems: Vec<String> = list(conn, schema_name, name, None, |row| row.get(0))?;
+    if problems.len() == 1 && problems[0] == "ok" {
+        problems.clear();
+    }
+    Ok(problems)
+}
+
+/// `PRAGMA integrity_check`: the problems found, none if the database is
+/// sound
+#[derive(Clone, Copy, Debug)]
+pub struct IntegrityCheck;
+
+impl ReadPragma for IntegrityCheck {
+    type Value = Vec<String>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<String>> {
+        check(conn, schema_name, "integrity_check")
+    }
+}
+
+/// `PRAGMA quick_check`: as `IntegrityCheck`, without checking indexes
+/// against tables
+#[derive(Clone, Copy, Debug)]
+pub struct QuickCheck;
+
+impl ReadPragma for QuickCheck {
+    type Value = Vec<String>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<String>> {
+        check(conn, schema_name, "quick_check")
+    }
+}
+
+/// A row of `PRAGMA table_info` or `PRAGMA table_xinfo`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableInfoRow {
+    pub cid: i32,
+    pub name: String,
+    /// The declared type, or `None`
+    pub decl_type: Option<String>,
+    pub not_null: bool,
+    /// The SQL text of the default value
+    pub default: Option<String>,
+    /// The position of the column in the primary key, from 1
+    pub primary_key: Option<u32>,
+    /// With `TableXInfo`, 1 for a hidden column of a virtual table, 2 for a
+    /// virtual generated column and 3 for a stored one; otherwise 0
+    pub hidden: i32,
+}
+
+fn table_info(
+    conn: &Connection,
+    schema_name: Option<DatabaseName<'_>>,
+    name: &str,
+    table: &str,
+) -> Result<Vec<TableInfoRow>> {
+    let xinfo = name == "table_xinfo";
+    list(conn, schema_name, name, Some(table), |row| {
+        let decl_type: String = row.get("type")?;
+        let pk: u32 = row.get("pk")?;
+        Ok(TableInfoRow {
+            cid: row.get("cid")?,
+            name: row.get("name")?,
+            decl_type: if decl_type.is_empty() {
+                None
+            } else {
+                Some(decl_type)
+            },
+            not_null: row.get("notnull")?,
+            default: row.get("dflt_value")?,
+            primary_key: if pk == 0 { None } else { Some(pk) },
+            hidden: if xinfo { row.get("hidden")? } else { 0 },
+        })
+    })
+}
+
+/// `PRAGMA table_info(table)`: the columns of a table or a view
+#[derive(Clone, Copy, Debug)]
+pub struct TableInfo<'a>(pub &'a str);
+
+impl ReadPragma for TableInfo<'_> {
+    type Value = Vec<TableInfoRow>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<TableInfoRow>> {
+        table_info(conn, schema_name, "table_info", self.0)
+    }
+}
+
+/// `PRAGMA table_xinfo(table)`: as `TableInfo`, with the hidden and
+/// generated columns
+#[derive(Clone, Copy, Debug)]
+pub struct TableXInfo<'a>(pub &'a str);
+
+impl ReadPragma for TableXInfo<'_> {
+    type Value = Vec<TableInfoRow>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<TableInfoRow>> {
+        table_info(conn, schema_name, "table_xinfo", self.0)
+    }
+}
+
+/// How an index was created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexOrigin {
+    /// By a `CREATE INDEX` statement
+    CreateIndex,
+    /// By a `UNIQUE` constraint
+    Unique,
+    /// By a `PRIMARY KEY` constraint
+    PrimaryKey,
+}
+
+/// A row of `PRAGMA index_list`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexListRow {
+    pub seq: i32,
+    pub name: String,
+    pub unique: bool,
+    pub origin: IndexOrigin,
+    /// Whether the index has a `WHERE` clause
+    pub partial: bool,
+}
+
+/// `PRAGMA index_list(table)`: the indexes of a table
+#[derive(Clone, Copy, Debug)]
+pub struct IndexList<'a>(pub &'a str);
+
+impl ReadPragma for IndexList<'_> {
+    type Value = Vec<IndexListRow>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<IndexListRow>> {
+        list(conn, schema_name, "index_list", Some(self.0), |row| {
+            let origin: String = row.get("origin")?;
+            Ok(IndexListRow {
+                seq: row.get("seq")?,
+                name: row.get("name")?,
+                unique: row.get("unique")?,
+                origin: match origin.as_str() {
+                    "u" => IndexOrigin::Unique,
+                    "pk" => IndexOrigin::PrimaryKey,
+                    _ => IndexOrigin::CreateIndex,
+                },
+                partial: row.get("partial")?,
+            })
+        })
+    }
+}
+
+/// A row of `PRAGMA index_info` or `PRAGMA index_xinfo`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexInfoRow {
+    /// The position of the column in the index
+    pub seqno: i32,
+    /// The position of the column in the table, -1 for the rowid and -2 for
+    /// an expression
+    pub cid: i32,
+    /// The name of the column, or `None` for the rowid or an expression
+    pub name: Option<String>,
+    /// With `IndexXInfo`, whether the column is sorted in descending order
+    pub descending: bool,
+    /// With `IndexXInfo`, the name of the collation of the column
+    pub collation: Option<String>,
+    /// `false` for the columns after the key that `IndexXInfo` also lists,
+    /// such as the rowid
+    pub key: bool,
+}
+
+fn index_info(
+    conn: &Connection,
+    schema_name: Option<DatabaseName<'_>>,
+    name: &str,
+    index: &str,
+) -> Result<Vec<IndexInfoRow>> {
+    let xinfo = name == "index_xinfo";
+    list(conn, schema_name, name, Some(index), |row| {
+        Ok(IndexInfoRow {
+            seqno: row.get("seqno")?,
+            cid: row.get("cid")?,
+            name: row.get("name")?,
+            descending: xinfo && row.get("desc")?,
+            collation: if xinfo { row.get("coll")? } else { None },
+            key: !xinfo || row.get("key")?,
+        })
+    })
+}
+
+/// `PRAGMA index_info(index)`: the key columns of an index
+#[derive(Clone, Copy, Debug)]
+pub struct IndexInfo<'a>(pub &'a str);
+
+impl ReadPragma for IndexInfo<'_> {
+    type Value = Vec<IndexInfoRow>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<IndexInfoRow>> {
+        index_info(conn, schema_name, "index_info", self.0)
+    }
+}
+
+/// `PRAGMA index_xinfo(index)`: as `IndexInfo`, with the sort order and
+/// collation of each column, and the columns after the key
+#[derive(Clone, Copy, Debug)]
+pub struct IndexXInfo<'a>(pub &'a str);
+
+impl ReadPragma for IndexXInfo<'_> {
+    type Value = Vec<IndexInfoRow>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<IndexInfoRow>> {
+        index_info(conn, schema_name, "index_xinfo", self.0)
+    }
+}
+
+/// A row of `PRAGMA foreign_key_list`: a column of a foreign key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKeyListRow {
+    /// The foreign key
+    pub id: i32,
+    /// The position of the column in the foreign key
+    pub seq: i32,
+    /// The referenced table
+    pub table: String,
+    pub from: String,
+    /// The referenced column, or `None` for the primary key
+    pub to: Option<String>,
+    pub on_update: String,
+    pub on_delete: String,
+}
+
+/// `PRAGMA foreign_key_list(table)`: the columns of the foreign keys of a
+/// table
+#[derive(Clone, Copy, Debug)]
+pub struct ForeignKeyList<'a>(pub &'a str);
+
+impl ReadPragma for ForeignKeyList<'_> {
+    type Value = Vec<ForeignKeyListRow>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<ForeignKeyListRow>> {
+        list(conn, schema_name, "foreign_key_list", Some(self.0), |row| {
+            Ok(ForeignKeyListRow {
+                id: row.get("id")?,
+                seq: row.get("seq")?,
+                table: row.get("table")?,
+                from: row.get("from")?,
+                to: row.get("to")?,
+                on_update: row.get("on_update")?,
+                on_delete: row.get("on_delete")?,
+            })
+        })
+    }
+}
+
+/// A row of `PRAGMA foreign_key_check`: a row violating a foreign key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKeyCheckRow {
+    pub table: String,
+    /// The rowid of the row, or `None` in a table without rowid
+    pub rowid: Option<i64>,
+    /// The referenced table
+    pub parent: String,
+    /// The `id` of the foreign key in `ForeignKeyList`
+    pub fkid: i32,
+}
+
+/// `PRAGMA foreign_key_check`: the rows violating foreign keys, in all
+/// tables
+#[derive(Clone, Copy, Debug)]
+pub struct ForeignKeyCheck;
+
+impl ReadPragma for ForeignKeyCheck {
+    type Value = Vec<ForeignKeyCheckRow>;
+
+    fn read(
+        &self,
+        conn: &Connection,
+        schema_name: Option<DatabaseName<'_>>,
+    ) -> Result<Vec<ForeignKeyCheckRow>> {
+        list(conn, schema_name, "foreign_key_check", None, |row| {
+            Ok(ForeignKeyCheckRow {
+                table: row.get(0)?,
+                rowid: row.get(1)?,
+                parent: row.get(2)?,
+                fkid: row.get(3)?,
+            })
+        })
+    }
+}
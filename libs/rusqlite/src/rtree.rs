@@ -0,0 +1,346 @@
+//! [R*Tree](https://sqlite.org/rtree.html) custom query functions.
+//!
+//! A query function decides, for each node of the tree, whether its
+//! bounding box is outside, partly within or fully within the region it
+//! describes, and can give it a score: nodes and entries are visited, and
+//! entries returned, in increasing score order.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! # use rusqlite::rtree;
+//! fn nearby(conn: &Connection) -> Result<Vec<i64>> {
+//!     rtree::load_functions(conn)?;
+//!     conn.execute_batch(
+//!         "CREATE VIRTUAL TABLE places USING rtree(id, min_lon, max_lon, min_lat, max_lat)",
+//!     )?;
+//!     // Places within 2 km of the Eiffel Tower, nearest first.
+//!     let mut stmt = conn.prepare(
+//!         "SELECT id FROM places WHERE id MATCH within_radius(48.8584, 2.2945, 2000)",
+//!     )?;
+//!     let rows = stmt.query_map(NO_PARAMS, |row| row.get(0))?;
+//!     rows.collect()
+//! }
+//! ```
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+
+use crate::ffi;
+use crate::functions::free_boxed_value;
+use crate::types::ValueRef;
+use crate::{str_to_cstring, Connection, Error, Result};
+
+thread_local! {
+    // The message of the last error of a query function, by connection:
+    // SQLite only keeps its code.
+    static ERRORS: RefCell<HashMap<*mut ffi::sqlite3, String>> = RefCell::new(HashMap::new());
+}
+
+/// Take the message of the error of a query function that failed the last
+/// statement of `db`.
+pub(crate) fn take_error(db: *mut ffi::sqlite3) -> Option<String> {
+    ERRORS.with(|errors| errors.borrow_mut().remove(&db))
+}
+
+/// How a node or an entry relates to the region of a query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Within {
+    /// Outside: the node and its children are skipped.
+    Not,
+    /// Overlapping: the children of the node are visited, or the entry is
+    /// returned.
+    Partly,
+    /// Inside: all entries below the node are returned.
+    Fully,
+}
+
+impl Within {
+    fn from_raw(within: c_int) -> Within {
+        match within {
+            ffi::NOT_WITHIN => Within::Not,
+            ffi::FULLY_WITHIN => Within::Fully,
+            _ => Within::Partly,
+        }
+    }
+
+    fn to_raw(self) -> c_int {
+        match self {
+            Within::Not => ffi::NOT_WITHIN,
+            Within::Partly => ffi::PARTLY_WITHIN,
+            Within::Fully => ffi::FULLY_WITHIN,
+        }
+    }
+}
+
+/// The node or entry tested by a query function.
+pub struct QueryInfo<'a> {
+    info: &'a mut ffi::sqlite3_rtree_query_info,
+}
+
+impl QueryInfo<'_> {
+    /// The arguments of the function in the `MATCH` expression, as numbers.
+    pub fn params(&self) -> &[f64] {
+        unsafe { raw_slice(self.info.aParam, self.info.nParam) }
+    }
+
+    /// The argument `idx` of the function in the `MATCH` expression, as
+    /// given, or `None` if out of range.
+    pub fn param_value(&self, idx: usize) -> Option<ValueRef<'_>> {
+        if idx >= self.info.nParam as usize || self.info.apSqlParam.is_null() {
+            return None;
+        }
+        Some(unsafe { ValueRef::from_value(*self.info.apSqlParam.add(idx)) })
+    }
+
+    /// The bounding box of the node or entry: the minimum and the maximum of
+    /// each dimension, in column order.
+    pub fn coords(&self) -> &[f64] {
+        unsafe { raw_slice(self.info.aCoord, self.info.nCoord) }
+    }
+
+    /// The level of the node in the tree: 0 for an entry, up to
+    /// `max_level()` for the root.
+    pub fn level(&self) -> i32 {
+        self.info.iLevel
+    }
+
+    /// The level of the root of the tree.
+    pub fn max_level(&self) -> i32 {
+        self.info.mxLevel
+    }
+
+    /// Whether an entry, rather than an inner node, is tested.
+    pub fn is_entry(&self) -> bool {
+        self.info.iLevel == 0
+    }
+
+    /// The rowid of the entry. Only meaningful when `is_entry()`.
+    pub fn rowid(&self) -> i64 {
+        self.info.iRowid
+    }
+
+    /// The score of the parent node.
+    pub fn parent_score(&self) -> f64 {
+        self.info.rParentScore
+    }
+
+    /// How the parent node relates to the region.
+    pub fn parent_within(&self) -> Within {
+        Within::from_raw(self.info.eParentWithin)
+    }
+
+    /// Set how the node or entry relates to the region. Defaults to
+    /// `parent_within()`.
+    pub fn set_within(&mut self, within: Within) {
+        self.info.eWithin = within.to_raw();
+    }
+
+    /// Set the score of the node or entry. Defaults to `parent_score()`.
+    pub fn set_score(&mut self, score: f64) {
+        self.info.rScore = score;
+    }
+}
+
+unsafe fn raw_slice<'a>(p: *const f64, n: c_int) -> &'a [f64] {
+    if p.is_null() || n <= 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(p, n as usize)
+    }
+}
+
+impl Connection {
+    /// Register the R*Tree query function `name`, used as
+    /// `id MATCH name(args...)` on an `rtree` or `rtree_i32` table.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the function could not be registered.
+    pub fn create_rtree_query_function<F>(&self, name: &str, x_query: F) -> Result<()>
+    where
+        F: FnMut(&mut QueryInfo<'_>) -> Result<()> + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            info: *mut ffi::sqlite3_rtree_query_info,
+        ) -> c_int
+        where
+            F: FnMut(&mut QueryInfo<'_>) -> Result<()>,
+        {
+            let boxed_f = (*info).pContext as *mut (*mut ffi::sqlite3, F);
+            let r = catch_unwind(AssertUnwindSafe(|| {
+                assert!(!boxed_f.is_null(), "Internal error - null function pointer");
+                ((*boxed_f).1)(&mut QueryInfo { info: &mut *info })
+            }));
+            let err = match r {
+                Ok(Ok(())) => return ffi::SQLITE_OK,
+                Ok(Err(err)) => err,
+                Err(_) => Error::UnwindingPanic,
+            };
+            if !boxed_f.is_null() {
+                let db = (*boxed_f).0;
+                ERRORS.with(|errors| errors.borrow_mut().insert(db, err.to_string()));
+            }
+            match err {
+                Error::SqliteFailure(err, _) => err.extended_code,
+                _ => ffi::SQLITE_ERROR,
+            }
+        }
+
+        let c_name = str_to_cstring(name)?;
+        let mut c = self.db.borrow_mut();
+        // The connection keys the messages of errors.
+        let boxed_f = Box::into_raw(Box::new((c.db(), x_query)));
+        let rc = unsafe {
+            ffi::sqlite3_rtree_query_callback(
+                c.db(),
+                c_name.as_ptr(),
+                Some(call_boxed_closure::<F>),
+                boxed_f as *mut c_void,
+                Some(free_boxed_value::<(*mut ffi::sqlite3, F)>),
+            )
+        };
+        c.decode_result(rc)
+    }
+}
+
+/// Register query functions over 2-dimensional trees, whose first dimension
+/// is the longitude or x and the second the latitude or y:
+/// * `within_radius(LAT, LON, METERS)`: entries within `METERS` of a point
+///   on the Earth, nearest first,
+/// * `within_polygon(X1, Y1, X2, Y2, X3, Y3, ...)`: entries within a
+///   polygon of at least 3 vertices.
+///
+/// Entries are expected to be points (their minimum and maximum equal);
+/// other boxes are tested by their centre.
+pub fn load_functions(conn: &Connection) -> Result<()> {
+    conn.create_rtree_query_function("within_radius", within_radius)?;
+    conn.create_rtree_query_function("within_polygon", within_polygon)
+}
+
+/// Mean radius of the Earth, in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Great-circle distance of two points, in meters.
+fn haversine(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = phi2 - phi1;
+    let d_lambda = (lon2 - lon1).to_radians();
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+/// Distance from a point to the nearest point of a box, in meters, for
+/// nodes to be pruned only when none of their entries can match.
+fn box_distance(lat: f64, lon: f64, [min_lon, max_lon, min_lat, max_lat]: [f64; 4]) -> f64 {
+    if min_lon <= lon && lon <= max_lon {
+        return haversine(lat, lon, lat.max(min_lat).min(max_lat), lon);
+    }
+    // The nearest point is on the western or eastern edge, either side of
+    // the antimeridian: where the great circle through the point crosses
+    // the meridian of the edge at a right angle, or at an end of the edge.
+    // Near a pole, that crossing is not at the latitude of the point.
+    [min_lon, max_lon]
+        .iter()
+        .map(|&edge| {
+            let d_lambda = (edge - lon).to_radians();
+            let foot = lat.to_radians().tan().atan2(d_lambda.cos()).to_degrees();
+            if min_lat <= foot && foot <= max_lat {
+                haversine(lat, lon, foot, edge)
+            } else {
+                haversine(lat, lon, min_lat, edge).min(haversine(lat, lon, max_lat, edge))
+            }
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+fn bounding_box(info: &QueryInfo<'_>, name: &str) -> Result<[f64; 4]> {
+    match *info.coords() {
+        [min_x, max_x, min_y, max_y] => Ok([min_x, max_x, min_y, max_y]),
+        _ => Err(Error::UserFunctionError(
+            format!("{}() requires a 2-dimensional rtree", name).into(),
+        )),
+    }
+}
+
+fn within_radius(info: &mut QueryInfo<'_>) -> Result<()> {
+    let (lat, lon, radius) = match *info.params() {
+        [lat, lon, radius] => (lat, lon, radius),
+        _ => {
+            return Err(Error::UserFunctionError(
+                "within_radius() takes 3 arguments".into(),
+            ))
+        }
+    };
+    let [min_lon, max_lon, min_lat, max_lat] = bounding_box(info, "within_radius")?;
+    let distance = if info.is_entry() {
+        haversine(
+            lat,
+            lon,
+            (min_lat + max_lat) / 2.0,
+            (min_lon + max_lon) / 2.0,
+        )
+    } else {
+        box_distance(lat, lon, [min_lon, max_lon, min_lat, max_lat])
+    };
+    if distance > radius {
+        info.set_within(Within::Not);
+    } else {
+        info.set_within(Within::Partly);
+        info.set_score(distance);
+    }
+    Ok(())
+}
+
+// Even-odd rule
+fn polygon_contains(vertices: &[f64], x: f64, y: f64) -> bool {
+    let n = vertices.len() / 2;
+    let mut inside = false;
+    let mut j = n - 1;
+    for i in 0..n {
+        let (xi, yi) = (vertices[2 * i], vertices[2 * i + 1]);
+        let (xj, yj) = (vertices[2 * j], vertices[2 * j + 1]);
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn within_polygon(info: &mut QueryInfo<'_>) -> Result<()> {
+    let vertices = info.params();
+    if vertices.len() < 6 || vertices.len() % 2 != 0 {
+        return Err(Error::UserFunctionError(
+            "within_polygon() takes the x and y of at least 3 vertices".into(),
+        ));
+    }
+    let [min_x, max_x, min_y, max_y] = bounding_box(info, "within_polygon")?;
+    let within = if info.is_entry() {
+        if polygon_contains(vertices, (min_x + max_x) / 2.0, (min_y + max_y) / 2.0) {
+            Within::Fully
+        } else {
+            Within::Not
+        }
+    } else {
+        // Skip nodes outside the bounding box of the polygon.
+        let (xs, ys): (Vec<f64>, Vec<f64>) = vertices.chunks_exact(2).map(|v| (v[0], v[1])).unzip();
+        let fold = |values: &[f64], f: fn(f64, f64) -> f64, init: f64| {
+            values.iter().cloned().fold(init, f)
+        };
+        let outside = fold(&xs, f64::min, f64::INFINITY) > max_x
+            || fold(&xs, f64::max, f64::NEG_INFINITY) < min_x
+            || fold(&ys, f64::min, f64::INFINITY) > max_y
+            || fold(&ys, f64::max, f64::NEG_INFINITY) < min_y;
+        if outside {
+            Within::Not
+        } else {
+            Within::Partly
+        }
+    };
+    info.set_within(within);
+    Ok(())
+}
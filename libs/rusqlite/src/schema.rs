@@ -0,0 +1,360 @@
+//! Typed schema introspection, over the `table_xinfo`, `index_list`,
+//! `index_xinfo` and `foreign_key_list` readers of `pragma::get`,
+//! `sqlite_master` and `sqlite3_table_column_metadata`.
+//!
+//! All methods read the main database.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! fn describe(conn: &Connection) -> Result<()> {
+//!     for table in conn.tables()? {
+//!         for column in conn.columns(&table.name)? {
+//!             println!("{}.{}: {:?}", table.name, column.name, column.affinity);
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use crate::ffi;
+use crate::pragma::get;
+use crate::{str_to_cstring, Connection, DatabaseName, Error, Result, NO_PARAMS};
+
+pub use crate::pragma::get::IndexOrigin;
+
+/// The type affinity of a column, derived from its declared type as
+/// described in [Datatypes In SQLite](https://sqlite.org/datatype3.html#determination_of_column_affinity).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Affinity {
+    Text,
+    Numeric,
+    Integer,
+    Real,
+    Blob,
+}
+
+impl Affinity {
+    /// The affinity of a column declared with `decl_type`.
+    pub fn from_decl_type(decl_type: Option<&str>) -> Affinity {
+        let decl_type = decl_type.unwrap_or("").to_ascii_uppercase();
+        let has = |s: &str| decl_type.contains(s);
+        if has("INT") {
+            Affinity::Integer
+        } else if has("CHAR") || has("CLOB") || has("TEXT") {
+            Affinity::Text
+        } else if has("BLOB") || decl_type.is_empty() {
+            Affinity::Blob
+        } else if has("REAL") || has("FLOA") || has("DOUB") {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+}
+
+/// A table, or a virtual table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    pub name: String,
+    /// The `CREATE TABLE` statement
+    pub sql: Option<String>,
+    pub virtual_table: bool,
+    pub without_rowid: bool,
+}
+
+/// A column of a table or a view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub name: String,
+    /// The declared type, as written
+    pub decl_type: Option<String>,
+    pub affinity: Affinity,
+    pub not_null: bool,
+    /// The SQL text of the default value
+    pub default: Option<String>,
+    /// The position of the column in the primary key, from 1
+    pub primary_key: Option<u32>,
+    /// The name of the default collation
+    pub collation: String,
+    pub auto_increment: bool,
+    /// A hidden column of a virtual table
+    pub hidden: bool,
+    /// A generated column, virtual or stored
+    pub generated: bool,
+}
+
+/// An index of a table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub unique: bool,
+    pub origin: IndexOrigin,
+    /// Whether the index has a `WHERE` clause
+    pub partial: bool,
+    /// The key columns, in order
+    pub columns: Vec<IndexColumn>,
+}
+
+/// A key column of an index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexColumn {
+    /// The name of the column, or `None` for an expression
+    pub name: Option<String>,
+    pub descending: bool,
+    pub collation: String,
+}
+
+/// A foreign key constraint of a table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKey {
+    /// The referenced table
+    pub table: String,
+    /// The referencing columns
+    pub from: Vec<String>,
+    /// The referenced columns, or `None` for the primary key of `table`
+    pub to: Vec<Option<String>>,
+    /// The action on update: `NO ACTION`, `RESTRICT`, `SET NULL`, `SET DEFAULT`
+    /// or `CASCADE`
+    pub on_update: String,
+    /// The action on delete
+    pub on_delete: String,
+}
+
+/// A trigger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trigger {
+    pub name: String,
+    /// The table or view of the trigger
+    pub table: String,
+    /// The `CREATE TRIGGER` statement
+    pub sql: String,
+}
+
+/// A view. Its columns are given by `Connection::columns`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct View {
+    pub name: String,
+    /// The `CREATE VIEW` statement
+    pub sql: String,
+}
+
+/// Metadata of a column, from `sqlite3_table_column_metadata`.
+struct ColumnMetadata {
+    collation: String,
+    auto_increment: bool,
+}
+
+impl Connection {
+    /// The tables, virtual tables included, in name order. Internal tables,
+    /// whose name starts with `sqlite_`, are not.
+    pub fn tables(&self) -> Result<Vec<Table>> {
+        let mut stmt = self.prepare(
+            "SELECT name, sql, rootpage = 0 FROM sqlite_master \
+             WHERE type = 'table' AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+             ORDER BY name",
+        )?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            Ok(Table {
+                name: row.get(0)?,
+                sql: row.get(1)?,
+                virtual_table: row.get(2)?,
+                without_rowid: false,
+            })
+        })?;
+        let mut tables = rows.collect::<Result<Vec<_>>>()?;
+        // A table without rowid is stored in its primary key index, which has
+        // no rowid entry (cid -1) unlike the primary key index of a rowid
+        // table.
+        let mut pk_without_rowid = self.prepare(
+            "SELECT count(*) FROM pragma_index_list(?, 'main') AS l \
+             WHERE l.origin = 'pk' AND NOT EXISTS \
+             (SELECT 1 FROM pragma_index_xinfo(l.name, 'main') WHERE cid = -1)",
+        )?;
+        for table in &mut tables {
+            if !table.virtual_table {
+                let count: i64 = pk_without_rowid.query_row(&[&table.name], |row| row.get(0))?;
+                table.without_rowid = count > 0;
+            }
+        }
+        Ok(tables)
+    }
+
+    /// The columns of the table or view `table`, hidden and generated ones
+    /// included, in order.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `table` does not exist.
+    pub fn columns(&self, table: &str) -> Result<Vec<Column>> {
+        self.check_table(table)?;
+        let rows = self.pragma_get(Some(DatabaseName::Main), get::TableXInfo(table))?;
+        let mut columns = Vec::with_capacity(rows.len());
+        for row in rows {
+            // Columns of views have no metadata.
+            let metadata = self.column_metadata(table, &row.name).ok();
+            columns.push(Column {
+                affinity: Affinity::from_decl_type(row.decl_type.as_ref().map(String::as_str)),
+                decl_type: row.decl_type,
+                not_null: row.not_null,
+                default: row.default,
+                primary_key: row.primary_key,
+                collation: metadata
+                    .as_ref()
+                    .map_or_else(|| "BINARY".to_owned(), |m| m.collation.clone()),
+                auto_increment: metadata.map_or(false, |m| m.auto_increment),
+                hidden: row.hidden == 1,
+                generated: row.hidden == 2 || row.hidden == 3,
+                name: row.name,
+            });
+        }
+        Ok(columns)
+    }
+
+    /// The indexes of `table`, automatic ones included.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `table` does not exist.
+    pub fn indexes(&self, table: &str) -> Result<Vec<Index>> {
+        self.check_table(table)?;
+        let rows = self.pragma_get(Some(DatabaseName::Main), get::IndexList(table))?;
+        let mut indexes = Vec::with_capacity(rows.len());
+        for row in rows {
+            let columns = self
+                .pragma_get(Some(DatabaseName::Main), get::IndexXInfo(&row.name))?
+                .into_iter()
+                .filter(|column| column.key)
+                .map(|column| IndexColumn {
+                    name: column.name,
+                    descending: column.descending,
+                    collation: column.collation.unwrap_or_else(|| "BINARY".to_owned()),
+                })
+                .collect();
+            indexes.push(Index {
+                name: row.name,
+                unique: row.unique,
+                origin: row.origin,
+                partial: row.partial,
+                columns,
+            });
+        }
+        Ok(indexes)
+    }
+
+    /// The foreign keys of `table`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `table` does not exist.
+    pub fn foreign_keys(&self, table: &str) -> Result<Vec<ForeignKey>> {
+        self.check_table(table)?;
+        let rows = self.pragma_get(Some(DatabaseName::Main), get::ForeignKeyList(table))?;
+        let mut foreign_keys: Vec<(i32, ForeignKey)> = Vec::new();
+        for row in rows {
+            // The columns of a key are consecutive rows.
+            match foreign_keys.last_mut() {
+                Some(&mut (last, ref mut key)) if last == row.id => {
+                    key.from.push(row.from);
+                    key.to.push(row.to);
+                }
+                _ => foreign_keys.push((
+                    row.id,
+                    ForeignKey {
+                        table: row.table,
+                        from: vec![row.from],
+                        to: vec![row.to],
+                        on_update: row.on_update,
+                        on_delete: row.on_delete,
+                    },
+                )),
+            }
+        }
+        // Keys are listed last declared first.
+        foreign_keys.sort_by_key(|&(id, _)| -id);
+        Ok(foreign_keys.into_iter().map(|(_, key)| key).collect())
+    }
+
+    /// The triggers, in name order.
+    pub fn triggers(&self) -> Result<Vec<Trigger>> {
+        let mut stmt = self.prepare(
+            "SELECT name, tbl_name, sql FROM sqlite_master WHERE type = 'trigger' ORDER BY name",
+        )?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            Ok(Trigger {
+                name: row.get(0)?,
+                table: row.get(1)?,
+                sql: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// The views, in name order.
+    pub fn views(&self) -> Result<Vec<View>> {
+        let mut stmt =
+            self.prepare("SELECT name, sql FROM sqlite_master WHERE type = 'view' ORDER BY name")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            Ok(View {
+                name: row.get(0)?,
+                sql: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Fails with a "no such table" error if the table or view `table` does
+    /// not exist, where pragmas return no rows.
+    fn check_table(&self, table: &str) -> Result<()> {
+        let columns: i64 = self.query_row(
+            "SELECT count(*) FROM pragma_table_xinfo(?, 'main')",
+            &[table],
+            |row| row.get(0),
+        )?;
+        if columns == 0 {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some(format!("no such table: {}", table)),
+            ));
+        }
+        Ok(())
+    }
+
+    fn column_metadata(&self, table: &str, column: &str) -> Result<ColumnMetadata> {
+        let db_name = DatabaseName::Main.to_cstring()?;
+        let table = str_to_cstring(table)?;
+        let column = str_to_cstring(column)?;
+        let mut collation: *const c_char = ptr::null();
+        let mut auto_increment: c_int = 0;
+        let mut c = self.db.borrow_mut();
+        let rc = unsafe {
+            ffi::sqlite3_table_column_metadata(
+                c.db(),
+                db_name.as_ptr(),
+                table.as_ptr(),
+                column.as_ptr(),
+                ptr::null_mut(),
+                &mut collation,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut auto_increment,
+            )
+        };
+        c.decode_result(rc)?;
+        let collation = if collation.is_null() {
+            "BINARY".to_owned()
+        } else {
+            unsafe { CStr::from_ptr(collation) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        Ok(ColumnMetadata {
+            collation,
+            auto_increment: auto_increment != 0,
+        })
+    }
+}
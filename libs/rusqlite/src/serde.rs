@@ -0,0 +1,837 @@
+//! Map rows to and parameters from types implementing the
+//! [serde](https://serde.rs) traits.
+//!
+//! Columns are matched to struct fields by name, and struct fields are
+//! bound to the named parameters `:field`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{params_from_serialize, Connection, Result, NO_PARAMS};
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Deserialize, Serialize)]
+//! struct Person {
+//!     name: String,
+//!     data: Option<Vec<u8>>,
+//! }
+//!
+//! fn copy_people(src: &Connection, dst: &Connection) -> Result<()> {
+//!     let mut select = src.prepare("SELECT name, data FROM person")?;
+//!     let mut insert = dst.prepare("INSERT INTO person VALUES (:name, :data)")?;
+//!     for person in select.query_as::<Person, _>(NO_PARAMS)? {
+//!         insert.execute_named(&params_from_serialize(&person?)?.to_named())?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::result;
+use std::str;
+
+use ::serde::de::value::SeqDeserializer;
+use ::serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, Expected, IntoDeserializer, MapAccess,
+    SeqAccess, Unexpected, Visitor,
+};
+use ::serde::ser::{self, Impossible, Serialize};
+
+use crate::error::UNKNOWN_COLUMN;
+use crate::row::MappedRows;
+use crate::types::{ToSql, Type, Value, ValueRef};
+use crate::{Error, Result, Row, Statement};
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::FromSqlConversionFailure(UNKNOWN_COLUMN, Type::Null, msg.to_string().into())
+    }
+
+    fn missing_field(field: &'static str) -> Error {
+        Error::InvalidColumnName(field.into())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::ToSqlConversionFailure(msg.to_string().into())
+    }
+}
+
+// An error decoding a single value, turned into an `Error` once the column
+// it comes from is known.
+#[derive(Debug)]
+enum ValueError {
+    InvalidType,
+    OutOfRange(i64),
+    Message(String),
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ValueError::InvalidType => write!(f, "invalid type"),
+            ValueError::OutOfRange(i) => write!(f, "integer {} out of range", i),
+            ValueError::Message(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl error::Error for ValueError {}
+
+impl de::Error for ValueError {
+    fn custom<T: fmt::Display>(msg: T) -> ValueError {
+        ValueError::Message(msg.to_string())
+    }
+
+    fn invalid_type(_: Unexpected<'_>, _: &dyn Expected) -> ValueError {
+        ValueError::InvalidType
+    }
+
+    fn invalid_value(unexp: Unexpected<'_>, exp: &dyn Expected) -> ValueError {
+        match unexp {
+            Unexpected::Signed(i) => ValueError::OutOfRange(i),
+            _ => de::Error::custom(format_args!("invalid value: {}, expected {}", unexp, exp)),
+        }
+    }
+}
+
+fn utf8(s: &[u8]) -> result::Result<&str, ValueError> {
+    str::from_utf8(s).map_err(|err| ValueError::Message(err.to_string()))
+}
+
+struct ValueDeserializer<'a> {
+    value: ValueRef<'a>,
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, ValueError> {
+        match self.value {
+            ValueRef::Null => visitor.visit_unit(),
+            ValueRef::Integer(i) => visitor.visit_i64(i),
+            ValueRef::Real(f) => visitor.visit_f64(f),
+            ValueRef::Text(s) => visitor.visit_str(utf8(s)?),
+            ValueRef::Blob(b) => visitor.visit_bytes(b),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, ValueError> {
+        match self.value {
+            ValueRef::Integer(i) => visitor.visit_bool(i != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, ValueError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, ValueError> {
+        match self.value {
+            ValueRef::Integer(i) => visitor.visit_f64(i as f64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> result::Result<V::Value, ValueError> {
+        match self.value {
+            ValueRef::Text(b) | ValueRef::Blob(b) => visitor.visit_bytes(b),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> result::Result<V::Value, ValueError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> result::Result<V::Value, ValueError> {
+        match self.value {
+            ValueRef::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> result::Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // `Vec<u8>` is deserialized as a sequence.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, ValueError> {
+        match self.value {
+            ValueRef::Blob(b) => visitor.visit_seq(SeqDeserializer::new(b.iter().cloned())),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    // Unit variants are stored as their name.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> result::Result<V::Value, ValueError> {
+        match self.value {
+            ValueRef::Text(s) => visitor.visit_enum(utf8(s)?.into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 u8 u16 u32 u64 char str string unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RowDeserializer<'a, 'stmt> {
+    row: &'a Row<'stmt>,
+}
+
+impl<'a> RowDeserializer<'a, '_> {
+    fn column(self, idx: usize) -> ValueDeserializer<'a> {
+        ValueDeserializer {
+            value: self.row.get_raw(idx),
+        }
+    }
+
+    fn column_error(self, idx: usize, err: ValueError) -> Error {
+        let data_type = self.row.get_raw(idx).data_type();
+        match err {
+            ValueError::InvalidType => Error::InvalidColumnType(
+                idx,
+                self.row.stmt.column_name_unwrap(idx).into(),
+                data_type,
+            ),
+            ValueError::OutOfRange(i) => Error::IntegralValueOutOfRange(idx, i),
+            ValueError::Message(msg) => Error::FromSqlConversionFailure(idx, data_type, msg.into()),
+        }
+    }
+
+    // Types other than structs, maps and sequences are read from the only
+    // column of the row.
+    fn single<T, F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce(ValueDeserializer<'a>) -> result::Result<T, ValueError>,
+    {
+        let count = self.row.column_count();
+        if count != 1 {
+            return Err(de::Error::custom(format_args!(
+                "expected a single column, got {}",
+                count
+            )));
+        }
+        f(self.column(0)).map_err(|err| self.column_error(0, err))
+    }
+}
+
+macro_rules! forward_to_column {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                self.single(|column| column.$method(visitor))
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer<'_, '_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(Columns { de: self, idx: 0 })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Columns { de: self, idx: 0 })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.single(|column| column.deserialize_unit_struct(name, visitor))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.single(|column| column.deserialize_enum(name, variants, visitor))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_column! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf option unit identifier
+    }
+}
+
+// The columns of a row, as a map keyed by name or as a sequence.
+struct Columns<'a, 'stmt> {
+    de: RowDeserializer<'a, 'stmt>,
+    idx: usize,
+}
+
+impl Columns<'_, '_> {
+    fn next_value<'de, T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value> {
+        let idx = self.idx;
+        self.idx += 1;
+        seed.deserialize(self.de.column(idx))
+            .map_err(|err| self.de.column_error(idx, err))
+    }
+}
+
+impl<'de> MapAccess<'de> for Columns<'_, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.idx >= self.de.row.column_count() {
+            return Ok(None);
+        }
+        let name = self.de.row.stmt.column_name_unwrap(self.idx);
+        seed.deserialize(name.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value> {
+        self.next_value(seed)
+    }
+}
+
+impl<'de> SeqAccess<'de> for Columns<'_, '_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.idx >= self.de.row.column_count() {
+            return Ok(None);
+        }
+        self.next_value(seed).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.de.row.column_count() - self.idx)
+    }
+}
+
+impl Row<'_> {
+    /// Deserialize the row into `T`. Structs and maps take the columns by
+    /// name, tuples and sequences in order; any other type is read from the
+    /// only column of the row.
+    ///
+    /// ## Failure
+    ///
+    /// Returns an `Error::InvalidColumnName` if a field has no matching
+    /// column, and an `Error::InvalidColumnType` if a column cannot be
+    /// converted to the type of its field.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        T::deserialize(RowDeserializer { row: self })
+    }
+}
+
+impl Statement<'_> {
+    /// Execute the prepared statement, returning an iterator over its rows
+    /// deserialized into `T` (see `Row::deserialize`).
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, NO_PARAMS};
+    /// # use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct Person {
+    ///     id: i32,
+    ///     name: String,
+    /// }
+    ///
+    /// fn get_people(conn: &Connection) -> Result<Vec<Person>> {
+    ///     let mut stmt = conn.prepare("SELECT id, name FROM person")?;
+    ///     let rows = stmt.query_as::<Person, _>(NO_PARAMS)?;
+    ///     rows.collect()
+    /// }
+    /// ```
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    pub fn query_as<T, P>(&mut self, params: P) -> Result<MappedRows<'_, fn(&Row<'_>) -> Result<T>>>
+    where
+        T: DeserializeOwned,
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        self.query_map(params, deserialize_row::<T> as fn(&Row<'_>) -> Result<T>)
+    }
+}
+
+fn deserialize_row<T: DeserializeOwned>(row: &Row<'_>) -> Result<T> {
+    row.deserialize()
+}
+
+/// Named parameters built by `params_from_serialize`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedParams {
+    params: Vec<(String, Value)>,
+}
+
+impl NamedParams {
+    /// Borrow the parameters in the form taken by `execute_named`,
+    /// `query_named` and the other `_named` methods.
+    pub fn to_named(&self) -> Vec<(&str, &dyn ToSql)> {
+        self.params
+            .iter()
+            .map(|&(ref name, ref value)| (name.as_str(), value as &dyn ToSql))
+            .collect()
+    }
+}
+
+/// Turn the fields of a struct, or the entries of a map, into the named
+/// parameters `:field`.
+///
+/// Fields must be scalars, options, unit variants or byte sequences (bound
+/// as blobs). Every field must appear in the statement: leave the others
+/// out with `#[serde(skip_serializing)]`.
+///
+/// ## Failure
+///
+/// Returns an `Error::ToSqlConversionFailure` if `value` is not a struct or
+/// map, or one of its fields cannot be bound.
+pub fn params_from_serialize<T: Serialize + ?Sized>(value: &T) -> Result<NamedParams> {
+    value.serialize(ParamsSerializer)
+}
+
+fn unsupported(what: &str) -> Error {
+    ser::Error::custom(format_args!("cannot bind {} as parameters", what))
+}
+
+struct ParamsSerializer;
+
+macro_rules! unsupported_params {
+    ($($method:ident: $t:ty),*) => {
+        $(
+            fn $method(self, _v: $t) -> Result<NamedParams> {
+                Err(unsupported("a scalar"))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for ParamsSerializer {
+    type Ok = NamedParams;
+    type Error = Error;
+    type SerializeSeq = Impossible<NamedParams, Error>;
+    type SerializeTuple = Impossible<NamedParams, Error>;
+    type SerializeTupleStruct = Impossible<NamedParams, Error>;
+    type SerializeTupleVariant = Impossible<NamedParams, Error>;
+    type SerializeMap = ParamsBuilder;
+    type SerializeStruct = ParamsBuilder;
+    type SerializeStructVariant = Impossible<NamedParams, Error>;
+
+    unsupported_params! {
+        serialize_bool: bool, serialize_i8: i8, serialize_i16: i16, serialize_i32: i32,
+        serialize_i64: i64, serialize_u8: u8, serialize_u16: u16, serialize_u32: u32,
+        serialize_u64: u64, serialize_f32: f32, serialize_f64: f64, serialize_char: char,
+        serialize_str: &str, serialize_bytes: &[u8]
+    }
+
+    fn serialize_none(self) -> Result<NamedParams> {
+        Err(unsupported("None"))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<NamedParams> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<NamedParams> {
+        Err(unsupported("()"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<NamedParams> {
+        Err(unsupported("a unit struct"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<NamedParams> {
+        Err(unsupported("an enum"))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<NamedParams> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<NamedParams> {
+        Err(unsupported("an enum"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(unsupported("a sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(unsupported("a tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(unsupported("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(unsupported("an enum"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<ParamsBuilder> {
+        Ok(ParamsBuilder::new(len.unwrap_or(0)))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<ParamsBuilder> {
+        Ok(ParamsBuilder::new(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(unsupported("an enum"))
+    }
+}
+
+struct ParamsBuilder {
+    params: Vec<(String, Value)>,
+    // The name of the map entry whose value comes next.
+    key: Option<String>,
+}
+
+impl ParamsBuilder {
+    fn new(len: usize) -> ParamsBuilder {
+        ParamsBuilder {
+            params: Vec::with_capacity(len),
+            key: None,
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, name: &str, value: &T) -> Result<()> {
+        let value = value.serialize(ValueSerializer)?;
+        self.params.push((format!(":{}", name), value));
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for ParamsBuilder {
+    type Ok = NamedParams;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<NamedParams> {
+        Ok(NamedParams {
+            params: self.params,
+        })
+    }
+}
+
+impl ser::SerializeMap for ParamsBuilder {
+    type Ok = NamedParams;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+        match key.serialize(ValueSerializer)? {
+            Value::Text(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(unsupported("a map with keys other than strings")),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        self.push(&key, value)
+    }
+
+    fn end(self) -> Result<NamedParams> {
+        Ok(NamedParams {
+            params: self.params,
+        })
+    }
+}
+
+// Serializes a single field into a `Value`.
+struct ValueSerializer;
+
+macro_rules! serialize_integer {
+    ($($method:ident: $t:ty),*) => {
+        $(
+            fn $method(self, v: $t) -> Result<Value> {
+                Ok(Value::Integer(v as i64))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = BlobBuilder;
+    type SerializeTuple = BlobBuilder;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+    type SerializeMap = Impossible<Value, Error>;
+    type SerializeStruct = Impossible<Value, Error>;
+    type SerializeStructVariant = Impossible<Value, Error>;
+
+    serialize_integer! {
+        serialize_bool: bool, serialize_i8: i8, serialize_i16: i16, serialize_i32: i32,
+        serialize_i64: i64, serialize_u8: u8, serialize_u16: u16, serialize_u32: u32
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        if v > i64::max_value() as u64 {
+            return Err(ser::Error::custom(format_args!(
+                "integer {} out of range",
+                v
+            )));
+        }
+        Ok(Value::Integer(v as i64))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        Ok(Value::Real(f64::from(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::Real(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::Text(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Blob(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        Ok(Value::Text(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Value> {
+        Err(unsupported("an enum with data"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<BlobBuilder> {
+        Ok(BlobBuilder {
+            bytes: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<BlobBuilder> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(unsupported("a nested tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(unsupported("an enum with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(unsupported("a nested map"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(unsupported("a nested struct"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(unsupported("an enum with data"))
+    }
+}
+
+// Serializes a sequence of bytes, such as a `Vec<u8>`, into a blob.
+struct BlobBuilder {
+    bytes: Vec<u8>,
+}
+
+impl BlobBuilder {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        match value.serialize(ValueSerializer)? {
+            Value::Integer(i) if (0..=255).contains(&i) => {
+                self.bytes.push(i as u8);
+                Ok(())
+            }
+            _ => Err(unsupported("a sequence of values other than bytes")),
+        }
+    }
+}
+
+impl ser::SerializeSeq for BlobBuilder {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Blob(self.bytes))
+    }
+}
+
+impl ser::SerializeTuple for BlobBuilder {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Blob(self.bytes))
+    }
+}
@@ -0,0 +1,94 @@
+//! [Serialize](https://sqlite.org/c3ref/serialize.html) a database into the
+//! bytes it would occupy on disk, and load a database back from such bytes.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, DatabaseName, Result};
+//! fn copy(src: &Connection) -> Result<Connection> {
+//!     let bytes = src.serialize(DatabaseName::Main)?;
+//!     let dst = Connection::open_in_memory()?;
+//!     dst.deserialize(DatabaseName::Main, &bytes, false)?;
+//!     Ok(dst)
+//! }
+//! ```
+
+use std::os::raw::{c_uint, c_void};
+use std::slice;
+
+use crate::error::error_from_sqlite_code;
+use crate::ffi;
+use crate::{Connection, DatabaseName, Result};
+
+impl Connection {
+    /// Return a copy of the content of the `schema` database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not attached or if memory cannot be
+    /// allocated.
+    pub fn serialize(&self, schema: DatabaseName<'_>) -> Result<Vec<u8>> {
+        let c_schema = schema.to_cstring()?;
+        let c = self.db.borrow();
+        let mut size: ffi::sqlite3_int64 = 0;
+        let data = unsafe { ffi::sqlite3_serialize(c.db(), c_schema.as_ptr(), &mut size, 0) };
+        if data.is_null() {
+            return match size {
+                // A database without any page.
+                0 => Ok(Vec::new()),
+                s if s < 0 => Err(error_from_sqlite_code(
+                    ffi::SQLITE_ERROR,
+                    Some(format!("unknown database {}", c_schema.to_string_lossy())),
+                )),
+                _ => Err(error_from_sqlite_code(ffi::SQLITE_NOMEM, None)),
+            };
+        }
+        let bytes = unsafe { slice::from_raw_parts(data, size as usize) }.to_vec();
+        unsafe { ffi::sqlite3_free(data as *mut c_void) };
+        Ok(bytes)
+    }
+
+    /// Replace the content of the `schema` database with a copy of `data`,
+    /// as produced by `serialize`. The database stays in memory and can grow
+    /// unless `read_only` is set.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not attached, is in use, or if memory
+    /// cannot be allocated.
+    pub fn deserialize(
+        &self,
+        schema: DatabaseName<'_>,
+        data: &[u8],
+        read_only: bool,
+    ) -> Result<()> {
+        let c_schema = schema.to_cstring()?;
+        let mut c = self.db.borrow_mut();
+        let len = data.len();
+        let buf = unsafe { ffi::sqlite3_malloc64(len as ffi::sqlite3_uint64) } as *mut u8;
+        if buf.is_null() && len > 0 {
+            return Err(error_from_sqlite_code(ffi::SQLITE_NOMEM, None));
+        }
+        if len > 0 {
+            unsafe { buf.copy_from_nonoverlapping(data.as_ptr(), len) };
+        }
+        let mut flags = ffi::SQLITE_DESERIALIZE_FREEONCLOSE;
+        if read_only {
+            flags |= ffi::SQLITE_DESERIALIZE_READONLY;
+        } else {
+            flags |= ffi::SQLITE_DESERIALIZE_RESIZEABLE;
+        }
+        // `buf` is released by SQLite, even on failure.
+        let rc = unsafe {
+            ffi::sqlite3_deserialize(
+                c.db(),
+                c_schema.as_ptr(),
+                buf,
+                len as ffi::sqlite3_int64,
+                len as ffi::sqlite3_int64,
+                flags as c_uint,
+            )
+        };
+        c.decode_result(rc)
+    }
+}
@@ -1,8 +1,6 @@
 use std::ffi::CStr;
 use std::iter::IntoIterator;
 use std::os::raw::{c_char, c_int, c_void};
-#[cfg(feature = "array")]
-use std::rc::Rc;
 use std::slice::from_raw_parts;
 use std::{convert, fmt, mem, ptr, result, str};
 
@@ -478,7 +476,7 @@ impl Statement<'_> {
                     ffi::sqlite3_bind_pointer(
                         ptr,
                         col as c_int,
-                        Rc::into_raw(a) as *mut c_void,
+                        Box::into_raw(Box::new(a)) as *mut c_void,
                         ARRAY_TYPE,
                         Some(free_array),
                     )
@@ -682,6 +680,16 @@ impl Statement<'_> {
     pub fn reset(&self) -> c_int {
         self.stmt.reset()
     }
+
+    /// The current row, after `step` returned `true`.
+    pub fn row(&self) -> Row<'_> {
+        Row { stmt: self }
+    }
+
+    /// The number of SQL parameters of the statement.
+    pub fn parameter_count(&self) -> usize {
+        self.stmt.bind_parameter_count()
+    }
 }
 
 /// Prepared statement status counters.
@@ -0,0 +1,295 @@
+//! Reactive query subscriptions driven by the data change and commit hooks.
+//!
+//! A subscribed `SELECT` is re-run by `Subscriptions::refresh` only when a
+//! committed transaction wrote to one of the tables the query reads.
+//! Changes to `WITHOUT ROWID` tables are not reported by
+//! [sqlite3_update_hook](https://sqlite.org/c3ref/update_hook.html) and so do
+//! not trigger a refresh.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use crate::ffi;
+use crate::types::Value;
+use crate::{Action, Connection, Error, Result, NO_PARAMS};
+
+/// The rows produced by a subscribed query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryResult {
+    /// Column names, in result order.
+    pub columns: Vec<String>,
+    /// One `Vec` of values per row.
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// Handle returned by `Subscriptions::subscribe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(pub u32);
+
+type Callback = Rc<dyn Fn(&QueryResult)>;
+
+struct Subscription {
+    id: SubscriptionId,
+    sql: String,
+    tables: HashSet<String>,
+    last: Option<QueryResult>,
+    /// Incremented each time `last` changes.
+    version: u64,
+    callback: Callback,
+}
+
+// A changed result, passed to its callback once the subscriptions are no
+// longer borrowed.
+struct Notification {
+    id: SubscriptionId,
+    version: u64,
+    callback: Callback,
+    result: QueryResult,
+}
+
+impl Subscription {
+    /// Re-run the query. Returns the new result if it changed.
+    fn run(&mut self, conn: &Connection) -> Result<Option<Notification>> {
+        let mut stmt = conn.prepare(&self.sql)?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = Vec::new();
+        {
+            let mut query = stmt.query(NO_PARAMS)?;
+            while let Some(row) = query.next()? {
+                let mut values = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    values.push(row.get::<_, Value>(i)?);
+                }
+                rows.push(values);
+            }
+        }
+        let result = QueryResult { columns, rows };
+        if self.last.as_ref() == Some(&result) {
+            return Ok(None);
+        }
+        self.last = Some(result.clone());
+        self.version += 1;
+        Ok(Some(Notification {
+            id: self.id,
+            version: self.version,
+            callback: Rc::clone(&self.callback),
+            result,
+        }))
+    }
+}
+
+#[derive(Default)]
+struct ChangedTables {
+    // Tables written by the transaction in progress.
+    pending: HashSet<String>,
+    // Tables written by committed transactions since the last refresh.
+    committed: HashSet<String>,
+}
+
+/// A set of `SELECT` queries whose results are pushed to callbacks whenever
+/// a committed transaction may have changed them.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::subscription::Subscriptions;
+/// fn watch(conn: &Connection) -> Result<()> {
+///     let subscriptions = Subscriptions::new(conn);
+///     subscriptions.subscribe(conn, "SELECT name FROM person", |result| {
+///         println!("{} people", result.rows.len());
+///     })?;
+///     conn.execute("INSERT INTO person (name) VALUES (?)", &["Joe"])?;
+///     subscriptions.refresh(conn)
+/// }
+/// ```
+pub struct Subscriptions {
+    changed: Arc<Mutex<ChangedTables>>,
+    subscriptions: RefCell<Vec<Subscription>>,
+    next_id: Cell<u32>,
+}
+
+impl Subscriptions {
+    /// Install the update, commit and rollback hooks on `conn` that track
+    /// which tables have changed.
+    ///
+    /// This replaces any hooks previously registered with
+    /// `Connection::update_hook`, `commit_hook` or `rollback_hook`.
+    pub fn new(conn: &Connection) -> Subscriptions {
+        let changed = Arc::new(Mutex::new(ChangedTables::default()));
+
+        let tables = Arc::clone(&changed);
+        conn.update_hook(Some(move |_: Action, _: &str, tbl: &str, _: i64| {
+            tables
+                .lock()
+                .unwrap()
+                .pending
+                .insert(tbl.to_ascii_lowercase());
+        }));
+        let tables = Arc::clone(&changed);
+        conn.commit_hook(Some(move || {
+            let mut tables = tables.lock().unwrap();
+            let pending = mem::replace(&mut tables.pending, HashSet::new());
+            tables.committed.extend(pending);
+            false
+        }));
+        let tables = Arc::clone(&changed);
+        conn.rollback_hook(Some(move || {
+            tables.lock().unwrap().pending.clear();
+        }));
+
+        Subscriptions {
+            changed,
+            subscriptions: RefCell::new(Vec::new()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    /// Register a read-only query. `callback` is invoked immediately with the
+    /// current result and then by `refresh` each time the result changes.
+    ///
+    /// The callback may use the connection and the subscriptions, even
+    /// refreshing them or unsubscribing itself.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be prepared, is not read-only, or
+    /// the initial run fails.
+    pub fn subscribe<F>(&self, conn: &Connection, sql: &str, callback: F) -> Result<SubscriptionId>
+    where
+        F: Fn(&QueryResult) + 'static,
+    {
+        let tables = read_tables(conn, sql)?;
+        let id = SubscriptionId(self.next_id.get());
+        self.next_id.set(id.0 + 1);
+
+        let mut subscription = Subscription {
+            id,
+            sql: sql.to_owned(),
+            tables,
+            last: None,
+            version: 0,
+            callback: Rc::new(callback),
+        };
+        let notification = subscription.run(conn)?;
+        self.subscriptions.borrow_mut().push(subscription);
+        self.notify(notification);
+        Ok(id)
+    }
+
+    /// Remove a subscription. Returns `false` if `id` was not registered.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut subscriptions = self.subscriptions.borrow_mut();
+        let len = subscriptions.len();
+        subscriptions.retain(|s| s.id != id);
+        subscriptions.len() != len
+    }
+
+    /// Re-run every query that reads a table written by a transaction
+    /// committed since the last refresh, invoking its callback if the result
+    /// differs from the previous one.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if re-running a query fails; the remaining queries
+    /// are still re-run on the next refresh.
+    pub fn refresh(&self, conn: &Connection) -> Result<()> {
+        let mut changed = {
+            let mut tables = self.changed.lock().unwrap();
+            mem::replace(&mut tables.committed, HashSet::new())
+        };
+        if changed.is_empty() {
+            return Ok(());
+        }
+        // Callbacks are invoked once `subscriptions` is released, as they may
+        // run statements that refresh, subscribe or unsubscribe.
+        let mut notifications = Vec::new();
+        let mut result = Ok(());
+        for subscription in self.subscriptions.borrow_mut().iter_mut() {
+            if subscription.tables.is_disjoint(&changed) {
+                continue;
+            }
+            match subscription.run(conn) {
+                Ok(notification) => notifications.extend(notification),
+                Err(e) => {
+                    // Keep the tables so the failed and remaining queries are
+                    // retried next time.
+                    let mut tables = self.changed.lock().unwrap();
+                    tables.committed.extend(changed.drain());
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        for notification in notifications {
+            self.notify(Some(notification));
+        }
+        result
+    }
+
+    // Invoke the callback of a changed result, unless an earlier callback
+    // removed the subscription or a nested refresh changed its result again.
+    fn notify(&self, notification: Option<Notification>) {
+        let notification = match notification {
+            Some(notification) => notification,
+            None => return,
+        };
+        let current = self
+            .subscriptions
+            .borrow()
+            .iter()
+            .any(|s| s.id == notification.id && s.version == notification.version);
+        if current {
+            (notification.callback)(&notification.result);
+        }
+    }
+
+    /// Returns `true` if a committed change has not been refreshed yet.
+    pub fn is_dirty(&self) -> bool {
+        !self.changed.lock().unwrap().committed.is_empty()
+    }
+}
+
+// Collect the tables read by `sql` with an authorizer pass over its
+// compilation. Reads through views are reported against the underlying
+// tables.
+fn read_tables(conn: &Connection, sql: &str) -> Result<HashSet<String>> {
+    unsafe extern "C" fn authorizer(
+        p_arg: *mut c_void,
+        action: c_int,
+        table: *const c_char,
+        _column: *const c_char,
+        _db: *const c_char,
+        _trigger_or_view: *const c_char,
+    ) -> c_int {
+        if action == ffi::SQLITE_READ && !table.is_null() {
+            let tables = &mut *(p_arg as *mut HashSet<String>);
+            let table = CStr::from_ptr(table).to_string_lossy();
+            tables.insert(table.to_ascii_lowercase());
+        }
+        ffi::SQLITE_OK
+    }
+
+    let mut tables = HashSet::new();
+    let db = conn.db.borrow().db();
+    let stmt = unsafe {
+        ffi::sqlite3_set_authorizer(
+            db,
+            Some(authorizer),
+            &mut tables as *mut HashSet<String> as *mut c_void,
+        );
+        let stmt = conn.prepare(sql);
+        ffi::sqlite3_set_authorizer(db, None, ptr::null_mut());
+        stmt
+    }?;
+    if !stmt.stmt.readonly() {
+        return Err(Error::InvalidQuery);
+    }
+    Ok(tables)
+}
@@ -94,6 +94,16 @@ impl Transaction<'_> {
     /// so as to prevent nested or concurrent transactions on the same
     /// connection.
     pub fn new(conn: &mut Connection, behavior: TransactionBehavior) -> Result<Transaction<'_>> {
+        Transaction::new_unchecked(conn, behavior)
+    }
+
+    /// Begin a new transaction on a shared connection, for example one held
+    /// in an `Rc`. The caller must make sure that no other transaction is
+    /// active on `conn`: SQLite then fails to begin this one.
+    pub fn new_unchecked(
+        conn: &Connection,
+        behavior: TransactionBehavior,
+    ) -> Result<Transaction<'_>> {
         let query = match behavior {
             TransactionBehavior::Deferred => "BEGIN DEFERRED",
             TransactionBehavior::Immediate => "BEGIN IMMEDIATE",
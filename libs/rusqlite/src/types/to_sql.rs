@@ -1,6 +1,6 @@
 use super::{Null, Value, ValueRef};
 #[cfg(feature = "array")]
-use crate::vtab::array::Array;
+use crate::vtab::array::ArrayData;
 use crate::Result;
 use std::borrow::Cow;
 
@@ -19,7 +19,7 @@ pub enum ToSqlOutput<'a> {
     ZeroBlob(i32),
 
     #[cfg(feature = "array")]
-    Array(Array),
+    Array(ArrayData),
 }
 
 // Generically allow any type that can be converted into a ValueRef
@@ -122,7 +122,12 @@ where
     }
 }
 
-#[cfg(any(feature = "functions",  feature = "vtab"))]
+#[cfg(any(
+    feature = "functions",
+    feature = "vtab",
+    feature = "preupdate_hook",
+    feature = "session"
+))]
 impl<'a> ValueRef<'a> {
     pub(crate) unsafe fn from_value(value: *mut crate::ffi::sqlite3_value) -> ValueRef<'a> {
         use crate::ffi;
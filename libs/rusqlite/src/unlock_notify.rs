@@ -1,10 +1,157 @@
 //! [Unlock Notification](http://sqlite.org/unlock_notify.html)
+//!
+//! WebAssembly runs on a single thread, so a statement blocked by a
+//! shared-cache lock cannot sleep until the lock is released: the connection
+//! holding it only gets to commit once control returns to JavaScript.
+//! `step` and `prepare` therefore retry at once only if the blocking
+//! transaction has already ended, and otherwise fail with
+//! `SQLITE_LOCKED_SHAREDCACHE`. `Connection::unlock_notify` can then be used
+//! to retry from the JS microtask queue once the lock is gone.
+//!
+//! `step` and `prepare` register their own notification to check whether
+//! the blocking transaction has ended, which replaces the one of
+//! `Connection::unlock_notify`: they register the callback again, to run
+//! when the transaction blocking them ends.
 
+#[cfg(feature = "unlock_notify")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "unlock_notify")]
+use std::collections::HashMap;
 use std::os::raw::c_int;
+#[cfg(feature = "unlock_notify")]
+use std::os::raw::c_void;
+#[cfg(feature = "unlock_notify")]
+use std::ptr;
+#[cfg(feature = "unlock_notify")]
+use std::slice::from_raw_parts;
 
+#[cfg(feature = "unlock_notify")]
+use wasm_bindgen::prelude::*;
 
 use crate::ffi;
+#[cfg(feature = "unlock_notify")]
+use crate::{Connection, Result};
 
+#[cfg(feature = "unlock_notify")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = queueMicrotask)]
+    fn queue_microtask(callback: &JsValue);
+}
+
+/// Callback registered by `Connection::unlock_notify`.
+#[cfg(feature = "unlock_notify")]
+pub(crate) type UnlockNotify = Cell<Option<Box<dyn FnOnce()>>>;
+
+#[cfg(feature = "unlock_notify")]
+thread_local! {
+    // The callbacks registered by `Connection::unlock_notify` and not called
+    // yet, by connection, for `wait_for_unlock_notify` to restore them.
+    static REGISTERED: RefCell<HashMap<*mut ffi::sqlite3, *const UnlockNotify>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Forget the callback registered on `db`, which is being closed.
+#[cfg(feature = "unlock_notify")]
+pub(crate) fn forget(db: *mut ffi::sqlite3) {
+    REGISTERED.with(|registered| registered.borrow_mut().remove(&db));
+}
+
+#[cfg(feature = "unlock_notify")]
+pub fn is_locked(db: *mut ffi::sqlite3, rc: c_int) -> bool {
+    rc == ffi::SQLITE_LOCKED_SHAREDCACHE
+        || (rc & 0xFF) == ffi::SQLITE_LOCKED
+            && unsafe { ffi::sqlite3_extended_errcode(db) } == ffi::SQLITE_LOCKED_SHAREDCACHE
+}
+
+#[cfg(feature = "unlock_notify")]
+unsafe extern "C" fn unlocked(ap_arg: *mut *mut c_void, n_arg: c_int) {
+    for &arg in from_raw_parts(ap_arg, n_arg as usize) {
+        (*(arg as *const Cell<bool>)).set(true);
+    }
+}
+
+/// This function assumes that an SQLite API call (either
+/// `sqlite3_prepare_v2()` or `sqlite3_step()`) has just returned
+/// `SQLITE_LOCKED_SHAREDCACHE` on `db`. It returns `SQLITE_OK` if the
+/// blocking transaction has already concluded and the call can be retried,
+/// `SQLITE_LOCKED` if waiting would deadlock, and
+/// `SQLITE_LOCKED_SHAREDCACHE` otherwise.
+#[cfg(feature = "unlock_notify")]
+pub fn wait_for_unlock_notify(db: *mut ffi::sqlite3) -> c_int {
+    let fired = Cell::new(false);
+    let rc = unsafe {
+        ffi::sqlite3_unlock_notify(
+            db,
+            Some(unlocked),
+            &fired as *const Cell<bool> as *mut c_void,
+        )
+    };
+    debug_assert!(rc == ffi::SQLITE_LOCKED || rc == ffi::SQLITE_OK);
+    if rc != ffi::SQLITE_OK || fired.get() {
+        return rc;
+    }
+    // The blocking connection belongs to this thread and cannot finish while
+    // we wait, so cancel the registration before `fired` goes away, and
+    // restore the one it replaced.
+    unsafe { ffi::sqlite3_unlock_notify(db, None, ptr::null_mut()) };
+    let registered = REGISTERED.with(|registered| registered.borrow().get(&db).cloned());
+    if let Some(arg) = registered {
+        unsafe { ffi::sqlite3_unlock_notify(db, Some(call_unlock_notify), arg as *mut c_void) };
+    }
+    ffi::SQLITE_LOCKED_SHAREDCACHE
+}
+
+// Invoked from inside the `COMMIT` or `ROLLBACK` of the blocking connection,
+// where SQLite must not be re-entered, so the user callbacks are deferred.
+#[cfg(feature = "unlock_notify")]
+unsafe extern "C" fn call_unlock_notify(ap_arg: *mut *mut c_void, n_arg: c_int) {
+    for &arg in from_raw_parts(ap_arg, n_arg as usize) {
+        let arg = arg as *const UnlockNotify;
+        REGISTERED.with(|registered| registered.borrow_mut().retain(|_, &mut a| a != arg));
+        if let Some(f) = (*arg).take() {
+            queue_microtask(&Closure::once_into_js(f));
+        }
+    }
+}
+
+#[cfg(feature = "unlock_notify")]
+impl Connection {
+    /// Register a callback to run once the transaction that made the last
+    /// statement on this connection fail with `SQLITE_LOCKED_SHAREDCACHE`
+    /// has ended, typically to retry that statement.
+    ///
+    /// The callback is queued on the JavaScript microtask queue rather than
+    /// called from inside the blocking connection's `COMMIT`, so it is free to
+    /// use either connection. If nothing is blocking this connection it is
+    /// queued right away. Only one callback can be pending per connection; a
+    /// new registration replaces the previous one, and closing the connection
+    /// drops it. A statement blocked while the callback is pending registers
+    /// it again, for the transaction blocking that statement.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if waiting for the blocking connection would
+    /// deadlock, in which case `f` is dropped.
+    pub fn unlock_notify<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() + 'static,
+    {
+        let mut c = self.db.borrow_mut();
+        c.unlock_notify.set(Some(Box::new(f)));
+        let arg = &*c.unlock_notify as *const UnlockNotify;
+        let db = c.db();
+        // Recorded first: the callback is called at once if nothing blocks.
+        REGISTERED.with(|registered| registered.borrow_mut().insert(db, arg));
+        let rc =
+            unsafe { ffi::sqlite3_unlock_notify(db, Some(call_unlock_notify), arg as *mut c_void) };
+        if rc != ffi::SQLITE_OK {
+            c.unlock_notify.set(None);
+            forget(db);
+        }
+        c.decode_result(rc)
+    }
+}
 
 #[cfg(not(feature = "unlock_notify"))]
 pub fn is_locked(_db: *mut ffi::sqlite3, _rc: c_int) -> bool {
@@ -1,68 +1,163 @@
 //! Array Virtual Table.
 //!
 //! Port of [carray](http://www.sqlite.org/cgi/src/finfo?name=ext/misc/carray.c) C extension.
+//!
+//! Arrays of values, integers, reals, strings or blobs are bound as the
+//! `rarray(?)` table, with a single `value` column. Arrays of tuples are
+//! bound as `rarray2(?)` to `rarray4(?)`, with columns `value1` to `valueN`.
 use std::default::Default;
 use std::os::raw::{c_char, c_int, c_void};
 use std::rc::Rc;
 
 use crate::ffi;
-use crate::types::{ToSql, ToSqlOutput, Value};
+use crate::types::{ToSql, ToSqlOutput, Value, ValueRef};
 use crate::vtab::{
     eponymous_only_module, Context, IndexConstraintOp, IndexInfo, Module, VTab, VTabConnection,
     VTabCursor, Values,
 };
-use crate::{Connection, Result};
+use crate::{Connection, Error, Result};
 
 // http://sqlite.org/bindptr.html
 
 pub(crate) const ARRAY_TYPE: *const c_char = b"rarray\0" as *const u8 as *const c_char;
 
 pub(crate) unsafe extern "C" fn free_array(p: *mut c_void) {
-    let _: Array = Rc::from_raw(p as *const Vec<Value>);
+    let _: Box<ArrayData> = Box::from_raw(p as *mut ArrayData);
 }
 
 pub type Array = Rc<Vec<Value>>;
 
-impl ToSql for Array {
+/// An array bound to `rarray(?)`, with elements kept in their Rust type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArrayData {
+    Values(Array),
+    Integers(Rc<Vec<i64>>),
+    Reals(Rc<Vec<f64>>),
+    Texts(Rc<Vec<String>>),
+    Blobs(Rc<Vec<Vec<u8>>>),
+    /// Rows of up to four values, for `rarray2(?)` to `rarray4(?)`.
+    Tuples(Rc<Vec<Vec<Value>>>),
+}
+
+impl ArrayData {
+    pub fn len(&self) -> usize {
+        match *self {
+            ArrayData::Values(ref v) => v.len(),
+            ArrayData::Integers(ref v) => v.len(),
+            ArrayData::Reals(ref v) => v.len(),
+            ArrayData::Texts(ref v) => v.len(),
+            ArrayData::Blobs(ref v) => v.len(),
+            ArrayData::Tuples(ref v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Column `col` of element `row`; missing columns are NULL.
+    fn value(&self, row: usize, col: usize) -> ValueRef<'_> {
+        match *self {
+            ArrayData::Values(ref v) if col == 0 => ValueRef::from(&v[row]),
+            ArrayData::Integers(ref v) if col == 0 => ValueRef::Integer(v[row]),
+            ArrayData::Reals(ref v) if col == 0 => ValueRef::Real(v[row]),
+            ArrayData::Texts(ref v) if col == 0 => ValueRef::from(v[row].as_str()),
+            ArrayData::Blobs(ref v) if col == 0 => ValueRef::Blob(&v[row]),
+            ArrayData::Tuples(ref v) => v[row].get(col).map_or(ValueRef::Null, ValueRef::from),
+            _ => ValueRef::Null,
+        }
+    }
+}
+
+impl ToSql for ArrayData {
     fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
         Ok(ToSqlOutput::Array(self.clone()))
     }
 }
 
-/// Register the "rarray" module.
+macro_rules! array_to_sql(
+    ($t:ty, $variant:ident) => (
+        impl ToSql for Rc<Vec<$t>> {
+            fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+                Ok(ToSqlOutput::Array(ArrayData::$variant(self.clone())))
+            }
+        }
+    )
+);
+array_to_sql!(Value, Values);
+array_to_sql!(i64, Integers);
+array_to_sql!(f64, Reals);
+array_to_sql!(String, Texts);
+array_to_sql!(Vec<u8>, Blobs);
+array_to_sql!(Vec<Value>, Tuples);
+
+/// Register the "rarray" module, and "rarray2" to "rarray4" for tuples.
 pub fn load_module(conn: &Connection) -> Result<()> {
-    let aux: Option<()> = None;
-    conn.create_module("rarray", &ARRAY_MODULE, aux)
+    conn.create_module("rarray", &ARRAY_MODULE, Some(1))?;
+    for n in 2..=MAX_COLUMNS {
+        conn.create_module(&format!("rarray{}", n), &ARRAY_MODULE, Some(n))?;
+    }
+    Ok(())
 }
 
 lazy_static::lazy_static! {
     static ref ARRAY_MODULE: Module<ArrayTab> = eponymous_only_module::<ArrayTab>(1);
 }
 
-// Column numbers
-// const CARRAY_COLUMN_VALUE : c_int = 0;
-const CARRAY_COLUMN_POINTER: c_int = 1;
+/// Number of columns of the widest tuple table, `rarray4`.
+pub const MAX_COLUMNS: usize = 4;
+
+// Arrays bound to `rarrayN(?)` must have rows of exactly `columns` values.
+fn check_width(array: &ArrayData, columns: usize) -> Result<()> {
+    let width = match *array {
+        ArrayData::Tuples(ref v) => match v.iter().find(|t| t.len() != columns) {
+            Some(t) => t.len(),
+            None => return Ok(()),
+        },
+        _ if columns == 1 => return Ok(()),
+        _ => 1,
+    };
+    let name = if columns == 1 {
+        "rarray".to_owned()
+    } else {
+        format!("rarray{}", columns)
+    };
+    Err(Error::ModuleError(format!(
+        "{} takes rows of {} values, got {}",
+        name, columns, width
+    )))
+}
 
 /// An instance of the Array virtual table
 #[repr(C)]
 struct ArrayTab {
     /// Base class. Must be first
     base: ffi::sqlite3_vtab,
+    /// Number of value columns, followed by the hidden "pointer" column
+    columns: usize,
 }
 
 impl VTab for ArrayTab {
-    type Aux = ();
+    type Aux = usize;
     type Cursor = ArrayTabCursor;
 
     fn connect(
         _: &mut VTabConnection,
-        _aux: Option<&()>,
+        aux: Option<&usize>,
         _args: &[&[u8]],
     ) -> Result<(String, ArrayTab)> {
+        let columns = aux.cloned().unwrap_or(1);
         let vtab = ArrayTab {
             base: ffi::sqlite3_vtab::default(),
+            columns,
+        };
+        let sql = if columns == 1 {
+            "CREATE TABLE x(value,pointer hidden)".to_owned()
+        } else {
+            let values: Vec<String> = (1..=columns).map(|i| format!("value{}", i)).collect();
+            format!("CREATE TABLE x({},pointer hidden)", values.join(","))
         };
-        Ok(("CREATE TABLE x(value,pointer hidden)".to_owned(), vtab))
+        Ok((sql, vtab))
     }
 
     fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
@@ -75,7 +170,7 @@ impl VTab for ArrayTab {
             if constraint.operator() != IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ {
                 continue;
             }
-            if let CARRAY_COLUMN_POINTER = constraint.column() {
+            if constraint.column() == self.columns as c_int {
                 ptr_idx = Some(i);
             }
         }
@@ -109,7 +204,7 @@ struct ArrayTabCursor {
     /// The rowid
     row_id: i64,
     /// Pointer to the array of values ("pointer")
-    ptr: Option<Array>,
+    ptr: Option<ArrayData>,
 }
 
 impl ArrayTabCursor {
@@ -127,11 +222,20 @@ impl ArrayTabCursor {
             _ => 0,
         }
     }
+
+    /// Accessor to the associated virtual table.
+    fn vtab(&self) -> &ArrayTab {
+        unsafe { &*(self.base.pVtab as *const ArrayTab) }
+    }
 }
 impl VTabCursor for ArrayTabCursor {
     fn filter(&mut self, idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
         if idx_num > 0 {
-            self.ptr = args.get_array(0)?;
+            let ptr = args.get_array(0)?;
+            if let Some(ref array) = ptr {
+                check_width(array, self.vtab().columns)?;
+            }
+            self.ptr = ptr;
         } else {
             self.ptr = None;
         }
@@ -149,16 +253,14 @@ impl VTabCursor for ArrayTabCursor {
     }
 
     fn column(&self, ctx: &mut Context, i: c_int) -> Result<()> {
-        match i {
-            CARRAY_COLUMN_POINTER => Ok(()),
-            _ => {
-                if let Some(ref array) = self.ptr {
-                    let value = &array[(self.row_id - 1) as usize];
-                    ctx.set_result(&value)
-                } else {
-                    Ok(())
-                }
-            }
+        if i as usize >= self.vtab().columns {
+            // The "pointer" column
+            return Ok(());
+        }
+        if let Some(ref array) = self.ptr {
+            ctx.set_result(&array.value((self.row_id - 1) as usize, i as usize))
+        } else {
+            Ok(())
         }
     }
 
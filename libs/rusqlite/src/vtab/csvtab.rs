@@ -1,34 +1,145 @@
 //! CSV Virtual Table.
 //!
 //! Port of [csv](http://www.sqlite.org/cgi/src/finfo?name=ext/misc/csv.c) C extension.
+//!
+//! Besides files, the content can be given inline with `data=` or read from
+//! a [`Buffers`](struct.Buffers.html) entry with `buffer=`, which works
+//! without a file system, as in the browser.
 use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::os::raw::c_int;
 use std::path::Path;
+use std::rc::Rc;
 use std::result;
-use std::str;
 
 use crate::ffi;
 use crate::types::Null;
 use crate::vtab::{
-    dequote, escape_double_quote, parse_boolean, read_only_module, Context, CreateVTab, IndexInfo,
-    Module, VTab, VTabConnection, VTabCursor, Values,
+    escape_double_quote, parameter, parse_boolean, read_only_module, Context, CreateVTab,
+    IndexInfo, Module, VTab, VTabConnection, VTabCursor, Values,
 };
 use crate::{Connection, Error, Result};
 
+pub use super::Buffers;
+
 /// Register the "csv" module.
 /// ```sql
 /// CREATE VIRTUAL TABLE vtab USING csv(
 ///   filename=FILENAME -- Name of file containing CSV content
+///   | data=TEXT -- CSV content
+///   | buffer=NAME -- Name of a buffer registered in `Buffers`
 ///   [, schema=SCHEMA] -- Alternative CSV schema. 'CREATE TABLE x(col1 TEXT NOT NULL, col2 INT, ...);'
 ///   [, header=YES|NO] -- First row of CSV defines the names of columns if "yes". Default "no".
 ///   [, columns=N] -- Assume the CSV file contains N columns.
 ///   [, delimiter=C] -- CSV delimiter. Default ','.
 ///   [, quote=C] -- CSV quote. Default '"'. 0 means no quote.
+///   [, infer=YES|NO] -- Type columns INTEGER or REAL when all their values are. Default "no".
 /// );
 /// ```
+///
+/// With `infer=yes`, the content is scanned when the table is connected and
+/// non-empty values of INTEGER and REAL columns are returned as numbers,
+/// empty ones as NULL.
 pub fn load_module(conn: &Connection) -> Result<()> {
-    let aux: Option<()> = None;
-    conn.create_module("csv", &CSV_MODULE, aux)
+    load_module_with_buffers(conn, &Buffers::new())
+}
+
+/// Register the "csv" module, reading `buffer=NAME` content from `buffers`.
+pub fn load_module_with_buffers(conn: &Connection, buffers: &Buffers) -> Result<()> {
+    conn.create_module("csv", &CSV_MODULE, Some(buffers.clone()))
+}
+
+/// Create the table `table` with the rows of a "csv" virtual table built
+/// from `args`, the module parameters, for bulk import. The module must be
+/// loaded.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result};
+/// # use rusqlite::vtab::csvtab::{self, Buffers};
+/// fn import(conn: &Connection, csv: Vec<u8>) -> Result<()> {
+///     let buffers = Buffers::new();
+///     csvtab::load_module_with_buffers(conn, &buffers)?;
+///     buffers.insert("upload", csv);
+///     csvtab::create_table_as(conn, "people", &["buffer=upload", "header=yes", "infer=yes"])
+/// }
+/// ```
+pub fn create_table_as(conn: &Connection, table: &str, args: &[&str]) -> Result<()> {
+    super::create_table_as(conn, "csv", table, args)
+}
+
+enum Source {
+    File(String),
+    Buffer(Rc<[u8]>),
+}
+
+/// What the CSV reader reads from.
+enum Input {
+    File(File),
+    Buffer(io::Cursor<Rc<[u8]>>),
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Input::File(ref mut f) => f.read(buf),
+            Input::Buffer(ref mut b) => b.read(buf),
+        }
+    }
+}
+
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            Input::File(ref mut f) => f.seek(pos),
+            Input::Buffer(ref mut b) => b.seek(pos),
+        }
+    }
+}
+
+/// Column types, from the narrowest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Affinity {
+    Integer,
+    Real,
+    Text,
+}
+
+impl Affinity {
+    fn of(value: &str) -> Affinity {
+        if value.parse::<i64>().is_ok() {
+            Affinity::Integer
+        } else if value.parse::<f64>().map_or(false, f64::is_finite) {
+            Affinity::Real
+        } else {
+            Affinity::Text
+        }
+    }
+
+    fn declared_type(self) -> &'static str {
+        match self {
+            Affinity::Integer => "INTEGER",
+            Affinity::Real => "REAL",
+            Affinity::Text => "TEXT",
+        }
+    }
+}
+
+// The narrowest affinity of each of `n` columns fitting all the rows of
+// `reader`. Empty values fit any.
+fn infer_affinities(reader: &mut csv::Reader<Input>, n: usize) -> Result<Vec<Affinity>> {
+    let mut affinities = vec![None; n];
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        for (affinity, value) in affinities.iter_mut().zip(record.iter()) {
+            if !value.is_empty() && *affinity != Some(Affinity::Text) {
+                *affinity = Some(Affinity::of(value).max(affinity.unwrap_or(Affinity::Integer)));
+            }
+        }
+    }
+    Ok(affinities
+        .into_iter()
+        .map(|a| a.unwrap_or(Affinity::Text))
+        .collect())
 }
 
 lazy_static::lazy_static! {
@@ -40,35 +151,28 @@ lazy_static::lazy_static! {
 struct CSVTab {
     /// Base class. Must be first
     base: ffi::sqlite3_vtab,
-    /// Name of the CSV file
-    filename: String,
+    /// Where the CSV content is read from
+    source: Source,
     has_headers: bool,
     delimiter: u8,
     quote: u8,
     /// Offset to start of data
     offset_first_row: csv::Position,
+    /// Inferred column types, empty unless `infer=yes`
+    affinities: Vec<Affinity>,
 }
 
 impl CSVTab {
-    fn reader(&self) -> result::Result<csv::Reader<File>, csv::Error> {
-        csv::ReaderBuilder::new()
+    fn reader(&self) -> result::Result<csv::Reader<Input>, csv::Error> {
+        let input = match self.source {
+            Source::File(ref filename) => Input::File(File::open(filename)?),
+            Source::Buffer(ref data) => Input::Buffer(io::Cursor::new(data.clone())),
+        };
+        Ok(csv::ReaderBuilder::new()
             .has_headers(self.has_headers)
             .delimiter(self.delimiter)
             .quote(self.quote)
-            .from_path(&self.filename)
-    }
-
-    fn parameter(c_slice: &[u8]) -> Result<(&str, &str)> {
-        let arg = str::from_utf8(c_slice)?.trim();
-        let mut split = arg.split('=');
-        if let Some(key) = split.next() {
-            if let Some(value) = split.next() {
-                let param = key.trim();
-                let value = dequote(value);
-                return Ok((param, value));
-            }
-        }
-        Err(Error::ModuleError(format!("illegal argument: '{}'", arg)))
+            .from_reader(input))
     }
 
     fn parse_byte(arg: &str) -> Option<u8> {
@@ -81,44 +185,58 @@ impl CSVTab {
 }
 
 impl VTab for CSVTab {
-    type Aux = ();
+    type Aux = Buffers;
     type Cursor = CSVTabCursor;
 
     fn connect(
         _: &mut VTabConnection,
-        _aux: Option<&()>,
+        aux: Option<&Buffers>,
         args: &[&[u8]],
     ) -> Result<(String, CSVTab)> {
         if args.len() < 4 {
             return Err(Error::ModuleError("no CSV file specified".to_owned()));
         }
 
-        let mut vtab = CSVTab {
-            base: ffi::sqlite3_vtab::default(),
-            filename: "".to_owned(),
-            has_headers: false,
-            delimiter: b',',
-            quote: b'"',
-            offset_first_row: csv::Position::new(),
-        };
+        let mut source = None;
+        let mut has_headers = false;
+        let mut delimiter = b',';
+        let mut quote = b'"';
+        let mut infer = false;
         let mut schema = None;
         let mut n_col = None;
 
         let args = &args[3..];
         for c_slice in args {
-            let (param, value) = CSVTab::parameter(c_slice)?;
+            let (param, value) = parameter(c_slice)?;
+            if source.is_some() && (param == "filename" || param == "data" || param == "buffer") {
+                return Err(Error::ModuleError(
+                    "more than one of 'filename', 'data' and 'buffer'".to_owned(),
+                ));
+            }
             match param {
                 "filename" => {
-                    if !Path::new(value).exists() {
+                    if !Path::new(&*value).exists() {
                         return Err(Error::ModuleError(format!(
                             "file '{}' does not exist",
                             value
                         )));
                     }
-                    vtab.filename = value.to_owned();
+                    source = Some(Source::File(value.into_owned()));
+                }
+                "data" => {
+                    source = Some(Source::Buffer(Rc::from(value.as_bytes())));
                 }
+                "buffer" => match aux.and_then(|buffers| buffers.get(&value)) {
+                    Some(data) => source = Some(Source::Buffer(data)),
+                    None => {
+                        return Err(Error::ModuleError(format!(
+                            "buffer '{}' does not exist",
+                            value
+                        )));
+                    }
+                },
                 "schema" => {
-                    schema = Some(value.to_owned());
+                    schema = Some(value.into_owned());
                 }
                 "columns" => {
                     if let Ok(n) = value.parse::<u16>() {
@@ -140,8 +258,8 @@ impl VTab for CSVTab {
                     }
                 }
                 "header" => {
-                    if let Some(b) = parse_boolean(value) {
-                        vtab.has_headers = b;
+                    if let Some(b) = parse_boolean(&value) {
+                        has_headers = b;
                     } else {
                         return Err(Error::ModuleError(format!(
                             "unrecognized argument to 'header': {}",
@@ -150,8 +268,8 @@ impl VTab for CSVTab {
                     }
                 }
                 "delimiter" => {
-                    if let Some(b) = CSVTab::parse_byte(value) {
-                        vtab.delimiter = b;
+                    if let Some(b) = CSVTab::parse_byte(&value) {
+                        delimiter = b;
                     } else {
                         return Err(Error::ModuleError(format!(
                             "unrecognized argument to 'delimiter': {}",
@@ -160,11 +278,11 @@ impl VTab for CSVTab {
                     }
                 }
                 "quote" => {
-                    if let Some(b) = CSVTab::parse_byte(value) {
+                    if let Some(b) = CSVTab::parse_byte(&value) {
                         if b == b'0' {
-                            vtab.quote = 0;
+                            quote = 0;
                         } else {
-                            vtab.quote = b;
+                            quote = b;
                         }
                     } else {
                         return Err(Error::ModuleError(format!(
@@ -173,6 +291,16 @@ impl VTab for CSVTab {
                         )));
                     }
                 }
+                "infer" => {
+                    if let Some(b) = parse_boolean(&value) {
+                        infer = b;
+                    } else {
+                        return Err(Error::ModuleError(format!(
+                            "unrecognized argument to 'infer': {}",
+                            value
+                        )));
+                    }
+                }
                 _ => {
                     return Err(Error::ModuleError(format!(
                         "unrecognized parameter '{}'",
@@ -182,9 +310,24 @@ impl VTab for CSVTab {
             }
         }
 
-        if vtab.filename.is_empty() {
-            return Err(Error::ModuleError("no CSV file specified".to_owned()));
+        let source = match source {
+            Some(source) => source,
+            None => return Err(Error::ModuleError("no CSV file specified".to_owned())),
+        };
+        if infer && schema.is_some() {
+            return Err(Error::ModuleError(
+                "'infer' cannot be used with 'schema'".to_owned(),
+            ));
         }
+        let mut vtab = CSVTab {
+            base: ffi::sqlite3_vtab::default(),
+            source,
+            has_headers,
+            delimiter,
+            quote,
+            offset_first_row: csv::Position::new(),
+            affinities: Vec::new(),
+        };
 
         let mut cols: Vec<String> = Vec::new();
         if vtab.has_headers || (n_col.is_none() && schema.is_none()) {
@@ -219,12 +362,17 @@ impl VTab for CSVTab {
             return Err(Error::ModuleError("no column specified".to_owned()));
         }
 
+        if infer {
+            vtab.affinities = infer_affinities(&mut vtab.reader()?, cols.len())?;
+        }
+
         if schema.is_none() {
             let mut sql = String::from("CREATE TABLE x(");
             for (i, col) in cols.iter().enumerate() {
                 sql.push('"');
                 sql.push_str(col);
-                sql.push_str("\" TEXT");
+                sql.push_str("\" ");
+                sql.push_str(vtab.affinities.get(i).map_or("TEXT", |a| a.declared_type()));
                 if i == cols.len() - 1 {
                     sql.push_str(");");
                 } else {
@@ -256,7 +404,7 @@ struct CSVTabCursor {
     /// Base class. Must be first
     base: ffi::sqlite3_vtab_cursor,
     /// The CSV reader object
-    reader: csv::Reader<File>,
+    reader: csv::Reader<Input>,
     /// Current cursor position used as rowid
     row_number: usize,
     /// Values of the current row
@@ -265,7 +413,7 @@ struct CSVTabCursor {
 }
 
 impl CSVTabCursor {
-    fn new(reader: csv::Reader<File>) -> CSVTabCursor {
+    fn new(reader: csv::Reader<Input>) -> CSVTabCursor {
         CSVTabCursor {
             base: ffi::sqlite3_vtab_cursor::default(),
             reader,
@@ -326,8 +474,23 @@ impl VTabCursor for CSVTabCursor {
         if self.cols.is_empty() {
             return ctx.set_result(&Null);
         }
-        // TODO Affinity
-        ctx.set_result(&self.cols[col as usize].to_owned())
+        let value = &self.cols[col as usize];
+        match self.vtab().affinities.get(col as usize) {
+            Some(&Affinity::Integer) | Some(&Affinity::Real) if value.is_empty() => {
+                ctx.set_result(&Null)
+            }
+            // The content may have changed since it was scanned, in which
+            // case values are returned as text.
+            Some(&Affinity::Integer) => match value.parse::<i64>() {
+                Ok(i) => ctx.set_result(&i),
+                Err(_) => ctx.set_result(&value.to_owned()),
+            },
+            Some(&Affinity::Real) => match value.parse::<f64>() {
+                Ok(f) => ctx.set_result(&f),
+                Err(_) => ctx.set_result(&value.to_owned()),
+            },
+            _ => ctx.set_result(&value.to_owned()),
+        }
     }
 
     fn rowid(&self) -> Result<i64> {
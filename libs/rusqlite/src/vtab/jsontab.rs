@@ -0,0 +1,304 @@
+//! JSON Virtual Table.
+//!
+//! Exposes a JSON array, or [JSON Lines](https://jsonlines.org) content, as
+//! one row per element or line, with columns read at JSON paths.
+use std::os::raw::c_int;
+use std::rc::Rc;
+
+use serde_json::Value as JsonValue;
+
+use crate::ffi;
+use crate::types::Null;
+use crate::vtab::{
+    escape_double_quote, parameter, read_only_module, Buffers, Context, CreateVTab, IndexInfo,
+    Module, VTab, VTabConnection, VTabCursor, Values,
+};
+use crate::{Connection, Error, Result};
+
+/// Register the "json" module.
+/// ```sql
+/// CREATE VIRTUAL TABLE vtab USING json(
+///   data=TEXT -- JSON content
+///   | buffer=NAME -- Name of a buffer registered in `Buffers`
+///   [, format=ARRAY|LINES] -- An array of rows, or one row per line. Default: "array" if the content starts with '['.
+///   [, NAME=PATH]... -- Column NAME holds the value at PATH, as '$.user.name' or '$.tags[0]'. Default: the members of the first row, in order.
+/// );
+/// ```
+///
+/// Numbers, strings and nulls are returned as such, booleans as 1 and 0,
+/// and objects and arrays as JSON text. Missing values are NULL.
+pub fn load_module(conn: &Connection) -> Result<()> {
+    load_module_with_buffers(conn, &Buffers::new())
+}
+
+/// Register the "json" module, reading `buffer=NAME` content from `buffers`.
+pub fn load_module_with_buffers(conn: &Connection, buffers: &Buffers) -> Result<()> {
+    conn.create_module("json", &JSON_MODULE, Some(buffers.clone()))
+}
+
+/// Create the table `table` with the rows of a "json" virtual table built
+/// from `args`, the module parameters, for bulk import. The module must be
+/// loaded.
+pub fn create_table_as(conn: &Connection, table: &str, args: &[&str]) -> Result<()> {
+    super::create_table_as(conn, "json", table, args)
+}
+
+lazy_static::lazy_static! {
+    static ref JSON_MODULE: Module<JSONTab> = read_only_module::<JSONTab>(1);
+}
+
+/// A step of a JSON path.
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+// Parse a path like `$.a.b[0]["c d"]`.
+fn parse_path(path: &str) -> Option<Vec<Step>> {
+    if !path.starts_with('$') {
+        return None;
+    }
+    let mut rest = &path[1..];
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if rest.starts_with('.') {
+            let end = rest[1..]
+                .find(|c| c == '.' || c == '[')
+                .map_or(rest.len(), |i| i + 1);
+            if end == 1 {
+                return None;
+            }
+            steps.push(Step::Key(rest[1..end].to_owned()));
+            rest = &rest[end..];
+        } else if rest.starts_with("[\"") {
+            let end = rest[2..].find("\"]")? + 2;
+            steps.push(Step::Key(rest[2..end].to_owned()));
+            rest = &rest[end + 2..];
+        } else if rest.starts_with('[') {
+            let end = rest.find(']')?;
+            steps.push(Step::Index(rest[1..end].parse().ok()?));
+            rest = &rest[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(steps)
+}
+
+fn lookup<'a>(value: &'a JsonValue, path: &[Step]) -> Option<&'a JsonValue> {
+    path.iter().try_fold(value, |value, step| match *step {
+        Step::Key(ref key) => value.get(key.as_str()),
+        Step::Index(i) => value.get(i),
+    })
+}
+
+fn parse_rows(content: &[u8], lines: bool) -> Result<Vec<JsonValue>> {
+    if lines {
+        content
+            .split(|&b| b == b'\n')
+            .enumerate()
+            .filter(|&(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+            .map(|(i, line)| {
+                serde_json::from_slice(line).map_err(|err| {
+                    Error::ModuleError(format!("invalid JSON at line {}: {}", i + 1, err))
+                })
+            })
+            .collect()
+    } else {
+        match serde_json::from_slice(content) {
+            Ok(JsonValue::Array(rows)) => Ok(rows),
+            Ok(_) => Err(Error::ModuleError(
+                "JSON content is not an array".to_owned(),
+            )),
+            Err(err) => Err(Error::ModuleError(format!("invalid JSON: {}", err))),
+        }
+    }
+}
+
+/// An instance of the JSON virtual table
+#[repr(C)]
+struct JSONTab {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab,
+    rows: Rc<Vec<JsonValue>>,
+    /// Path of each column
+    paths: Vec<Vec<Step>>,
+}
+
+impl VTab for JSONTab {
+    type Aux = Buffers;
+    type Cursor = JSONTabCursor;
+
+    fn connect(
+        _: &mut VTabConnection,
+        aux: Option<&Buffers>,
+        args: &[&[u8]],
+    ) -> Result<(String, JSONTab)> {
+        let mut content = None;
+        let mut lines = None;
+        let mut columns = Vec::new();
+
+        for c_slice in &args[3..] {
+            let (param, value) = parameter(c_slice)?;
+            match param {
+                "data" | "buffer" if content.is_some() => {
+                    return Err(Error::ModuleError(
+                        "more than one of 'data' and 'buffer'".to_owned(),
+                    ));
+                }
+                "data" => content = Some(Rc::from(value.as_bytes())),
+                "buffer" => match aux.and_then(|buffers| buffers.get(&value)) {
+                    Some(data) => content = Some(data),
+                    None => {
+                        return Err(Error::ModuleError(format!(
+                            "buffer '{}' does not exist",
+                            value
+                        )));
+                    }
+                },
+                "format" => {
+                    if value.eq_ignore_ascii_case("array") {
+                        lines = Some(false);
+                    } else if value.eq_ignore_ascii_case("lines") {
+                        lines = Some(true);
+                    } else {
+                        return Err(Error::ModuleError(format!(
+                            "unrecognized argument to 'format': {}",
+                            value
+                        )));
+                    }
+                }
+                _ if value.starts_with('$') => match parse_path(&value) {
+                    Some(path) => columns.push((param.to_owned(), path)),
+                    None => {
+                        return Err(Error::ModuleError(format!(
+                            "invalid path for column '{}': {}",
+                            param, value
+                        )));
+                    }
+                },
+                _ => {
+                    return Err(Error::ModuleError(format!(
+                        "unrecognized parameter '{}'",
+                        param
+                    )));
+                }
+            }
+        }
+
+        let content: Rc<[u8]> = match content {
+            Some(content) => content,
+            None => return Err(Error::ModuleError("no JSON content specified".to_owned())),
+        };
+        let lines = lines.unwrap_or_else(|| {
+            content
+                .iter()
+                .find(|b| !b.is_ascii_whitespace())
+                .map_or(false, |&b| b != b'[')
+        });
+        let rows = parse_rows(&content, lines)?;
+
+        if columns.is_empty() {
+            columns = match rows.first() {
+                Some(JsonValue::Object(members)) => members
+                    .keys()
+                    .map(|key| (key.clone(), vec![Step::Key(key.clone())]))
+                    .collect(),
+                // The whole row.
+                Some(_) => vec![("value".to_owned(), vec![])],
+                None => return Err(Error::ModuleError("no column specified".to_owned())),
+            };
+        }
+
+        let names: Vec<String> = columns
+            .iter()
+            .map(|&(ref name, _)| format!("\"{}\"", escape_double_quote(name)))
+            .collect();
+        let vtab = JSONTab {
+            base: ffi::sqlite3_vtab::default(),
+            rows: Rc::new(rows),
+            paths: columns.into_iter().map(|(_, path)| path).collect(),
+        };
+        Ok((format!("CREATE TABLE x({});", names.join(", ")), vtab))
+    }
+
+    // Only a full table scan is supported.
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        info.set_estimated_cost(self.rows.len() as f64);
+        Ok(())
+    }
+
+    fn open(&self) -> Result<JSONTabCursor> {
+        Ok(JSONTabCursor {
+            base: ffi::sqlite3_vtab_cursor::default(),
+            row: 0,
+        })
+    }
+}
+
+impl CreateVTab for JSONTab {}
+
+/// A cursor for the JSON virtual table
+#[repr(C)]
+struct JSONTabCursor {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab_cursor,
+    /// Index of the current row
+    row: usize,
+}
+
+impl JSONTabCursor {
+    /// Accessor to the associated virtual table.
+    fn vtab(&self) -> &JSONTab {
+        unsafe { &*(self.base.pVtab as *const JSONTab) }
+    }
+}
+
+impl VTabCursor for JSONTabCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _args: &Values<'_>,
+    ) -> Result<()> {
+        self.row = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.row += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.row >= self.vtab().rows.len()
+    }
+
+    fn column(&self, ctx: &mut Context, col: c_int) -> Result<()> {
+        let vtab = self.vtab();
+        let path = match vtab.paths.get(col as usize) {
+            Some(path) => path,
+            None => {
+                return Err(Error::ModuleError(format!(
+                    "column index out of bounds: {}",
+                    col
+                )))
+            }
+        };
+        match lookup(&vtab.rows[self.row], path) {
+            None | Some(JsonValue::Null) => ctx.set_result(&Null),
+            Some(JsonValue::Bool(b)) => ctx.set_result(b),
+            Some(JsonValue::Number(n)) => match n.as_i64() {
+                Some(i) => ctx.set_result(&i),
+                None => ctx.set_result(&n.as_f64()),
+            },
+            Some(JsonValue::String(s)) => ctx.set_result(s),
+            Some(value) => ctx.set_result(value),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.row as i64 + 1)
+    }
+}
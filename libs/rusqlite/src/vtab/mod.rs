@@ -10,10 +10,16 @@
 //!
 //! (See [SQLite doc](http://sqlite.org/vtab.html))
 use std::borrow::Cow::{self, Borrowed, Owned};
+#[cfg(any(feature = "csvtab", feature = "jsontab"))]
+use std::cell::RefCell;
+#[cfg(any(feature = "csvtab", feature = "jsontab"))]
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::marker::Sync;
 use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
+#[cfg(any(feature = "csvtab", feature = "jsontab"))]
+use std::rc::Rc;
 use std::slice;
 
 use crate::context::set_result;
@@ -154,6 +160,37 @@ pub fn eponymous_only_module<T: VTab>(version: c_int) -> Module<T> {
     }
 }
 
+/// Create a writable virtual table implementation.
+///
+/// Step 2 of [Creating New Virtual Table Implementations](https://sqlite.org/vtab.html#creating_new_virtual_table_implementations).
+pub fn update_module<T: UpdateVTab>(version: c_int) -> Module<T> {
+    let mut module = read_only_module::<T>(version);
+    module.base.xUpdate = Some(rust_update::<T>);
+    module.base.xFindFunction = Some(rust_find_function::<T>);
+    module.base.xRename = Some(rust_rename::<T>);
+    module
+}
+
+/// Create a writable virtual table implementation that takes part in
+/// transactions. `xSavepoint`, `xRelease` and `xRollbackTo` are only called
+/// when `version` is 2 or more.
+///
+/// Step 2 of [Creating New Virtual Table Implementations](https://sqlite.org/vtab.html#creating_new_virtual_table_implementations).
+pub fn transactional_update_module<T>(version: c_int) -> Module<T>
+where
+    T: UpdateVTab + TransactionVTab,
+{
+    let mut module = update_module::<T>(version);
+    module.base.xBegin = Some(rust_begin::<T>);
+    module.base.xSync = Some(rust_sync::<T>);
+    module.base.xCommit = Some(rust_commit::<T>);
+    module.base.xRollback = Some(rust_rollback::<T>);
+    module.base.xSavepoint = Some(rust_savepoint::<T>);
+    module.base.xRelease = Some(rust_release::<T>);
+    module.base.xRollbackTo = Some(rust_rollback_to::<T>);
+    module
+}
+
 pub struct VTabConnection(*mut ffi::sqlite3);
 
 impl VTabConnection {
@@ -243,6 +280,85 @@ pub trait CreateVTab: VTab {
     }
 }
 
+/// A function overloaded by a virtual table (see `UpdateVTab::find_function`).
+/// The arguments are the arguments of the SQL function call.
+pub type VTabFunction = fn(&mut Context, &Values<'_>) -> Result<()>;
+
+/// Writable virtual table instance trait.
+///
+/// (See [SQLite doc](https://sqlite.org/vtab.html#xupdate))
+pub trait UpdateVTab: CreateVTab {
+    /// Delete the row whose rowid is `arg`.
+    fn delete(&mut self, arg: ValueRef<'_>) -> Result<()>;
+    /// Insert a new row and return its rowid.
+    ///
+    /// `args[0]` is `NULL`, `args[1]` is the rowid requested by the
+    /// statement, or `NULL` if the table should choose one, and `args[2..]`
+    /// are the column values.
+    fn insert(&mut self, args: &Values<'_>) -> Result<i64>;
+    /// Update an existing row.
+    ///
+    /// `args[0]` is the rowid of the row to update and `args[1]` its new
+    /// rowid, which differs when the statement changes the rowid;
+    /// `args[2..]` are the new column values.
+    fn update(&mut self, args: &Values<'_>) -> Result<()>;
+
+    /// Notify the table that it is renamed to `new_name` by `ALTER TABLE`.
+    /// Returning an error aborts the rename.
+    ///
+    /// Do nothing by default.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xrename_method))
+    fn rename(&mut self, _new_name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Return an implementation of the SQL function `name` with `n_arg`
+    /// arguments to use instead of the global one when its first argument
+    /// is a column of this table.
+    ///
+    /// Return `None` by default.
+    /// (See [SQLite doc](https://sqlite.org/vtab.html#the_xfindfunction_method))
+    fn find_function(&self, _n_arg: c_int, _name: &str) -> Option<VTabFunction> {
+        None
+    }
+}
+
+/// Virtual table transaction trait, for tables created with
+/// `transactional_update_module`. Every method does nothing by default.
+///
+/// (See [SQLite doc](https://sqlite.org/vtab.html#the_xbegin_method))
+pub trait TransactionVTab: VTab {
+    /// Begin a transaction.
+    fn begin(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Start the first phase of a two-phase commit. Returning an error
+    /// rolls back the transaction.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Commit the transaction.
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Roll back the transaction.
+    fn rollback(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Save the current state as savepoint `n`.
+    fn savepoint(&mut self, _n: c_int) -> Result<()> {
+        Ok(())
+    }
+    /// Release savepoint `n` and every later one.
+    fn release(&mut self, _n: c_int) -> Result<()> {
+        Ok(())
+    }
+    /// Restore the state saved by savepoint `n`.
+    fn rollback_to(&mut self, _n: c_int) -> Result<()> {
+        Ok(())
+    }
+}
+
 ///Index constraint operator.
 #[derive(Debug, PartialEq)]
 #[allow(non_snake_case, non_camel_case_types)]
@@ -354,7 +470,21 @@ impl IndexInfo {
     // TODO idxFlags
     // TODO colUsed
 
-    // TODO sqlite3_vtab_collation (http://sqlite.org/c3ref/vtab_collation.html)
+    /// The name of the collation of the constraint `constraint_idx`, as
+    /// `BINARY` or `NOCASE`.
+    ///
+    /// (See [SQLite doc](http://sqlite.org/c3ref/vtab_collation.html))
+    pub fn collation(&self, constraint_idx: usize) -> Result<&str> {
+        use std::ffi::CStr;
+        let collation = unsafe { ffi::sqlite3_vtab_collation(self.0, constraint_idx as c_int) };
+        if collation.is_null() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_MISUSE),
+                Some(format!("constraint {} is out of range", constraint_idx)),
+            ));
+        }
+        Ok(unsafe { CStr::from_ptr(collation) }.to_str()?)
+    }
 }
 
 pub struct IndexConstraintIter<'a> {
@@ -526,19 +656,13 @@ impl Values<'_> {
     // `sqlite3_value_type` returns `SQLITE_NULL` for pointer.
     // So it seems not possible to enhance `ValueRef::from_value`.
     #[cfg(feature = "array")]
-    pub(crate) fn get_array(&self, idx: usize) -> Result<Option<array::Array>> {
-        use crate::types::Value;
+    pub(crate) fn get_array(&self, idx: usize) -> Result<Option<array::ArrayData>> {
         let arg = self.args[idx];
         let ptr = unsafe { ffi::sqlite3_value_pointer(arg, array::ARRAY_TYPE) };
         if ptr.is_null() {
             Ok(None)
         } else {
-            Ok(Some(unsafe {
-                let rc = array::Array::from_raw(ptr as *const Vec<Value>);
-                let array = rc.clone();
-                array::Array::into_raw(rc); // don't consume it
-                array
-            }))
+            Ok(Some(unsafe { (*(ptr as *const array::ArrayData)).clone() }))
         }
     }
 
@@ -670,6 +794,78 @@ pub fn parse_boolean(s: &str) -> Option<bool> {
     }
 }
 
+/// Split a `key=value` module argument, dequoting the value.
+pub(crate) fn parameter(c_slice: &[u8]) -> Result<(&str, Cow<'_, str>)> {
+    let arg = std::str::from_utf8(c_slice)?.trim();
+    let mut split = arg.splitn(2, '=');
+    if let Some(key) = split.next() {
+        if let Some(value) = split.next() {
+            let param = key.trim();
+            let value = value.trim();
+            let unquoted = dequote(value);
+            let value = if unquoted.len() < value.len() {
+                // Quotes inside a quoted value are doubled, as in SQL.
+                let quote = &value[..1];
+                Cow::Owned(unquoted.replace(&quote.repeat(2), quote))
+            } else {
+                Cow::Borrowed(unquoted)
+            };
+            return Ok((param, value));
+        }
+    }
+    Err(Error::ModuleError(format!("illegal argument: '{}'", arg)))
+}
+
+/// Table content held in memory, by name, as read by the "csv" and "json"
+/// modules with `buffer=NAME`.
+///
+/// A table reads its buffer when it is connected: replacing or removing the
+/// buffer afterwards does not change tables in use.
+#[cfg(any(feature = "csvtab", feature = "jsontab"))]
+#[derive(Clone, Debug, Default)]
+pub struct Buffers(Rc<RefCell<HashMap<String, Rc<[u8]>>>>);
+
+#[cfg(any(feature = "csvtab", feature = "jsontab"))]
+impl Buffers {
+    pub fn new() -> Buffers {
+        Buffers::default()
+    }
+
+    /// Register `data` as `name`, replacing any buffer with that name.
+    pub fn insert<D: Into<Vec<u8>>>(&self, name: &str, data: D) {
+        self.0
+            .borrow_mut()
+            .insert(name.to_owned(), Rc::from(data.into()));
+    }
+
+    /// Remove the buffer `name`. Returns `false` if there is none.
+    pub fn remove(&self, name: &str) -> bool {
+        self.0.borrow_mut().remove(name).is_some()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Rc<[u8]>> {
+        self.0.borrow().get(name).cloned()
+    }
+}
+
+// Create `table` with the rows of a temporary `module` table built from
+// `args`, then drop it.
+#[cfg(any(feature = "csvtab", feature = "jsontab"))]
+fn create_table_as(conn: &Connection, module: &str, table: &str, args: &[&str]) -> Result<()> {
+    let result = conn.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE temp.rusqlite_{module}_import USING {module}({}); \
+         CREATE TABLE \"{}\" AS SELECT * FROM temp.rusqlite_{module}_import;",
+        args.join(", "),
+        escape_double_quote(table),
+        module = module
+    ));
+    conn.execute_batch(&format!(
+        "DROP TABLE IF EXISTS temp.rusqlite_{}_import",
+        module
+    ))?;
+    result
+}
+
 // FIXME copy/paste from function.rs
 unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
     let _: Box<T> = Box::from_raw(p as *mut T);
@@ -947,6 +1143,140 @@ where
     }
 }
 
+unsafe extern "C" fn rust_update<T>(
+    vtab: *mut ffi::sqlite3_vtab,
+    argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+    p_rowid: *mut ffi::sqlite3_int64,
+) -> c_int
+where
+    T: UpdateVTab,
+{
+    let vt = vtab as *mut T;
+    let args = slice::from_raw_parts_mut(argv, argc as usize);
+    let values = Values { args };
+    let r = if args.len() == 1 {
+        (*vt).delete(ValueRef::from_value(args[0]))
+    } else if ffi::sqlite3_value_type(args[0]) == ffi::SQLITE_NULL {
+        (*vt).insert(&values).map(|rowid| *p_rowid = rowid)
+    } else {
+        (*vt).update(&values)
+    };
+    vtab_error(vtab, r)
+}
+
+unsafe extern "C" fn rust_rename<T>(vtab: *mut ffi::sqlite3_vtab, new_name: *const c_char) -> c_int
+where
+    T: UpdateVTab,
+{
+    use std::ffi::CStr;
+    let vt = vtab as *mut T;
+    let r = CStr::from_ptr(new_name)
+        .to_str()
+        .map_err(Error::Utf8Error)
+        .and_then(|name| (*vt).rename(name));
+    vtab_error(vtab, r)
+}
+
+unsafe extern "C" fn rust_find_function<T>(
+    vtab: *mut ffi::sqlite3_vtab,
+    n_arg: c_int,
+    name: *const c_char,
+    px_func: *mut Option<
+        unsafe extern "C" fn(*mut ffi::sqlite3_context, c_int, *mut *mut ffi::sqlite3_value),
+    >,
+    pp_arg: *mut *mut c_void,
+) -> c_int
+where
+    T: UpdateVTab,
+{
+    use std::ffi::CStr;
+    let vt = vtab as *mut T;
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name,
+        Err(_) => return 0,
+    };
+    match (*vt).find_function(n_arg, name) {
+        Some(f) => {
+            *px_func = Some(call_vtab_function);
+            *pp_arg = f as *mut c_void;
+            1
+        }
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn call_vtab_function(
+    ctx: *mut ffi::sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) {
+    let f: VTabFunction = std::mem::transmute(ffi::sqlite3_user_data(ctx));
+    let args = slice::from_raw_parts_mut(argv, argc as usize);
+    let values = Values { args };
+    let mut ctxt = Context(ctx);
+    result_error(ctx, f(&mut ctxt, &values));
+}
+
+macro_rules! transaction_callback {
+    ($($callback:ident => $method:ident),*) => {
+        $(
+            unsafe extern "C" fn $callback<T>(vtab: *mut ffi::sqlite3_vtab) -> c_int
+            where
+                T: TransactionVTab,
+            {
+                let vt = vtab as *mut T;
+                vtab_error(vtab, (*vt).$method())
+            }
+        )*
+    };
+}
+
+transaction_callback!(
+    rust_begin => begin,
+    rust_sync => sync,
+    rust_commit => commit,
+    rust_rollback => rollback
+);
+
+macro_rules! savepoint_callback {
+    ($($callback:ident => $method:ident),*) => {
+        $(
+            unsafe extern "C" fn $callback<T>(vtab: *mut ffi::sqlite3_vtab, n: c_int) -> c_int
+            where
+                T: TransactionVTab,
+            {
+                let vt = vtab as *mut T;
+                vtab_error(vtab, (*vt).$method(n))
+            }
+        )*
+    };
+}
+
+savepoint_callback!(
+    rust_savepoint => savepoint,
+    rust_release => release,
+    rust_rollback_to => rollback_to
+);
+
+/// Turn the result of a virtual table method into a result code, setting
+/// `zErrMsg` on error.
+unsafe fn vtab_error<T>(vtab: *mut ffi::sqlite3_vtab, result: Result<T>) -> c_int {
+    match result {
+        Ok(_) => ffi::SQLITE_OK,
+        Err(Error::SqliteFailure(err, s)) => {
+            if let Some(err_msg) = s {
+                set_err_msg(vtab, &err_msg);
+            }
+            err.extended_code
+        }
+        Err(err) => {
+            set_err_msg(vtab, &err.to_string());
+            ffi::SQLITE_ERROR
+        }
+    }
+}
+
 /// Virtual table cursors can set an error message by assigning a string to
 /// `zErrMsg`.
 unsafe fn cursor_error<T>(cursor: *mut ffi::sqlite3_vtab_cursor, result: Result<T>) -> c_int {
@@ -1031,5 +1361,9 @@ unsafe fn alloc<S: AsRef<[u8]>>(s: S) -> *mut c_char {
 pub mod array;
 #[cfg(feature = "csvtab")]
 pub mod csvtab;
+#[cfg(feature = "jsontab")]
+pub mod jsontab;
 #[cfg(feature = "series")]
 pub mod series; // SQLite >= 3.9.0
+#[cfg(feature = "vector")]
+pub mod vector;
@@ -1,21 +1,45 @@
 //! generate series virtual table.
 //!
-//! Port of C [generate series "function"](http://www.sqlite.org/cgi/src/finfo?name=ext/misc/series.c).
-use std::default::Default;
+//! Port of C [generate series "function"](http://www.sqlite.org/cgi/src/finfo?name=ext/misc/series.c),
+//! with siblings over reals and, with the `chrono` feature, dates.
+//!
+//! ```sql
+//! SELECT value FROM generate_series(0, 100, 10);
+//! SELECT value FROM generate_series_real(0, 1, 0.25);
+//! SELECT value FROM generate_series_date('2020-01-31', '2020-12-31', '+1 month');
+//! ```
+//!
+//! The values are those from `start` up to `stop` by `|step|`. A negative
+//! step yields them in descending order, unless the query orders by `value`.
+#[cfg(feature = "chrono")]
+use std::convert::TryFrom;
 use std::os::raw::c_int;
 
+#[cfg(feature = "chrono")]
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+
 use crate::ffi;
-use crate::types::Type;
+use crate::types::{Type, Value};
 use crate::vtab::{
     eponymous_only_module, Context, IndexConstraintOp, IndexInfo, Module, VTab, VTabConnection,
     VTabCursor, Values,
 };
-use crate::{Connection, Result};
+use crate::{Connection, Error, Result};
 
-/// Register the "generate_series" module.
+/// Register the "generate_series" and "generate_series_real" modules, and
+/// "generate_series_date" with the `chrono` feature.
+///
+/// `generate_series_date` takes dates as `YYYY-MM-DD` or `YYYY-MM-DD
+/// HH:MM[:SS]` text and a step like `'+1 day'`, `'-2 hours'` or `'+1
+/// month'` (default `'+1 day'`). Like the `date()` SQL function, a day past
+/// the end of a month overflows into the next one. Values are dates if
+/// `start` is one and the step is a whole number of days.
 pub fn load_module(conn: &Connection) -> Result<()> {
-    let aux: Option<()> = None;
-    conn.create_module("generate_series", &SERIES_MODULE, aux)
+    conn.create_module("generate_series", &SERIES_MODULE, Some(Kind::Integer))?;
+    conn.create_module("generate_series_real", &SERIES_MODULE, Some(Kind::Real))?;
+    #[cfg(feature = "chrono")]
+    conn.create_module("generate_series_date", &SERIES_MODULE, Some(Kind::Date))?;
+    Ok(())
 }
 
 lazy_static::lazy_static! {
@@ -23,7 +47,7 @@ lazy_static::lazy_static! {
 }
 
 // Column numbers
-// const SERIES_COLUMN_VALUE : c_int = 0;
+const SERIES_COLUMN_VALUE: c_int = 0;
 const SERIES_COLUMN_START: c_int = 1;
 const SERIES_COLUMN_STOP: c_int = 2;
 const SERIES_COLUMN_STEP: c_int = 3;
@@ -39,29 +63,42 @@ bitflags::bitflags! {
         const STEP  = 4;
         // output in descending order
         const DESC  = 8;
+        // output in ascending order
+        const ASC   = 16;
         // Both start and stop
         const BOTH  = QueryPlanFlags::START.bits | QueryPlanFlags::STOP.bits;
     }
 }
 
+/// The type of the values of a series.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Integer,
+    Real,
+    #[cfg(feature = "chrono")]
+    Date,
+}
+
 /// An instance of the Series virtual table
 #[repr(C)]
 struct SeriesTab {
     /// Base class. Must be first
     base: ffi::sqlite3_vtab,
+    kind: Kind,
 }
 
 impl VTab for SeriesTab {
-    type Aux = ();
+    type Aux = Kind;
     type Cursor = SeriesTabCursor;
 
     fn connect(
         _: &mut VTabConnection,
-        _aux: Option<&()>,
+        aux: Option<&Kind>,
         _args: &[&[u8]],
     ) -> Result<(String, SeriesTab)> {
         let vtab = SeriesTab {
             base: ffi::sqlite3_vtab::default(),
+            kind: aux.cloned().unwrap_or(Kind::Integer),
         };
         Ok((
             "CREATE TABLE x(value,start hidden,stop hidden,step hidden)".to_owned(),
@@ -131,23 +168,31 @@ impl VTab for SeriesTab {
                 },
             ));
             info.set_estimated_rows(1000);
-            let order_by_consumed = {
+            // Only an ORDER BY on the value alone can be consumed.
+            let order_by = {
                 let mut order_bys = info.order_bys();
-                if let Some(order_by) = order_bys.next() {
-                    if order_by.is_order_by_desc() {
-                        idx_num |= QueryPlanFlags::DESC;
+                match (order_bys.next(), order_bys.next()) {
+                    (Some(ref order_by), None) if order_by.column() == SERIES_COLUMN_VALUE => {
+                        Some(order_by.is_order_by_desc())
                     }
-                    true
-                } else {
-                    false
+                    _ => None,
                 }
             };
-            if order_by_consumed {
+            if let Some(desc) = order_by {
+                idx_num |= if desc {
+                    QueryPlanFlags::DESC
+                } else {
+                    QueryPlanFlags::ASC
+                };
                 info.set_order_by_consumed(true);
             }
-        } else {
+        } else if self.kind == Kind::Integer {
             info.set_estimated_cost(2_147_483_647f64);
             info.set_estimated_rows(2_147_483_647);
+        } else {
+            // Without both bounds the series cannot be generated.
+            info.set_estimated_cost(f64::MAX);
+            info.set_estimated_rows(i64::max_value());
         }
         info.set_idx_num(idx_num.bits());
         Ok(())
@@ -158,105 +203,304 @@ impl VTab for SeriesTab {
     }
 }
 
+/// Step of a date series, always positive.
+#[cfg(feature = "chrono")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DateStep {
+    amount: i64,
+    /// Whether `amount` is a number of months rather than seconds.
+    months: bool,
+}
+
+#[cfg(feature = "chrono")]
+impl DateStep {
+    /// Parse a modifier like `+1 day` or `-6 months`. Returns the step and
+    /// whether it is negative.
+    fn parse(s: &str) -> Option<(DateStep, bool)> {
+        let mut words = s.split_whitespace();
+        let (n, unit) = match (words.next(), words.next(), words.next()) {
+            (Some(n), Some(unit), None) => (n.parse::<i64>().ok()?, unit.to_ascii_lowercase()),
+            _ => return None,
+        };
+        let unit = if unit.ends_with('s') {
+            &unit[..unit.len() - 1]
+        } else {
+            &unit[..]
+        };
+        let (scale, months) = match unit {
+            "second" => (1, false),
+            "minute" => (60, false),
+            "hour" => (3600, false),
+            "day" => (86400, false),
+            "month" => (1, true),
+            "year" => (12, true),
+            _ => return None,
+        };
+        let amount = n.checked_abs()?.checked_mul(scale)?;
+        if amount == 0 {
+            return None;
+        }
+        Some((DateStep { amount, months }, n < 0))
+    }
+
+    /// The `k`th date from `start`.
+    fn nth(&self, start: &NaiveDateTime, k: i64) -> Option<NaiveDateTime> {
+        let n = self.amount.checked_mul(k)?;
+        if self.months {
+            let month = i64::from(start.year()) * 12 + i64::from(start.month0()) + n;
+            let year = i32::try_from(month.div_euclid(12)).ok()?;
+            let first = NaiveDate::from_ymd_opt(year, month.rem_euclid(12) as u32 + 1, 1)?;
+            // Days past the end of the month overflow into the next one.
+            first
+                .checked_add_signed(Duration::days(i64::from(start.day()) - 1))
+                .map(|date| date.and_time(start.time()))
+        } else {
+            start.checked_add_signed(Duration::milliseconds(n.checked_mul(1000)?))
+        }
+    }
+
+    /// The number of dates from `start` up to `stop`.
+    fn terms(&self, start: &NaiveDateTime, stop: &NaiveDateTime) -> u64 {
+        let mut k = if self.months {
+            let months = |d: &NaiveDateTime| i64::from(d.year()) * 12 + i64::from(d.month0());
+            (months(stop) - months(start)) / self.amount
+        } else {
+            (*stop - *start).num_seconds() / self.amount
+        };
+        if k < 0 {
+            // `stop` is before `start`.
+            return 0;
+        }
+        while k >= 0 && self.nth(start, k).map_or(true, |date| date > *stop) {
+            k -= 1;
+        }
+        (k + 1) as u64
+    }
+}
+
+/// Parse `YYYY-MM-DD` or `YYYY-MM-DD HH:MM[:SS[.SSS]]`. Returns the date and
+/// whether it has no time.
+#[cfg(feature = "chrono")]
+fn parse_date(s: &str) -> Option<(NaiveDateTime, bool)> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Some((date.and_hms(0, 0, 0), true));
+    }
+    [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .filter_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+    .next()
+    .map(|date| (date, false))
+}
+
+#[cfg(feature = "chrono")]
+fn date_arg(value: &Value, name: &str) -> Result<(NaiveDateTime, bool)> {
+    match *value {
+        Value::Text(ref s) => parse_date(s),
+        _ => None,
+    }
+    .ok_or_else(|| Error::ModuleError(format!("invalid {} date: {:?}", name, value)))
+}
+
+/// First value and step of a series, the step being positive.
+#[derive(Clone, Debug, PartialEq)]
+enum Bounds {
+    Integer(i64, u64),
+    Real(f64, f64),
+    #[cfg(feature = "chrono")]
+    Date(NaiveDateTime, DateStep, bool),
+}
+
 /// A cursor for the Series virtual table
-#[derive(Default)]
 #[repr(C)]
 struct SeriesTabCursor {
     /// Base class. Must be first
     base: ffi::sqlite3_vtab_cursor,
     /// True to count down rather than up
     is_desc: bool,
-    /// The rowid
-    row_id: i64,
-    /// Current value ("value")
-    value: i64,
-    /// Mimimum value ("start")
-    min_value: i64,
-    /// Maximum value ("stop")
-    max_value: i64,
-    /// Increment ("step")
-    step: i64,
+    /// Index of the current value, in ascending order
+    index: u128,
+    /// Number of values, which is 2^64 for the full range of integers
+    terms: u128,
+    bounds: Bounds,
+    /// Arguments ("start", "stop" and "step")
+    start: Value,
+    stop: Value,
+    step: Value,
 }
 
 impl SeriesTabCursor {
     fn new() -> SeriesTabCursor {
-        SeriesTabCursor::default()
+        SeriesTabCursor {
+            base: ffi::sqlite3_vtab_cursor::default(),
+            is_desc: false,
+            index: 0,
+            terms: 0,
+            bounds: Bounds::Integer(0, 1),
+            start: Value::Null,
+            stop: Value::Null,
+            step: Value::Null,
+        }
+    }
+
+    /// Accessor to the associated virtual table.
+    fn vtab(&self) -> &SeriesTab {
+        unsafe { &*(self.base.pVtab as *const SeriesTab) }
+    }
+
+    // Compute the bounds and number of terms. Returns whether the step is
+    // negative.
+    fn set_bounds(&mut self, kind: Kind) -> Result<bool> {
+        match kind {
+            Kind::Integer => {
+                let (start, stop, step) = match (&self.start, &self.stop, &self.step) {
+                    (&Value::Integer(start), &Value::Integer(stop), &Value::Integer(step)) => {
+                        (start, stop, step)
+                    }
+                    _ => return Err(Error::ModuleError("integer arguments expected".to_owned())),
+                };
+                // A zero step is taken as 1.
+                let abs_step = step.unsigned_abs().max(1);
+                self.terms = if start > stop {
+                    0
+                } else {
+                    (i128::from(stop) - i128::from(start)) as u128 / u128::from(abs_step) + 1
+                };
+                self.bounds = Bounds::Integer(start, abs_step);
+                Ok(step < 0)
+            }
+            Kind::Real => {
+                let real = |value: &Value| match *value {
+                    Value::Integer(i) => Some(i as f64),
+                    Value::Real(f) if f.is_finite() => Some(f),
+                    _ => None,
+                };
+                let (start, stop, step) =
+                    match (real(&self.start), real(&self.stop), real(&self.step)) {
+                        (Some(start), Some(stop), Some(step)) if step != 0.0 => (start, stop, step),
+                        _ => {
+                            return Err(Error::ModuleError(
+                                "finite start and stop, and a non-zero step expected".to_owned(),
+                            ))
+                        }
+                    };
+                // Allow for rounding errors, so that 0.3 is reached from 0
+                // by 0.1.
+                let n = ((stop - start) / step.abs() + 1e-9).floor();
+                self.terms = if n < 0.0 { 0 } else { n as u128 + 1 };
+                self.bounds = Bounds::Real(start, step.abs());
+                Ok(step < 0.0)
+            }
+            #[cfg(feature = "chrono")]
+            Kind::Date => {
+                let (start, date_only) = date_arg(&self.start, "start")?;
+                let (stop, _) = date_arg(&self.stop, "stop")?;
+                let (step, negative) = match self.step {
+                    Value::Text(ref s) => DateStep::parse(s),
+                    _ => None,
+                }
+                .ok_or_else(|| Error::ModuleError(format!("invalid step: {:?}", self.step)))?;
+                self.terms = u128::from(step.terms(&start, &stop));
+                let date_only = date_only && (step.months || step.amount % 86400 == 0);
+                self.bounds = Bounds::Date(start, step, date_only);
+                Ok(negative)
+            }
+        }
     }
 }
+
 impl VTabCursor for SeriesTabCursor {
     fn filter(&mut self, idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
+        let kind = self.vtab().kind;
         let idx_num = QueryPlanFlags::from_bits_truncate(idx_num);
         let mut i = 0;
         if idx_num.contains(QueryPlanFlags::START) {
-            self.min_value = args.get(i)?;
+            self.start = args.get(i)?;
             i += 1;
         } else {
-            self.min_value = 0;
+            self.start = Value::Integer(0);
         }
         if idx_num.contains(QueryPlanFlags::STOP) {
-            self.max_value = args.get(i)?;
+            self.stop = args.get(i)?;
             i += 1;
+        } else if kind == Kind::Integer {
+            self.stop = Value::Integer(0xffff_ffff);
         } else {
-            self.max_value = 0xffff_ffff;
+            return Err(Error::ModuleError("missing stop argument".to_owned()));
         }
         if idx_num.contains(QueryPlanFlags::STEP) {
             self.step = args.get(i)?;
-            if self.step < 1 {
-                self.step = 1;
-            }
         } else {
-            self.step = 1;
+            self.step = match kind {
+                Kind::Integer => Value::Integer(1),
+                Kind::Real => Value::Real(1.0),
+                #[cfg(feature = "chrono")]
+                Kind::Date => Value::Text("+1 day".to_owned()),
+            };
         };
-        for arg in args.iter() {
-            if arg.data_type() == Type::Null {
-                // If any of the constraints have a NULL value, then return no rows.
-                self.min_value = 1;
-                self.max_value = 0;
-                break;
-            }
+        self.index = 0;
+        if args.iter().any(|arg| arg.data_type() == Type::Null) {
+            // If any of the constraints have a NULL value, then return no rows.
+            self.terms = 0;
+            return Ok(());
         }
-        self.is_desc = idx_num.contains(QueryPlanFlags::DESC);
-        if self.is_desc {
-            self.value = self.max_value;
-            if self.step > 0 {
-                self.value -= (self.max_value - self.min_value) % self.step;
-            }
+        let negative = self.set_bounds(kind)?;
+        self.is_desc = if idx_num.contains(QueryPlanFlags::DESC) {
+            true
+        } else if idx_num.contains(QueryPlanFlags::ASC) {
+            false
         } else {
-            self.value = self.min_value;
-        }
-        self.row_id = 1;
+            negative
+        };
         Ok(())
     }
 
     fn next(&mut self) -> Result<()> {
-        if self.is_desc {
-            self.value -= self.step;
-        } else {
-            self.value += self.step;
-        }
-        self.row_id += 1;
+        self.index += 1;
         Ok(())
     }
 
     fn eof(&self) -> bool {
-        if self.is_desc {
-            self.value < self.min_value
-        } else {
-            self.value > self.max_value
-        }
+        self.index >= self.terms
     }
 
     fn column(&self, ctx: &mut Context, i: c_int) -> Result<()> {
-        let x = match i {
-            SERIES_COLUMN_START => self.min_value,
-            SERIES_COLUMN_STOP => self.max_value,
-            SERIES_COLUMN_STEP => self.step,
-            _ => self.value,
+        match i {
+            SERIES_COLUMN_START => return ctx.set_result(&self.start),
+            SERIES_COLUMN_STOP => return ctx.set_result(&self.stop),
+            SERIES_COLUMN_STEP => return ctx.set_result(&self.step),
+            _ => {}
+        }
+        let k = if self.is_desc {
+            self.terms - 1 - self.index
+        } else {
+            self.index
         };
-        ctx.set_result(&x)
+        match self.bounds {
+            Bounds::Integer(start, step) => {
+                ctx.set_result(&start.wrapping_add((k as u64).wrapping_mul(step) as i64))
+            }
+            Bounds::Real(start, step) => ctx.set_result(&(start + k as f64 * step)),
+            #[cfg(feature = "chrono")]
+            Bounds::Date(ref start, ref step, date_only) => {
+                let date = step
+                    .nth(start, k as i64)
+                    .ok_or_else(|| Error::ModuleError("date out of range".to_owned()))?;
+                let fmt = if date_only {
+                    "%Y-%m-%d"
+                } else {
+                    "%Y-%m-%d %H:%M:%S"
+                };
+                ctx.set_result(&date.format(fmt).to_string())
+            }
+        }
     }
 
     fn rowid(&self) -> Result<i64> {
-        Ok(self.row_id)
+        Ok(self.index as i64 + 1)
     }
 }
@@ -0,0 +1,850 @@
+//! Vector similarity search.
+//!
+//! Vectors of `f32` are BLOBs of their little-endian bytes. The "vector"
+//! module stores them in a shadow table and answers k-nearest-neighbour
+//! queries, by brute force or through an IVF index: once trained, vectors
+//! are grouped in `lists` clusters, and only the `probes` clusters nearest to
+//! the query are searched. Distances are computed with wasm `simd128`
+//! instructions when the target has them, which `.cargo/config.toml` enables
+//! for `wasm32-unknown-unknown`.
+//!
+//! ```sql
+//! CREATE VIRTUAL TABLE embeddings USING vector(dims=3, metric=cosine, lists=16);
+//! INSERT INTO embeddings(rowid, vector) VALUES (1, '[0.1, 0.2, 0.3]');
+//! -- Build the IVF index, after loading enough vectors.
+//! INSERT INTO embeddings(command) VALUES ('train');
+//! SELECT rowid, distance FROM embeddings
+//!  WHERE vector MATCH '[0.1, 0.2, 0.25]' AND k = 5;
+//! ```
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::functions::FunctionFlags;
+use crate::types::{Null, ToSql, Value, ValueRef};
+use crate::vtab::{
+    escape_double_quote, parameter, update_module, Context, CreateVTab, IndexConstraintOp,
+    IndexInfo, Module, UpdateVTab, VTab, VTabConnection, VTabCursor, Values,
+};
+use crate::{Connection, Error, Result, NO_PARAMS};
+
+/// Register the "vector" module and the SQL functions:
+/// * `vector(X)`: X, a JSON array of numbers or a vector, as a vector,
+/// * `vector_json(V)`: V as a JSON array,
+/// * `vector_distance_cosine(A, B)`: 1 minus the cosine of A and B,
+/// * `vector_distance_l2(A, B)`: the euclidean distance of A and B,
+/// * `vector_dot(A, B)`: the dot product of A and B.
+///
+/// ```sql
+/// CREATE VIRTUAL TABLE vtab USING vector(
+///   dims=N -- Number of dimensions of the vectors
+///   [, metric=COSINE|L2|DOT] -- Distance of the `distance` column. Default: cosine. Dot is the negated dot product.
+///   [, lists=N] -- Number of clusters of the IVF index. Default: 0, no index.
+///   [, probes=N] -- Number of clusters searched by a query, unless the `probes` column is constrained. Default: 1.
+/// );
+/// ```
+///
+/// Vectors are inserted in the `vector` column, as BLOBs or JSON text. A
+/// query constrains `vector MATCH` to the query vector, and optionally `k`
+/// to the number of neighbours; rows are returned nearest first, with their
+/// `distance`. The IVF index is (re)built by inserting `'train'` in the
+/// `command` column; vectors inserted later join their nearest cluster.
+pub fn load_module(conn: &Connection) -> Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_scalar_function("vector", 1, flags, |ctx| {
+        function_arg(ctx.get_raw(0)).map(|v| to_blob(&v))
+    })?;
+    conn.create_scalar_function("vector_json", 1, flags, |ctx| {
+        function_arg(ctx.get_raw(0)).map(|v| to_json(&v))
+    })?;
+    for &(name, metric) in &[
+        ("vector_distance_cosine", Metric::Cosine),
+        ("vector_distance_l2", Metric::L2),
+    ] {
+        conn.create_scalar_function(name, 2, flags, move |ctx| {
+            let (a, b) = function_args(ctx.get_raw(0), ctx.get_raw(1))?;
+            Ok(metric.distance(&a, &b))
+        })?;
+    }
+    conn.create_scalar_function("vector_dot", 2, flags, |ctx| {
+        let (a, b) = function_args(ctx.get_raw(0), ctx.get_raw(1))?;
+        Ok(f64::from(dot(&a, &b)))
+    })?;
+    conn.create_module("vector", &VECTOR_MODULE, None)
+}
+
+lazy_static::lazy_static! {
+    static ref VECTOR_MODULE: Module<VectorTab> = update_module::<VectorTab>(1);
+}
+
+/// Convert a vector to a BLOB.
+pub fn to_blob(vector: &[f32]) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(vector.len() * 4);
+    for x in vector {
+        blob.extend_from_slice(&x.to_le_bytes());
+    }
+    blob
+}
+
+/// Convert a BLOB to a vector, or `None` if its length is not a multiple
+/// of 4.
+pub fn from_blob(blob: &[u8]) -> Option<Vec<f32>> {
+    let mut vector = Vec::with_capacity(blob.len() / 4);
+    if decode(blob, &mut vector) {
+        Some(vector)
+    } else {
+        None
+    }
+}
+
+fn decode(blob: &[u8], vector: &mut Vec<f32>) -> bool {
+    vector.clear();
+    vector.extend(
+        blob.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+    );
+    blob.len() % 4 == 0
+}
+
+fn to_json(vector: &[f32]) -> String {
+    let items: Vec<String> = vector.iter().map(f32::to_string).collect();
+    format!("[{}]", items.join(","))
+}
+
+// Read a vector from a BLOB or a JSON array.
+fn parse_vector(value: ValueRef<'_>) -> std::result::Result<Vec<f32>, String> {
+    match value {
+        ValueRef::Blob(b) => {
+            from_blob(b).ok_or_else(|| format!("invalid vector of {} bytes", b.len()))
+        }
+        ValueRef::Text(t) => {
+            let s = String::from_utf8_lossy(t);
+            let s = s.trim();
+            if !s.starts_with('[') || !s.ends_with(']') || s.len() < 2 {
+                return Err(format!("invalid vector: {}", s));
+            }
+            let items = s[1..s.len() - 1].trim();
+            if items.is_empty() {
+                return Ok(Vec::new());
+            }
+            items
+                .split(',')
+                .map(|x| x.trim().parse::<f32>())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|_| format!("invalid vector: {}", s))
+        }
+        _ => Err(format!("invalid vector of type {}", value.data_type())),
+    }
+}
+
+fn function_arg(value: ValueRef<'_>) -> Result<Vec<f32>> {
+    parse_vector(value).map_err(|err| Error::UserFunctionError(err.into()))
+}
+
+fn function_args(a: ValueRef<'_>, b: ValueRef<'_>) -> Result<(Vec<f32>, Vec<f32>)> {
+    let (a, b) = (function_arg(a)?, function_arg(b)?);
+    if a.len() != b.len() {
+        return Err(Error::UserFunctionError(
+            format!("vectors of {} and {} dimensions", a.len(), b.len()).into(),
+        ));
+    }
+    Ok((a, b))
+}
+
+fn dot_scalar(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn l2_squared_scalar(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+fn sum_lanes(v: std::arch::wasm32::v128) -> f32 {
+    use std::arch::wasm32::f32x4_extract_lane;
+    f32x4_extract_lane::<0>(v)
+        + f32x4_extract_lane::<1>(v)
+        + f32x4_extract_lane::<2>(v)
+        + f32x4_extract_lane::<3>(v)
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    use std::arch::wasm32::*;
+    let n = a.len().min(b.len()) / 4 * 4;
+    let mut acc = f32x4_splat(0.0);
+    for i in (0..n).step_by(4) {
+        // `v128_load` does not require alignment.
+        let (x, y) = unsafe {
+            (
+                v128_load(a[i..].as_ptr() as *const v128),
+                v128_load(b[i..].as_ptr() as *const v128),
+            )
+        };
+        acc = f32x4_add(acc, f32x4_mul(x, y));
+    }
+    sum_lanes(acc) + dot_scalar(&a[n..], &b[n..])
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    use std::arch::wasm32::*;
+    let n = a.len().min(b.len()) / 4 * 4;
+    let mut acc = f32x4_splat(0.0);
+    for i in (0..n).step_by(4) {
+        let d = unsafe {
+            f32x4_sub(
+                v128_load(a[i..].as_ptr() as *const v128),
+                v128_load(b[i..].as_ptr() as *const v128),
+            )
+        };
+        acc = f32x4_add(acc, f32x4_mul(d, d));
+    }
+    sum_lanes(acc) + l2_squared_scalar(&a[n..], &b[n..])
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    dot_scalar(a, b)
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    l2_squared_scalar(a, b)
+}
+
+/// How distances are measured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Metric {
+    Cosine,
+    L2,
+    Dot,
+}
+
+impl Metric {
+    fn distance(self, a: &[f32], b: &[f32]) -> f64 {
+        match self {
+            Metric::Cosine => {
+                let norms = (dot(a, a) * dot(b, b)).sqrt();
+                // A zero vector is taken as orthogonal to every other.
+                if norms == 0.0 {
+                    1.0
+                } else {
+                    1.0 - f64::from(dot(a, b) / norms)
+                }
+            }
+            Metric::L2 => f64::from(l2_squared(a, b).sqrt()),
+            Metric::Dot => -f64::from(dot(a, b)),
+        }
+    }
+
+    // The metric used to cluster vectors: the mean of a cluster is not the
+    // nearest point to its members by dot product.
+    fn clustering(self) -> Metric {
+        match self {
+            Metric::Dot => Metric::L2,
+            metric => metric,
+        }
+    }
+}
+
+const TRAINING_ITERATIONS: usize = 10;
+
+fn nearest_centroid(metric: Metric, centroids: &[Vec<f32>], vector: &[f32]) -> usize {
+    let mut nearest = (0, f64::INFINITY);
+    for (i, centroid) in centroids.iter().enumerate() {
+        let distance = metric.distance(vector, centroid);
+        if distance < nearest.1 {
+            nearest = (i, distance);
+        }
+    }
+    nearest.0
+}
+
+// Column numbers
+// const VECTOR_COLUMN_VECTOR: c_int = 0;
+const VECTOR_COLUMN_DISTANCE: c_int = 1;
+const VECTOR_COLUMN_K: c_int = 2;
+const VECTOR_COLUMN_PROBES: c_int = 3;
+const VECTOR_COLUMN_COMMAND: c_int = 4;
+
+bitflags::bitflags! {
+    #[repr(C)]
+    struct QueryPlanFlags: ::std::os::raw::c_int {
+        // vector MATCH $value
+        const MATCH  = 1;
+        // k = $value
+        const K      = 2;
+        // probes = $value
+        const PROBES = 4;
+        // rowid = $value
+        const ROWID  = 8;
+    }
+}
+
+/// An instance of the vector virtual table
+#[repr(C)]
+struct VectorTab {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab,
+    /// The connection of the table, to access the shadow tables
+    conn: Connection,
+    schema: String,
+    name: String,
+    dims: usize,
+    metric: Metric,
+    lists: usize,
+    probes: usize,
+}
+
+impl VectorTab {
+    /// The quoted name of the shadow table `suffix`.
+    fn shadow(&self, suffix: &str) -> String {
+        format!(
+            "\"{}\".\"{}_{}\"",
+            escape_double_quote(&self.schema),
+            escape_double_quote(&self.name),
+            suffix
+        )
+    }
+
+    /// Read the vector of column `col` of an xUpdate call.
+    fn vector_arg(&self, args: &Values<'_>, col: c_int) -> Result<Vec<f32>> {
+        let value: Value = args.get(2 + col as usize)?;
+        self.check(ValueRef::from(&value))
+    }
+
+    /// Read a vector of the table dimensions.
+    fn check(&self, value: ValueRef<'_>) -> Result<Vec<f32>> {
+        let vector = parse_vector(value).map_err(Error::ModuleError)?;
+        if vector.len() != self.dims {
+            return Err(Error::ModuleError(format!(
+                "expected a vector of {} dimensions, got {}",
+                self.dims,
+                vector.len()
+            )));
+        }
+        Ok(vector)
+    }
+
+    fn centroids(&self) -> Result<Vec<(i64, Vec<f32>)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT list, centroid FROM {} ORDER BY list",
+            self.shadow("centroids")
+        ))?;
+        let rows = stmt.query_map(NO_PARAMS, |row| {
+            let centroid: Vec<u8> = row.get(1)?;
+            Ok((row.get(0)?, from_blob(&centroid).unwrap_or_default()))
+        })?;
+        rows.collect()
+    }
+
+    /// The cluster of `vector`, if the index is trained.
+    fn assign(&self, vector: &[f32]) -> Result<Option<i64>> {
+        let centroids = self.centroids()?;
+        let metric = self.metric.clustering();
+        Ok(centroids
+            .iter()
+            .map(|&(list, ref centroid)| (metric.distance(vector, centroid), list))
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+            .map(|(_, list)| list))
+    }
+
+    /// Run k-means over all vectors, and assign each to its cluster.
+    fn train(&mut self) -> Result<()> {
+        let mut vectors = Vec::new();
+        {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT id, vector FROM {} ORDER BY id",
+                self.shadow("vectors")
+            ))?;
+            let mut rows = stmt.query(NO_PARAMS)?;
+            while let Some(row) = rows.next()? {
+                if let ValueRef::Blob(b) = row.get_raw(1) {
+                    if let Some(vector) = from_blob(b) {
+                        vectors.push((row.get::<_, i64>(0)?, vector));
+                    }
+                }
+            }
+        }
+        self.conn.execute_batch(&format!(
+            "DELETE FROM {}; UPDATE {} SET list = NULL;",
+            self.shadow("centroids"),
+            self.shadow("vectors")
+        ))?;
+        let lists = self.lists.min(vectors.len());
+        if lists == 0 {
+            return Ok(());
+        }
+
+        let metric = self.metric.clustering();
+        // Start from vectors spread over the table.
+        let mut centroids: Vec<Vec<f32>> = (0..lists)
+            .map(|i| vectors[i * vectors.len() / lists].1.clone())
+            .collect();
+        let mut assignment = vec![0; vectors.len()];
+        for iteration in 0..=TRAINING_ITERATIONS {
+            for (a, &(_, ref vector)) in assignment.iter_mut().zip(&vectors) {
+                *a = nearest_centroid(metric, &centroids, vector);
+            }
+            if iteration == TRAINING_ITERATIONS {
+                break;
+            }
+            let mut sums = vec![vec![0f32; self.dims]; lists];
+            let mut counts = vec![0usize; lists];
+            for (&a, &(_, ref vector)) in assignment.iter().zip(&vectors) {
+                counts[a] += 1;
+                for (sum, x) in sums[a].iter_mut().zip(vector) {
+                    *sum += x;
+                }
+            }
+            // An empty cluster keeps its centroid.
+            for ((centroid, sum), &count) in centroids.iter_mut().zip(sums).zip(&counts) {
+                if count > 0 {
+                    *centroid = sum.into_iter().map(|x| x / count as f32).collect();
+                }
+            }
+        }
+
+        let mut insert = self.conn.prepare(&format!(
+            "INSERT INTO {}(list, centroid) VALUES (?, ?)",
+            self.shadow("centroids")
+        ))?;
+        for (list, centroid) in centroids.iter().enumerate() {
+            insert.execute(&[&(list as i64) as &dyn ToSql, &to_blob(centroid)])?;
+        }
+        let mut update = self.conn.prepare(&format!(
+            "UPDATE {} SET list = ? WHERE id = ?",
+            self.shadow("vectors")
+        ))?;
+        for (&list, &(id, _)) in assignment.iter().zip(&vectors) {
+            update.execute(&[list as i64, id])?;
+        }
+        Ok(())
+    }
+
+    /// The `k` rows nearest to `query`.
+    fn nearest(&self, query: &[f32], k: usize, probes: usize) -> Result<Vec<Row>> {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+        let vectors = self.shadow("vectors");
+        let metric = self.metric.clustering();
+        let mut lists: Vec<(f64, i64)> = self
+            .centroids()?
+            .iter()
+            .map(|&(list, ref centroid)| (metric.distance(query, centroid), list))
+            .collect();
+        let sql = if lists.is_empty() {
+            format!("SELECT id, vector FROM {}", vectors)
+        } else {
+            lists.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            let probed: Vec<String> = lists
+                .iter()
+                .take(probes.max(1))
+                .map(|&(_, list)| list.to_string())
+                .collect();
+            format!(
+                "SELECT id, vector FROM {} WHERE list IS NULL OR list IN ({})",
+                vectors,
+                probed.join(",")
+            )
+        };
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+        // The nearest rows so far, the farthest on top.
+        let mut heap = BinaryHeap::new();
+        let mut vector = Vec::with_capacity(self.dims);
+        while let Some(row) = rows.next()? {
+            let blob = match row.get_raw(1) {
+                ValueRef::Blob(b) => b,
+                _ => continue,
+            };
+            if !decode(blob, &mut vector) || vector.len() != query.len() {
+                continue;
+            }
+            let distance = self.metric.distance(query, &vector);
+            let distance = if distance.is_nan() {
+                f64::INFINITY
+            } else {
+                distance
+            };
+            if heap.len() == k {
+                if heap.peek().map_or(false, |farthest: &Row| {
+                    farthest.distance.unwrap_or(f64::INFINITY) <= distance
+                }) {
+                    continue;
+                }
+                heap.pop();
+            }
+            heap.push(Row {
+                id: row.get(0)?,
+                vector: blob.to_vec(),
+                distance: Some(distance),
+            });
+        }
+        Ok(heap.into_sorted_vec())
+    }
+
+    fn command(&mut self, command: &str) -> Result<()> {
+        match command {
+            "train" => self.train(),
+            _ => Err(Error::ModuleError(format!("unknown command '{}'", command))),
+        }
+    }
+}
+
+impl VTab for VectorTab {
+    type Aux = ();
+    type Cursor = VectorTabCursor;
+
+    fn connect(
+        db: &mut VTabConnection,
+        _aux: Option<&()>,
+        args: &[&[u8]],
+    ) -> Result<(String, VectorTab)> {
+        if args.len() < 4 {
+            return Err(Error::ModuleError("no dims specified".to_owned()));
+        }
+        let mut vtab = VectorTab {
+            base: ffi::sqlite3_vtab::default(),
+            conn: unsafe { Connection::from_handle(db.handle())? },
+            // args[1] is the name of the database, args[2] of the table.
+            schema: String::from_utf8_lossy(args[1]).into_owned(),
+            name: String::from_utf8_lossy(args[2]).into_owned(),
+            dims: 0,
+            metric: Metric::Cosine,
+            lists: 0,
+            probes: 1,
+        };
+        for c_slice in &args[3..] {
+            let (param, value) = parameter(c_slice)?;
+            let number = || match value.parse::<usize>() {
+                Ok(n) if n > 0 || param == "lists" => Ok(n),
+                _ => Err(Error::ModuleError(format!(
+                    "unrecognized argument to '{}': {}",
+                    param, value
+                ))),
+            };
+            match param {
+                "dims" => vtab.dims = number()?,
+                "lists" => vtab.lists = number()?,
+                "probes" => vtab.probes = number()?,
+                "metric" => {
+                    vtab.metric = match value.to_ascii_lowercase().as_str() {
+                        "cosine" => Metric::Cosine,
+                        "l2" => Metric::L2,
+                        "dot" => Metric::Dot,
+                        _ => {
+                            return Err(Error::ModuleError(format!(
+                                "unrecognized argument to 'metric': {}",
+                                value
+                            )));
+                        }
+                    }
+                }
+                _ => {
+                    return Err(Error::ModuleError(format!(
+                        "unrecognized parameter '{}'",
+                        param
+                    )));
+                }
+            }
+        }
+        if vtab.dims == 0 {
+            return Err(Error::ModuleError("no dims specified".to_owned()));
+        }
+        Ok((
+            "CREATE TABLE x(vector, distance hidden, k hidden, probes hidden, command hidden)"
+                .to_owned(),
+            vtab,
+        ))
+    }
+
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        let mut idx_num = QueryPlanFlags::empty();
+        // Index of the constraint of each argument of `filter`
+        let mut arg_constraints = [None; 4];
+        for (i, constraint) in info.constraints().enumerate() {
+            if !constraint.is_usable() {
+                continue;
+            }
+            let (flag, arg) = match (constraint.column(), constraint.operator()) {
+                (0, IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_MATCH) => (QueryPlanFlags::MATCH, 0),
+                (VECTOR_COLUMN_K, IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ) => {
+                    (QueryPlanFlags::K, 1)
+                }
+                (VECTOR_COLUMN_PROBES, IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ) => {
+                    (QueryPlanFlags::PROBES, 2)
+                }
+                (-1, IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ) => (QueryPlanFlags::ROWID, 3),
+                _ => continue,
+            };
+            idx_num |= flag;
+            arg_constraints[arg] = Some(i);
+        }
+        // `k` and `probes` only apply to a search, and a rowid lookup only
+        // without one.
+        if idx_num.contains(QueryPlanFlags::MATCH) {
+            idx_num.remove(QueryPlanFlags::ROWID);
+            arg_constraints[3] = None;
+        } else {
+            idx_num.remove(QueryPlanFlags::K | QueryPlanFlags::PROBES);
+            arg_constraints[1] = None;
+            arg_constraints[2] = None;
+        }
+        let mut num_of_arg = 0;
+        for &i in arg_constraints.iter().flatten() {
+            num_of_arg += 1;
+            let mut constraint_usage = info.constraint_usage(i);
+            constraint_usage.set_argv_index(num_of_arg);
+            constraint_usage.set_omit(true);
+        }
+
+        if idx_num.contains(QueryPlanFlags::MATCH) {
+            let k = idx_num.contains(QueryPlanFlags::K);
+            info.set_estimated_cost(if k { 1000. } else { 10_000. });
+            info.set_estimated_rows(if k { 10 } else { 10_000 });
+            // Rows come nearest first.
+            let order_by_distance = {
+                let mut order_bys = info.order_bys();
+                match (order_bys.next(), order_bys.next()) {
+                    (Some(ref order_by), None) => {
+                        order_by.column() == VECTOR_COLUMN_DISTANCE && !order_by.is_order_by_desc()
+                    }
+                    _ => false,
+                }
+            };
+            if order_by_distance {
+                info.set_order_by_consumed(true);
+            }
+        } else if idx_num.contains(QueryPlanFlags::ROWID) {
+            info.set_estimated_cost(1.);
+            info.set_estimated_rows(1);
+        } else {
+            info.set_estimated_cost(1_000_000.);
+            info.set_estimated_rows(1_000_000);
+        }
+        info.set_idx_num(idx_num.bits());
+        Ok(())
+    }
+
+    fn open(&self) -> Result<VectorTabCursor> {
+        Ok(VectorTabCursor {
+            base: ffi::sqlite3_vtab_cursor::default(),
+            rows: Vec::new(),
+            pos: 0,
+        })
+    }
+}
+
+impl CreateVTab for VectorTab {
+    fn create(
+        db: &mut VTabConnection,
+        aux: Option<&()>,
+        args: &[&[u8]],
+    ) -> Result<(String, VectorTab)> {
+        let (sql, vtab) = VectorTab::connect(db, aux, args)?;
+        vtab.conn.execute_batch(&format!(
+            "CREATE TABLE {vectors}(id INTEGER PRIMARY KEY, vector BLOB NOT NULL, list INTEGER);
+             CREATE INDEX \"{schema}\".\"{name}_vectors_list\" ON \"{name}_vectors\"(list);
+             CREATE TABLE {centroids}(list INTEGER PRIMARY KEY, centroid BLOB NOT NULL);",
+            vectors = vtab.shadow("vectors"),
+            centroids = vtab.shadow("centroids"),
+            schema = escape_double_quote(&vtab.schema),
+            name = escape_double_quote(&vtab.name),
+        ))?;
+        Ok((sql, vtab))
+    }
+
+    fn destroy(&self) -> Result<()> {
+        self.conn.execute_batch(&format!(
+            "DROP TABLE {}; DROP TABLE {};",
+            self.shadow("vectors"),
+            self.shadow("centroids")
+        ))
+    }
+}
+
+impl UpdateVTab for VectorTab {
+    fn delete(&mut self, arg: ValueRef<'_>) -> Result<()> {
+        self.conn.execute(
+            &format!("DELETE FROM {} WHERE id = ?", self.shadow("vectors")),
+            &[arg.as_i64()?],
+        )?;
+        Ok(())
+    }
+
+    fn insert(&mut self, args: &Values<'_>) -> Result<i64> {
+        let command: Option<String> = args.get(2 + VECTOR_COLUMN_COMMAND as usize)?;
+        if let Some(command) = command {
+            self.command(&command)?;
+            return Ok(0);
+        }
+        let id: Option<i64> = args.get(1)?;
+        let vector = self.vector_arg(args, 0)?;
+        let list = self.assign(&vector)?;
+        self.conn.execute(
+            &format!(
+                "INSERT INTO {}(id, vector, list) VALUES (?, ?, ?)",
+                self.shadow("vectors")
+            ),
+            &[&id as &dyn ToSql, &to_blob(&vector), &list],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn update(&mut self, args: &Values<'_>) -> Result<()> {
+        if args
+            .get::<Option<String>>(2 + VECTOR_COLUMN_COMMAND as usize)?
+            .is_some()
+        {
+            return Err(Error::ModuleError("commands are run by INSERT".to_owned()));
+        }
+        let old_id: i64 = args.get(0)?;
+        let new_id: i64 = args.get(1)?;
+        let vector = self.vector_arg(args, 0)?;
+        let list = self.assign(&vector)?;
+        self.conn.execute(
+            &format!(
+                "UPDATE {} SET id = ?, vector = ?, list = ? WHERE id = ?",
+                self.shadow("vectors")
+            ),
+            &[&new_id as &dyn ToSql, &to_blob(&vector), &list, &old_id],
+        )?;
+        Ok(())
+    }
+
+    fn rename(&mut self, new_name: &str) -> Result<()> {
+        for suffix in &["vectors", "centroids"] {
+            self.conn.execute_batch(&format!(
+                "ALTER TABLE {} RENAME TO \"{}_{}\"",
+                self.shadow(suffix),
+                escape_double_quote(new_name),
+                suffix
+            ))?;
+        }
+        self.name = new_name.to_owned();
+        Ok(())
+    }
+}
+
+/// A row of a cursor.
+struct Row {
+    id: i64,
+    vector: Vec<u8>,
+    /// Distance to the query vector, when searching
+    distance: Option<f64>,
+}
+
+// Rows are ordered by distance, then rowid.
+impl Ord for Row {
+    fn cmp(&self, other: &Row) -> Ordering {
+        self.distance
+            .partial_cmp(&other.distance)
+            .unwrap_or(Ordering::Equal)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Row {
+    fn partial_cmp(&self, other: &Row) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Row {
+    fn eq(&self, other: &Row) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Row {}
+
+/// A cursor for the vector virtual table
+#[repr(C)]
+struct VectorTabCursor {
+    /// Base class. Must be first
+    base: ffi::sqlite3_vtab_cursor,
+    rows: Vec<Row>,
+    pos: usize,
+}
+
+impl VectorTabCursor {
+    /// Accessor to the associated virtual table.
+    fn vtab(&self) -> &VectorTab {
+        unsafe { &*(self.base.pVtab as *const VectorTab) }
+    }
+}
+
+impl VTabCursor for VectorTabCursor {
+    fn filter(&mut self, idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
+        let idx_num = QueryPlanFlags::from_bits_truncate(idx_num);
+        let vtab = self.vtab();
+        let rows = if idx_num.contains(QueryPlanFlags::MATCH) {
+            let query: Value = args.get(0)?;
+            let query = vtab.check(ValueRef::from(&query))?;
+            let mut i = 1;
+            let mut k = usize::max_value();
+            if idx_num.contains(QueryPlanFlags::K) {
+                k = args.get::<i64>(i)?.max(0) as usize;
+                i += 1;
+            }
+            let mut probes = vtab.probes;
+            if idx_num.contains(QueryPlanFlags::PROBES) {
+                probes = args.get::<i64>(i)?.max(1) as usize;
+            }
+            vtab.nearest(&query, k, probes)?
+        } else {
+            let vectors = vtab.shadow("vectors");
+            let (sql, params) = if idx_num.contains(QueryPlanFlags::ROWID) {
+                let id: Value = args.get(0)?;
+                (
+                    format!("SELECT id, vector FROM {} WHERE id = ?", vectors),
+                    vec![id],
+                )
+            } else {
+                (
+                    format!("SELECT id, vector FROM {} ORDER BY id", vectors),
+                    vec![],
+                )
+            };
+            let mut stmt = vtab.conn.prepare(&sql)?;
+            let rows = stmt.query_map(&params, |row| {
+                Ok(Row {
+                    id: row.get(0)?,
+                    vector: row.get(1)?,
+                    distance: None,
+                })
+            })?;
+            rows.collect::<Result<_>>()?
+        };
+        self.rows = rows;
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.rows.len()
+    }
+
+    fn column(&self, ctx: &mut Context, col: c_int) -> Result<()> {
+        let row = &self.rows[self.pos];
+        match col {
+            0 => ctx.set_result(&row.vector),
+            VECTOR_COLUMN_DISTANCE => ctx.set_result(&row.distance),
+            _ => ctx.set_result(&Null),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rows[self.pos].id)
+    }
+}
@@ -0,0 +1,33 @@
+const assert = require('assert');
+const { Worker } = require('worker_threads');
+const { WorkerDatabase } = require('./dist-nodejs/c_to_rust_sqlite_wasm.js');
+
+async function main() {
+  const worker = new Worker('./worker.js');
+  const db = new WorkerDatabase(worker);
+  await db.open();
+  await db.exec('CREATE TABLE foo (x INTEGER, b BLOB)');
+  assert.strictEqual(await db.run('INSERT INTO foo VALUES (?, ?)', [1, new Uint8Array([1, 2])]), 1);
+  assert.strictEqual(await db.run('INSERT INTO foo VALUES (?, ?)', [2, null]), 1);
+
+  const rows = await db.query('SELECT x, b FROM foo ORDER BY x', []);
+  assert.strictEqual(rows.length, 2);
+  assert.deepStrictEqual(Array.from(rows[0].b), [1, 2]);
+
+  const data = await db.serialize();
+  await db.open(data);
+  const stmt = await db.prepare('SELECT count(*) AS n FROM foo');
+  assert.strictEqual((await db.step(stmt)).n, 2);
+  assert.strictEqual(await db.step(stmt), null);
+  assert.strictEqual(await db.finalize(stmt), true);
+
+  await assert.rejects(db.exec('SELECT * FROM missing'));
+  await db.close();
+  await worker.terminate();
+  console.log('worker ok');
+}
+
+main().catch((e) => {
+  console.error(e);
+  process.exit(1);
+});
@@ -0,0 +1,382 @@
+//! Virtual table presenting a JS `Array` of plain objects as a SQL table.
+//!
+//! Arrays are registered by name with `Database.registerArray`, which
+//! creates a temporary table reading the objects in place: each object is a
+//! row, whose rowid is its index in the array, and each column a property.
+//! Equality constraints with the `BINARY` collation are answered from a
+//! per-column hash index built on first use; it is dropped when the array is
+//! registered again, which must be done after changing the objects in place.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::c_int;
+use std::rc::Rc;
+use std::str;
+
+use js_sys::{Array, Object, Reflect, JSON};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::database::js_to_value;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::vtab::{
+    escape_double_quote, read_only_module, sqlite3_vtab, sqlite3_vtab_cursor, Context, CreateVTab,
+    IndexConstraintOp, IndexInfo, Module, VTab, VTabConnection, VTabCursor, Values,
+};
+use rusqlite::{Connection, Error, Result};
+
+/// The name of the module.
+pub const MODULE_NAME: &str = "js_array";
+
+lazy_static! {
+    static ref ARRAY_TABLE_MODULE: Module<ArrayTab> = read_only_module::<ArrayTab>(1);
+}
+
+// `idx_num` values of `best_index`; a column lookup adds the column index.
+const FULL_SCAN: c_int = 0;
+const ROWID_LOOKUP: c_int = 1;
+const COLUMN_LOOKUP: c_int = 2;
+
+/// The arrays registered on a connection, by table name.
+pub type Registry = Rc<RefCell<HashMap<String, Rc<ArraySource>>>>;
+
+// A value usable as a hash key, compared the way SQLite compares values
+// without affinity.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum Key {
+    Integer(i64),
+    Real(u64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl Key {
+    fn new(value: ValueRef<'_>) -> Option<Key> {
+        match value {
+            ValueRef::Null => None,
+            ValueRef::Integer(i) => Some(Key::Integer(i)),
+            ValueRef::Real(f) => {
+                if f.fract() == 0.0 && f >= i64::min_value() as f64 && f < i64::max_value() as f64 {
+                    Some(Key::Integer(f as i64))
+                } else {
+                    Some(Key::Real(f.to_bits()))
+                }
+            }
+            ValueRef::Text(t) => Some(Key::Text(String::from_utf8_lossy(t).into_owned())),
+            ValueRef::Blob(b) => Some(Key::Blob(b.to_vec())),
+        }
+    }
+}
+
+type Index = HashMap<Key, Vec<u32>>;
+
+/// A registered array and the columns read from its objects.
+pub struct ArraySource {
+    array: Array,
+    columns: Vec<String>,
+    indexes: RefCell<HashMap<usize, Rc<Index>>>,
+}
+
+impl ArraySource {
+    pub fn new(array: Array, columns: Vec<String>) -> ArraySource {
+        ArraySource {
+            array,
+            columns,
+            indexes: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // The value of column `i` of row `row`.
+    fn value(&self, row: u32, i: usize) -> Value {
+        let object = self.array.get(row);
+        if !object.is_object() {
+            return Value::Null;
+        }
+        match Reflect::get(&object, &JsValue::from_str(&self.columns[i])) {
+            Ok(value) => column_value(&value),
+            Err(_) => Value::Null,
+        }
+    }
+
+    fn index(&self, i: usize) -> Rc<Index> {
+        if let Some(index) = self.indexes.borrow().get(&i) {
+            return index.clone();
+        }
+        let mut index = Index::new();
+        for row in 0..self.array.length() {
+            if let Some(key) = Key::new(ValueRef::from(&self.value(row, i))) {
+                index.entry(key).or_insert_with(Vec::new).push(row);
+            }
+        }
+        let index = Rc::new(index);
+        self.indexes.borrow_mut().insert(i, index.clone());
+        index
+    }
+}
+
+// Convert a property to a SQL value: dates become milliseconds since the
+// epoch, and other objects JSON text.
+fn column_value(value: &JsValue) -> Value {
+    if let Ok(value) = js_to_value(value) {
+        return value;
+    }
+    if let Some(date) = value.dyn_ref::<js_sys::Date>() {
+        return Value::Real(date.get_time());
+    }
+    match JSON::stringify(value).ok().and_then(|s| s.as_string()) {
+        Some(s) => Value::Text(s),
+        None => Value::Null,
+    }
+}
+
+/// Return the property names of the first object of `array`.
+pub fn infer_columns(array: &Array) -> ::std::result::Result<Vec<String>, JsValue> {
+    let first = array.get(0);
+    if !first.is_object() {
+        return Err(
+            js_sys::TypeError::new("cannot infer columns: first element is not an object").into(),
+        );
+    }
+    Ok(Object::keys(&Object::from(first))
+        .iter()
+        .filter_map(|k| k.as_string())
+        .collect())
+}
+
+/// Register the "js_array" module with the arrays of `registry`.
+pub fn load_module(conn: &Connection, registry: &Registry) -> Result<()> {
+    conn.create_module(MODULE_NAME, &ARRAY_TABLE_MODULE, Some(registry.clone()))
+}
+
+/// Make `source` readable as the temporary table `name`, replacing any array
+/// registered with that name.
+pub fn register(
+    conn: &Connection,
+    registry: &Registry,
+    name: &str,
+    source: ArraySource,
+) -> Result<()> {
+    let columns = source.columns.clone();
+    // The table reads the array from the registry, also when it is created.
+    let previous = registry
+        .borrow_mut()
+        .insert(name.to_owned(), Rc::new(source));
+    let table = escape_double_quote(name);
+    let result = match previous {
+        Some(ref previous) if previous.columns == columns => return Ok(()),
+        Some(_) => conn
+            .execute_batch(&format!(
+                "SAVEPOINT js_array;
+                 DROP TABLE temp.\"{table}\";
+                 CREATE VIRTUAL TABLE temp.\"{table}\" USING {};
+                 RELEASE js_array;",
+                MODULE_NAME,
+                table = table
+            ))
+            .map_err(|e| {
+                // Keep the table of the previous array.
+                let _ = conn.execute_batch("ROLLBACK TO js_array; RELEASE js_array;");
+                e
+            }),
+        None => conn.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE temp.\"{}\" USING {}",
+            table, MODULE_NAME
+        )),
+    };
+    if result.is_err() {
+        // Leave the registry as the tables are, for a retry to create them.
+        let mut registry = registry.borrow_mut();
+        match previous {
+            Some(previous) => registry.insert(name.to_owned(), previous),
+            None => registry.remove(name),
+        };
+    }
+    result
+}
+
+/// Drop the table of the array registered as `name`. Returns `false` if
+/// there is none.
+pub fn unregister(conn: &Connection, registry: &Registry, name: &str) -> Result<bool> {
+    if !registry.borrow().contains_key(name) {
+        return Ok(false);
+    }
+    conn.execute_batch(&format!(
+        "DROP TABLE temp.\"{}\"",
+        escape_double_quote(name)
+    ))?;
+    registry.borrow_mut().remove(name);
+    Ok(true)
+}
+
+/// An instance of the array virtual table
+#[repr(C)]
+struct ArrayTab {
+    /// Base class. Must be first
+    base: sqlite3_vtab,
+    registry: Registry,
+    name: String,
+}
+
+impl ArrayTab {
+    fn source(&self) -> Result<Rc<ArraySource>> {
+        self.registry
+            .borrow()
+            .get(&self.name)
+            .cloned()
+            .ok_or_else(|| Error::ModuleError(format!("no array registered as {}", self.name)))
+    }
+}
+
+impl VTab for ArrayTab {
+    type Aux = Registry;
+    type Cursor = ArrayTabCursor;
+
+    fn connect(
+        _: &mut VTabConnection,
+        aux: Option<&Registry>,
+        args: &[&[u8]],
+    ) -> Result<(String, ArrayTab)> {
+        let registry = aux.expect("registry").clone();
+        // args[2] is the name of the table.
+        let name = str::from_utf8(args[2])?.to_owned();
+        let vtab = ArrayTab {
+            base: sqlite3_vtab::default(),
+            registry,
+            name,
+        };
+        let columns: Vec<String> = vtab
+            .source()?
+            .columns
+            .iter()
+            .map(|c| format!("\"{}\"", escape_double_quote(c)))
+            .collect();
+        Ok((format!("CREATE TABLE x({})", columns.join(", ")), vtab))
+    }
+
+    fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+        // The hash indexes compare text as the `BINARY` collation does.
+        let lookup = info.constraints().enumerate().find(|&(i, ref c)| {
+            c.is_usable()
+                && c.operator() == IndexConstraintOp::SQLITE_INDEX_CONSTRAINT_EQ
+                && (c.column() < 0
+                    || info
+                        .collation(i)
+                        .map_or(false, |name| name.eq_ignore_ascii_case("BINARY")))
+        });
+        match lookup.map(|(i, c)| (i, c.column())) {
+            Some((i, column)) => {
+                // SQLite still checks the constraint: the index only narrows
+                // down the rows.
+                info.constraint_usage(i).set_argv_index(1);
+                info.set_idx_num(if column < 0 {
+                    ROWID_LOOKUP
+                } else {
+                    COLUMN_LOOKUP + column
+                });
+                info.set_estimated_cost(10.);
+                info.set_estimated_rows(10);
+            }
+            None => {
+                let len = self.source().map_or(0, |s| s.array.length());
+                info.set_idx_num(FULL_SCAN);
+                info.set_estimated_cost(f64::from(len));
+                info.set_estimated_rows(i64::from(len));
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&self) -> Result<ArrayTabCursor> {
+        Ok(ArrayTabCursor {
+            base: sqlite3_vtab_cursor::default(),
+            source: self.source()?,
+            rows: Rows::All(0),
+            pos: 0,
+        })
+    }
+}
+
+impl CreateVTab for ArrayTab {}
+
+enum Rows {
+    // Rows `0..len`.
+    All(u32),
+    Matches(Rc<Index>, Key),
+    Single(u32),
+    Empty,
+}
+
+/// A cursor for the array virtual table
+#[repr(C)]
+struct ArrayTabCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    source: Rc<ArraySource>,
+    rows: Rows,
+    pos: usize,
+}
+
+impl ArrayTabCursor {
+    fn len(&self) -> usize {
+        match self.rows {
+            Rows::All(len) => len as usize,
+            Rows::Matches(ref index, ref key) => index.get(key).map_or(0, Vec::len),
+            Rows::Single(_) => 1,
+            Rows::Empty => 0,
+        }
+    }
+
+    fn row(&self) -> Result<u32> {
+        match self.rows {
+            Rows::All(_) => Ok(self.pos as u32),
+            Rows::Matches(ref index, ref key) => Ok(index[key][self.pos]),
+            Rows::Single(row) => Ok(row),
+            Rows::Empty => Err(Error::ModuleError("no current row".to_owned())),
+        }
+    }
+}
+
+impl VTabCursor for ArrayTabCursor {
+    fn filter(&mut self, idx_num: c_int, _idx_str: Option<&str>, args: &Values<'_>) -> Result<()> {
+        let len = self.source.array.length();
+        self.rows = match idx_num {
+            FULL_SCAN => Rows::All(len),
+            // Only an integer, or a real equal to one, matches a rowid.
+            ROWID_LOOKUP => match args.iter().next() {
+                Some(ValueRef::Integer(rowid)) if rowid >= 0 && rowid < i64::from(len) => {
+                    Rows::Single(rowid as u32)
+                }
+                Some(ValueRef::Real(r)) if r >= 0.0 && r < f64::from(len) && r.fract() == 0.0 => {
+                    Rows::Single(r as u32)
+                }
+                _ => Rows::Empty,
+            },
+            _ => match args.iter().next().and_then(Key::new) {
+                Some(key) => {
+                    let index = self.source.index((idx_num - COLUMN_LOOKUP) as usize);
+                    Rows::Matches(index, key)
+                }
+                None => Rows::Empty,
+            },
+        };
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.len()
+    }
+
+    fn column(&self, ctx: &mut Context, i: c_int) -> Result<()> {
+        ctx.set_result(&self.source.value(self.row()?, i as usize))
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        self.row().map(i64::from)
+    }
+}
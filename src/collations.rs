@@ -0,0 +1,91 @@
+//! Collations for user-visible text: the Rust ones of
+//! `rusqlite::collation::builtin`, and a collation per locale delegating to
+//! JS `Intl.Collator`, named `locale_` followed by a BCP 47 language tag
+//! with `_` for `-`:
+//!
+//! ```sql
+//! SELECT name FROM cities ORDER BY name COLLATE locale_sv;
+//! SELECT name FROM cities ORDER BY name COLLATE locale_de_CH;
+//! ```
+//!
+//! Locale collations are created when a statement first uses them. A locale
+//! that `Intl.Collator` does not support gives a "no such collation
+//! sequence" error, rather than falling back to the default locale. An
+//! exception thrown while comparing is logged, and the strings compare as
+//! equal.
+
+use std::cmp::Ordering;
+
+use js_sys::{Array, Function, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::js_functions::{exception_message, SendJs};
+use rusqlite::collation::builtin;
+use rusqlite::{Connection, Result};
+
+const LOCALE_PREFIX: &str = "locale_";
+
+/// The `compare` function of an `Intl.Collator`.
+struct LocaleCollator {
+    compare: Function,
+}
+
+impl LocaleCollator {
+    /// The collator of `tag`, or an error if the tag is invalid or the
+    /// locale not supported.
+    fn new(tag: &str) -> std::result::Result<LocaleCollator, JsValue> {
+        let intl = Reflect::get(&js_sys::global(), &JsValue::from_str("Intl"))?;
+        let constructor =
+            Reflect::get(&intl, &JsValue::from_str("Collator"))?.dyn_into::<Function>()?;
+        let locales = Array::of1(&JsValue::from_str(tag));
+        let supported = Reflect::get(&constructor, &JsValue::from_str("supportedLocalesOf"))?
+            .dyn_into::<Function>()?;
+        // Throws a RangeError for an invalid tag.
+        let supported: Array =
+            Reflect::apply(&supported, &constructor, &Array::of1(&locales))?.dyn_into()?;
+        if supported.length() == 0 {
+            return Err(js_sys::RangeError::new(&format!("unsupported locale: {}", tag)).into());
+        }
+        let collator = Reflect::construct(&constructor, &Array::of1(&locales))?;
+        let compare = Reflect::get(&collator, &JsValue::from_str("compare"))?.dyn_into()?;
+        Ok(LocaleCollator { compare })
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let (a, b) = (JsValue::from_str(a), JsValue::from_str(b));
+        let result = match self.compare.call2(&JsValue::NULL, &a, &b) {
+            Ok(result) => result.as_f64().unwrap_or(0.0),
+            Err(e) => {
+                // A collation cannot fail the statement.
+                eprintln!("Intl.Collator compare failed: {}", exception_message(&e));
+                0.0
+            }
+        };
+        result.partial_cmp(&0.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// The language tag of a locale collation name, if it is one.
+fn locale_tag(name: &str) -> Option<String> {
+    let prefix = name.get(..LOCALE_PREFIX.len())?;
+    if !prefix.eq_ignore_ascii_case(LOCALE_PREFIX) || name.len() == LOCALE_PREFIX.len() {
+        return None;
+    }
+    Some(name[LOCALE_PREFIX.len()..].replace('_', "-"))
+}
+
+fn collation_needed(conn: &Connection, name: &str) -> Result<()> {
+    let collator = match locale_tag(name).and_then(|tag| LocaleCollator::new(&tag).ok()) {
+        Some(collator) => SendJs(collator),
+        None => return Ok(()),
+    };
+    conn.create_collation(name, move |a, b| collator.compare(a, b))
+}
+
+/// Register `UNICODE_NOCASE` and `NATURAL`, and create locale collations on
+/// demand. This replaces any `collation_needed` callback of `conn`.
+pub fn load_collations(conn: &Connection) -> Result<()> {
+    builtin::load_collations(conn)?;
+    conn.collation_needed(collation_needed)
+}
@@ -0,0 +1,142 @@
+//! Columnar query results exposed to JavaScript as typed array views.
+
+use std::mem;
+use std::rc::Rc;
+
+use js_sys::{Array, Float64Array, Function, Int32Array, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use rusqlite::columnar::{ColumnArray, ColumnData, ColumnType, Field, RecordBatch};
+
+// A `BigInt64Array` over `len` values at `ptr` in wasm memory.
+fn big_int64_view(values: &[i64]) -> Result<JsValue, JsValue> {
+    let constructor = Reflect::get(&js_sys::global(), &JsValue::from_str("BigInt64Array"))?
+        .dyn_into::<Function>()?;
+    let buffer = Reflect::get(&wasm_bindgen::memory(), &JsValue::from_str("buffer"))?;
+    let args = Array::of3(
+        &buffer,
+        &JsValue::from(values.as_ptr() as u32),
+        &JsValue::from(values.len() as u32),
+    );
+    Reflect::construct(&constructor, &args)
+}
+
+/// A batch of query results decoded into one typed array per column.
+///
+/// The arrays returned by `values`, `offsets` and `validity` are views into
+/// wasm memory, not copies: they are invalidated when the batch is freed
+/// and when the memory grows, so read them before running anything else.
+#[wasm_bindgen]
+pub struct ColumnarBatch {
+    fields: Rc<Vec<Field>>,
+    batch: RecordBatch,
+}
+
+impl ColumnarBatch {
+    pub fn new(fields: Rc<Vec<Field>>, batch: RecordBatch) -> ColumnarBatch {
+        ColumnarBatch { fields, batch }
+    }
+
+    fn column(&self, i: usize) -> Result<&ColumnArray, JsValue> {
+        self.batch
+            .columns
+            .get(i)
+            .ok_or_else(|| js_sys::RangeError::new("column index out of range").into())
+    }
+}
+
+#[wasm_bindgen]
+impl ColumnarBatch {
+    #[wasm_bindgen(getter, js_name = numRows)]
+    pub fn num_rows(&self) -> usize {
+        self.batch.num_rows
+    }
+
+    #[wasm_bindgen(getter, js_name = numColumns)]
+    pub fn num_columns(&self) -> usize {
+        self.fields.len()
+    }
+
+    #[wasm_bindgen(js_name = columnName)]
+    pub fn column_name(&self, i: usize) -> Option<String> {
+        self.fields.get(i).map(|f| f.name.clone())
+    }
+
+    /// One of `int64`, `float64`, `utf8` or `binary`.
+    #[wasm_bindgen(js_name = columnType)]
+    pub fn column_type(&self, i: usize) -> Option<String> {
+        self.fields.get(i).map(|f| {
+            match f.column_type {
+                ColumnType::Int64 => "int64",
+                ColumnType::Float64 => "float64",
+                ColumnType::Utf8 => "utf8",
+                ColumnType::Binary => "binary",
+            }
+            .to_owned()
+        })
+    }
+
+    #[wasm_bindgen(js_name = nullCount)]
+    pub fn null_count(&self, i: usize) -> Result<usize, JsValue> {
+        Ok(self.column(i)?.null_count)
+    }
+
+    /// The values of column `i`: a `BigInt64Array` or `Float64Array`, or for
+    /// text and blobs the `Uint8Array` of all values end to end.
+    pub fn values(&self, i: usize) -> Result<JsValue, JsValue> {
+        Ok(match self.column(i)?.data {
+            ColumnData::Int64(ref v) => big_int64_view(v)?,
+            ColumnData::Float64(ref v) => unsafe { Float64Array::view(v) }.into(),
+            ColumnData::Utf8 { ref data, .. } | ColumnData::Binary { ref data, .. } => {
+                unsafe { Uint8Array::view(data) }.into()
+            }
+        })
+    }
+
+    /// For text and blob columns, the `Int32Array` of `numRows + 1` offsets
+    /// delimiting each value in `values`.
+    pub fn offsets(&self, i: usize) -> Result<Option<Int32Array>, JsValue> {
+        Ok(match self.column(i)?.data {
+            ColumnData::Utf8 { ref offsets, .. } | ColumnData::Binary { ref offsets, .. } => {
+                Some(unsafe { Int32Array::view(offsets) })
+            }
+            _ => None,
+        })
+    }
+
+    /// The Arrow validity bitmap of column `i`, or `undefined` if it has no
+    /// null.
+    pub fn validity(&self, i: usize) -> Result<Option<Uint8Array>, JsValue> {
+        let column = self.column(i)?;
+        Ok(if column.null_count > 0 {
+            Some(unsafe { Uint8Array::view(&column.validity) })
+        } else {
+            None
+        })
+    }
+
+    /// The size in bytes of the buffers held by this batch.
+    #[wasm_bindgen(getter, js_name = byteLength)]
+    pub fn byte_length(&self) -> usize {
+        self.batch
+            .columns
+            .iter()
+            .map(|c| {
+                c.validity.len()
+                    + match c.data {
+                        ColumnData::Int64(ref v) => v.len() * mem::size_of::<i64>(),
+                        ColumnData::Float64(ref v) => v.len() * mem::size_of::<f64>(),
+                        ColumnData::Utf8 {
+                            ref offsets,
+                            ref data,
+                        }
+                        | ColumnData::Binary {
+                            ref offsets,
+                            ref data,
+                        } => offsets.len() * mem::size_of::<i32>() + data.len(),
+                    }
+            })
+            .sum()
+    }
+}
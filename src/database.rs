@@ -0,0 +1,969 @@
+//! JavaScript facing wrapper around a rusqlite `Connection`.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::io::{self, Write};
+use std::mem;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use js_sys::{
+    Array, Float32Array, Float64Array, Function, Int32Array, Object, Promise, Reflect, Symbol,
+    Uint8Array,
+};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use crate::array_table::{self, ArraySource, Registry};
+use crate::collations;
+use crate::columnar::ColumnarBatch;
+use crate::js_functions;
+use rusqlite::csv_export::{BlobEncoding, CsvOptions, QuoteStyle};
+use rusqlite::functions::{builtin, stats};
+use rusqlite::migrations::{Migration, Migrations};
+use rusqlite::subscription::{QueryResult, SubscriptionId, Subscriptions};
+use rusqlite::types::{ToSql, ToSqlOutput, Value, ValueRef};
+use rusqlite::vtab::array::{self, ArrayData};
+use rusqlite::vtab::csvtab::{self, Buffers};
+use rusqlite::vtab::{series, vector};
+use rusqlite::{rtree, Connection, Row, Statement};
+
+/// Default number of VDBE instructions between yields of the async API.
+const DEFAULT_YIELD_OPS: i32 = 10_000;
+
+/// Size of the parts of the `Blob` returned by `exportCsv`.
+const CSV_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `Number.MAX_SAFE_INTEGER`
+const MAX_SAFE_INTEGER: i64 = 9_007_199_254_740_991;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(callback: &Function, ms: i32);
+}
+
+pub fn to_js_error(e: rusqlite::Error) -> JsValue {
+    js_sys::Error::new(&e.to_string()).into()
+}
+
+// `BigInt(i)`, or `i` as a number if `BigInt` is not supported.
+fn big_int(i: i64) -> JsValue {
+    Reflect::get(&js_sys::global(), &JsValue::from_str("BigInt"))
+        .and_then(|constructor| constructor.dyn_into::<Function>().map_err(JsValue::from))
+        .and_then(|constructor| {
+            constructor.call1(&JsValue::NULL, &JsValue::from_str(&i.to_string()))
+        })
+        .unwrap_or_else(|_| JsValue::from_f64(i as f64))
+}
+
+/// Convert a value to JS. Integers beyond `Number.MAX_SAFE_INTEGER` become
+/// `BigInt`s, which keep their precision.
+pub fn value_to_js(value: ValueRef<'_>) -> JsValue {
+    match value {
+        ValueRef::Null => JsValue::NULL,
+        ValueRef::Integer(i) if i.unsigned_abs() > MAX_SAFE_INTEGER as u64 => big_int(i),
+        ValueRef::Integer(i) => JsValue::from_f64(i as f64),
+        ValueRef::Real(r) => JsValue::from_f64(r),
+        ValueRef::Text(t) => JsValue::from_str(&String::from_utf8_lossy(t)),
+        ValueRef::Blob(b) => Uint8Array::from(b).into(),
+    }
+}
+
+pub fn js_to_value(value: &JsValue) -> Result<Value, JsValue> {
+    if value.is_null() || value.is_undefined() {
+        Ok(Value::Null)
+    } else if let Some(b) = value.as_bool() {
+        Ok(Value::Integer(b as i64))
+    } else if let Some(f) = value.as_f64() {
+        if f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER as f64 {
+            Ok(Value::Integer(f as i64))
+        } else {
+            Ok(Value::Real(f))
+        }
+    } else if let Some(s) = value.as_string() {
+        Ok(Value::Text(s))
+    } else if value.is_instance_of::<Uint8Array>() {
+        Ok(Value::Blob(Uint8Array::from(value.clone()).to_vec()))
+    } else if let Some(f) = value.dyn_ref::<Float32Array>() {
+        // A vector, as its little-endian bytes.
+        let bytes = Uint8Array::new_with_byte_offset_and_length(
+            &f.buffer(),
+            f.byte_offset(),
+            f.byte_length(),
+        );
+        Ok(Value::Blob(bytes.to_vec()))
+    } else {
+        Err(js_sys::TypeError::new("unsupported parameter type").into())
+    }
+}
+
+/// A query parameter: a value, or an array for `rarray(?)`.
+pub enum Param {
+    Value(Value),
+    Array(ArrayData),
+}
+
+impl ToSql for Param {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match *self {
+            Param::Value(ref v) => v.to_sql(),
+            Param::Array(ref a) => a.to_sql(),
+        }
+    }
+}
+
+// The name of the constructor of `value`, as "BigInt64Array".
+fn constructor_name(value: &JsValue) -> Option<String> {
+    let constructor = Reflect::get(value, &JsValue::from_str("constructor")).ok()?;
+    Reflect::get(&constructor, &JsValue::from_str("name"))
+        .ok()?
+        .as_string()
+}
+
+// Convert a typed array, an array of strings, or a `{ rarray: [...] }`
+// wrapper of strings, numbers or tuples (arrays of values), to the array
+// bound to `rarray(?)`.
+fn js_to_array(value: &JsValue) -> Result<Option<ArrayData>, JsValue> {
+    if let Some(a) = value.dyn_ref::<Float64Array>() {
+        return Ok(Some(ArrayData::Reals(Rc::new(a.to_vec()))));
+    }
+    if let Some(a) = value.dyn_ref::<Int32Array>() {
+        let integers = a.to_vec().into_iter().map(i64::from).collect();
+        return Ok(Some(ArrayData::Integers(Rc::new(integers))));
+    }
+    if constructor_name(value).as_ref().map(String::as_str) == Some("BigInt64Array") {
+        // Read the elements through the bytes of their buffer.
+        let field = |key: &str| Reflect::get(value, &JsValue::from_str(key));
+        let bytes = Uint8Array::new_with_byte_offset_and_length(
+            &field("buffer")?,
+            field("byteOffset")?.as_f64().unwrap_or(0.0) as u32,
+            field("byteLength")?.as_f64().unwrap_or(0.0) as u32,
+        )
+        .to_vec();
+        let integers = bytes
+            .chunks(8)
+            .map(|b| {
+                let mut le = [0u8; 8];
+                le.copy_from_slice(b);
+                i64::from_le_bytes(le)
+            })
+            .collect();
+        return Ok(Some(ArrayData::Integers(Rc::new(integers))));
+    }
+    if !value.is_object() {
+        return Ok(None);
+    }
+    let (array, wrapped) = if Array::is_array(value) {
+        (Array::from(value), false)
+    } else {
+        match Reflect::get(value, &JsValue::from_str("rarray"))?.dyn_into::<Array>() {
+            Ok(array) => (array, true),
+            Err(_) => return Ok(None),
+        }
+    };
+    if array.iter().all(|e| e.is_string()) {
+        let texts = array.iter().filter_map(|e| e.as_string()).collect();
+        return Ok(Some(ArrayData::Texts(Rc::new(texts))));
+    }
+    // Only strings are unambiguous without the wrapper.
+    if !wrapped {
+        return Ok(None);
+    }
+    if array.length() > 0 && array.iter().all(|e| Array::is_array(&e)) {
+        let tuples = array
+            .iter()
+            .map(|e| {
+                let tuple = Array::from(&e);
+                if tuple.length() as usize > array::MAX_COLUMNS {
+                    let message = format!(
+                        "tuples of {} values, rarray{} takes at most {}",
+                        tuple.length(),
+                        array::MAX_COLUMNS,
+                        array::MAX_COLUMNS
+                    );
+                    return Err(js_sys::RangeError::new(&message).into());
+                }
+                tuple.iter().map(|v| js_to_value(&v)).collect()
+            })
+            .collect::<Result<Vec<Vec<Value>>, JsValue>>()?;
+        return Ok(Some(ArrayData::Tuples(Rc::new(tuples))));
+    }
+    let values: Vec<Value> = array
+        .iter()
+        .map(|e| js_to_value(&e))
+        .collect::<Result<_, _>>()?;
+    let integers: Option<Vec<i64>> = values
+        .iter()
+        .map(|v| match *v {
+            Value::Integer(i) => Some(i),
+            _ => None,
+        })
+        .collect();
+    Ok(Some(match integers {
+        Some(integers) => ArrayData::Integers(Rc::new(integers)),
+        None => ArrayData::Values(Rc::new(values)),
+    }))
+}
+
+/// Convert query parameters. `Float64Array`, `Int32Array` and
+/// `BigInt64Array` values, arrays of strings, and arrays wrapped as
+/// `{ rarray: [...] }`, are bound as arrays for `rarray(?)`, as in
+/// `WHERE id IN rarray(?)`. Arrays of tuples are bound for `rarray2(?)` to
+/// `rarray4(?)`.
+pub fn params_from_js(params: &Array) -> Result<Vec<Param>, JsValue> {
+    params
+        .iter()
+        .map(|p| match js_to_array(&p)? {
+            Some(array) => Ok(Param::Array(array)),
+            None => js_to_value(&p).map(Param::Value),
+        })
+        .collect()
+}
+
+pub fn row_to_js(columns: &[String], row: &Row<'_>) -> Result<Object, JsValue> {
+    let object = Object::new();
+    for (i, column) in columns.iter().enumerate() {
+        let value = row.get_raw_checked(i).map_err(to_js_error)?;
+        Reflect::set(&object, &JsValue::from_str(column), &value_to_js(value))?;
+    }
+    Ok(object)
+}
+
+fn result_to_js(result: &QueryResult) -> Array {
+    result
+        .rows
+        .iter()
+        .map(|row| {
+            let object = Object::new();
+            for (column, value) in result.columns.iter().zip(row) {
+                let _ = Reflect::set(
+                    &object,
+                    &JsValue::from_str(column),
+                    &value_to_js(ValueRef::from(value)),
+                );
+            }
+            JsValue::from(object)
+        })
+        .collect()
+}
+
+// Resolves on a later turn of the event loop, after pending rendering and
+// I/O have had a chance to run.
+fn yield_now() -> JsFuture {
+    JsFuture::from(Promise::new(&mut |resolve, _| set_timeout(&resolve, 0)))
+}
+
+// `%IteratorPrototype%[Symbol.iterator]`, a built-in function returning
+// `this`, which makes an object its own async iterator without `eval`, as
+// `new Function` would need against a Content Security Policy.
+fn return_this() -> Result<Function, JsValue> {
+    let array_iterator = Object::get_prototype_of(&Array::new().values().into());
+    let iterator_prototype = Object::get_prototype_of(&array_iterator);
+    Reflect::get(&iterator_prototype, &Symbol::iterator())?.dyn_into()
+}
+
+fn iter_result(value: &JsValue, done: bool) -> JsValue {
+    let result = Object::new();
+    let _ = Reflect::set(&result, &JsValue::from_str("value"), value);
+    let _ = Reflect::set(&result, &JsValue::from_str("done"), &JsValue::from(done));
+    result.into()
+}
+
+// Make the progress handler count to `progress` every `ops` instructions.
+fn install_progress_handler(conn: &Connection, ops: i32, progress: &Arc<AtomicUsize>) {
+    let progress = Arc::clone(progress);
+    conn.progress_handler(
+        ops,
+        Some(move || {
+            progress.fetch_add(1, Ordering::Relaxed);
+            false
+        }),
+    );
+}
+
+// A running query that owns its statement, so that it can outlive the call
+// that started it. It steps the statement itself, as a `Rows` would borrow
+// it.
+pub(crate) struct Cursor {
+    // Declared before `conn`, which it borrows, to be finalized first.
+    stmt: Statement<'static>,
+    conn: Rc<Connection>,
+    columns: Vec<String>,
+    done: bool,
+    // Refreshed once the statement is reset, as it may have committed a
+    // write.
+    subscriptions: Option<Rc<Subscriptions>>,
+    // The progress count when this cursor last yielded. Each cursor keeps
+    // its own, so that concurrent queries all yield.
+    yielded_at: usize,
+}
+
+impl Cursor {
+    pub(crate) fn new(
+        conn: &Rc<Connection>,
+        sql: &str,
+        params: &[Param],
+    ) -> Result<Cursor, JsValue> {
+        let conn = Rc::clone(conn);
+        // The statement only borrows the connection shared, and `conn` keeps
+        // it alive at the same address until the statement is finalized.
+        let stmt = {
+            let c: &'static Connection = unsafe { &*(&*conn as *const Connection) };
+            c.prepare(sql).map_err(to_js_error)?
+        };
+        if params.len() != stmt.parameter_count() {
+            return Err(js_sys::RangeError::new(&format!(
+                "expected {} parameters, got {}",
+                stmt.parameter_count(),
+                params.len()
+            ))
+            .into());
+        }
+        for (i, param) in params.iter().enumerate() {
+            stmt.bind_parameter(param, i + 1).map_err(to_js_error)?;
+        }
+        let columns = stmt.column_names().into_iter().map(String::from).collect();
+        Ok(Cursor {
+            stmt,
+            conn,
+            columns,
+            done: false,
+            subscriptions: None,
+            yielded_at: 0,
+        })
+    }
+
+    pub(crate) fn step(&mut self) -> Result<Option<Object>, JsValue> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.stmt.step() {
+            Ok(true) => row_to_js(&self.columns, &self.stmt.row()).map(Some),
+            Ok(false) => {
+                self.finish()?;
+                Ok(None)
+            }
+            Err(e) => {
+                let _ = self.finish();
+                Err(to_js_error(e))
+            }
+        }
+    }
+
+    // Whether the progress handler ran since this cursor last yielded.
+    fn should_yield(&mut self, progress: &AtomicUsize) -> bool {
+        let now = progress.load(Ordering::Relaxed);
+        let yielded_at = mem::replace(&mut self.yielded_at, now);
+        yielded_at != now
+    }
+
+    // Reset the statement, releasing its transaction, and notify
+    // subscribers of what it committed.
+    fn finish(&mut self) -> Result<(), JsValue> {
+        self.done = true;
+        self.stmt.reset();
+        match self.subscriptions {
+            Some(ref subscriptions) => subscriptions.refresh(&self.conn).map_err(to_js_error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.finish();
+        }
+    }
+}
+
+// Collects every row of a query into an array.
+struct CollectRows {
+    cursor: Cursor,
+    rows: Array,
+    progress: Arc<AtomicUsize>,
+    pending: Option<JsFuture>,
+}
+
+impl Future for CollectRows {
+    type Output = Result<JsValue, JsValue>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            if let Some(pending) = this.pending.as_mut() {
+                match Pin::new(pending).poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(_) => this.pending = None,
+                }
+            }
+            if this.cursor.should_yield(&this.progress) {
+                this.pending = Some(yield_now());
+                continue;
+            }
+            match this.cursor.step() {
+                Ok(Some(row)) => {
+                    this.rows.push(&row);
+                }
+                Ok(None) => return Poll::Ready(Ok(this.rows.clone().into())),
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+// Produces the next iterator result of a `RowIterator`.
+struct NextRow {
+    cursor: Rc<RefCell<Option<Cursor>>>,
+    progress: Arc<AtomicUsize>,
+    pending: Option<JsFuture>,
+}
+
+impl Future for NextRow {
+    type Output = Result<JsValue, JsValue>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            if let Some(pending) = this.pending.as_mut() {
+                match Pin::new(pending).poll(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(_) => this.pending = None,
+                }
+            }
+            // Stepped out of the cell, as the subscribers it notifies once
+            // it finishes may use the iterator.
+            let mut cursor = match this.cursor.borrow_mut().take() {
+                Some(cursor) => cursor,
+                None => return Poll::Ready(Ok(iter_result(&JsValue::UNDEFINED, true))),
+            };
+            if cursor.should_yield(&this.progress) {
+                *this.cursor.borrow_mut() = Some(cursor);
+                this.pending = Some(yield_now());
+                continue;
+            }
+            // The statement is finalized as soon as it is exhausted.
+            return match cursor.step() {
+                Ok(Some(row)) => {
+                    *this.cursor.borrow_mut() = Some(cursor);
+                    Poll::Ready(Ok(iter_result(&row, false)))
+                }
+                Ok(None) => Poll::Ready(Ok(iter_result(&JsValue::UNDEFINED, true))),
+                Err(e) => Poll::Ready(Err(e)),
+            };
+        }
+    }
+}
+
+// Registers the content of a `Blob` as a CSV buffer once it is read.
+struct LoadCsvBlob {
+    buffers: Buffers,
+    name: String,
+    bytes: JsFuture,
+}
+
+impl Future for LoadCsvBlob {
+    type Output = Result<JsValue, JsValue>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        match Pin::new(&mut this.bytes).poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(buffer)) => {
+                let data = Uint8Array::new(&buffer).to_vec();
+                this.buffers.insert(&this.name, data);
+                Poll::Ready(Ok(JsValue::UNDEFINED))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+// Copies written bytes to JS in `Uint8Array` parts of `CSV_CHUNK_SIZE`
+// bytes, so that an export holds no more than a part in wasm memory.
+struct ChunkWriter {
+    parts: Array,
+    buf: Vec<u8>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CSV_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.parts.push(&Uint8Array::from(&self.buf[..]));
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+// Read the options of `exportCsv`.
+fn csv_options(options: &JsValue) -> Result<CsvOptions, JsValue> {
+    let mut csv = CsvOptions::default();
+    if options.is_undefined() || options.is_null() {
+        return Ok(csv);
+    }
+    let get = |key: &str| Reflect::get(options, &JsValue::from_str(key));
+    let invalid = |key: &str| -> JsValue {
+        js_sys::TypeError::new(&format!("invalid '{}' option", key)).into()
+    };
+    let delimiter = get("delimiter")?;
+    if !delimiter.is_undefined() {
+        csv.delimiter = match delimiter.as_string() {
+            Some(ref d) if d.len() == 1 => d.as_bytes()[0],
+            _ => return Err(invalid("delimiter")),
+        };
+    }
+    let quote = get("quote")?;
+    if !quote.is_undefined() {
+        csv.quote_style = match quote.as_string().as_ref().map(String::as_str) {
+            Some("always") => QuoteStyle::Always,
+            Some("necessary") => QuoteStyle::Necessary,
+            Some("nonnumeric") => QuoteStyle::NonNumeric,
+            Some("never") => QuoteStyle::Never,
+            _ => return Err(invalid("quote")),
+        };
+    }
+    let header = get("header")?;
+    if !header.is_undefined() {
+        csv.header = header.as_bool().ok_or_else(|| invalid("header"))?;
+    }
+    let null = get("null")?;
+    if !null.is_undefined() {
+        csv.null = null.as_string().ok_or_else(|| invalid("null"))?;
+    }
+    let blob = get("blob")?;
+    if !blob.is_undefined() {
+        csv.blob_encoding = match blob.as_string().as_ref().map(String::as_str) {
+            Some("hex") => BlobEncoding::Hex,
+            Some("base64") => BlobEncoding::Base64,
+            _ => return Err(invalid("blob")),
+        };
+    }
+    Ok(csv)
+}
+
+fn new_blob(parts: &Array, mime_type: &str) -> Result<JsValue, JsValue> {
+    let constructor =
+        Reflect::get(&js_sys::global(), &JsValue::from_str("Blob"))?.dyn_into::<Function>()?;
+    let options = Object::new();
+    Reflect::set(
+        &options,
+        &JsValue::from_str("type"),
+        &JsValue::from_str(mime_type),
+    )?;
+    Reflect::construct(&constructor, &Array::of2(parts, &options))
+}
+
+/// An async iterator over the rows of a query, returned by
+/// `Database.iterate`.
+#[wasm_bindgen]
+pub struct RowIterator {
+    cursor: Rc<RefCell<Option<Cursor>>>,
+    progress: Arc<AtomicUsize>,
+}
+
+#[wasm_bindgen]
+impl RowIterator {
+    /// Resolve to the next `{ value, done }` result, where `value` is a row
+    /// object keyed by column name.
+    pub fn next(&self) -> Promise {
+        future_to_promise(NextRow {
+            cursor: Rc::clone(&self.cursor),
+            progress: Arc::clone(&self.progress),
+            pending: None,
+        })
+    }
+
+    /// Finalize the statement early, as when a `for await` loop is left
+    /// with `break`.
+    #[wasm_bindgen(js_name = "return")]
+    pub fn finish(&self) -> Promise {
+        // Dropped once the cursor is released, as subscribers it notifies may
+        // use this iterator.
+        let cursor = self.cursor.borrow_mut().take();
+        drop(cursor);
+        Promise::resolve(&iter_result(&JsValue::UNDEFINED, true))
+    }
+}
+
+/// An in-memory SQLite database.
+#[wasm_bindgen]
+pub struct Database {
+    conn: Rc<Connection>,
+    subscriptions: Rc<Subscriptions>,
+    // Counted by the progress handler for the async API to yield.
+    progress: Arc<AtomicUsize>,
+    arrays: Registry,
+    csv_buffers: Buffers,
+}
+
+impl Database {
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    // Start running `sql`, first yielding at the next progress count.
+    fn cursor(&self, sql: &str, params: &Array) -> Result<Cursor, JsValue> {
+        let params = params_from_js(params)?;
+        let mut cursor = Cursor::new(&self.conn, sql, &params)?;
+        cursor.subscriptions = Some(Rc::clone(&self.subscriptions));
+        cursor.yielded_at = self.progress.load(Ordering::Relaxed);
+        Ok(cursor)
+    }
+
+    // Push changes committed by the last call to subscribers.
+    fn refresh(&self) -> Result<(), JsValue> {
+        self.subscriptions.refresh(&self.conn).map_err(to_js_error)
+    }
+}
+
+#[wasm_bindgen]
+impl Database {
+    /// Open a new in-memory database.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Database, JsValue> {
+        let conn = Connection::open_in_memory().map_err(to_js_error)?;
+        let subscriptions = Rc::new(Subscriptions::new(&conn));
+        let progress = Arc::new(AtomicUsize::new(0));
+        install_progress_handler(&conn, DEFAULT_YIELD_OPS, &progress);
+        let arrays = Registry::default();
+        array::load_module(&conn).map_err(to_js_error)?;
+        series::load_module(&conn).map_err(to_js_error)?;
+        vector::load_module(&conn).map_err(to_js_error)?;
+        rtree::load_functions(&conn).map_err(to_js_error)?;
+        builtin::load_functions(&conn).map_err(to_js_error)?;
+        stats::load_functions(&conn).map_err(to_js_error)?;
+        collations::load_collations(&conn).map_err(to_js_error)?;
+        array_table::load_module(&conn, &arrays).map_err(to_js_error)?;
+        let csv_buffers = Buffers::new();
+        csvtab::load_module_with_buffers(&conn, &csv_buffers).map_err(to_js_error)?;
+        Ok(Database {
+            conn: Rc::new(conn),
+            subscriptions,
+            progress,
+            arrays,
+            csv_buffers,
+        })
+    }
+
+    /// Set how many VDBE instructions `queryAsync` and `iterate` may run
+    /// before yielding to the event loop. SQLite cannot be suspended in the
+    /// middle of a step, so yields happen at the next row boundary.
+    #[wasm_bindgen(js_name = setYieldInterval)]
+    pub fn set_yield_interval(&self, ops: i32) {
+        install_progress_handler(&self.conn, ops, &self.progress);
+    }
+
+    /// Run one or more SQL statements that take no parameters.
+    pub fn exec(&self, sql: &str) -> Result<(), JsValue> {
+        self.conn.execute_batch(sql).map_err(to_js_error)?;
+        self.refresh()
+    }
+
+    /// Run a single statement with positional parameters, returning the
+    /// number of changed rows.
+    pub fn run(&self, sql: &str, params: Array) -> Result<usize, JsValue> {
+        let params = params_from_js(&params)?;
+        let changes = self.conn.execute(sql, &params).map_err(to_js_error)?;
+        self.refresh()?;
+        Ok(changes)
+    }
+
+    /// Run a query with positional parameters, returning an array of row
+    /// objects keyed by column name.
+    pub fn query(&self, sql: &str, params: Array) -> Result<Array, JsValue> {
+        let params = params_from_js(&params)?;
+        let mut stmt = self.conn.prepare(sql).map_err(to_js_error)?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = stmt.query(&params).map_err(to_js_error)?;
+        let result = Array::new();
+        while let Some(row) = rows.next().map_err(to_js_error)? {
+            result.push(&row_to_js(&columns, row)?);
+        }
+        drop(rows);
+        self.refresh()?;
+        Ok(result)
+    }
+
+    /// Like `query`, but returns a `Promise` and periodically yields to the
+    /// event loop while the rows are produced.
+    #[wasm_bindgen(js_name = queryAsync)]
+    pub fn query_async(&self, sql: &str, params: Array) -> Promise {
+        match self.cursor(sql, &params) {
+            Ok(cursor) => future_to_promise(CollectRows {
+                cursor,
+                rows: Array::new(),
+                progress: Arc::clone(&self.progress),
+                pending: None,
+            }),
+            Err(e) => Promise::reject(&e),
+        }
+    }
+
+    /// Return an async iterator over the rows of `sql`, for use with
+    /// `for await (const row of db.iterate(sql))`.
+    pub fn iterate(&self, sql: &str, params: Option<Array>) -> Result<JsValue, JsValue> {
+        let cursor = self.cursor(sql, &params.unwrap_or_else(Array::new))?;
+        let iterator = JsValue::from(RowIterator {
+            cursor: Rc::new(RefCell::new(Some(cursor))),
+            progress: Arc::clone(&self.progress),
+        });
+        Reflect::set(&iterator, &Symbol::async_iterator(), &return_this()?)?;
+        Ok(iterator)
+    }
+
+    /// Run a query, returning its rows as an array of `ColumnarBatch`es of up
+    /// to `batchSize` rows each.
+    #[wasm_bindgen(js_name = queryColumnar)]
+    pub fn query_columnar(
+        &self,
+        sql: &str,
+        params: Array,
+        batch_size: usize,
+    ) -> Result<Array, JsValue> {
+        let params = params_from_js(&params)?;
+        let mut stmt = self.conn.prepare(sql).map_err(to_js_error)?;
+        let mut rows = stmt
+            .query_columnar(&params, batch_size)
+            .map_err(to_js_error)?;
+        let result = Array::new();
+        while let Some(batch) = rows.next_batch().map_err(to_js_error)? {
+            // Every batch has the types of the first one.
+            let fields = Rc::new(rows.fields());
+            result.push(&ColumnarBatch::new(fields, batch).into());
+        }
+        Ok(result)
+    }
+
+    /// Run a query, returning its rows as an Arrow IPC stream.
+    #[wasm_bindgen(js_name = queryArrow)]
+    pub fn query_arrow(
+        &self,
+        sql: &str,
+        params: Array,
+        batch_size: usize,
+    ) -> Result<Uint8Array, JsValue> {
+        let params = params_from_js(&params)?;
+        let mut stmt = self.conn.prepare(sql).map_err(to_js_error)?;
+        let rows = stmt
+            .query_columnar(&params, batch_size)
+            .map_err(to_js_error)?;
+        let bytes = rows.to_arrow_ipc().map_err(to_js_error)?;
+        Ok(Uint8Array::from(&bytes[..]))
+    }
+
+    /// Run a query, returning its rows as a CSV `Blob` built in parts.
+    /// `options` may set `delimiter` (default `","`), `quote`
+    /// (`"necessary"`, the default, `"always"`, `"nonnumeric"` or
+    /// `"never"`), `header` (default `true`), `null`, the text written for
+    /// NULL (default empty), and `blob` (`"hex"`, the default, or
+    /// `"base64"`).
+    #[wasm_bindgen(js_name = exportCsv)]
+    pub fn export_csv(
+        &self,
+        sql: &str,
+        params: Array,
+        options: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let options = csv_options(&options)?;
+        let params = params_from_js(&params)?;
+        let mut stmt = self.conn.prepare(sql).map_err(to_js_error)?;
+        let mut writer = ChunkWriter {
+            parts: Array::new(),
+            buf: Vec::with_capacity(CSV_CHUNK_SIZE),
+        };
+        stmt.write_csv(&params, &mut writer, &options)
+            .map_err(to_js_error)?;
+        // Writing to JS cannot fail.
+        writer.flush().unwrap();
+        let mime_type = if options.delimiter == b'\t' {
+            "text/tab-separated-values"
+        } else {
+            "text/csv"
+        };
+        new_blob(&writer.parts, mime_type)
+    }
+
+    /// Expose `array`, an array of plain objects, as the temporary table
+    /// `name`. Its columns are the properties named in `columns`, or else
+    /// those of the first object. The objects are read in place: register
+    /// the array again after modifying them to refresh the indexes.
+    #[wasm_bindgen(js_name = registerArray)]
+    pub fn register_array(
+        &self,
+        name: &str,
+        array: Array,
+        columns: Option<Array>,
+    ) -> Result<(), JsValue> {
+        let columns = match columns {
+            Some(columns) => columns
+                .iter()
+                .map(|c| {
+                    c.as_string().ok_or_else(|| {
+                        js_sys::TypeError::new("column names must be strings").into()
+                    })
+                })
+                .collect::<Result<Vec<String>, JsValue>>()?,
+            None => array_table::infer_columns(&array)?,
+        };
+        let source = ArraySource::new(array, columns);
+        array_table::register(&self.conn, &self.arrays, name, source).map_err(to_js_error)
+    }
+
+    /// Drop the table of an array registered with `registerArray`. Returns
+    /// `false` if there is none.
+    #[wasm_bindgen(js_name = unregisterArray)]
+    pub fn unregister_array(&self, name: &str) -> Result<bool, JsValue> {
+        array_table::unregister(&self.conn, &self.arrays, name).map_err(to_js_error)
+    }
+
+    /// Register the CSV content `data` as `name`, for tables created with
+    /// `USING csv(buffer=name, ...)`.
+    #[wasm_bindgen(js_name = registerCsv)]
+    pub fn register_csv(&self, name: &str, data: &Uint8Array) {
+        self.csv_buffers.insert(name, data.to_vec());
+    }
+
+    /// Like `registerCsv`, reading a `Blob` such as a `File` dropped onto
+    /// the page. Resolves once the buffer is registered.
+    #[wasm_bindgen(js_name = registerCsvBlob)]
+    pub fn register_csv_blob(&self, name: &str, blob: &JsValue) -> Result<Promise, JsValue> {
+        let bytes = Reflect::get(blob, &JsValue::from_str("arrayBuffer"))?
+            .dyn_into::<Function>()?
+            .call0(blob)?
+            .dyn_into::<Promise>()?;
+        Ok(future_to_promise(LoadCsvBlob {
+            buffers: self.csv_buffers.clone(),
+            name: name.to_owned(),
+            bytes: JsFuture::from(bytes),
+        }))
+    }
+
+    /// Remove a buffer registered with `registerCsv`. Returns `false` if
+    /// there is none.
+    #[wasm_bindgen(js_name = unregisterCsv)]
+    pub fn unregister_csv(&self, name: &str) -> bool {
+        self.csv_buffers.remove(name)
+    }
+
+    /// Create the table `table` with the rows of the CSV buffer `name`,
+    /// typing columns INTEGER or REAL when all their values are. The first
+    /// row names the columns unless `header` is `false`.
+    #[wasm_bindgen(js_name = importCsv)]
+    pub fn import_csv(&self, table: &str, name: &str, header: Option<bool>) -> Result<(), JsValue> {
+        let buffer = format!("buffer='{}'", name.replace('\'', "''"));
+        let header = format!(
+            "header={}",
+            if header.unwrap_or(true) { "yes" } else { "no" }
+        );
+        csvtab::create_table_as(&self.conn, table, &[&buffer, &header, "infer=yes"])
+            .map_err(to_js_error)?;
+        self.refresh()
+    }
+
+    /// Register `func` as the SQL function `name`, with options
+    /// `{ deterministic, varargs }`. See the `js_functions` module.
+    #[wasm_bindgen(js_name = createFunction)]
+    pub fn create_function(
+        &self,
+        name: &str,
+        func: Function,
+        options: JsValue,
+    ) -> Result<(), JsValue> {
+        js_functions::create_function(&self.conn, name, func, &options)
+    }
+
+    /// Register the aggregate, or window function if it has an `inverse`,
+    /// defined by `{ init, step, final, inverse, value }`. See the
+    /// `js_functions` module.
+    #[wasm_bindgen(js_name = createAggregate)]
+    pub fn create_aggregate(&self, name: &str, definition: JsValue) -> Result<(), JsValue> {
+        js_functions::create_aggregate(&self.conn, name, &definition)
+    }
+
+    /// Migrate the database with `migrations`, an array of
+    /// `{ name, up, down }` whose `up` and optional `down` are SQL scripts,
+    /// to the latest version or the version `to` of `options`. Versions are
+    /// recorded in `PRAGMA user_version`, or in the bookkeeping table named
+    /// `table` of `options`. Only the bookkeeping table keeps checksums, so
+    /// changes to applied scripts are detected only with `table`. Returns the
+    /// version of the database.
+    pub fn migrate(&self, migrations: Array, options: JsValue) -> Result<u32, JsValue> {
+        let field = |object: &JsValue, key: &str| -> Result<Option<JsValue>, JsValue> {
+            if object.is_undefined() || object.is_null() {
+                return Ok(None);
+            }
+            let value = Reflect::get(object, &JsValue::from_str(key))?;
+            Ok(if value.is_undefined() || value.is_null() {
+                None
+            } else {
+                Some(value)
+            })
+        };
+        let mut list = Vec::new();
+        for (i, definition) in migrations.iter().enumerate() {
+            let name = field(&definition, "name")?
+                .and_then(|name| name.as_string())
+                .unwrap_or_else(|| format!("migration {}", i + 1));
+            let up = field(&definition, "up")?
+                .and_then(|up| up.as_string())
+                .ok_or_else(|| js_sys::TypeError::new(&format!("{}: up is not SQL", name)))?;
+            let mut migration = Migration::sql(&name, &up);
+            if let Some(down) = field(&definition, "down")? {
+                let down = down
+                    .as_string()
+                    .ok_or_else(|| js_sys::TypeError::new(&format!("{}: down is not SQL", name)))?;
+                migration = migration.down_sql(&down);
+            }
+            list.push(migration);
+        }
+        let mut migrations = Migrations::new(list);
+        if let Some(table) = field(&options, "table")?.and_then(|table| table.as_string()) {
+            migrations = migrations.with_table(&table);
+        }
+        let version = match field(&options, "to")? {
+            Some(to) => match to.as_f64() {
+                Some(to) if to.is_finite() && to >= 0.0 && to.fract() == 0.0 => to as usize,
+                _ => {
+                    return Err(js_sys::RangeError::new("to is not a version number").into());
+                }
+            },
+            None => migrations.latest(),
+        };
+        migrations
+            .to_version(&self.conn, version)
+            .map_err(to_js_error)?;
+        self.refresh()?;
+        Ok(version as u32)
+    }
+
+    /// Call `callback` with the rows of `sql` now and after every committed
+    /// transaction that changes them. Returns an id for `unsubscribe`.
+    pub fn subscribe(&self, sql: &str, callback: Function) -> Result<u32, JsValue> {
+        let id = self
+            .subscriptions
+            .subscribe(&self.conn, sql, move |result| {
+                let _ = callback.call1(&JsValue::NULL, &result_to_js(result));
+            })
+            .map_err(to_js_error)?;
+        Ok(id.0)
+    }
+
+    /// Stop notifying a subscription. Returns `false` for an unknown id.
+    pub fn unsubscribe(&self, id: u32) -> bool {
+        self.subscriptions.unsubscribe(SubscriptionId(id))
+    }
+}
@@ -0,0 +1,291 @@
+//! SQL functions and aggregates implemented in JavaScript.
+//!
+//! Arguments are passed to JS as query results are (numbers, strings,
+//! `Uint8Array`s and `null`), and return values converted back as query
+//! parameters are. An exception thrown by a JS callback fails the SQL
+//! statement, with the message of the exception.
+//!
+//! ```js
+//! db.createFunction("slugify", (s) => s.toLowerCase().replace(/\W+/g, "-"), {
+//!   deterministic: true,
+//! });
+//! db.createAggregate("product", {
+//!   init: 1,
+//!   step: (acc, x) => acc * x,
+//! });
+//! // A window function, when `inverse` is given.
+//! db.createAggregate("running_sum", {
+//!   init: () => 0,
+//!   step: (acc, x) => acc + x,
+//!   inverse: (acc, x) => acc - x,
+//! });
+//! ```
+
+use std::ops::Deref;
+use std::os::raw::c_int;
+
+use js_sys::{Array, Function, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::database::{js_to_value, to_js_error, value_to_js};
+use rusqlite::functions::{Aggregate, Context, FunctionFlags, WindowAggregate};
+use rusqlite::types::Value;
+use rusqlite::{Connection, Error, Result};
+
+/// JS values captured by a callback that must be `Send`, such as scalar
+/// functions and collations, for the `Connection` to be.
+pub struct SendJs<T>(pub T);
+
+// This crate is built for wasm32 without threads, where a JS value never
+// leaves the thread that created it.
+unsafe impl<T> Send for SendJs<T> {}
+
+impl<T> Deref for SendJs<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// A JS function called by SQLite.
+struct JsFunction {
+    name: String,
+    func: Function,
+}
+
+/// The options of `createFunction`, also read from the definition object of
+/// `createAggregate`.
+struct Options {
+    /// The function always gives the same result for the same arguments.
+    deterministic: bool,
+    /// The function takes any number of arguments, rather than the `length`
+    /// of its JS function.
+    varargs: bool,
+}
+
+impl Options {
+    fn from_js(options: &JsValue) -> std::result::Result<Options, JsValue> {
+        if options.is_undefined() || options.is_null() {
+            return Ok(Options {
+                deterministic: false,
+                varargs: false,
+            });
+        }
+        let flag = |key: &str| -> std::result::Result<bool, JsValue> {
+            Ok(Reflect::get(options, &JsValue::from_str(key))?.is_truthy())
+        };
+        Ok(Options {
+            deterministic: flag("deterministic")?,
+            varargs: flag("varargs")?,
+        })
+    }
+
+    fn flags(&self) -> FunctionFlags {
+        if self.deterministic {
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC
+        } else {
+            FunctionFlags::SQLITE_UTF8
+        }
+    }
+
+    /// The number of SQL arguments of `func`, whose first `state_args`
+    /// arguments are not passed from SQL.
+    fn n_arg(&self, func: &Function, state_args: u32) -> c_int {
+        if self.varargs {
+            -1
+        } else {
+            func.length().saturating_sub(state_args) as c_int
+        }
+    }
+}
+
+pub fn exception_message(exception: &JsValue) -> String {
+    match exception.dyn_ref::<js_sys::Error>() {
+        Some(e) => String::from(e.message()),
+        None => exception
+            .as_string()
+            .unwrap_or_else(|| format!("{:?}", exception)),
+    }
+}
+
+/// Call `func` with `state`, if any, followed by the SQL arguments of `ctx`.
+fn call(
+    func: &Function,
+    this: &JsValue,
+    state: Option<&JsValue>,
+    ctx: Option<&Context<'_>>,
+) -> Result<JsValue> {
+    let args = Array::new();
+    if let Some(state) = state {
+        args.push(state);
+    }
+    if let Some(ctx) = ctx {
+        for i in 0..ctx.len() {
+            args.push(&value_to_js(ctx.get_raw(i)));
+        }
+    }
+    func.apply(this, &args)
+        .map_err(|e| Error::UserFunctionError(exception_message(&e).into()))
+}
+
+fn to_value(name: &str, value: &JsValue) -> Result<Value> {
+    js_to_value(value).map_err(|_| {
+        Error::UserFunctionError(format!("{}() returned an unsupported value", name).into())
+    })
+}
+
+/// Register the JS function `func` as the SQL scalar function `name`.
+pub fn create_function(
+    conn: &Connection,
+    name: &str,
+    func: Function,
+    options: &JsValue,
+) -> std::result::Result<(), JsValue> {
+    let options = Options::from_js(options)?;
+    let n_arg = options.n_arg(&func, 0);
+    let function = SendJs(JsFunction {
+        name: name.to_owned(),
+        func,
+    });
+    conn.create_scalar_function(name, n_arg, options.flags(), move |ctx| {
+        let result = call(&function.func, &JsValue::NULL, None, Some(ctx))?;
+        to_value(&function.name, &result)
+    })
+    .map_err(to_js_error)
+}
+
+/// An aggregate defined by JS callbacks, called with the definition object
+/// as `this`.
+struct JsAggregate {
+    name: String,
+    this: JsValue,
+    /// The initial state, or a function returning it
+    init: JsValue,
+    step: Function,
+    finalize: Option<Function>,
+    inverse: Option<Function>,
+    value: Option<Function>,
+}
+
+impl JsAggregate {
+    fn initial_state(&self) -> Result<JsValue> {
+        match self.init.dyn_ref::<Function>() {
+            Some(init) => call(init, &self.this, None, None),
+            None => Ok(self.init.clone()),
+        }
+    }
+
+    /// Call `func` with the state, the initial one without rows, or return
+    /// the state without `func`.
+    fn result(&self, func: Option<&Function>, state: Option<&JsValue>) -> Result<Value> {
+        let initial;
+        let state = match state {
+            Some(state) => state,
+            None => {
+                initial = self.initial_state()?;
+                &initial
+            }
+        };
+        let result = match func {
+            Some(func) => call(func, &self.this, Some(state), None)?,
+            None => state.clone(),
+        };
+        to_value(&self.name, &result)
+    }
+
+    /// Replace the state by the result of `func`, unless it is `undefined`:
+    /// the state was then updated in place.
+    fn update(
+        &self,
+        func: &Function,
+        ctx: &Context<'_>,
+        state: &mut Option<JsValue>,
+    ) -> Result<()> {
+        let current = match state.take() {
+            Some(current) => current,
+            None => self.initial_state()?,
+        };
+        let next = call(func, &self.this, Some(&current), Some(ctx))?;
+        *state = Some(if next.is_undefined() { current } else { next });
+        Ok(())
+    }
+}
+
+// The state is created by the first `step`, where an exception thrown by
+// `init` can be reported.
+impl Aggregate<Option<JsValue>, Value> for JsAggregate {
+    fn init(&self) -> Option<JsValue> {
+        None
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, state: &mut Option<JsValue>) -> Result<()> {
+        self.update(&self.step, ctx, state)
+    }
+
+    fn finalize(&self, state: Option<Option<JsValue>>) -> Result<Value> {
+        self.result(self.finalize.as_ref(), state.flatten().as_ref())
+    }
+}
+
+impl WindowAggregate<Option<JsValue>, Value> for JsAggregate {
+    fn value(&self, state: Option<&Option<JsValue>>) -> Result<Value> {
+        let func = self.value.as_ref().or_else(|| self.finalize.as_ref());
+        self.result(func, state.and_then(Option::as_ref))
+    }
+
+    fn inverse(&self, ctx: &mut Context<'_>, state: &mut Option<JsValue>) -> Result<()> {
+        let inverse = self
+            .inverse
+            .as_ref()
+            .expect("window function without inverse");
+        self.update(inverse, ctx, state)
+    }
+}
+
+/// Register the aggregate defined by `definition`, an object with:
+/// * `init`: the initial state, or a function returning it (default: `undefined`),
+/// * `step(state, ...args)`: returns the state after a row,
+/// * `final(state)`: returns the result (default: the state),
+/// * `inverse(state, ...args)`: returns the state after removing a row from
+///   the window, which makes the aggregate a window function,
+/// * `value(state)`: returns the current result of a window (default: `final`),
+///
+/// and the options of `create_function`.
+pub fn create_aggregate(
+    conn: &Connection,
+    name: &str,
+    definition: &JsValue,
+) -> std::result::Result<(), JsValue> {
+    let options = Options::from_js(definition)?;
+    let get = |key: &str| Reflect::get(definition, &JsValue::from_str(key));
+    let function = |key: &str| -> std::result::Result<Option<Function>, JsValue> {
+        let value = get(key)?;
+        if value.is_undefined() || value.is_null() {
+            return Ok(None);
+        }
+        value
+            .dyn_into::<Function>()
+            .map(Some)
+            .map_err(|_| js_sys::TypeError::new(&format!("{} is not a function", key)).into())
+    };
+    let step = function("step")?
+        .ok_or_else(|| JsValue::from(js_sys::TypeError::new("step is required")))?;
+    let aggregate = JsAggregate {
+        name: name.to_owned(),
+        this: definition.clone(),
+        init: get("init")?,
+        finalize: function("final")?,
+        inverse: function("inverse")?,
+        value: function("value")?,
+        step,
+    };
+    let n_arg = options.n_arg(&aggregate.step, 1);
+    let result = if aggregate.inverse.is_some() {
+        conn.create_window_function(name, n_arg, options.flags(), aggregate)
+    } else {
+        conn.create_aggregate_function(name, n_arg, options.flags(), aggregate)
+    };
+    result.map_err(to_js_error)
+}
@@ -4,7 +4,7 @@ use wasm_bindgen::prelude::*;
 
 extern crate rusqlite;
 
-use rusqlite::{params, Connection};
+use rusqlite::{params, params_from_serialize, Connection};
 
 /*extern crate libc_sys;*/
 /*
@@ -33,11 +33,24 @@ extern crate wee_alloc;
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 extern crate js_sys;
-#[cfg(test)]
+extern crate serde;
+extern crate wasm_bindgen_futures;
 #[macro_use]
 extern crate lazy_static;
 
-#[derive(Debug, Clone)]
+pub mod array_table;
+pub mod collations;
+pub mod columnar;
+pub mod database;
+pub mod js_functions;
+pub mod worker;
+
+pub use database::Database;
+pub use worker::{start_worker, WorkerDatabase};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct Person {
     id: i32,
     name: String,
@@ -82,19 +95,22 @@ pub fn start() {
             };
             println!(
                 "Inserting into TABLE: \
-            INSERT INTO person(name,time_created,data) VALUES (?1, ?2, ?3,?4)"
+            INSERT INTO person(id,name,time_created,data) VALUES (:id, :name, :time_created, :data)"
             );
 
             for i in 0..10 {
-                conn.execute(
-                    "INSERT INTO person (id,name, time_created, data)
-                                   VALUES (?1, ?2, ?3,?4)",
-                    params![
-                        i,
-                        me.name.clone() + &i.to_string().clone(),
-                        js_sys::Date::new_0().value_of(),
-                        me.data
-                    ],
+                let person = Person {
+                    id: i,
+                    name: me.name.clone() + &i.to_string(),
+                    time_created: js_sys::Date::new_0().value_of(),
+                    data: me.data.clone(),
+                };
+                conn.execute_named(
+                    "INSERT INTO person (id, name, time_created, data)
+                                   VALUES (:id, :name, :time_created, :data)",
+                    &params_from_serialize(&person)
+                        .expect("Error binding record.")
+                        .to_named(),
                 )
                 .expect("Error inserting record.");
             }
@@ -107,14 +123,7 @@ pub fn start() {
                 .expect("Error preparing statement.");
 
             let person_iter = stmt
-                .query_map(params![], |row| {
-                    Ok(Person {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        time_created: row.get(2)?,
-                        data: row.get(3)?,
-                    })
-                })
+                .query_as::<Person, _>(params![])
                 .expect("Select query failed");
 
             for p in person_iter {
@@ -2008,1440 +2017,4582 @@ mod test {
         assert!(CALLED.load(Ordering::Relaxed));
     }
 
-    use rusqlite::ffi::Limit;
+    #[wasm_bindgen_test]
+    fn test_preupdate_hook() {
+        use rusqlite::types::ValueRef;
+        use rusqlite::PreUpdateCase;
+
+        let db = Connection::open_in_memory().unwrap();
+
+        lazy_static! {
+            static ref CALLED: AtomicBool = AtomicBool::new(false);
+        }
+        db.execute_batch("CREATE TABLE foo (t TEXT)").unwrap();
+        db.execute_batch("INSERT INTO foo VALUES ('lisa')").unwrap();
+        db.preupdate_hook(Some(|action, db: &str, tbl: &str, case: &PreUpdateCase| {
+            assert_eq!(Action::SQLITE_UPDATE, action);
+            assert_eq!("main", db);
+            assert_eq!("foo", tbl);
+            assert_eq!(1, case.column_count());
+            assert_eq!(0, case.depth());
+            assert_eq!(1, case.old_rowid());
+            assert_eq!(ValueRef::Text(b"lisa"), case.old(0).unwrap());
+            assert_eq!(ValueRef::Text(b"bart"), case.new(0).unwrap());
+            assert!(case.old(1).is_err());
+            CALLED.store(true, Ordering::Relaxed);
+        }));
+        db.execute_batch("UPDATE foo SET t = 'bart'").unwrap();
+        assert!(CALLED.load(Ordering::Relaxed));
+    }
 
     #[wasm_bindgen_test]
-    fn test_limit() {
+    fn test_progress_handler() {
         let db = Connection::open_in_memory().unwrap();
-        db.set_limit(Limit::SQLITE_LIMIT_LENGTH, 1024);
-        assert_eq!(1024, db.limit(Limit::SQLITE_LIMIT_LENGTH));
 
-        db.set_limit(Limit::SQLITE_LIMIT_SQL_LENGTH, 1024);
-        assert_eq!(1024, db.limit(Limit::SQLITE_LIMIT_SQL_LENGTH));
+        lazy_static! {
+            static ref CALLED: AtomicBool = AtomicBool::new(false);
+        }
+        db.progress_handler(
+            1,
+            Some(|| {
+                CALLED.store(true, Ordering::Relaxed);
+                false
+            }),
+        );
+        db.execute_batch("BEGIN; CREATE TABLE foo (t TEXT); COMMIT;")
+            .unwrap();
+        assert!(CALLED.load(Ordering::Relaxed));
+    }
 
-        db.set_limit(Limit::SQLITE_LIMIT_COLUMN, 64);
-        assert_eq!(64, db.limit(Limit::SQLITE_LIMIT_COLUMN));
+    #[wasm_bindgen_test]
+    fn test_progress_handler_interrupt() {
+        let db = Connection::open_in_memory().unwrap();
 
-        db.set_limit(Limit::SQLITE_LIMIT_EXPR_DEPTH, 256);
-        assert_eq!(256, db.limit(Limit::SQLITE_LIMIT_EXPR_DEPTH));
+        fn handler() -> bool {
+            true
+        }
 
-        db.set_limit(Limit::SQLITE_LIMIT_COMPOUND_SELECT, 32);
-        assert_eq!(32, db.limit(Limit::SQLITE_LIMIT_COMPOUND_SELECT));
+        db.progress_handler(1, Some(handler));
+        db.execute_batch("BEGIN; CREATE TABLE foo (t TEXT); COMMIT;")
+            .unwrap_err();
 
-        db.set_limit(Limit::SQLITE_LIMIT_FUNCTION_ARG, 32);
-        assert_eq!(32, db.limit(Limit::SQLITE_LIMIT_FUNCTION_ARG));
+        db.progress_handler(0, None::<fn() -> bool>);
+        db.execute_batch("CREATE TABLE bar (t TEXT)").unwrap();
+    }
 
-        db.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 2);
-        assert_eq!(2, db.limit(Limit::SQLITE_LIMIT_ATTACHED));
+    #[wasm_bindgen_test]
+    fn test_session_changeset_apply() {
+        use fallible_streaming_iterator::FallibleStreamingIterator;
+        use rusqlite::session::{Changeset, ConflictAction, ConflictType, Session};
 
-        db.set_limit(Limit::SQLITE_LIMIT_LIKE_PATTERN_LENGTH, 128);
-        assert_eq!(128, db.limit(Limit::SQLITE_LIMIT_LIKE_PATTERN_LENGTH));
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(t TEXT PRIMARY KEY NOT NULL);")
+            .unwrap();
 
-        db.set_limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER, 99);
-        assert_eq!(99, db.limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER));
+        let bytes = {
+            let mut session = Session::new(&db).unwrap();
+            assert!(session.is_empty());
+            session.attach(None).unwrap();
+            db.execute("INSERT INTO foo (t) VALUES (?);", &["bar"])
+                .unwrap();
+            let changeset = session.changeset().unwrap();
+            changeset.as_bytes().to_vec()
+        };
+        let changeset = Changeset::from_bytes(&bytes).unwrap();
+
+        let mut iter = changeset.iter().unwrap();
+        let item = iter.next().unwrap().unwrap();
+        let op = item.op().unwrap();
+        assert_eq!("foo", op.table_name());
+        assert_eq!(1, op.number_of_columns());
+        assert_eq!(Action::SQLITE_INSERT, op.code());
+        assert_eq!(&[1], item.pk().unwrap());
+        assert_eq!(Some("bar"), item.new_value(0).unwrap().as_str().ok());
+        drop(iter);
+
+        let inverted = changeset.invert().unwrap();
+        db.apply(
+            &inverted,
+            None::<fn(&str) -> bool>,
+            |_conflict_type, _item| ConflictAction::SQLITE_CHANGESET_OMIT,
+        )
+        .unwrap();
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, count);
 
-        // SQLITE_LIMIT_TRIGGER_DEPTH was added in SQLite 3.6.18.
-        if rusqlite::version_number() >= 3_006_018 {
-            db.set_limit(Limit::SQLITE_LIMIT_TRIGGER_DEPTH, 32);
-            assert_eq!(32, db.limit(Limit::SQLITE_LIMIT_TRIGGER_DEPTH));
-        }
-        /*
-        // SQLITE_LIMIT_WORKER_THREADS was added in SQLite 3.8.7.
-        if rusqlite::version_number() >= 3_008_007 {
-            db.set_limit(Limit::SQLITE_LIMIT_WORKER_THREADS, 2);
-            assert_eq!(2, db.limit(Limit::SQLITE_LIMIT_WORKER_THREADS));
+        lazy_static! {
+            static ref CALLED: AtomicBool = AtomicBool::new(false);
         }
-        */
+        db.execute("INSERT INTO foo (t) VALUES (?);", &["bar"])
+            .unwrap();
+        db.apply(
+            &changeset,
+            Some(|tbl: &str| tbl == "foo"),
+            |conflict_type, item| {
+                assert_eq!(ConflictType::SQLITE_CHANGESET_CONFLICT, conflict_type);
+                let conflict = item.conflict(0).unwrap();
+                assert_eq!(Ok("bar"), conflict.as_str());
+                CALLED.store(true, Ordering::Relaxed);
+                ConflictAction::SQLITE_CHANGESET_OMIT
+            },
+        )
+        .unwrap();
+        assert!(CALLED.load(Ordering::Relaxed));
     }
 
-    use rusqlite::pragma;
-    use rusqlite::pragma::Sql;
-
     #[wasm_bindgen_test]
-    fn pragma_query_value() {
+    fn test_subscription_refresh() {
+        use rusqlite::subscription::Subscriptions;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
         let db = Connection::open_in_memory().unwrap();
-        let user_version: i32 = db
-            .pragma_query_value(None, "user_version", |row| row.get(0))
+        db.execute_batch("CREATE TABLE foo (t TEXT); CREATE TABLE bar (t TEXT);")
             .unwrap();
-        assert_eq!(0, user_version);
+        let subscriptions = Subscriptions::new(&db);
+
+        let counts = Rc::new(RefCell::new(Vec::new()));
+        let seen = counts.clone();
+        let id = subscriptions
+            .subscribe(&db, "SELECT count(*) FROM foo", move |result| {
+                seen.borrow_mut().push(result.rows[0][0].clone());
+            })
+            .unwrap();
+        assert_eq!(vec![Value::Integer(0)], *counts.borrow());
+        assert!(subscriptions
+            .subscribe(&db, "DELETE FROM foo", |_| {})
+            .is_err());
+
+        db.execute_batch("INSERT INTO bar VALUES ('lisa')").unwrap();
+        subscriptions.refresh(&db).unwrap();
+        assert_eq!(1, counts.borrow().len());
+
+        db.execute_batch("BEGIN; INSERT INTO foo VALUES ('lisa'); ROLLBACK;")
+            .unwrap();
+        subscriptions.refresh(&db).unwrap();
+        assert_eq!(1, counts.borrow().len());
+
+        db.execute_batch("INSERT INTO foo VALUES ('bart')").unwrap();
+        assert!(subscriptions.is_dirty());
+        subscriptions.refresh(&db).unwrap();
+        assert_eq!(
+            vec![Value::Integer(0), Value::Integer(1)],
+            *counts.borrow()
+        );
+
+        assert!(subscriptions.unsubscribe(id));
+        db.execute_batch("INSERT INTO foo VALUES ('homer')").unwrap();
+        subscriptions.refresh(&db).unwrap();
+        assert_eq!(2, counts.borrow().len());
     }
 
     #[wasm_bindgen_test]
-    fn pragma_func_query_value() {
-        use rusqlite::NO_PARAMS;
+    fn test_subscription_reentrancy() {
+        use rusqlite::subscription::Subscriptions;
+        use std::cell::RefCell;
+        use std::rc::Rc;
 
-        let db = Connection::open_in_memory().unwrap();
-        let user_version: i32 = db
-            .query_row(
-                "SELECT user_version FROM pragma_user_version",
-                NO_PARAMS,
-                |row| row.get(0),
-            )
+        let db = Rc::new(Connection::open_in_memory().unwrap());
+        db.execute_batch("CREATE TABLE foo (x); CREATE TABLE log (n);")
             .unwrap();
-        assert_eq!(0, user_version);
+        let subscriptions = Rc::new(Subscriptions::new(&db));
+
+        // Log the count of foo from the callback, and push it at once.
+        let (conn, subs) = (db.clone(), subscriptions.clone());
+        let counter = subscriptions
+            .subscribe(&db, "SELECT count(*) FROM foo", move |result| {
+                conn.execute("INSERT INTO log VALUES (?)", &[&result.rows[0][0]])
+                    .unwrap();
+                subs.refresh(&conn).unwrap();
+            })
+            .unwrap();
+        let logged = Rc::new(RefCell::new(Vec::new()));
+        let seen = logged.clone();
+        let log = subscriptions
+            .subscribe(&db, "SELECT n FROM log", move |result| {
+                *seen.borrow_mut() = result.rows.iter().map(|row| row[0].clone()).collect();
+            })
+            .unwrap();
+        assert_eq!(vec![Value::Integer(0)], *logged.borrow());
+
+        db.execute("INSERT INTO foo VALUES (1)", NO_PARAMS).unwrap();
+        subscriptions.refresh(&db).unwrap();
+        assert_eq!(vec![Value::Integer(0), Value::Integer(1)], *logged.borrow());
+
+        // Unsubscribe from a callback.
+        let subs = subscriptions.clone();
+        let remover = subscriptions
+            .subscribe(&db, "SELECT max(x) FROM foo", move |result| {
+                if result.rows[0][0] == Value::Integer(2) {
+                    assert!(subs.unsubscribe(log));
+                }
+            })
+            .unwrap();
+        db.execute("INSERT INTO foo VALUES (2)", NO_PARAMS).unwrap();
+        subscriptions.refresh(&db).unwrap();
+        assert_eq!(3, logged.borrow().len());
+        db.execute("INSERT INTO foo VALUES (3)", NO_PARAMS).unwrap();
+        subscriptions.refresh(&db).unwrap();
+        assert_eq!(3, logged.borrow().len());
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM log", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(4, count);
+        assert!(subscriptions.unsubscribe(counter));
+        assert!(subscriptions.unsubscribe(remover));
     }
 
     #[wasm_bindgen_test]
-    fn pragma_query_no_schema() {
-        let db = Connection::open_in_memory().unwrap();
-        let mut user_version = -1;
-        db.pragma_query(None, "user_version", |row| {
-            user_version = row.get(0)?;
-            Ok(())
-        })
-        .unwrap();
-        assert_eq!(0, user_version);
+    fn test_database_big_integers() {
+        use crate::database::Database;
+        use js_sys::{Array, Object, Reflect};
+
+        let db = Database::new().unwrap();
+        let rows = db
+            .query(
+                "SELECT 9007199254740991 AS safe, 9007199254740993 AS big,
+                        -9223372036854775808 AS min",
+                Array::new(),
+            )
+            .unwrap();
+        let field = |key: &str| Reflect::get(&rows.get(0), &key.into()).unwrap();
+        assert_eq!(Some(9_007_199_254_740_991.0), field("safe").as_f64());
+        // BigInts, which keep the precision that numbers would lose
+        for &(key, text) in &[("big", "9007199254740993"), ("min", "-9223372036854775808")] {
+            assert_eq!(None, field(key).as_f64());
+            assert_eq!(text, String::from(Object::from(field(key)).to_string()));
+        }
     }
 
     #[wasm_bindgen_test]
-    fn pragma_query_with_schema() {
-        let db = Connection::open_in_memory().unwrap();
-        let mut user_version = -1;
-        db.pragma_query(Some(DatabaseName::Main), "user_version", |row| {
-            user_version = row.get(0)?;
-            Ok(())
-        })
-        .unwrap();
-        assert_eq!(0, user_version);
+    async fn test_unlock_notify() {
+        use js_sys::Promise;
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use wasm_bindgen::JsValue;
+        use wasm_bindgen_futures::JsFuture;
+
+        let db1 = Connection::open_shared_in_memory("test_unlock_notify").unwrap();
+        let db2 = Rc::new(Connection::open_shared_in_memory("test_unlock_notify").unwrap());
+        db1.execute_batch("CREATE TABLE foo (x)").unwrap();
+
+        db1.execute_batch("BEGIN; INSERT INTO foo VALUES (42);")
+            .unwrap();
+        let select = |db: &Connection| -> Result<i64> {
+            db.query_row("SELECT x FROM foo", NO_PARAMS, |r| r.get(0))
+        };
+        match select(&db2).unwrap_err() {
+            Error::SqliteFailure(err, _) => {
+                assert_eq!(err.code, ErrorCode::DatabaseLocked);
+                assert_eq!(err.extended_code, ffi::SQLITE_LOCKED_SHAREDCACHE);
+            }
+            err => panic!("Unexpected error {}", err),
+        }
+        // The callback retries the statement.
+        let fired = Rc::new(Cell::new(false));
+        let retried = Promise::new(&mut |resolve, _| {
+            let (db, fired) = (db2.clone(), fired.clone());
+            db2.unlock_notify(move || {
+                fired.set(true);
+                let x = select(&db).unwrap();
+                resolve.call1(&JsValue::NULL, &(x as f64).into()).unwrap();
+            })
+            .unwrap();
+        });
+        // A statement blocked meanwhile leaves the callback registered.
+        assert!(select(&db2).is_err());
+        JsFuture::from(Promise::resolve(&JsValue::NULL))
+            .await
+            .unwrap();
+        assert!(!fired.get());
+
+        db1.execute_batch("COMMIT").unwrap();
+        assert_eq!(Some(42.0), JsFuture::from(retried).await.unwrap().as_f64());
+        assert!(fired.get());
     }
 
     #[wasm_bindgen_test]
-    fn pragma() {
-        let db = Connection::open_in_memory().unwrap();
-        let mut columns = Vec::new();
-        db.pragma(None, "table_info", &"sqlite_master", |row| {
-            let column: String = row.get(1)?;
-            columns.push(column);
-            Ok(())
-        })
+    async fn test_database_query_async() {
+        use crate::database::Database;
+        use js_sys::{Array, Reflect};
+        use wasm_bindgen_futures::JsFuture;
+
+        let db = Database::new().unwrap();
+        db.exec("CREATE TABLE t(x); INSERT INTO t SELECT value FROM generate_series(1, 1000);")
+            .unwrap();
+        // Yield many times while the rows are collected.
+        db.set_yield_interval(100);
+        let rows = JsFuture::from(db.query_async(
+            "SELECT x FROM t WHERE x > ? ORDER BY x",
+            Array::of1(&10.into()),
+        ))
+        .await
         .unwrap();
-        assert_eq!(5, columns.len());
+        let rows = Array::from(&rows);
+        assert_eq!(990, rows.length());
+        let x = |i: u32| Reflect::get(&rows.get(i), &"x".into()).unwrap().as_f64();
+        assert_eq!((Some(11.0), Some(1000.0)), (x(0), x(989)));
+
+        let query = |sql: &str, params: Array| JsFuture::from(db.query_async(sql, params));
+        assert!(query("SELECT * FROM missing", Array::new()).await.is_err());
+        assert!(query("SELECT ?", Array::new()).await.is_err());
+        // The statement of the first query is finalized.
+        db.exec("DROP TABLE t").unwrap();
     }
 
     #[wasm_bindgen_test]
-    fn pragma_func() {
-        let db = Connection::open_in_memory().unwrap();
-        let mut table_info = db.prepare("SELECT * FROM pragma_table_info(?)").unwrap();
-        let mut columns = Vec::new();
-        let mut rows = table_info.query(&["sqlite_master"]).unwrap();
+    async fn test_database_iterate() {
+        use crate::database::Database;
+        use js_sys::{Function, Promise, Reflect, Symbol};
+        use wasm_bindgen::{JsCast, JsValue};
+        use wasm_bindgen_futures::JsFuture;
+
+        let method = |object: &JsValue, key: &JsValue| -> Function {
+            Reflect::get(object, key).unwrap().dyn_into().unwrap()
+        };
+        let next = |iterator: &JsValue| {
+            let promise = method(iterator, &"next".into()).call0(iterator).unwrap();
+            JsFuture::from(Promise::from(promise))
+        };
+        let field = |object: &JsValue, key: &str| Reflect::get(object, &key.into()).unwrap();
 
-        while let Some(row) = rows.next().unwrap() {
-            let row = row;
-            let column: String = row.get(1).unwrap();
-            columns.push(column);
+        let db = Database::new().unwrap();
+        db.set_yield_interval(10);
+        let iterator = db
+            .iterate("SELECT value FROM generate_series(1, 5)", None)
+            .unwrap();
+        // `for await` calls `iterator[Symbol.asyncIterator]()`.
+        let async_iterator = method(&iterator, &Symbol::async_iterator().into());
+        assert_eq!(iterator, async_iterator.call0(&iterator).unwrap());
+        let mut values = Vec::new();
+        loop {
+            let result = next(&iterator).await.unwrap();
+            if field(&result, "done").as_bool().unwrap() {
+                break;
+            }
+            values.push(field(&field(&result, "value"), "value").as_f64().unwrap());
         }
-        assert_eq!(5, columns.len());
-    }
+        assert_eq!(vec![1.0, 2.0, 3.0, 4.0, 5.0], values);
+        assert!(field(&next(&iterator).await.unwrap(), "done").as_bool().unwrap());
 
-    #[wasm_bindgen_test]
-    fn pragma_update() {
-        let db = Connection::open_in_memory().unwrap();
-        db.pragma_update(None, "user_version", &1).unwrap();
-    }
+        // Leaving a loop early finalizes the statement.
+        db.exec("CREATE TABLE t(x); INSERT INTO t VALUES (1), (2);")
+            .unwrap();
+        let iterator = db.iterate("SELECT x FROM t", None).unwrap();
+        let first = next(&iterator).await.unwrap();
+        assert_eq!(Some(1.0), field(&field(&first, "value"), "x").as_f64());
+        assert!(db.exec("DROP TABLE t").is_err());
+        let finish = method(&iterator, &"return".into()).call0(&iterator).unwrap();
+        JsFuture::from(Promise::from(finish)).await.unwrap();
+        db.exec("DROP TABLE t").unwrap();
 
-    /* #[wasm_bindgen_test]
-        fn pragma_update_and_check() {
-            let db = Connection::open_in_memory().unwrap();
-            let journal_mode: String = db
-                .pragma_update_and_check(None, "journal_mode", &"OFF", |row| row.get(0))
-                .unwrap();
-            assert_eq!("off", &journal_mode);
-        }
-    */
-    #[wasm_bindgen_test]
-    fn is_identifier() {
-        assert!(pragma::is_identifier("full"));
-        assert!(pragma::is_identifier("r2d2"));
-        assert!(!pragma::is_identifier("sp ce"));
-        assert!(!pragma::is_identifier("semi;colon"));
+        assert!(db.iterate("SELECT * FROM missing", None).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn double_quote() {
-        let mut sql = Sql::new();
-        sql.push_schema_name(DatabaseName::Attached(r#"schema";--"#));
-        assert_eq!(r#""schema"";--""#, sql.as_str());
+    async fn test_database_query_notifies_subscribers() {
+        use crate::database::Database;
+        use js_sys::{Array, Function, Promise, Reflect};
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+
+        let db = Database::new().unwrap();
+        db.exec("CREATE TABLE t(x)").unwrap();
+        let results = Array::new();
+        let callback = Function::new_with_args("result", "this.push(result)").bind(&results);
+        db.subscribe("SELECT count(*) FROM t", callback).unwrap();
+        assert_eq!(1, results.length());
+
+        db.query("INSERT INTO t VALUES (1)", Array::new()).unwrap();
+        assert_eq!(2, results.length());
+        JsFuture::from(db.query_async("INSERT INTO t VALUES (2)", Array::new()))
+            .await
+            .unwrap();
+        assert_eq!(3, results.length());
+        let iterator = db.iterate("INSERT INTO t VALUES (3)", None).unwrap();
+        let next: Function = Reflect::get(&iterator, &"next".into())
+            .unwrap()
+            .dyn_into()
+            .unwrap();
+        let promise = Promise::from(next.call0(&iterator).unwrap());
+        JsFuture::from(promise).await.unwrap();
+        assert_eq!(4, results.length());
     }
 
     #[wasm_bindgen_test]
-    fn wrap_and_escape() {
-        let mut sql = Sql::new();
-        sql.push_string_literal("value'; --");
-        assert_eq!("'value''; --'", sql.as_str());
-    }
+    fn test_worker_host() {
+        use js_sys::{Array, Object, Reflect, Uint8Array};
+        use wasm_bindgen::{JsCast, JsValue};
+        use crate::worker::Host;
+
+        fn request(host: &mut Host, fields: &[(&str, JsValue)]) -> (JsValue, Array) {
+            let request = Object::new();
+            for &(key, ref value) in fields {
+                Reflect::set(&request, &JsValue::from_str(key), value).unwrap();
+            }
+            host.handle(&request)
+        }
+        fn field(response: &JsValue, key: &str) -> JsValue {
+            Reflect::get(response, &JsValue::from_str(key)).unwrap()
+        }
 
-    use rusqlite::types::{FromSql, ToSql, Value};
+        let mut host = Host::default();
+        let (response, _) = request(&mut host, &[("id", 7.into()), ("op", "exec".into())]);
+        assert_eq!(JsValue::from(7), field(&response, "id"));
+        assert!(field(&response, "error").is_string());
 
-    #[wasm_bindgen_test]
-    fn test_execute_named() {
-        let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+        let (response, _) = request(&mut host, &[("id", 0.into()), ("op", "open".into())]);
+        assert!(field(&response, "error").is_undefined());
+        request(
+            &mut host,
+            &[
+                ("id", 1.into()),
+                ("op", "exec".into()),
+                ("sql", "CREATE TABLE foo (x INTEGER, b BLOB)".into()),
+            ],
+        );
+        let params = Array::of2(&1.into(), &Uint8Array::from(&[1u8, 2][..]));
+        let (response, _) = request(
+            &mut host,
+            &[
+                ("id", 2.into()),
+                ("op", "exec".into()),
+                ("sql", "INSERT INTO foo VALUES (?, ?)".into()),
+                ("params", params.into()),
+            ],
+        );
+        assert_eq!(JsValue::from(1), field(&response, "result"));
 
-        assert_eq!(
-            db.execute_named("INSERT INTO foo(x) VALUES (:x)", &[(":x", &1i32)])
-                .unwrap(),
-            1
+        let (response, transfer) = request(
+            &mut host,
+            &[
+                ("id", 10.into()),
+                ("op", "query".into()),
+                ("sql", "SELECT x, b FROM foo".into()),
+            ],
+        );
+        let rows = field(&response, "result").dyn_into::<Array>().unwrap();
+        assert_eq!(1, rows.length());
+        assert_eq!(JsValue::from(1), field(&rows.get(0), "x"));
+        assert_eq!(1, transfer.length());
+
+        let (response, _) = request(
+            &mut host,
+            &[
+                ("id", 3.into()),
+                ("op", "prepare".into()),
+                ("sql", "SELECT x, b FROM foo".into()),
+            ],
         );
+        let stmt = field(&response, "result");
+        let step = [("id", 4.into()), ("op", "step".into()), ("stmt", stmt.clone())];
+        let (response, transfer) = request(&mut host, &step);
+        let row = field(&response, "result");
+        assert_eq!(JsValue::from(1), field(&row, "x"));
         assert_eq!(
-            db.execute_named("INSERT INTO foo(x) VALUES (:x)", &[(":x", &2i32)])
-                .unwrap(),
-            1
+            vec![1u8, 2],
+            field(&row, "b").dyn_into::<Uint8Array>().unwrap().to_vec()
+        );
+        assert_eq!(1, transfer.length());
+        let (response, _) = request(&mut host, &step);
+        assert!(field(&response, "result").is_null());
+        let (response, _) = request(
+            &mut host,
+            &[("id", 5.into()), ("op", "finalize".into()), ("stmt", stmt)],
         );
+        assert_eq!(JsValue::TRUE, field(&response, "result"));
 
-        assert_eq!(
-            3i32,
-            db.query_row_named::<i32, _>(
-                "SELECT SUM(x) FROM foo WHERE x > :x",
-                &[(":x", &0i32)],
-                |r| r.get(0),
-            )
-            .unwrap()
+        let (response, transfer) =
+            request(&mut host, &[("id", 6.into()), ("op", "serialize".into())]);
+        let data = field(&response, "result");
+        assert!(data.is_instance_of::<Uint8Array>());
+        assert_eq!(1, transfer.length());
+
+        let (response, _) = request(
+            &mut host,
+            &[("id", 7.into()), ("op", "open".into()), ("data", data)],
+        );
+        assert!(field(&response, "error").is_undefined());
+        let (response, _) = request(
+            &mut host,
+            &[
+                ("id", 8.into()),
+                ("op", "exec".into()),
+                ("sql", "DELETE FROM foo".into()),
+                ("params", Array::new().into()),
+            ],
         );
+        assert_eq!(JsValue::from(1), field(&response, "result"));
+        let (response, _) = request(&mut host, &[("id", 9.into()), ("op", "close".into())]);
+        assert!(field(&response, "error").is_undefined());
     }
 
     #[wasm_bindgen_test]
-    fn test_stmt_execute_named() {
+    fn test_serialize() {
         let db = Connection::open_in_memory().unwrap();
-        let sql = "CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag \
-                   INTEGER)";
-        db.execute_batch(sql).unwrap();
+        db.execute_batch("CREATE TABLE foo (x); INSERT INTO foo VALUES (42);")
+            .unwrap();
+        let bytes = db.serialize(DatabaseName::Main).unwrap();
+        assert!(db.serialize(DatabaseName::Attached("missing")).is_err());
 
-        let mut stmt = db
-            .prepare("INSERT INTO test (name) VALUES (:name)")
+        let copy = Connection::open_in_memory().unwrap();
+        copy.deserialize(DatabaseName::Main, &bytes, false).unwrap();
+        let x: i64 = copy
+            .query_row("SELECT x FROM foo", NO_PARAMS, |r| r.get(0))
             .unwrap();
-        stmt.execute_named(&[(":name", &"one")]).unwrap();
-
-        let mut stmt = db
-            .prepare("SELECT COUNT(*) FROM test WHERE name = :name")
+        assert_eq!(42, x);
+        copy.execute_batch("INSERT INTO foo VALUES (43)").unwrap();
+
+        let empty = Connection::open_in_memory().unwrap();
+        let bytes = empty.serialize(DatabaseName::Main).unwrap();
+        assert!(bytes.is_empty());
+        let copy = Connection::open_in_memory().unwrap();
+        copy.deserialize(DatabaseName::Main, &bytes, false).unwrap();
+        copy.execute_batch("CREATE TABLE foo (x); INSERT INTO foo VALUES (42);")
             .unwrap();
-        assert_eq!(
-            1i32,
-            stmt.query_row_named::<i32, _>(&[(":name", &"one")], |r| r.get(0))
-                .unwrap()
-        );
     }
 
+    use rusqlite::columnar::{ColumnData, ColumnType};
+
     #[wasm_bindgen_test]
-    fn test_query_named() {
+    fn test_query_columnar() {
+        use rusqlite::types::Type;
         let db = Connection::open_in_memory().unwrap();
-        let sql = r#"
-        CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag INTEGER);
-        INSERT INTO test(id, name) VALUES (1, 'one');
-        "#;
-        db.execute_batch(sql).unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo (i INTEGER, t TEXT, x);
+             INSERT INTO foo VALUES (1, 'a', 1.5);
+             INSERT INTO foo VALUES (NULL, 'bc', NULL);
+             INSERT INTO foo VALUES (3, NULL, 2.5);",
+        )
+        .unwrap();
 
-        let mut stmt = db
-            .prepare("SELECT id FROM test where name = :name")
+        let mut stmt = db.prepare("SELECT i, t, x FROM foo").unwrap();
+        let mut rows = stmt.query_columnar(NO_PARAMS, 2).unwrap();
+        let batch = rows.next_batch().unwrap().unwrap();
+        assert_eq!(2, batch.num_rows);
+        assert_eq!(ColumnData::Int64(vec![1, 0]), batch.columns[0].data);
+        assert_eq!(1, batch.columns[0].null_count);
+        assert!(!batch.columns[0].is_null(0));
+        assert!(batch.columns[0].is_null(1));
+        assert_eq!(
+            ColumnData::Utf8 {
+                offsets: vec![0, 1, 3],
+                data: b"abc".to_vec(),
+            },
+            batch.columns[1].data
+        );
+        assert!(batch.columns[1].validity.is_empty());
+        // No declared type: inferred from the first value.
+        assert_eq!(ColumnData::Float64(vec![1.5, 0.0]), batch.columns[2].data);
+
+        let batch = rows.next_batch().unwrap().unwrap();
+        assert_eq!(1, batch.num_rows);
+        assert!(batch.columns[1].is_null(0));
+        assert!(rows.next_batch().unwrap().is_none());
+        let types: Vec<ColumnType> = rows.fields().iter().map(|f| f.column_type).collect();
+        assert_eq!(
+            vec![ColumnType::Int64, ColumnType::Utf8, ColumnType::Float64],
+            types
+        );
+
+        let mut stmt = db.prepare("SELECT 1 UNION ALL SELECT x'00'").unwrap();
+        let mut rows = stmt.query_columnar(NO_PARAMS, 10).unwrap();
+        match rows.next_batch() {
+            Err(Error::InvalidColumnType(0, _, Type::Blob)) => (),
+            _ => panic!("expected InvalidColumnType"),
+        }
+
+        let mut stmt = db.prepare("SELECT i, t, x FROM foo").unwrap();
+        let ipc = stmt
+            .query_columnar(NO_PARAMS, 2)
+            .unwrap()
+            .to_arrow_ipc()
             .unwrap();
-        let mut rows = stmt.query_named(&[(":name", &"one")]).unwrap();
+        assert_eq!(&[0xFF; 4], &ipc[..4]);
+        assert_eq!(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0], &ipc[ipc.len() - 8..]);
+        assert_eq!(0, ipc.len() % 8);
+    }
 
-        let id: Result<i32> = rows.next().unwrap().unwrap().get(0);
-        assert_eq!(Ok(1), id);
+    // A flatbuffer table at `pos` in `buf`, read back to check the Arrow IPC
+    // metadata. Absent scalar fields read as 0.
+    struct FlatTable<'a> {
+        buf: &'a [u8],
+        pos: usize,
     }
 
-    #[wasm_bindgen_test]
-    fn test_query_map_named() {
-        let db = Connection::open_in_memory().unwrap();
-        let sql = r#"
-        CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag INTEGER);
-        INSERT INTO test(id, name) VALUES (1, 'one');
-        "#;
-        db.execute_batch(sql).unwrap();
+    impl<'a> FlatTable<'a> {
+        fn uint(buf: &[u8], pos: usize, size: usize) -> u64 {
+            buf[pos..pos + size]
+                .iter()
+                .rev()
+                .fold(0, |n, &b| n << 8 | u64::from(b))
+        }
 
-        let mut stmt = db
-            .prepare("SELECT id FROM test where name = :name")
-            .unwrap();
-        let mut rows = stmt
-            .query_map_named(&[(":name", &"one")], |row| {
-                let id: Result<i32> = row.get(0);
-                id.map(|i| 2 * i)
-            })
-            .unwrap();
+        fn root(buf: &'a [u8]) -> FlatTable<'a> {
+            let pos = Self::uint(buf, 0, 4) as usize;
+            FlatTable { buf, pos }
+        }
 
-        let doubled_id: i32 = rows.next().unwrap().unwrap();
-        assert_eq!(2, doubled_id);
-    }
+        // The position of the field in `slot`, if present.
+        fn field(&self, slot: usize) -> Option<usize> {
+            let vtable = self.pos - Self::uint(self.buf, self.pos, 4) as usize;
+            if 4 + 2 * slot >= Self::uint(self.buf, vtable, 2) as usize {
+                return None;
+            }
+            match Self::uint(self.buf, vtable + 4 + 2 * slot, 2) as usize {
+                0 => None,
+                offset => Some(self.pos + offset),
+            }
+        }
 
-    #[wasm_bindgen_test]
-    fn test_query_and_then_named() {
-        let db = Connection::open_in_memory().unwrap();
-        let sql = r#"
-        CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag INTEGER);
-        INSERT INTO test(id, name) VALUES (1, 'one');
-        INSERT INTO test(id, name) VALUES (2, 'one');
-        "#;
-        db.execute_batch(sql).unwrap();
+        fn scalar(&self, slot: usize, size: usize) -> u64 {
+            self.field(slot)
+                .map_or(0, |pos| Self::uint(self.buf, pos, size))
+        }
 
-        let mut stmt = db
-            .prepare("SELECT id FROM test where name = :name ORDER BY id ASC")
-            .unwrap();
-        let mut rows = stmt
-            .query_and_then_named(&[(":name", &"one")], |row| {
-                let id: i32 = row.get(0)?;
-                if id == 1 {
-                    Ok(id)
-                } else {
-                    Err(Error::SqliteSingleThreadedMode)
-                }
-            })
-            .unwrap();
+        // The target of the offset in `slot`.
+        fn target(&self, slot: usize) -> usize {
+            let pos = self.field(slot).unwrap();
+            pos + Self::uint(self.buf, pos, 4) as usize
+        }
 
-        // first row should be Ok
-        let doubled_id: i32 = rows.next().unwrap().unwrap();
-        assert_eq!(1, doubled_id);
+        fn table(&self, slot: usize) -> FlatTable<'a> {
+            FlatTable {
+                buf: self.buf,
+                pos: self.target(slot),
+            }
+        }
 
-        // second row should be Err
-        #[allow(clippy::match_wild_err_arm)]
-        match rows.next().unwrap() {
-            Ok(_) => panic!("invalid Ok"),
-            Err(Error::SqliteSingleThreadedMode) => (),
-            Err(_) => panic!("invalid Err"),
+        fn string(&self, slot: usize) -> &'a str {
+            let pos = self.target(slot);
+            let len = Self::uint(self.buf, pos, 4) as usize;
+            std::str::from_utf8(&self.buf[pos + 4..pos + 4 + len]).unwrap()
+        }
+
+        fn tables(&self, slot: usize) -> Vec<FlatTable<'a>> {
+            let pos = self.target(slot);
+            (0..Self::uint(self.buf, pos, 4) as usize)
+                .map(|i| {
+                    let offset = pos + 4 + 4 * i;
+                    FlatTable {
+                        buf: self.buf,
+                        pos: offset + Self::uint(self.buf, offset, 4) as usize,
+                    }
+                })
+                .collect()
+        }
+
+        // A vector of structs made of two longs.
+        fn pairs(&self, slot: usize) -> Vec<(usize, usize)> {
+            let pos = self.target(slot);
+            assert_eq!(0, (pos + 4) % 8);
+            (0..Self::uint(self.buf, pos, 4) as usize)
+                .map(|i| {
+                    let at = pos + 4 + 16 * i;
+                    (
+                        Self::uint(self.buf, at, 8) as usize,
+                        Self::uint(self.buf, at + 8, 8) as usize,
+                    )
+                })
+                .collect()
         }
     }
 
+    // Split an Arrow IPC stream into its messages: the metadata flatbuffer
+    // and the body.
+    fn ipc_messages(ipc: &[u8]) -> Vec<(FlatTable<'_>, &[u8])> {
+        let mut messages = Vec::new();
+        let mut pos = 0;
+        loop {
+            assert_eq!(0xFFFF_FFFF, FlatTable::uint(ipc, pos, 4));
+            let len = FlatTable::uint(ipc, pos + 4, 4) as usize;
+            pos += 8;
+            if len == 0 {
+                break;
+            }
+            assert_eq!(0, len % 8);
+            let message = FlatTable::root(&ipc[pos..pos + len]);
+            pos += len;
+            let body_len = message.scalar(3, 8) as usize;
+            messages.push((message, &ipc[pos..pos + body_len]));
+            pos += body_len;
+        }
+        assert_eq!(ipc.len(), pos);
+        messages
+    }
+
     #[wasm_bindgen_test]
-    fn test_unbound_parameters_are_null() {
+    fn test_arrow_ipc_decode() {
         let db = Connection::open_in_memory().unwrap();
-        let sql = "CREATE TABLE test (x TEXT, y TEXT)";
-        db.execute_batch(sql).unwrap();
-
-        let mut stmt = db
-            .prepare("INSERT INTO test (x, y) VALUES (:x, :y)")
+        db.execute_batch(
+            "CREATE TABLE foo (n, i INTEGER, f REAL, s TEXT, b BLOB);
+             INSERT INTO foo VALUES (NULL, 1, 1.5, 'a', x'0102');
+             INSERT INTO foo VALUES (NULL, NULL, -2.0, 'bcd', x'');
+             INSERT INTO foo VALUES (NULL, 3, NULL, NULL, x'ff');",
+        )
+        .unwrap();
+        let mut stmt = db.prepare("SELECT n, i, f, s, b FROM foo").unwrap();
+        let ipc = stmt
+            .query_columnar(NO_PARAMS, 10)
+            .unwrap()
+            .to_arrow_ipc()
             .unwrap();
-        stmt.execute_named(&[(":x", &"one")]).unwrap();
-
-        let result: Option<String> = db
-            .query_row("SELECT y FROM test WHERE x = 'one'", NO_PARAMS, |row| {
-                row.get(0)
+        let messages = ipc_messages(&ipc);
+        assert_eq!(2, messages.len());
+
+        // Schema: version V4, header type Schema, no body.
+        let (ref message, body) = messages[0];
+        assert_eq!(3, message.scalar(0, 2));
+        assert_eq!(1, message.scalar(1, 1));
+        assert!(body.is_empty());
+        let schema = message.table(2);
+        assert_eq!(0, schema.scalar(0, 2));
+        let fields = schema.tables(1);
+        let names: Vec<&str> = fields.iter().map(|f| f.string(0)).collect();
+        assert_eq!(vec!["n", "i", "f", "s", "b"], names);
+        // Null-only columns without declared type are Utf8.
+        let types: Vec<u64> = fields.iter().map(|f| f.scalar(2, 1)).collect();
+        assert_eq!(vec![5, 2, 3, 5, 4], types);
+        for field in &fields {
+            assert_eq!(1, field.scalar(1, 1));
+            assert!(field.tables(5).is_empty());
+        }
+        // Int: 64 bits, signed. FloatingPoint: double.
+        assert_eq!(64, fields[1].table(3).scalar(0, 4));
+        assert_eq!(1, fields[1].table(3).scalar(1, 1));
+        assert_eq!(2, fields[2].table(3).scalar(0, 2));
+
+        // Record batch: header type RecordBatch.
+        let (ref message, body) = messages[1];
+        assert_eq!(3, message.scalar(0, 2));
+        assert_eq!(3, message.scalar(1, 1));
+        let batch = message.table(2);
+        assert_eq!(3, batch.scalar(0, 8));
+        let nodes = batch.pairs(1);
+        assert_eq!(vec![(3, 3), (3, 1), (3, 1), (3, 1), (3, 0)], nodes);
+        let buffers: Vec<&[u8]> = batch
+            .pairs(2)
+            .into_iter()
+            .map(|(offset, len)| {
+                assert_eq!(0, offset % 8);
+                &body[offset..offset + len]
             })
-            .unwrap();
-        assert!(result.is_none());
+            .collect();
+        let ints = |bytes: &[u8], size: usize| -> Vec<u64> {
+            (0..bytes.len() / size)
+                .map(|i| FlatTable::uint(bytes, i * size, size))
+                .collect()
+        };
+        // Validity bitmaps (empty without nulls), then values, or offsets
+        // and data.
+        assert_eq!(13, buffers.len());
+        assert_eq!(&[0], buffers[0]);
+        assert_eq!(vec![0, 0, 0, 0], ints(buffers[1], 4));
+        assert!(buffers[2].is_empty());
+        assert_eq!(&[0b101], buffers[3]);
+        assert_eq!(vec![1, 0, 3], ints(buffers[4], 8));
+        assert_eq!(&[0b011], buffers[5]);
+        let reals: Vec<f64> = ints(buffers[6], 8)
+            .into_iter()
+            .map(f64::from_bits)
+            .collect();
+        assert_eq!(vec![1.5, -2.0, 0.0], reals);
+        assert_eq!(&[0b011], buffers[7]);
+        assert_eq!(vec![0, 1, 4, 4], ints(buffers[8], 4));
+        assert_eq!(b"abcd", buffers[9]);
+        assert!(buffers[10].is_empty());
+        assert_eq!(vec![0, 2, 2, 3], ints(buffers[11], 4));
+        assert_eq!(&[1, 2, 0xff], buffers[12]);
     }
 
     #[wasm_bindgen_test]
-    fn test_unbound_parameters_are_reused() {
+    fn test_serde_rows() {
+        use rusqlite::params_from_serialize;
+        use rusqlite::types::Type;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        enum Kind {
+            Cat,
+            Dog,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Pet {
+            name: String,
+            kind: Kind,
+            age: u8,
+            weight: f64,
+            data: Option<Vec<u8>>,
+        }
+
         let db = Connection::open_in_memory().unwrap();
-        let sql = "CREATE TABLE test (x TEXT, y TEXT)";
-        db.execute_batch(sql).unwrap();
+        db.execute_batch(
+            "CREATE TABLE pet (name TEXT, kind TEXT, age INTEGER, weight REAL, data BLOB)",
+        )
+        .unwrap();
+        let pets = vec![
+            Pet {
+                name: "Felix".to_owned(),
+                kind: Kind::Cat,
+                age: 3,
+                weight: 4.5,
+                data: None,
+            },
+            Pet {
+                name: "Rex".to_owned(),
+                kind: Kind::Dog,
+                age: 7,
+                weight: 30.0,
+                data: Some(vec![1, 2, 3]),
+            },
+        ];
+        let mut insert = db
+            .prepare("INSERT INTO pet VALUES (:name, :kind, :age, :weight, :data)")
+            .unwrap();
+        for pet in &pets {
+            let params = params_from_serialize(pet).unwrap();
+            insert.execute_named(&params.to_named()).unwrap();
+        }
+        assert!(params_from_serialize(&42).is_err());
 
+        // Columns are matched by name, whatever their order.
         let mut stmt = db
-            .prepare("INSERT INTO test (x, y) VALUES (:x, :y)")
+            .prepare("SELECT data, weight, age, kind, name FROM pet ORDER BY name")
             .unwrap();
-        stmt.execute_named(&[(":x", &"one")]).unwrap();
-        stmt.execute_named(&[(":y", &"two")]).unwrap();
-
-        let result: String = db
-            .query_row("SELECT x FROM test WHERE y = 'two'", NO_PARAMS, |row| {
-                row.get(0)
-            })
+        let rows: Vec<Pet> = stmt
+            .query_as(NO_PARAMS)
+            .unwrap()
+            .collect::<Result<_>>()
             .unwrap();
-        assert_eq!(result, "one");
-    }
+        assert_eq!(pets, rows);
 
-    #[wasm_bindgen_test]
-    fn test_insert() {
-        let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)")
+        let (name, age): (String, i64) = db
+            .query_row("SELECT name, age FROM pet WHERE age = 7", NO_PARAMS, |r| {
+                r.deserialize()
+            })
             .unwrap();
-        let mut stmt = db
-            .prepare("INSERT OR IGNORE INTO foo (x) VALUES (?)")
+        assert_eq!(("Rex".to_owned(), 7), (name, age));
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM pet", NO_PARAMS, |r| r.deserialize())
             .unwrap();
-        assert_eq!(stmt.insert(&[1i32]).unwrap(), 1);
-        assert_eq!(stmt.insert(&[2i32]).unwrap(), 2);
-        match stmt.insert(&[1i32]).unwrap_err() {
-            Error::StatementChangedRows(0) => (),
-            err => panic!("Unexpected error {}", err),
+        assert_eq!(2, count);
+
+        match db.query_row("SELECT name FROM pet", NO_PARAMS, |r| {
+            r.deserialize::<Pet>()
+        }) {
+            Err(Error::InvalidColumnName(ref name)) if name == "kind" => (),
+            r => panic!("expected InvalidColumnName, got {:?}", r),
         }
-        let mut multi = db
-            .prepare("INSERT INTO foo (x) SELECT 3 UNION ALL SELECT 4")
-            .unwrap();
-        match multi.insert(NO_PARAMS).unwrap_err() {
-            Error::StatementChangedRows(2) => (),
-            err => panic!("Unexpected error {}", err),
+        let err = db
+            .query_row("SELECT 1000 AS age", NO_PARAMS, |r| r.deserialize::<u8>())
+            .unwrap_err();
+        assert_eq!(Error::IntegralValueOutOfRange(0, 1000), err);
+        match db.query_row("SELECT 'x' AS age", NO_PARAMS, |r| r.deserialize::<i64>()) {
+            Err(Error::InvalidColumnType(0, ref name, Type::Text)) if name == "age" => (),
+            r => panic!("expected InvalidColumnType, got {:?}", r),
         }
     }
 
     #[wasm_bindgen_test]
-    fn test_insert_different_tables() {
-        // Test for https://github.com/jgallagher/rusqlite/issues/171
-        let db = Connection::open_in_memory().unwrap();
-        db.execute_batch(
-            r"
-            CREATE TABLE foo(x INTEGER);
-            CREATE TABLE bar(x INTEGER);
-        ",
+    fn test_array_table() {
+        use crate::database::Database;
+        use js_sys::{Array, JSON};
+
+        let db = Database::new().unwrap();
+        let todos: Array = JSON::parse(
+            r#"[{"id": 1, "title": "write", "done": false, "tags": ["a"]},
+                {"id": 2, "title": "test", "done": true},
+                {"id": 3, "title": "ship", "done": false}]"#,
+        )
+        .unwrap()
+        .into();
+        db.register_array("todos", todos.clone(), None).unwrap();
+        let conn = db.connection();
+        conn.execute_batch(
+            "CREATE TABLE owner (todo INTEGER, name TEXT);
+             INSERT INTO owner VALUES (1, 'ann'), (3, 'bob');",
         )
         .unwrap();
 
-        assert_eq!(
-            db.prepare("INSERT INTO foo VALUES (10)")
-                .unwrap()
-                .insert(NO_PARAMS)
-                .unwrap(),
-            1
-        );
-        assert_eq!(
-            db.prepare("INSERT INTO bar VALUES (10)")
+        let names: Vec<String> = conn
+            .prepare("SELECT title || ':' || name FROM todos JOIN owner ON todo = id ORDER BY id")
+            .unwrap()
+            .query_map(NO_PARAMS, |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec!["write:ann", "ship:bob"], names);
+
+        // Equality lookups through the index and by rowid.
+        let title: String = conn
+            .query_row("SELECT title FROM todos WHERE done = 1", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!("test", title);
+        let (id, tags): (i64, String) = conn
+            .query_row(
+                "SELECT id, tags FROM todos WHERE rowid = 0",
+                NO_PARAMS,
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((1, "[\"a\"]".to_owned()), (id, tags));
+        for &sql in &[
+            "SELECT count(*) FROM todos WHERE rowid = 'x'",
+            "SELECT count(*) FROM todos WHERE rowid = 0.5",
+            "SELECT count(*) FROM todos WHERE rowid = -1",
+        ] {
+            let count: i64 = conn.query_row(sql, NO_PARAMS, |r| r.get(0)).unwrap();
+            assert_eq!(0, count, "{}", sql);
+        }
+        let missing: i64 = conn
+            .query_row("SELECT count(*) FROM todos WHERE id = 42", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(0, missing);
+        // Other collations than BINARY are not answered from the index.
+        let ship: i64 = conn
+            .query_row(
+                "SELECT id FROM todos WHERE title = 'SHIP' COLLATE NOCASE",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(3, ship);
+
+        // Registering again picks up changes and explicit columns.
+        todos.push(&JSON::parse(r#"{"id": 4, "title": "rest"}"#).unwrap());
+        let columns = Array::of1(&"title".into());
+        db.register_array("todos", todos.clone(), Some(columns))
+            .unwrap();
+        let count = || -> i64 {
+            conn.query_row("SELECT count(title) FROM todos", NO_PARAMS, |r| r.get(0))
                 .unwrap()
-                .insert(NO_PARAMS)
-                .unwrap(),
-            1
-        );
+        };
+        assert_eq!(4, count());
+        assert!(conn.prepare("SELECT id FROM todos").is_err());
+
+        // A failed registration keeps the previous table, or creates none.
+        let duplicate = Array::of2(&"title".into(), &"title".into());
+        assert!(db
+            .register_array("todos", todos.clone(), Some(duplicate.clone()))
+            .is_err());
+        assert_eq!(4, count());
+        assert!(db
+            .register_array("other", todos.clone(), Some(duplicate))
+            .is_err());
+        assert!(!db.unregister_array("other").unwrap());
+        db.register_array("other", todos, None).unwrap();
+        assert!(db.unregister_array("other").unwrap());
+
+        assert!(db.unregister_array("todos").unwrap());
+        assert!(!db.unregister_array("todos").unwrap());
+        assert!(conn.prepare("SELECT * FROM todos").is_err());
     }
 
-    #[wasm_bindgen_test]
-    fn test_exists() {
-        let db = Connection::open_in_memory().unwrap();
-        let sql = "BEGIN;
-                   CREATE TABLE foo(x INTEGER);
-                   INSERT INTO foo VALUES(1);
-                   INSERT INTO foo VALUES(2);
-                   END;";
-        db.execute_batch(sql).unwrap();
-        let mut stmt = db.prepare("SELECT 1 FROM foo WHERE x = ?").unwrap();
-        assert!(stmt.exists(&[1i32]).unwrap());
-        assert!(stmt.exists(&[2i32]).unwrap());
-        assert!(!stmt.exists(&[0i32]).unwrap());
-    }
+    use rusqlite::ffi::Limit;
 
     #[wasm_bindgen_test]
-    fn test_query_rowx() {
+    fn test_limit() {
         let db = Connection::open_in_memory().unwrap();
-        let sql = "BEGIN;
-                   CREATE TABLE foo(x INTEGER, y INTEGER);
-                   INSERT INTO foo VALUES(1, 3);
-                   INSERT INTO foo VALUES(2, 4);
-                   END;";
-        db.execute_batch(sql).unwrap();
-        let mut stmt = db.prepare("SELECT y FROM foo WHERE x = ?").unwrap();
-        let y: Result<i64> = stmt.query_row(&[1i32], |r| r.get(0));
-        assert_eq!(3i64, y.unwrap());
-    }
+        db.set_limit(Limit::SQLITE_LIMIT_LENGTH, 1024);
+        assert_eq!(1024, db.limit(Limit::SQLITE_LIMIT_LENGTH));
 
-    #[wasm_bindgen_test]
-    fn test_query_by_column_name() {
-        let db = Connection::open_in_memory().unwrap();
-        let sql = "BEGIN;
-                   CREATE TABLE foo(x INTEGER, y INTEGER);
-                   INSERT INTO foo VALUES(1, 3);
-                   END;";
+        db.set_limit(Limit::SQLITE_LIMIT_SQL_LENGTH, 1024);
+        assert_eq!(1024, db.limit(Limit::SQLITE_LIMIT_SQL_LENGTH));
+
+        db.set_limit(Limit::SQLITE_LIMIT_COLUMN, 64);
+        assert_eq!(64, db.limit(Limit::SQLITE_LIMIT_COLUMN));
+
+        db.set_limit(Limit::SQLITE_LIMIT_EXPR_DEPTH, 256);
+        assert_eq!(256, db.limit(Limit::SQLITE_LIMIT_EXPR_DEPTH));
+
+        db.set_limit(Limit::SQLITE_LIMIT_COMPOUND_SELECT, 32);
+        assert_eq!(32, db.limit(Limit::SQLITE_LIMIT_COMPOUND_SELECT));
+
+        db.set_limit(Limit::SQLITE_LIMIT_FUNCTION_ARG, 32);
+        assert_eq!(32, db.limit(Limit::SQLITE_LIMIT_FUNCTION_ARG));
+
+        db.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 2);
+        assert_eq!(2, db.limit(Limit::SQLITE_LIMIT_ATTACHED));
+
+        db.set_limit(Limit::SQLITE_LIMIT_LIKE_PATTERN_LENGTH, 128);
+        assert_eq!(128, db.limit(Limit::SQLITE_LIMIT_LIKE_PATTERN_LENGTH));
+
+        db.set_limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER, 99);
+        assert_eq!(99, db.limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER));
+
+        // SQLITE_LIMIT_TRIGGER_DEPTH was added in SQLite 3.6.18.
+        if rusqlite::version_number() >= 3_006_018 {
+            db.set_limit(Limit::SQLITE_LIMIT_TRIGGER_DEPTH, 32);
+            assert_eq!(32, db.limit(Limit::SQLITE_LIMIT_TRIGGER_DEPTH));
+        }
+        /*
+        // SQLITE_LIMIT_WORKER_THREADS was added in SQLite 3.8.7.
+        if rusqlite::version_number() >= 3_008_007 {
+            db.set_limit(Limit::SQLITE_LIMIT_WORKER_THREADS, 2);
+            assert_eq!(2, db.limit(Limit::SQLITE_LIMIT_WORKER_THREADS));
+        }
+        */
+    }
+
+    use rusqlite::pragma;
+    use rusqlite::pragma::Sql;
+
+    #[wasm_bindgen_test]
+    fn pragma_query_value() {
+        let db = Connection::open_in_memory().unwrap();
+        let user_version: i32 = db
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(0, user_version);
+    }
+
+    #[wasm_bindgen_test]
+    fn pragma_func_query_value() {
+        use rusqlite::NO_PARAMS;
+
+        let db = Connection::open_in_memory().unwrap();
+        let user_version: i32 = db
+            .query_row(
+                "SELECT user_version FROM pragma_user_version",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(0, user_version);
+    }
+
+    #[wasm_bindgen_test]
+    fn pragma_query_no_schema() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut user_version = -1;
+        db.pragma_query(None, "user_version", |row| {
+            user_version = row.get(0)?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(0, user_version);
+    }
+
+    #[wasm_bindgen_test]
+    fn pragma_query_with_schema() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut user_version = -1;
+        db.pragma_query(Some(DatabaseName::Main), "user_version", |row| {
+            user_version = row.get(0)?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(0, user_version);
+    }
+
+    #[wasm_bindgen_test]
+    fn pragma() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut columns = Vec::new();
+        db.pragma(None, "table_info", &"sqlite_master", |row| {
+            let column: String = row.get(1)?;
+            columns.push(column);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(5, columns.len());
+    }
+
+    #[wasm_bindgen_test]
+    fn pragma_func() {
+        let db = Connection::open_in_memory().unwrap();
+        let mut table_info = db.prepare("SELECT * FROM pragma_table_info(?)").unwrap();
+        let mut columns = Vec::new();
+        let mut rows = table_info.query(&["sqlite_master"]).unwrap();
+
+        while let Some(row) = rows.next().unwrap() {
+            let row = row;
+            let column: String = row.get(1).unwrap();
+            columns.push(column);
+        }
+        assert_eq!(5, columns.len());
+    }
+
+    #[wasm_bindgen_test]
+    fn pragma_update() {
+        let db = Connection::open_in_memory().unwrap();
+        db.pragma_update(None, "user_version", &1).unwrap();
+    }
+
+    /* #[wasm_bindgen_test]
+        fn pragma_update_and_check() {
+            let db = Connection::open_in_memory().unwrap();
+            let journal_mode: String = db
+                .pragma_update_and_check(None, "journal_mode", &"OFF", |row| row.get(0))
+                .unwrap();
+            assert_eq!("off", &journal_mode);
+        }
+    */
+    #[wasm_bindgen_test]
+    fn is_identifier() {
+        assert!(pragma::is_identifier("full"));
+        assert!(pragma::is_identifier("r2d2"));
+        assert!(!pragma::is_identifier("sp ce"));
+        assert!(!pragma::is_identifier("semi;colon"));
+    }
+
+    #[wasm_bindgen_test]
+    fn double_quote() {
+        let mut sql = Sql::new();
+        sql.push_schema_name(DatabaseName::Attached(r#"schema";--"#));
+        assert_eq!(r#""schema"";--""#, sql.as_str());
+    }
+
+    #[wasm_bindgen_test]
+    fn wrap_and_escape() {
+        let mut sql = Sql::new();
+        sql.push_string_literal("value'; --");
+        assert_eq!("'value''; --'", sql.as_str());
+    }
+
+    use rusqlite::types::{FromSql, ToSql, Value};
+
+    #[wasm_bindgen_test]
+    fn test_execute_named() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER)").unwrap();
+
+        assert_eq!(
+            db.execute_named("INSERT INTO foo(x) VALUES (:x)", &[(":x", &1i32)])
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.execute_named("INSERT INTO foo(x) VALUES (:x)", &[(":x", &2i32)])
+                .unwrap(),
+            1
+        );
+
+        assert_eq!(
+            3i32,
+            db.query_row_named::<i32, _>(
+                "SELECT SUM(x) FROM foo WHERE x > :x",
+                &[(":x", &0i32)],
+                |r| r.get(0),
+            )
+            .unwrap()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_stmt_execute_named() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag \
+                   INTEGER)";
         db.execute_batch(sql).unwrap();
-        let mut stmt = db.prepare("SELECT y FROM foo").unwrap();
-        let y: Result<i64> = stmt.query_row(NO_PARAMS, |r| r.get("y"));
-        assert_eq!(3i64, y.unwrap());
+
+        let mut stmt = db
+            .prepare("INSERT INTO test (name) VALUES (:name)")
+            .unwrap();
+        stmt.execute_named(&[(":name", &"one")]).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT COUNT(*) FROM test WHERE name = :name")
+            .unwrap();
+        assert_eq!(
+            1i32,
+            stmt.query_row_named::<i32, _>(&[(":name", &"one")], |r| r.get(0))
+                .unwrap()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_named() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = r#"
+        CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag INTEGER);
+        INSERT INTO test(id, name) VALUES (1, 'one');
+        "#;
+        db.execute_batch(sql).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT id FROM test where name = :name")
+            .unwrap();
+        let mut rows = stmt.query_named(&[(":name", &"one")]).unwrap();
+
+        let id: Result<i32> = rows.next().unwrap().unwrap().get(0);
+        assert_eq!(Ok(1), id);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_map_named() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = r#"
+        CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag INTEGER);
+        INSERT INTO test(id, name) VALUES (1, 'one');
+        "#;
+        db.execute_batch(sql).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT id FROM test where name = :name")
+            .unwrap();
+        let mut rows = stmt
+            .query_map_named(&[(":name", &"one")], |row| {
+                let id: Result<i32> = row.get(0);
+                id.map(|i| 2 * i)
+            })
+            .unwrap();
+
+        let doubled_id: i32 = rows.next().unwrap().unwrap();
+        assert_eq!(2, doubled_id);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_and_then_named() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = r#"
+        CREATE TABLE test (id INTEGER PRIMARY KEY NOT NULL, name TEXT NOT NULL, flag INTEGER);
+        INSERT INTO test(id, name) VALUES (1, 'one');
+        INSERT INTO test(id, name) VALUES (2, 'one');
+        "#;
+        db.execute_batch(sql).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT id FROM test where name = :name ORDER BY id ASC")
+            .unwrap();
+        let mut rows = stmt
+            .query_and_then_named(&[(":name", &"one")], |row| {
+                let id: i32 = row.get(0)?;
+                if id == 1 {
+                    Ok(id)
+                } else {
+                    Err(Error::SqliteSingleThreadedMode)
+                }
+            })
+            .unwrap();
+
+        // first row should be Ok
+        let doubled_id: i32 = rows.next().unwrap().unwrap();
+        assert_eq!(1, doubled_id);
+
+        // second row should be Err
+        #[allow(clippy::match_wild_err_arm)]
+        match rows.next().unwrap() {
+            Ok(_) => panic!("invalid Ok"),
+            Err(Error::SqliteSingleThreadedMode) => (),
+            Err(_) => panic!("invalid Err"),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_unbound_parameters_are_null() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "CREATE TABLE test (x TEXT, y TEXT)";
+        db.execute_batch(sql).unwrap();
+
+        let mut stmt = db
+            .prepare("INSERT INTO test (x, y) VALUES (:x, :y)")
+            .unwrap();
+        stmt.execute_named(&[(":x", &"one")]).unwrap();
+
+        let result: Option<String> = db
+            .query_row("SELECT y FROM test WHERE x = 'one'", NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(result.is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_unbound_parameters_are_reused() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "CREATE TABLE test (x TEXT, y TEXT)";
+        db.execute_batch(sql).unwrap();
+
+        let mut stmt = db
+            .prepare("INSERT INTO test (x, y) VALUES (:x, :y)")
+            .unwrap();
+        stmt.execute_named(&[(":x", &"one")]).unwrap();
+        stmt.execute_named(&[(":y", &"two")]).unwrap();
+
+        let result: String = db
+            .query_row("SELECT x FROM test WHERE y = 'two'", NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(result, "one");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_insert() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo(x INTEGER UNIQUE)")
+            .unwrap();
+        let mut stmt = db
+            .prepare("INSERT OR IGNORE INTO foo (x) VALUES (?)")
+            .unwrap();
+        assert_eq!(stmt.insert(&[1i32]).unwrap(), 1);
+        assert_eq!(stmt.insert(&[2i32]).unwrap(), 2);
+        match stmt.insert(&[1i32]).unwrap_err() {
+            Error::StatementChangedRows(0) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+        let mut multi = db
+            .prepare("INSERT INTO foo (x) SELECT 3 UNION ALL SELECT 4")
+            .unwrap();
+        match multi.insert(NO_PARAMS).unwrap_err() {
+            Error::StatementChangedRows(2) => (),
+            err => panic!("Unexpected error {}", err),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_insert_different_tables() {
+        // Test for https://github.com/jgallagher/rusqlite/issues/171
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            r"
+            CREATE TABLE foo(x INTEGER);
+            CREATE TABLE bar(x INTEGER);
+        ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            db.prepare("INSERT INTO foo VALUES (10)")
+                .unwrap()
+                .insert(NO_PARAMS)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.prepare("INSERT INTO bar VALUES (10)")
+                .unwrap()
+                .insert(NO_PARAMS)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_exists() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "BEGIN;
+                   CREATE TABLE foo(x INTEGER);
+                   INSERT INTO foo VALUES(1);
+                   INSERT INTO foo VALUES(2);
+                   END;";
+        db.execute_batch(sql).unwrap();
+        let mut stmt = db.prepare("SELECT 1 FROM foo WHERE x = ?").unwrap();
+        assert!(stmt.exists(&[1i32]).unwrap());
+        assert!(stmt.exists(&[2i32]).unwrap());
+        assert!(!stmt.exists(&[0i32]).unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_rowx() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "BEGIN;
+                   CREATE TABLE foo(x INTEGER, y INTEGER);
+                   INSERT INTO foo VALUES(1, 3);
+                   INSERT INTO foo VALUES(2, 4);
+                   END;";
+        db.execute_batch(sql).unwrap();
+        let mut stmt = db.prepare("SELECT y FROM foo WHERE x = ?").unwrap();
+        let y: Result<i64> = stmt.query_row(&[1i32], |r| r.get(0));
+        assert_eq!(3i64, y.unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_by_column_name() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "BEGIN;
+                   CREATE TABLE foo(x INTEGER, y INTEGER);
+                   INSERT INTO foo VALUES(1, 3);
+                   END;";
+        db.execute_batch(sql).unwrap();
+        let mut stmt = db.prepare("SELECT y FROM foo").unwrap();
+        let y: Result<i64> = stmt.query_row(NO_PARAMS, |r| r.get("y"));
+        assert_eq!(3i64, y.unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_by_column_name_ignore_case() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "BEGIN;
+                   CREATE TABLE foo(x INTEGER, y INTEGER);
+                   INSERT INTO foo VALUES(1, 3);
+                   END;";
+        db.execute_batch(sql).unwrap();
+        let mut stmt = db.prepare("SELECT y as Y FROM foo").unwrap();
+        let y: Result<i64> = stmt.query_row(NO_PARAMS, |r| r.get("y"));
+        assert_eq!(3i64, y.unwrap());
+    }
+
+    /*  #[wasm_bindgen_test]
+    fn test_expanded_sql() {
+        let db = Connection::open_in_memory().unwrap();
+        let stmt = db.prepare("SELECT ?").unwrap();
+        stmt.bind_parameter(&1, 1).unwrap();
+        assert_eq!(Some("SELECT 1".to_owned()), stmt.expanded_sql());
+    }*/
+
+    #[wasm_bindgen_test]
+    fn test_bind_parameters() {
+        let db = Connection::open_in_memory().unwrap();
+        // dynamic slice:
+        db.query_row(
+            "SELECT ?1, ?2, ?3",
+            &[&1u8 as &dyn ToSql, &"one", &Some("one")],
+            |row| row.get::<_, u8>(0),
+        )
+        .unwrap();
+        // existing collection:
+        let data = vec![1, 2, 3];
+        db.query_row("SELECT ?1, ?2, ?3", &data, |row| row.get::<_, u8>(0))
+            .unwrap();
+        db.query_row("SELECT ?1, ?2, ?3", data.as_slice(), |row| {
+            row.get::<_, u8>(0)
+        })
+        .unwrap();
+        db.query_row("SELECT ?1, ?2, ?3", data, |row| row.get::<_, u8>(0))
+            .unwrap();
+
+        use std::collections::BTreeSet;
+        let data: BTreeSet<String> = ["one", "two", "three"]
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect();
+        db.query_row("SELECT ?1, ?2, ?3", &data, |row| row.get::<_, String>(0))
+            .unwrap();
+
+        let data = [0; 3];
+        db.query_row("SELECT ?1, ?2, ?3", &data, |row| row.get::<_, u8>(0))
+            .unwrap();
+        db.query_row("SELECT ?1, ?2, ?3", data.iter(), |row| row.get::<_, u8>(0))
+            .unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    fn test_empty_stmtx() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut stmt = conn.prepare("").unwrap();
+        assert_eq!(0, stmt.column_count());
+        assert!(stmt.parameter_index("test").is_ok());
+        assert!(stmt.step().is_err());
+        stmt.reset();
+        assert!(stmt.execute(NO_PARAMS).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_comment_stmt() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.prepare("/*SELECT 1;*/").unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    fn test_comment_and_sql_stmt() {
+        let conn = Connection::open_in_memory().unwrap();
+        let stmt = conn.prepare("/*...*/ SELECT 1;").unwrap();
+        assert_eq!(1, stmt.column_count());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_semi_colon_stmt() {
+        let conn = Connection::open_in_memory().unwrap();
+        let stmt = conn.prepare(";").unwrap();
+        assert_eq!(0, stmt.column_count());
+    }
+
+    use rusqlite::DropBehavior;
+
+    fn checked_memory_handlex() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+        db
+    }
+
+    #[wasm_bindgen_test]
+    fn test_drop() {
+        let mut db = checked_memory_handlex();
+        {
+            let tx = db.transaction().unwrap();
+            tx.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+            // default: rollback
+        }
+        {
+            let mut tx = db.transaction().unwrap();
+            tx.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+            tx.set_drop_behavior(DropBehavior::Commit)
+        }
+        {
+            let tx = db.transaction().unwrap();
+            assert_eq!(
+                2i32,
+                tx.query_row::<i32, _, _>("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
+                    .unwrap()
+            );
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_explicit_rollback_commit() {
+        let mut db = checked_memory_handlex();
+        {
+            let mut tx = db.transaction().unwrap();
+            {
+                let mut sp = tx.savepoint().unwrap();
+                sp.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+                sp.rollback().unwrap();
+                sp.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+                sp.commit().unwrap();
+            }
+            tx.commit().unwrap();
+        }
+        {
+            let tx = db.transaction().unwrap();
+            tx.execute_batch("INSERT INTO foo VALUES(4)").unwrap();
+            tx.commit().unwrap();
+        }
+        {
+            let tx = db.transaction().unwrap();
+            assert_eq!(
+                6i32,
+                tx.query_row::<i32, _, _>("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
+                    .unwrap()
+            );
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_savepoint() {
+        let mut db = checked_memory_handlex();
+        {
+            let mut tx = db.transaction().unwrap();
+            tx.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
+            assert_current_sum(1, &tx);
+            tx.set_drop_behavior(DropBehavior::Commit);
+            {
+                let mut sp1 = tx.savepoint().unwrap();
+                sp1.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
+                assert_current_sum(3, &sp1);
+                // will rollback sp1
+                {
+                    let mut sp2 = sp1.savepoint().unwrap();
+                    sp2.execute_batch("INSERT INTO foo VALUES(4)").unwrap();
+                    assert_current_sum(7, &sp2);
+                    // will rollback sp2
+                    {
+                        let sp3 = sp2.savepoint().unwrap();
+                        sp3.execute_batch("INSERT INTO foo VALUES(8)").unwrap();
+                        assert_current_sum(15, &sp3);
+                        sp3.commit().unwrap();
+                        // committed sp3, but will be erased by sp2 rollback
+                    }
+                    assert_current_sum(15, &sp2);
+                }
+                assert_current_sum(3, &sp1);
+            }
+            assert_current_sum(1, &tx);
+        }
+        assert_current_sum(1, &db);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_ignore_drop_behavior() {
+        let mut db = checked_memory_handlex();
+
+        let mut tx = db.transaction().unwrap();
+        {
+            let mut sp1 = tx.savepoint().unwrap();
+            insert(1, &sp1);
+            sp1.rollback().unwrap();
+            insert(2, &sp1);
+            {
+                let mut sp2 = sp1.savepoint().unwrap();
+                sp2.set_drop_behavior(DropBehavior::Ignore);
+                insert(4, &sp2);
+            }
+            assert_current_sum(6, &sp1);
+            sp1.commit().unwrap();
+        }
+        assert_current_sum(6, &tx);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_savepoint_names() {
+        let mut db = checked_memory_handlex();
+
+        {
+            let mut sp1 = db.savepoint_with_name("my_sp").unwrap();
+            insert(1, &sp1);
+            assert_current_sum(1, &sp1);
+            {
+                let mut sp2 = sp1.savepoint_with_name("my_sp").unwrap();
+                sp2.set_drop_behavior(DropBehavior::Commit);
+                insert(2, &sp2);
+                assert_current_sum(3, &sp2);
+                sp2.rollback().unwrap();
+                assert_current_sum(1, &sp2);
+                insert(4, &sp2);
+            }
+            assert_current_sum(5, &sp1);
+            sp1.rollback().unwrap();
+            {
+                let mut sp2 = sp1.savepoint_with_name("my_sp").unwrap();
+                sp2.set_drop_behavior(DropBehavior::Ignore);
+                insert(8, &sp2);
+            }
+            assert_current_sum(8, &sp1);
+            sp1.commit().unwrap();
+        }
+        assert_current_sum(8, &db);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_rc() {
+        use std::rc::Rc;
+        let mut conn = Connection::open_in_memory().unwrap();
+        let rc_txn = Rc::new(conn.transaction().unwrap());
+
+        // This will compile only if Transaction is Debug
+        Rc::try_unwrap(rc_txn).unwrap();
+    }
+
+    fn insert(x: i32, conn: &Connection) {
+        conn.execute("INSERT INTO foo VALUES(?)", &[x]).unwrap();
+    }
+
+    fn assert_current_sum(x: i32, conn: &Connection) {
+        let i = conn
+            .query_row::<i32, _, _>("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(x, i);
+    }
+
+    fn checked_memory_handlexx() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (b BLOB, t TEXT, i INTEGER, f FLOAT, n)")
+            .unwrap();
+        db
+    }
+
+    #[wasm_bindgen_test]
+    fn test_blobx() {
+        let db = checked_memory_handlexx();
+
+        let v1234 = vec![1u8, 2, 3, 4];
+        db.execute("INSERT INTO foo(b) VALUES (?)", &[&v1234])
+            .unwrap();
+
+        let v: Vec<u8> = db
+            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(v, v1234);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_empty_blob() {
+        let db = checked_memory_handlexx();
+
+        let empty = vec![];
+        db.execute("INSERT INTO foo(b) VALUES (?)", &[&empty])
+            .unwrap();
+
+        let v: Vec<u8> = db
+            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(v, empty);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_str() {
+        let db = checked_memory_handlexx();
+
+        let s = "hello, world!";
+        db.execute("INSERT INTO foo(t) VALUES (?)", &[&s]).unwrap();
+
+        let from: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(from, s);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_string() {
+        let db = checked_memory_handlexx();
+
+        let s = "hello, world!";
+        db.execute("INSERT INTO foo(t) VALUES (?)", &[s.to_owned()])
+            .unwrap();
+
+        let from: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(from, s);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_value() {
+        let db = checked_memory_handlexx();
+
+        db.execute("INSERT INTO foo(i) VALUES (?)", &[Value::Integer(10)])
+            .unwrap();
+
+        assert_eq!(
+            10i64,
+            db.query_row::<i64, _, _>("SELECT i FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_option() {
+        let db = checked_memory_handlexx();
+
+        let s = Some("hello, world!");
+        let b = Some(vec![1u8, 2, 3, 4]);
+
+        db.execute("INSERT INTO foo(t) VALUES (?)", &[&s]).unwrap();
+        db.execute("INSERT INTO foo(b) VALUES (?)", &[&b]).unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT t, b FROM foo ORDER BY ROWID ASC")
+            .unwrap();
+        let mut rows = stmt.query(NO_PARAMS).unwrap();
+
+        {
+            let row1 = rows.next().unwrap().unwrap();
+            let s1: Option<String> = row1.get_unwrap(0);
+            let b1: Option<Vec<u8>> = row1.get_unwrap(1);
+            assert_eq!(s.unwrap(), s1.unwrap());
+            assert!(b1.is_none());
+        }
+
+        {
+            let row2 = rows.next().unwrap().unwrap();
+            let s2: Option<String> = row2.get_unwrap(0);
+            let b2: Option<Vec<u8>> = row2.get_unwrap(1);
+            assert!(s2.is_none());
+            assert_eq!(b, b2);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    #[allow(clippy::cognitive_complexity)]
+    fn test_mismatched_types() {
+        fn is_invalid_column_type(err: Error) -> bool {
+            match err {
+                Error::InvalidColumnType(..) => true,
+                _ => false,
+            }
+        }
+
+        let db = checked_memory_handlexx();
+
+        db.execute(
+            "INSERT INTO foo(b, t, i, f) VALUES (X'0102', 'text', 1, 1.5)",
+            NO_PARAMS,
+        )
+        .unwrap();
+
+        let mut stmt = db.prepare("SELECT b, t, i, f, n FROM foo").unwrap();
+        let mut rows = stmt.query(NO_PARAMS).unwrap();
+
+        let row = rows.next().unwrap().unwrap();
+
+        // check the correct types come back as expected
+        assert_eq!(vec![1, 2], row.get::<_, Vec<u8>>(0).unwrap());
+        assert_eq!("text", row.get::<_, String>(1).unwrap());
+        assert_eq!(1, row.get::<_, c_int>(2).unwrap());
+        assert!((1.5 - row.get::<_, c_double>(3).unwrap()).abs() < EPSILON);
+        assert!(row.get::<_, Option<c_int>>(4).unwrap().is_none());
+        assert!(row.get::<_, Option<c_double>>(4).unwrap().is_none());
+        assert!(row.get::<_, Option<String>>(4).unwrap().is_none());
+
+        // check some invalid types
+
+        // 0 is actually a blob (Vec<u8>)
+        assert!(is_invalid_column_type(
+            row.get::<_, c_int>(0).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, c_int>(0).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(row.get::<_, i64>(0).err().unwrap()));
+        assert!(is_invalid_column_type(
+            row.get::<_, c_double>(0).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, String>(0).err().unwrap()
+        ));
+        // need to sort out time lib
+        /*assert!(is_invalid_column_type(
+            row.get::<_, time::Timespec>(0).err().unwrap()
+        ));*/
+        assert!(is_invalid_column_type(
+            row.get::<_, Option<c_int>>(0).err().unwrap()
+        ));
+
+        // 1 is actually a text (String)
+        assert!(is_invalid_column_type(
+            row.get::<_, c_int>(1).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(row.get::<_, i64>(1).err().unwrap()));
+        assert!(is_invalid_column_type(
+            row.get::<_, c_double>(1).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, Vec<u8>>(1).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, Option<c_int>>(1).err().unwrap()
+        ));
+
+        // 2 is actually an integer
+        assert!(is_invalid_column_type(
+            row.get::<_, String>(2).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, Vec<u8>>(2).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, Option<String>>(2).err().unwrap()
+        ));
+
+        // 3 is actually a float (c_double)
+        assert!(is_invalid_column_type(
+            row.get::<_, c_int>(3).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(row.get::<_, i64>(3).err().unwrap()));
+        assert!(is_invalid_column_type(
+            row.get::<_, String>(3).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, Vec<u8>>(3).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, Option<c_int>>(3).err().unwrap()
+        ));
+
+        // 4 is actually NULL
+        assert!(is_invalid_column_type(
+            row.get::<_, c_int>(4).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(row.get::<_, i64>(4).err().unwrap()));
+        assert!(is_invalid_column_type(
+            row.get::<_, c_double>(4).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, String>(4).err().unwrap()
+        ));
+        assert!(is_invalid_column_type(
+            row.get::<_, Vec<u8>>(4).err().unwrap()
+        ));
+        // need to sort out time lib
+        /*        assert!(is_invalid_column_type(
+            row.get::<_, time::Timespec>(4).err().unwrap()
+        ));*/
+    }
+
+    #[wasm_bindgen_test]
+    fn test_dynamic_type() {
+        use rusqlite::types::Value;
+        let db = checked_memory_handlexx();
+
+        db.execute(
+            "INSERT INTO foo(b, t, i, f) VALUES (X'0102', 'text', 1, 1.5)",
+            NO_PARAMS,
+        )
+        .unwrap();
+
+        let mut stmt = db.prepare("SELECT b, t, i, f, n FROM foo").unwrap();
+        let mut rows = stmt.query(NO_PARAMS).unwrap();
+
+        let row = rows.next().unwrap().unwrap();
+        assert_eq!(Value::Blob(vec![1, 2]), row.get::<_, Value>(0).unwrap());
+        assert_eq!(
+            Value::Text(String::from("text")),
+            row.get::<_, Value>(1).unwrap()
+        );
+        assert_eq!(Value::Integer(1), row.get::<_, Value>(2).unwrap());
+        match row.get::<_, Value>(3).unwrap() {
+            Value::Real(val) => assert!((1.5 - val).abs() < EPSILON),
+            x => panic!("Invalid Value {:?}", x),
+        }
+        assert_eq!(Value::Null, row.get::<_, Value>(4).unwrap());
+    }
+
+    use self::url::ParseError;
+    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+    fn checked_memory_handlexxx() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT, i INTEGER, f FLOAT, b BLOB)")
+            .unwrap();
+        db
+    }
+
+    #[wasm_bindgen_test]
+    fn test_naive_date() {
+        let db = checked_memory_handlexxx();
+        let date = NaiveDate::from_ymd(2016, 2, 23);
+        db.execute("INSERT INTO foo (t) VALUES (?)", &[&date])
+            .unwrap();
+
+        let s: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("2016-02-23", s);
+        let t: NaiveDate = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(date, t);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_naive_time() {
+        let db = checked_memory_handlexxx();
+        let time = NaiveTime::from_hms(23, 56, 4);
+        db.execute("INSERT INTO foo (t) VALUES (?)", &[&time])
+            .unwrap();
+
+        let s: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("23:56:04", s);
+        let v: NaiveTime = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(time, v);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_naive_date_time() {
+        let db = checked_memory_handlexxx();
+        let date = NaiveDate::from_ymd(2016, 2, 23);
+        let time = NaiveTime::from_hms(23, 56, 4);
+        let dt = NaiveDateTime::new(date, time);
+
+        db.execute("INSERT INTO foo (t) VALUES (?)", &[&dt])
+            .unwrap();
+
+        let s: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("2016-02-23T23:56:04", s);
+        let v: NaiveDateTime = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(dt, v);
+
+        db.execute("UPDATE foo set b = datetime(t)", NO_PARAMS)
+            .unwrap(); // "YYYY-MM-DD HH:MM:SS"
+        let hms: NaiveDateTime = db
+            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(dt, hms);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_date_time_utc() {
+        let db = checked_memory_handlexxx();
+        let date = NaiveDate::from_ymd(2016, 2, 23);
+        let time = NaiveTime::from_hms_milli(23, 56, 4, 789);
+        let dt = NaiveDateTime::new(date, time);
+        let utc = Utc.from_utc_datetime(&dt);
+
+        db.execute("INSERT INTO foo (t) VALUES (?)", &[&utc])
+            .unwrap();
+
+        let s: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("2016-02-23T23:56:04.789+00:00", s);
+
+        let v1: DateTime<Utc> = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(utc, v1);
+
+        let v2: DateTime<Utc> = db
+            .query_row("SELECT '2016-02-23 23:56:04.789'", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(utc, v2);
+
+        let v3: DateTime<Utc> = db
+            .query_row("SELECT '2016-02-23 23:56:04'", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(utc - chrono::Duration::milliseconds(789), v3);
+
+        let v4: DateTime<Utc> = db
+            .query_row("SELECT '2016-02-23 23:56:04.789+00:00'", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(utc, v4);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_date_time_local() {
+        let db = checked_memory_handlexxx();
+        let date = NaiveDate::from_ymd(2016, 2, 23);
+        let time = NaiveTime::from_hms_milli(23, 56, 4, 789);
+        let dt = NaiveDateTime::new(date, time);
+        let local = Local.from_local_datetime(&dt).single().unwrap();
+
+        db.execute("INSERT INTO foo (t) VALUES (?)", &[&local])
+            .unwrap();
+
+        // Stored string should be in UTC
+        let s: String = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert!(s.ends_with("+00:00"));
+
+        let v: DateTime<Local> = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(local, v);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_sqlite_functions() {
+        let db = checked_memory_handlexxx();
+        let result: Result<NaiveTime> =
+            db.query_row("SELECT CURRENT_TIME", NO_PARAMS, |r| r.get(0));
+        assert!(result.is_ok());
+        let result: Result<NaiveDate> =
+            db.query_row("SELECT CURRENT_DATE", NO_PARAMS, |r| r.get(0));
+        assert!(result.is_ok());
+        let result: Result<NaiveDateTime> =
+            db.query_row("SELECT CURRENT_TIMESTAMP", NO_PARAMS, |r| r.get(0));
+        assert!(result.is_ok());
+        let result: Result<DateTime<Utc>> =
+            db.query_row("SELECT CURRENT_TIMESTAMP", NO_PARAMS, |r| r.get(0));
+        assert!(result.is_ok());
+    }
+
+    fn checked_memory_handle_from_sql() -> Connection {
+        Connection::open_in_memory().unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_integral_ranges() {
+        let db = checked_memory_handle_from_sql();
+
+        fn check_ranges<T>(db: &Connection, out_of_range: &[i64], in_range: &[i64])
+        where
+            T: Into<i64> + FromSql + ::std::fmt::Debug,
+        {
+            for n in out_of_range {
+                let err = db
+                    .query_row("SELECT ?", &[n], |r| r.get::<_, T>(0))
+                    .unwrap_err();
+                match err {
+                    Error::IntegralValueOutOfRange(_, value) => assert_eq!(*n, value),
+                    _ => panic!("unexpected error: {}", err),
+                }
+            }
+            for n in in_range {
+                assert_eq!(
+                    *n,
+                    db.query_row("SELECT ?", &[n], |r| r.get::<_, T>(0))
+                        .unwrap()
+                        .into()
+                );
+            }
+        }
+
+        check_ranges::<i8>(&db, &[-129, 128], &[-128, 0, 1, 127]);
+        check_ranges::<i16>(&db, &[-32769, 32768], &[-32768, -1, 0, 1, 32767]);
+        check_ranges::<i32>(
+            &db,
+            &[-2_147_483_649, 2_147_483_648],
+            &[-2_147_483_648, -1, 0, 1, 2_147_483_647],
+        );
+        check_ranges::<u8>(&db, &[-2, -1, 256], &[0, 1, 255]);
+        check_ranges::<u16>(&db, &[-2, -1, 65536], &[0, 1, 65535]);
+        check_ranges::<u32>(&db, &[-2, -1, 4_294_967_296], &[0, 1, 4_294_967_295]);
+    }
+
+    extern crate serde_json;
+
+    fn checked_memory_handle_serde_json() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (t TEXT, b BLOB)")
+            .unwrap();
+        db
+    }
+
+    #[wasm_bindgen_test]
+    fn test_json_value() {
+        let db = checked_memory_handle_serde_json();
+
+        let json = r#"{"foo": 13, "bar": "baz"}"#;
+        let data: serde_json::Value = serde_json::from_str(json).unwrap();
+        db.execute(
+            "INSERT INTO foo (t, b) VALUES (?, ?)",
+            &[&data as &dyn ToSql, &json.as_bytes()],
+        )
+        .unwrap();
+
+        let t: serde_json::Value = db
+            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(data, t);
+        let b: serde_json::Value = db
+            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(data, b);
     }
 
+    fn is_to_sql<T: ToSql>() {}
+
     #[wasm_bindgen_test]
-    fn test_query_by_column_name_ignore_case() {
-        let db = Connection::open_in_memory().unwrap();
-        let sql = "BEGIN;
-                   CREATE TABLE foo(x INTEGER, y INTEGER);
-                   INSERT INTO foo VALUES(1, 3);
-                   END;";
-        db.execute_batch(sql).unwrap();
-        let mut stmt = db.prepare("SELECT y as Y FROM foo").unwrap();
-        let y: Result<i64> = stmt.query_row(NO_PARAMS, |r| r.get("y"));
-        assert_eq!(3i64, y.unwrap());
+    fn test_integral_types() {
+        is_to_sql::<i8>();
+        is_to_sql::<i16>();
+        is_to_sql::<i32>();
+        is_to_sql::<i64>();
+        is_to_sql::<u8>();
+        is_to_sql::<u16>();
+        is_to_sql::<u32>();
     }
 
-    /*  #[wasm_bindgen_test]
-    fn test_expanded_sql() {
+    #[wasm_bindgen_test]
+    fn test_cow_str() {
+        use std::borrow::Cow;
+        let s = "str";
+        let cow = Cow::Borrowed(s);
+        let r = cow.to_sql();
+        assert!(r.is_ok());
+        let cow = Cow::Owned::<str>(String::from(s));
+        let r = cow.to_sql();
+        assert!(r.is_ok());
+    }
+
+    #[cfg(feature = "i128_blob")]
+    #[wasm_bindgen_test]
+    fn test_i128() {
+        use crate::{Connection, NO_PARAMS};
+        use std::i128;
         let db = Connection::open_in_memory().unwrap();
-        let stmt = db.prepare("SELECT ?").unwrap();
-        stmt.bind_parameter(&1, 1).unwrap();
-        assert_eq!(Some("SELECT 1".to_owned()), stmt.expanded_sql());
-    }*/
+        db.execute_batch("CREATE TABLE foo (i128 BLOB, desc TEXT)")
+            .unwrap();
+        db.execute(
+            "
+            INSERT INTO foo(i128, desc) VALUES
+                (?, 'zero'),
+                (?, 'neg one'), (?, 'neg two'),
+                (?, 'pos one'), (?, 'pos two'),
+                (?, 'min'), (?, 'max')",
+            &[0i128, -1i128, -2i128, 1i128, 2i128, i128::MIN, i128::MAX],
+        )
+        .unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT i128, desc FROM foo ORDER BY i128 ASC")
+            .unwrap();
+
+        let res = stmt
+            .query_map(NO_PARAMS, |row| {
+                Ok((row.get::<_, i128>(0)?, row.get::<_, String>(1)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            res,
+            &[
+                (i128::MIN, "min".to_owned()),
+                (-2, "neg two".to_owned()),
+                (-1, "neg one".to_owned()),
+                (0, "zero".to_owned()),
+                (1, "pos one".to_owned()),
+                (2, "pos two".to_owned()),
+                (i128::MAX, "max".to_owned()),
+            ]
+        );
+    }
 
+    #[cfg(feature = "uuid")]
     #[wasm_bindgen_test]
-    fn test_bind_parameters() {
+    fn test_uuid() {
+        use crate::{params, Connection};
+        use uuid::Uuid;
+
         let db = Connection::open_in_memory().unwrap();
-        // dynamic slice:
-        db.query_row(
-            "SELECT ?1, ?2, ?3",
-            &[&1u8 as &dyn ToSql, &"one", &Some("one")],
-            |row| row.get::<_, u8>(0),
+        db.execute_batch("CREATE TABLE foo (id BLOB CHECK(length(id) = 16), label TEXT);")
+            .unwrap();
+
+        let id = Uuid::new_v4();
+
+        db.execute(
+            "INSERT INTO foo (id, label) VALUES (?, ?)",
+            params![id, "target"],
         )
         .unwrap();
-        // existing collection:
-        let data = vec![1, 2, 3];
-        db.query_row("SELECT ?1, ?2, ?3", &data, |row| row.get::<_, u8>(0))
+
+        let mut stmt = db
+            .prepare("SELECT id, label FROM foo WHERE id = ?")
             .unwrap();
-        db.query_row("SELECT ?1, ?2, ?3", data.as_slice(), |row| {
-            row.get::<_, u8>(0)
-        })
+
+        let mut rows = stmt.query(params![id]).unwrap();
+        let row = rows.next().unwrap().unwrap();
+
+        let found_id: Uuid = row.get_unwrap(0);
+        let found_label: String = row.get_unwrap(1);
+
+        assert_eq!(found_id, id);
+        assert_eq!(found_label, "target");
+    }
+
+    extern crate url;
+
+    fn checked_memory_handle_url() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE urls (i INTEGER, v TEXT)")
+            .unwrap();
+        db
+    }
+
+    fn get_url(db: &Connection, id: i64) -> Result<url::Url> {
+        db.query_row("SELECT v FROM urls WHERE i = ?", params![id], |r| r.get(0))
+    }
+
+    #[wasm_bindgen_test]
+    fn test_sql_url() {
+        let db = &checked_memory_handle_url();
+
+        let url0 = url::Url::parse("http://www.example1.com").unwrap();
+        let url1 = url::Url::parse("http://www.example1.com/👌").unwrap();
+        let url2 = "http://www.example2.com/👌";
+
+        db.execute(
+            "INSERT INTO urls (i, v) VALUES (0, ?), (1, ?), (2, ?), (3, ?)",
+            // also insert a non-hex encoded url (which might be present if it was
+            // inserted separately)
+            params![url0, url1, url2, "illegal"],
+        )
         .unwrap();
-        db.query_row("SELECT ?1, ?2, ?3", data, |row| row.get::<_, u8>(0))
+
+        assert_eq!(get_url(db, 0).unwrap(), url0);
+
+        assert_eq!(get_url(db, 1).unwrap(), url1);
+
+        // Should successfully read it, even though it wasn't inserted as an
+        // escaped url.
+        let out_url2: url::Url = get_url(db, 2).unwrap();
+        assert_eq!(out_url2, url::Url::parse(url2).unwrap());
+
+        // Make sure the conversion error comes through correctly.
+        let err = get_url(db, 3).unwrap_err();
+        match err {
+            Error::FromSqlConversionFailure(_, _, e) => {
+                assert_eq!(
+                    *e.downcast::<ParseError>().unwrap(),
+                    ParseError::RelativeUrlWithoutBase,
+                );
+            }
+            e => {
+                panic!("Expected conversion failure, got {}", e);
+            }
+        }
+    }
+
+    use rusqlite::vtab::{array, dequote, parse_boolean};
+    use std::rc::Rc;
+
+    #[wasm_bindgen_test]
+    fn test_dequote() {
+        assert_eq!("", dequote(""));
+        assert_eq!("'", dequote("'"));
+        assert_eq!("\"", dequote("\""));
+        assert_eq!("'\"", dequote("'\""));
+        assert_eq!("", dequote("''"));
+        assert_eq!("", dequote("\"\""));
+        assert_eq!("x", dequote("'x'"));
+        assert_eq!("x", dequote("\"x\""));
+        assert_eq!("x", dequote("x"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_parse_boolean() {
+        assert_eq!(None, parse_boolean(""));
+        assert_eq!(Some(true), parse_boolean("1"));
+        assert_eq!(Some(true), parse_boolean("yes"));
+        assert_eq!(Some(true), parse_boolean("on"));
+        assert_eq!(Some(true), parse_boolean("true"));
+        assert_eq!(Some(false), parse_boolean("0"));
+        assert_eq!(Some(false), parse_boolean("no"));
+        assert_eq!(Some(false), parse_boolean("off"));
+        assert_eq!(Some(false), parse_boolean("false"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_array_module() {
+        let db = Connection::open_in_memory().unwrap();
+        array::load_module(&db).unwrap();
+
+        let v = vec![1i64, 2, 3, 4];
+        let values = v.into_iter().map(Value::from).collect();
+        let ptr = Rc::new(values);
+        {
+            let mut stmt = db.prepare("SELECT value from rarray(?);").unwrap();
+
+            let rows = stmt.query_map(&[&ptr], |row| row.get::<_, i64>(0)).unwrap();
+            assert_eq!(2, Rc::strong_count(&ptr));
+            let mut count = 0;
+            for (i, value) in rows.enumerate() {
+                assert_eq!(i as i64, value.unwrap() - 1);
+                count += 1;
+            }
+            assert_eq!(4, count);
+        }
+        assert_eq!(1, Rc::strong_count(&ptr));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_typed_array_module() {
+        use rusqlite::vtab::array::ArrayData;
+
+        let db = Connection::open_in_memory().unwrap();
+        array::load_module(&db).unwrap();
+        db.execute_batch(
+            "CREATE TABLE t (id INTEGER, name TEXT);
+             INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd');",
+        )
+        .unwrap();
+
+        let ids = Rc::new(vec![4i64, 2]);
+        let names: Vec<String> = db
+            .prepare("SELECT name FROM t WHERE id IN rarray(?) ORDER BY id")
+            .unwrap()
+            .query_map(&[&ids], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec!["b", "d"], names);
+
+        let texts = Rc::new(vec!["c".to_owned(), "z".to_owned()]);
+        let count: i64 = db
+            .query_row(
+                "SELECT count(*) FROM t WHERE name IN rarray(?)",
+                &[&texts],
+                |r| r.get(0),
+            )
             .unwrap();
+        assert_eq!(1, count);
 
-        use std::collections::BTreeSet;
-        let data: BTreeSet<String> = ["one", "two", "three"]
-            .iter()
-            .map(|s| (*s).to_string())
-            .collect();
-        db.query_row("SELECT ?1, ?2, ?3", &data, |row| row.get::<_, String>(0))
+        let reals = ArrayData::Reals(Rc::new(vec![0.5, 1.5]));
+        let sum: f64 = db
+            .query_row("SELECT sum(value) FROM rarray(?)", &[&reals], |r| r.get(0))
             .unwrap();
-
-        let data = [0; 3];
-        db.query_row("SELECT ?1, ?2, ?3", &data, |row| row.get::<_, u8>(0))
+        assert_eq!(2.0, sum);
+
+        let pairs = Rc::new(vec![
+            vec![Value::from(1), Value::from("a".to_owned())],
+            vec![Value::from(2), Value::from("x".to_owned())],
+            vec![Value::from(3), Value::Null],
+        ]);
+        let matched: Vec<i64> = db
+            .prepare(
+                "SELECT id FROM t WHERE (id, name) IN \
+                 (SELECT value1, value2 FROM rarray2(?)) ORDER BY id",
+            )
+            .unwrap()
+            .query_map(&[&pairs], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
             .unwrap();
-        db.query_row("SELECT ?1, ?2, ?3", data.iter(), |row| row.get::<_, u8>(0))
+        assert_eq!(vec![1], matched);
+        let missing: Option<String> = db
+            .query_row(
+                "SELECT value2 FROM rarray2(?) WHERE value1 = 3",
+                &[&pairs],
+                |r| r.get(0),
+            )
             .unwrap();
+        assert_eq!(None, missing);
+        // Rows must be as wide as the table.
+        assert!(db
+            .query_row("SELECT count(*) FROM rarray3(?)", &[&pairs], |r| {
+                r.get::<_, i64>(0)
+            })
+            .is_err());
+        assert!(db
+            .query_row("SELECT count(*) FROM rarray2(?)", &[&ids], |r| {
+                r.get::<_, i64>(0)
+            })
+            .is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_empty_stmtx() {
-        let conn = Connection::open_in_memory().unwrap();
-        let mut stmt = conn.prepare("").unwrap();
-        assert_eq!(0, stmt.column_count());
-        assert!(stmt.parameter_index("test").is_ok());
-        assert!(stmt.step().is_err());
-        stmt.reset();
-        assert!(stmt.execute(NO_PARAMS).is_err());
+    fn test_query_array_params() {
+        use crate::database::Database;
+        use js_sys::{Array, Float64Array, Function, Object, Reflect};
+        use wasm_bindgen::{JsCast, JsValue};
+
+        let db = Database::new().unwrap();
+        db.exec(
+            "CREATE TABLE t (id INTEGER, name TEXT);
+             INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, 'c'),
+               (9007199254740992, 'even'), (9007199254740993, 'odd');",
+        )
+        .unwrap();
+        let query = |sql: &str, param: JsValue| db.query(sql, Array::of1(&param));
+        let count = |sql: &str, param: JsValue| -> usize {
+            query(sql, param).unwrap().length() as usize
+        };
+        let rarray = |values: Array| -> JsValue {
+            let wrapper = Object::new();
+            Reflect::set(&wrapper, &"rarray".into(), &values).unwrap();
+            wrapper.into()
+        };
+        let sql = "SELECT * FROM t WHERE id IN rarray(?)";
+
+        let ids = Array::of2(&1.into(), &3.into());
+        assert_eq!(2, count(sql, rarray(ids.clone())));
+        // Plain arrays of numbers are not bound as arrays.
+        assert!(query(sql, ids.into()).is_err());
+        let reals = Float64Array::from(&[2.0, 5.0][..]);
+        assert_eq!(1, count(sql, reals.into()));
+        let global = js_sys::global();
+        let function = |name: &str| -> Function {
+            Reflect::get(&global, &name.into()).unwrap().dyn_into().unwrap()
+        };
+        // Integers that a `Number` cannot tell apart, and `i64::MIN`.
+        let bigs = Array::of2(
+            &function("BigInt")
+                .call1(&JsValue::NULL, &"9007199254740993".into())
+                .unwrap(),
+            &function("BigInt")
+                .call1(&JsValue::NULL, &"-9223372036854775808".into())
+                .unwrap(),
+        );
+        let big = Reflect::construct(&function("BigInt64Array"), &Array::of1(&bigs)).unwrap();
+        let rows = query("SELECT name FROM t WHERE id IN rarray(?)", big).unwrap();
+        assert_eq!(1, rows.length());
+        let name = Reflect::get(&rows.get(0), &"name".into()).unwrap();
+        assert_eq!(Some("odd".to_owned()), name.as_string());
+        let names = Array::of2(&"b".into(), &"c".into());
+        let sql = "SELECT * FROM t WHERE name IN rarray(?)";
+        assert_eq!(2, count(sql, rarray(names.clone())));
+        // Plain arrays of strings are.
+        assert_eq!(2, count(sql, names.into()));
+        let pairs = Array::of2(
+            &Array::of2(&1.into(), &"a".into()),
+            &Array::of2(&2.into(), &"c".into()),
+        );
+        let sql = "SELECT * FROM t WHERE (id, name) IN (SELECT value1, value2 FROM rarray2(?))";
+        assert_eq!(1, count(sql, rarray(pairs.clone())));
+        let triples = "SELECT * FROM t WHERE (id, name, 0) IN \
+                       (SELECT value1, value2, value3 FROM rarray3(?))";
+        assert!(query(triples, rarray(pairs)).is_err());
+        let five: Array = (1..=5).map(|i| JsValue::from_f64(f64::from(i))).collect();
+        let wide = Array::of1(&five);
+        assert!(query(sql, rarray(wide)).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_comment_stmt() {
-        let conn = Connection::open_in_memory().unwrap();
-        conn.prepare("/*SELECT 1;*/").unwrap();
-    }
+    fn test_update_module() {
+        use rusqlite::vtab::{
+            sqlite3_vtab, sqlite3_vtab_cursor, transactional_update_module, Context, CreateVTab,
+            IndexInfo, TransactionVTab, UpdateVTab, VTab, VTabConnection, VTabCursor, VTabFunction,
+            Values,
+        };
+        use std::cell::RefCell;
+        use std::collections::BTreeMap;
+        use std::os::raw::c_int;
 
-    #[wasm_bindgen_test]
-    fn test_comment_and_sql_stmt() {
-        let conn = Connection::open_in_memory().unwrap();
-        let stmt = conn.prepare("/*...*/ SELECT 1;").unwrap();
-        assert_eq!(1, stmt.column_count());
-    }
+        type Rows = Rc<RefCell<BTreeMap<i64, String>>>;
 
-    #[wasm_bindgen_test]
-    fn test_semi_colon_stmt() {
-        let conn = Connection::open_in_memory().unwrap();
-        let stmt = conn.prepare(";").unwrap();
-        assert_eq!(0, stmt.column_count());
-    }
+        #[repr(C)]
+        struct KvTab {
+            base: sqlite3_vtab,
+            rows: Rows,
+            saved: Option<BTreeMap<i64, String>>,
+            name: Rc<RefCell<String>>,
+        }
 
-    use rusqlite::DropBehavior;
+        impl VTab for KvTab {
+            type Aux = (Rows, Rc<RefCell<String>>);
+            type Cursor = KvTabCursor;
+
+            fn connect(
+                _: &mut VTabConnection,
+                aux: Option<&Self::Aux>,
+                _args: &[&[u8]],
+            ) -> Result<(String, KvTab)> {
+                let aux = aux.unwrap();
+                let vtab = KvTab {
+                    base: sqlite3_vtab::default(),
+                    rows: aux.0.clone(),
+                    saved: None,
+                    name: aux.1.clone(),
+                };
+                Ok(("CREATE TABLE x(value)".to_owned(), vtab))
+            }
 
-    fn checked_memory_handlex() -> Connection {
-        let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
-        db
-    }
+            fn best_index(&self, info: &mut IndexInfo) -> Result<()> {
+                info.set_estimated_cost(1.);
+                Ok(())
+            }
 
-    #[wasm_bindgen_test]
-    fn test_drop() {
-        let mut db = checked_memory_handlex();
-        {
-            let tx = db.transaction().unwrap();
-            tx.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
-            // default: rollback
-        }
-        {
-            let mut tx = db.transaction().unwrap();
-            tx.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
-            tx.set_drop_behavior(DropBehavior::Commit)
+            fn open(&self) -> Result<KvTabCursor> {
+                Ok(KvTabCursor {
+                    base: sqlite3_vtab_cursor::default(),
+                    rows: self.rows.clone(),
+                    snapshot: Vec::new(),
+                    pos: 0,
+                })
+            }
         }
-        {
-            let tx = db.transaction().unwrap();
-            assert_eq!(
-                2i32,
-                tx.query_row::<i32, _, _>("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
-                    .unwrap()
-            );
+
+        impl CreateVTab for KvTab {}
+
+        fn shout(ctx: &mut Context, args: &Values<'_>) -> Result<()> {
+            let s: String = args.get(0)?;
+            ctx.set_result(&format!("{}!", s))
         }
-    }
 
-    #[wasm_bindgen_test]
-    fn test_explicit_rollback_commit() {
-        let mut db = checked_memory_handlex();
-        {
-            let mut tx = db.transaction().unwrap();
-            {
-                let mut sp = tx.savepoint().unwrap();
-                sp.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
-                sp.rollback().unwrap();
-                sp.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
-                sp.commit().unwrap();
+        impl UpdateVTab for KvTab {
+            fn delete(&mut self, arg: types::ValueRef<'_>) -> Result<()> {
+                self.rows.borrow_mut().remove(&arg.as_i64()?);
+                Ok(())
             }
-            tx.commit().unwrap();
-        }
-        {
-            let tx = db.transaction().unwrap();
-            tx.execute_batch("INSERT INTO foo VALUES(4)").unwrap();
-            tx.commit().unwrap();
-        }
-        {
-            let tx = db.transaction().unwrap();
-            assert_eq!(
-                6i32,
-                tx.query_row::<i32, _, _>("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
-                    .unwrap()
-            );
-        }
-    }
 
-    #[wasm_bindgen_test]
-    fn test_savepoint() {
-        let mut db = checked_memory_handlex();
-        {
-            let mut tx = db.transaction().unwrap();
-            tx.execute_batch("INSERT INTO foo VALUES(1)").unwrap();
-            assert_current_sum(1, &tx);
-            tx.set_drop_behavior(DropBehavior::Commit);
-            {
-                let mut sp1 = tx.savepoint().unwrap();
-                sp1.execute_batch("INSERT INTO foo VALUES(2)").unwrap();
-                assert_current_sum(3, &sp1);
-                // will rollback sp1
-                {
-                    let mut sp2 = sp1.savepoint().unwrap();
-                    sp2.execute_batch("INSERT INTO foo VALUES(4)").unwrap();
-                    assert_current_sum(7, &sp2);
-                    // will rollback sp2
-                    {
-                        let sp3 = sp2.savepoint().unwrap();
-                        sp3.execute_batch("INSERT INTO foo VALUES(8)").unwrap();
-                        assert_current_sum(15, &sp3);
-                        sp3.commit().unwrap();
-                        // committed sp3, but will be erased by sp2 rollback
-                    }
-                    assert_current_sum(15, &sp2);
-                }
-                assert_current_sum(3, &sp1);
+            fn insert(&mut self, args: &Values<'_>) -> Result<i64> {
+                let mut rows = self.rows.borrow_mut();
+                let rowid = match args.get::<Option<i64>>(1)? {
+                    Some(rowid) => rowid,
+                    None => rows.keys().next_back().map_or(1, |k| k + 1),
+                };
+                rows.insert(rowid, args.get(2)?);
+                Ok(rowid)
             }
-            assert_current_sum(1, &tx);
-        }
-        assert_current_sum(1, &db);
-    }
 
-    #[wasm_bindgen_test]
-    fn test_ignore_drop_behavior() {
-        let mut db = checked_memory_handlex();
+            fn update(&mut self, args: &Values<'_>) -> Result<()> {
+                let mut rows = self.rows.borrow_mut();
+                rows.remove(&args.get::<i64>(0)?);
+                rows.insert(args.get(1)?, args.get(2)?);
+                Ok(())
+            }
 
-        let mut tx = db.transaction().unwrap();
-        {
-            let mut sp1 = tx.savepoint().unwrap();
-            insert(1, &sp1);
-            sp1.rollback().unwrap();
-            insert(2, &sp1);
-            {
-                let mut sp2 = sp1.savepoint().unwrap();
-                sp2.set_drop_behavior(DropBehavior::Ignore);
-                insert(4, &sp2);
+            fn rename(&mut self, new_name: &str) -> Result<()> {
+                *self.name.borrow_mut() = new_name.to_owned();
+                Ok(())
+            }
+
+            fn find_function(&self, n_arg: c_int, name: &str) -> Option<VTabFunction> {
+                if n_arg == 1 && name == "upper" {
+                    Some(shout)
+                } else {
+                    None
+                }
             }
-            assert_current_sum(6, &sp1);
-            sp1.commit().unwrap();
         }
-        assert_current_sum(6, &tx);
-    }
 
-    #[wasm_bindgen_test]
-    fn test_savepoint_names() {
-        let mut db = checked_memory_handlex();
+        impl TransactionVTab for KvTab {
+            fn begin(&mut self) -> Result<()> {
+                self.saved = Some(self.rows.borrow().clone());
+                Ok(())
+            }
 
-        {
-            let mut sp1 = db.savepoint_with_name("my_sp").unwrap();
-            insert(1, &sp1);
-            assert_current_sum(1, &sp1);
-            {
-                let mut sp2 = sp1.savepoint_with_name("my_sp").unwrap();
-                sp2.set_drop_behavior(DropBehavior::Commit);
-                insert(2, &sp2);
-                assert_current_sum(3, &sp2);
-                sp2.rollback().unwrap();
-                assert_current_sum(1, &sp2);
-                insert(4, &sp2);
+            fn commit(&mut self) -> Result<()> {
+                self.saved = None;
+                Ok(())
             }
-            assert_current_sum(5, &sp1);
-            sp1.rollback().unwrap();
-            {
-                let mut sp2 = sp1.savepoint_with_name("my_sp").unwrap();
-                sp2.set_drop_behavior(DropBehavior::Ignore);
-                insert(8, &sp2);
+
+            fn rollback(&mut self) -> Result<()> {
+                if let Some(saved) = self.saved.take() {
+                    *self.rows.borrow_mut() = saved;
+                }
+                Ok(())
             }
-            assert_current_sum(8, &sp1);
-            sp1.commit().unwrap();
         }
-        assert_current_sum(8, &db);
-    }
 
-    #[wasm_bindgen_test]
-    fn test_rc() {
-        use std::rc::Rc;
-        let mut conn = Connection::open_in_memory().unwrap();
-        let rc_txn = Rc::new(conn.transaction().unwrap());
+        #[repr(C)]
+        struct KvTabCursor {
+            base: sqlite3_vtab_cursor,
+            rows: Rows,
+            snapshot: Vec<(i64, String)>,
+            pos: usize,
+        }
 
-        // This will compile only if Transaction is Debug
-        Rc::try_unwrap(rc_txn).unwrap();
-    }
+        impl VTabCursor for KvTabCursor {
+            fn filter(&mut self, _: c_int, _: Option<&str>, _: &Values<'_>) -> Result<()> {
+                self.snapshot = self
+                    .rows
+                    .borrow()
+                    .iter()
+                    .map(|(&k, v)| (k, v.clone()))
+                    .collect();
+                self.pos = 0;
+                Ok(())
+            }
 
-    fn insert(x: i32, conn: &Connection) {
-        conn.execute("INSERT INTO foo VALUES(?)", &[x]).unwrap();
-    }
+            fn next(&mut self) -> Result<()> {
+                self.pos += 1;
+                Ok(())
+            }
 
-    fn assert_current_sum(x: i32, conn: &Connection) {
-        let i = conn
-            .query_row::<i32, _, _>("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(x, i);
-    }
+            fn eof(&self) -> bool {
+                self.pos >= self.snapshot.len()
+            }
 
-    fn checked_memory_handlexx() -> Connection {
+            fn column(&self, ctx: &mut Context, _: c_int) -> Result<()> {
+                ctx.set_result(&self.snapshot[self.pos].1)
+            }
+
+            fn rowid(&self) -> Result<i64> {
+                Ok(self.snapshot[self.pos].0)
+            }
+        }
+
+        let rows: Rows = Rc::new(RefCell::new(BTreeMap::new()));
+        let name = Rc::new(RefCell::new(String::new()));
         let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo (b BLOB, t TEXT, i INTEGER, f FLOAT, n)")
+        let module = transactional_update_module::<KvTab>(2);
+        db.create_module("kv", &module, Some((rows.clone(), name.clone())))
             .unwrap();
-        db
-    }
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE t USING kv;
+             INSERT INTO t VALUES ('a');
+             INSERT INTO t(rowid, value) VALUES (10, 'b');
+             INSERT INTO t VALUES ('c');
+             UPDATE t SET value = 'B' WHERE rowid = 10;
+             UPDATE t SET rowid = 2 WHERE value = 'a';
+             DELETE FROM t WHERE value = 'c';",
+        )
+        .unwrap();
+        let expected: BTreeMap<i64, String> = vec![(2, "a".to_owned()), (10, "B".to_owned())]
+            .into_iter()
+            .collect();
+        assert_eq!(expected, *rows.borrow());
 
-    #[wasm_bindgen_test]
-    fn test_blobx() {
-        let db = checked_memory_handlexx();
+        db.execute_batch("BEGIN; INSERT INTO t VALUES ('x'); ROLLBACK;")
+            .unwrap();
+        assert_eq!(expected, *rows.borrow());
 
-        let v1234 = vec![1u8, 2, 3, 4];
-        db.execute("INSERT INTO foo(b) VALUES (?)", &[&v1234])
+        let shouted: String = db
+            .query_row(
+                "SELECT upper(value) FROM t WHERE rowid = 2",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
             .unwrap();
+        assert_eq!("a!", shouted);
 
-        let v: Vec<u8> = db
-            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
+        db.execute_batch("ALTER TABLE t RENAME TO u").unwrap();
+        assert_eq!("u", *name.borrow());
+    }
+    use rusqlite::vtab::csvtab::{self, Buffers};
+
+    fn csv_memory_handle() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        let buffers = Buffers::new();
+        csvtab::load_module_with_buffers(&db, &buffers).unwrap();
+        buffers.insert("test.csv", &include_bytes!("../libs/rusqlite/test.csv")[..]);
+        db.execute_batch("CREATE VIRTUAL TABLE vtab USING csv(buffer='test.csv', header=yes)")
             .unwrap();
-        assert_eq!(v, v1234);
+        db
     }
 
     #[wasm_bindgen_test]
-    fn test_empty_blob() {
-        let db = checked_memory_handlexx();
+    fn test_csv_module() {
+        let db = csv_memory_handle();
 
-        let empty = vec![];
-        db.execute("INSERT INTO foo(b) VALUES (?)", &[&empty])
-            .unwrap();
+        {
+            let mut s = db.prepare("SELECT rowid, * FROM vtab").unwrap();
+            {
+                let headers = s.column_names();
+                assert_eq!(vec!["rowid", "colA", "colB", "colC"], headers);
+            }
 
-        let v: Vec<u8> = db
-            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(v, empty);
+            let ids: Result<Vec<i32>> = s
+                .query(NO_PARAMS)
+                .unwrap()
+                .map(|row| row.get::<_, i32>(0))
+                .collect();
+            let sum = ids.unwrap().iter().sum::<i32>();
+            assert_eq!(sum, 15);
+        }
+        db.execute_batch("DROP TABLE vtab").unwrap();
     }
 
     #[wasm_bindgen_test]
-    fn test_str() {
-        let db = checked_memory_handlexx();
+    fn test_csv_cursor() {
+        let db = csv_memory_handle();
 
-        let s = "hello, world!";
-        db.execute("INSERT INTO foo(t) VALUES (?)", &[&s]).unwrap();
+        {
+            let mut s = db
+                .prepare(
+                    "SELECT v1.rowid, v1.* FROM vtab v1 NATURAL JOIN vtab v2 WHERE \
+                     v1.rowid < v2.rowid",
+                )
+                .unwrap();
 
-        let from: String = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(from, s);
+            let mut rows = s.query(NO_PARAMS).unwrap();
+            let row = rows.next().unwrap().unwrap();
+            assert_eq!(row.get_unwrap::<_, i32>(0), 2);
+        }
+        db.execute_batch("DROP TABLE vtab").unwrap();
     }
 
     #[wasm_bindgen_test]
-    fn test_string() {
-        let db = checked_memory_handlexx();
+    fn test_csv_data_and_inference() {
+        let db = Connection::open_in_memory().unwrap();
+        csvtab::load_module(&db).unwrap();
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE vtab USING csv(data='id,score,name
+1,2.5,O''Brien
+2,,x=y
+3,4,z', header=yes, infer=yes)",
+        )
+        .unwrap();
 
-        let s = "hello, world!";
-        db.execute("INSERT INTO foo(t) VALUES (?)", &[s.to_owned()])
+        let types: Vec<String> = db
+            .prepare("SELECT type FROM pragma_table_info('vtab') ORDER BY cid")
+            .unwrap()
+            .query_map(NO_PARAMS, |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
             .unwrap();
-
-        let from: String = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+        assert_eq!(vec!["INTEGER", "REAL", "TEXT"], types);
+        let row: (i64, Option<f64>, String) = db
+            .query_row(
+                "SELECT sum(id), max(score), group_concat(name) FROM vtab \
+                 WHERE score IS NOT NULL",
+                NO_PARAMS,
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
             .unwrap();
-        assert_eq!(from, s);
+        assert_eq!((4, Some(4.0), "O'Brien,z".to_owned()), row);
+
+        assert!(db
+            .execute_batch("CREATE VIRTUAL TABLE t USING csv(buffer='missing')")
+            .is_err());
+        assert!(db
+            .execute_batch(
+                "CREATE VIRTUAL TABLE t USING csv(data='a', schema='CREATE TABLE x(a)', infer=yes)"
+            )
+            .is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_value() {
-        let db = checked_memory_handlexx();
+    fn test_csv_create_table_as() {
+        let db = Connection::open_in_memory().unwrap();
+        let buffers = Buffers::new();
+        csvtab::load_module_with_buffers(&db, &buffers).unwrap();
+        buffers.insert("upload", "n;label\n1;one\n2;two\n".as_bytes().to_vec());
 
-        db.execute("INSERT INTO foo(i) VALUES (?)", &[Value::Integer(10)])
+        csvtab::create_table_as(
+            &db,
+            "imported",
+            &["buffer=upload", "header=yes", "delimiter=';'", "infer=yes"],
+        )
+        .unwrap();
+        // The buffer can go once imported.
+        assert!(buffers.remove("upload"));
+        let total: i64 = db
+            .query_row(
+                "SELECT sum(n) FROM imported WHERE typeof(n) = 'integer'",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
             .unwrap();
-
-        assert_eq!(
-            10i64,
-            db.query_row::<i64, _, _>("SELECT i FROM foo", NO_PARAMS, |r| r.get(0))
-                .unwrap()
-        );
+        assert_eq!(3, total);
+        let leftover: i64 = db
+            .query_row("SELECT count(*) FROM sqlite_temp_master", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(0, leftover);
+        assert!(csvtab::create_table_as(&db, "imported", &["buffer=upload"]).is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_option() {
-        let db = checked_memory_handlexx();
-
-        let s = Some("hello, world!");
-        let b = Some(vec![1u8, 2, 3, 4]);
+    fn test_write_csv() {
+        use rusqlite::csv_export::{BlobEncoding, CsvOptions, QuoteStyle};
 
-        db.execute("INSERT INTO foo(t) VALUES (?)", &[&s]).unwrap();
-        db.execute("INSERT INTO foo(b) VALUES (?)", &[&b]).unwrap();
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE t (i INTEGER, r REAL, s TEXT, b BLOB);
+             INSERT INTO t VALUES (1, 2.0, 'plain', x'00ff10');
+             INSERT INTO t VALUES (NULL, 0.5, 'a, \"b\"', x'666f6f');",
+        )
+        .unwrap();
+        let mut stmt = db.prepare("SELECT * FROM t ORDER BY rowid").unwrap();
 
-        let mut stmt = db
-            .prepare("SELECT t, b FROM foo ORDER BY ROWID ASC")
+        let mut csv = Vec::new();
+        let count = stmt
+            .write_csv(NO_PARAMS, &mut csv, &CsvOptions::default())
             .unwrap();
-        let mut rows = stmt.query(NO_PARAMS).unwrap();
-
-        {
-            let row1 = rows.next().unwrap().unwrap();
-            let s1: Option<String> = row1.get_unwrap(0);
-            let b1: Option<Vec<u8>> = row1.get_unwrap(1);
-            assert_eq!(s.unwrap(), s1.unwrap());
-            assert!(b1.is_none());
-        }
+        assert_eq!(2, count);
+        assert_eq!(
+            "i,r,s,b\n1,2.0,plain,00FF10\n,0.5,\"a, \"\"b\"\"\",666F6F\n",
+            String::from_utf8(csv).unwrap()
+        );
 
-        {
-            let row2 = rows.next().unwrap().unwrap();
-            let s2: Option<String> = row2.get_unwrap(0);
-            let b2: Option<Vec<u8>> = row2.get_unwrap(1);
-            assert!(s2.is_none());
-            assert_eq!(b, b2);
-        }
+        let options = CsvOptions {
+            quote_style: QuoteStyle::NonNumeric,
+            header: false,
+            null: "NULL".to_owned(),
+            blob_encoding: BlobEncoding::Base64,
+            ..CsvOptions::tsv()
+        };
+        let mut tsv = Vec::new();
+        stmt.write_csv(NO_PARAMS, &mut tsv, &options).unwrap();
+        assert_eq!(
+            "1\t2.0\t\"plain\"\t\"AP8Q\"\n\"NULL\"\t0.5\t\"a, \"\"b\"\"\"\t\"Zm9v\"\n",
+            String::from_utf8(tsv).unwrap()
+        );
     }
 
     #[wasm_bindgen_test]
-    #[allow(clippy::cognitive_complexity)]
-    fn test_mismatched_types() {
-        fn is_invalid_column_type(err: Error) -> bool {
-            match err {
-                Error::InvalidColumnType(..) => true,
-                _ => false,
-            }
-        }
-
-        let db = checked_memory_handlexx();
+    fn test_json_module() {
+        use rusqlite::vtab::jsontab;
 
-        db.execute(
-            "INSERT INTO foo(b, t, i, f) VALUES (X'0102', 'text', 1, 1.5)",
-            NO_PARAMS,
+        let db = Connection::open_in_memory().unwrap();
+        let buffers = Buffers::new();
+        jsontab::load_module_with_buffers(&db, &buffers).unwrap();
+        buffers.insert(
+            "events",
+            r#"{"user": {"name": "ann"}, "tags": ["a", "b"], "n": 1, "ok": true}
+
+{"user": {"name": "bob"}, "tags": [], "n": 2.5, "ok": false}
+{"user": null, "n": 3}
+"#,
+        );
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE events USING json(buffer=events, \
+             name='$.user.name', first_tag='$.tags[0]', tags='$[\"tags\"]', n='$.n', ok='$.ok')",
         )
         .unwrap();
+        let rows: Vec<String> = db
+            .prepare(
+                "SELECT quote(rowid) || ' ' || quote(name) || ' ' || quote(first_tag) || ' ' || \
+                 quote(tags) || ' ' || quote(n) || ' ' || quote(ok) FROM events",
+            )
+            .unwrap()
+            .query_map(NO_PARAMS, |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                r#"1 'ann' 'a' '["a","b"]' 1 1"#,
+                "2 'bob' NULL '[]' 2.5 0",
+                "3 NULL NULL NULL 3 NULL",
+            ],
+            rows
+        );
 
-        let mut stmt = db.prepare("SELECT b, t, i, f, n FROM foo").unwrap();
-        let mut rows = stmt.query(NO_PARAMS).unwrap();
-
-        let row = rows.next().unwrap().unwrap();
-
-        // check the correct types come back as expected
-        assert_eq!(vec![1, 2], row.get::<_, Vec<u8>>(0).unwrap());
-        assert_eq!("text", row.get::<_, String>(1).unwrap());
-        assert_eq!(1, row.get::<_, c_int>(2).unwrap());
-        assert!((1.5 - row.get::<_, c_double>(3).unwrap()).abs() < EPSILON);
-        assert!(row.get::<_, Option<c_int>>(4).unwrap().is_none());
-        assert!(row.get::<_, Option<c_double>>(4).unwrap().is_none());
-        assert!(row.get::<_, Option<String>>(4).unwrap().is_none());
-
-        // check some invalid types
-
-        // 0 is actually a blob (Vec<u8>)
-        assert!(is_invalid_column_type(
-            row.get::<_, c_int>(0).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, c_int>(0).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(row.get::<_, i64>(0).err().unwrap()));
-        assert!(is_invalid_column_type(
-            row.get::<_, c_double>(0).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, String>(0).err().unwrap()
-        ));
-        // need to sort out time lib
-        /*assert!(is_invalid_column_type(
-            row.get::<_, time::Timespec>(0).err().unwrap()
-        ));*/
-        assert!(is_invalid_column_type(
-            row.get::<_, Option<c_int>>(0).err().unwrap()
-        ));
-
-        // 1 is actually a text (String)
-        assert!(is_invalid_column_type(
-            row.get::<_, c_int>(1).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(row.get::<_, i64>(1).err().unwrap()));
-        assert!(is_invalid_column_type(
-            row.get::<_, c_double>(1).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, Vec<u8>>(1).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, Option<c_int>>(1).err().unwrap()
-        ));
+        // Without column paths, the members of the first object, in order.
+        jsontab::create_table_as(
+            &db,
+            "people",
+            &["data='[{\"name\": \"O''Brien\", \"id\": 1}, {\"id\": 2}]'"],
+        )
+        .unwrap();
+        let stmt = db.prepare("SELECT * FROM people").unwrap();
+        assert_eq!(vec!["name", "id"], stmt.column_names());
+        let name: String = db
+            .query_row("SELECT name FROM people WHERE id = 1", NO_PARAMS, |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!("O'Brien", name);
 
-        // 2 is actually an integer
-        assert!(is_invalid_column_type(
-            row.get::<_, String>(2).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, Vec<u8>>(2).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, Option<String>>(2).err().unwrap()
-        ));
+        assert!(db
+            .execute_batch("CREATE VIRTUAL TABLE bad USING json(data='{\"a\": 1}', format=array)")
+            .is_err());
+        assert!(db
+            .execute_batch("CREATE VIRTUAL TABLE bad USING json(data='[]', x='$.a[')")
+            .is_err());
+    }
 
-        // 3 is actually a float (c_double)
-        assert!(is_invalid_column_type(
-            row.get::<_, c_int>(3).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(row.get::<_, i64>(3).err().unwrap()));
-        assert!(is_invalid_column_type(
-            row.get::<_, String>(3).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, Vec<u8>>(3).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, Option<c_int>>(3).err().unwrap()
-        ));
+    #[wasm_bindgen_test]
+    fn test_write_ndjson() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE t (id INTEGER, meta JSON, note TEXT, data BLOB, score REAL);
+             INSERT INTO t VALUES (1, '{\"k\":[1,2]}', 'say \"hi\"', x'0102', 0.5);
+             INSERT INTO t VALUES (2, NULL, NULL, NULL, NULL);",
+        )
+        .unwrap();
+        let mut stmt = db.prepare("SELECT * FROM t ORDER BY id").unwrap();
+        let mut out = Vec::new();
+        assert_eq!(2, stmt.write_ndjson(NO_PARAMS, &mut out).unwrap());
+        assert_eq!(
+            "{\"id\":1,\"meta\":{\"k\":[1,2]},\"note\":\"say \\\"hi\\\"\",\"data\":[1,2],\"score\":0.5}\n\
+             {\"id\":2,\"meta\":null,\"note\":null,\"data\":null,\"score\":null}\n",
+            String::from_utf8(out).unwrap()
+        );
 
-        // 4 is actually NULL
-        assert!(is_invalid_column_type(
-            row.get::<_, c_int>(4).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(row.get::<_, i64>(4).err().unwrap()));
-        assert!(is_invalid_column_type(
-            row.get::<_, c_double>(4).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, String>(4).err().unwrap()
-        ));
-        assert!(is_invalid_column_type(
-            row.get::<_, Vec<u8>>(4).err().unwrap()
-        ));
-        // need to sort out time lib
-        /*        assert!(is_invalid_column_type(
-            row.get::<_, time::Timespec>(4).err().unwrap()
-        ));*/
+        db.execute("UPDATE t SET meta = 'not json' WHERE id = 2", NO_PARAMS)
+            .unwrap();
+        assert!(stmt.write_ndjson(NO_PARAMS, Vec::new()).is_err());
     }
 
+    use rusqlite::vtab::series;
+
     #[wasm_bindgen_test]
-    fn test_dynamic_type() {
-        use rusqlite::types::Value;
-        let db = checked_memory_handlexx();
+    fn test_series_module() {
+        let version = unsafe { ffi::sqlite3_libversion_number() };
+        if version < 3_008_012 {
+            return;
+        }
 
-        db.execute(
-            "INSERT INTO foo(b, t, i, f) VALUES (X'0102', 'text', 1, 1.5)",
-            NO_PARAMS,
-        )
-        .unwrap();
+        let db = Connection::open_in_memory().unwrap();
+        series::load_module(&db).unwrap();
 
-        let mut stmt = db.prepare("SELECT b, t, i, f, n FROM foo").unwrap();
-        let mut rows = stmt.query(NO_PARAMS).unwrap();
+        let mut s = db.prepare("SELECT * FROM generate_series(0,20,5)").unwrap();
+
+        let series = s.query_map(NO_PARAMS, |row| row.get::<_, i32>(0)).unwrap();
 
-        let row = rows.next().unwrap().unwrap();
-        assert_eq!(Value::Blob(vec![1, 2]), row.get::<_, Value>(0).unwrap());
-        assert_eq!(
-            Value::Text(String::from("text")),
-            row.get::<_, Value>(1).unwrap()
-        );
-        assert_eq!(Value::Integer(1), row.get::<_, Value>(2).unwrap());
-        match row.get::<_, Value>(3).unwrap() {
-            Value::Real(val) => assert!((1.5 - val).abs() < EPSILON),
-            x => panic!("Invalid Value {:?}", x),
+        let mut expected = 0;
+        for value in series {
+            assert_eq!(expected, value.unwrap());
+            expected += 5;
         }
-        assert_eq!(Value::Null, row.get::<_, Value>(4).unwrap());
     }
 
-    use self::url::ParseError;
-    use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+    // The rows of `sql`, mapped by `f`.
+    fn query_rows<T, F>(db: &Connection, sql: &str, f: F) -> Vec<T>
+    where
+        F: FnMut(&Row<'_>) -> Result<T>,
+    {
+        let mut stmt = db.prepare(sql).unwrap();
+        let rows = stmt.query_map(NO_PARAMS, f).unwrap();
+        rows.collect::<Result<_>>().unwrap()
+    }
 
-    fn checked_memory_handlexxx() -> Connection {
-        let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo (t TEXT, i INTEGER, f FLOAT, b BLOB)")
-            .unwrap();
-        db
+    // The first column of the rows of `sql`.
+    fn column_values<T: FromSql>(db: &Connection, sql: &str) -> Vec<T> {
+        query_rows(db, sql, |row| row.get(0))
     }
 
     #[wasm_bindgen_test]
-    fn test_naive_date() {
-        let db = checked_memory_handlexxx();
-        let date = NaiveDate::from_ymd(2016, 2, 23);
-        db.execute("INSERT INTO foo (t) VALUES (?)", &[&date])
-            .unwrap();
+    fn test_series_order_and_negative_step() {
+        let db = Connection::open_in_memory().unwrap();
+        series::load_module(&db).unwrap();
 
-        let s: String = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+        let values: Vec<i64> = column_values(&db, "SELECT value FROM generate_series(1, 10, -3)");
+        assert_eq!(vec![10, 7, 4, 1], values);
+        let values: Vec<i64> = column_values(
+            &db,
+            "SELECT value FROM generate_series(1, 10, -3) ORDER BY value",
+        );
+        assert_eq!(vec![1, 4, 7, 10], values);
+        let values: Vec<i64> = column_values(
+            &db,
+            "SELECT value FROM generate_series(0, 10, 4) ORDER BY value DESC",
+        );
+        assert_eq!(vec![8, 4, 0], values);
+        let values: Vec<i64> = column_values(&db, "SELECT value FROM generate_series(5, 1)");
+        assert!(values.is_empty());
+        let values: Vec<i64> = column_values(&db, "SELECT value FROM generate_series(5, 1, -1)");
+        assert!(values.is_empty());
+        let values: Vec<i64> = column_values(
+            &db,
+            "SELECT value FROM generate_series(
+                 -9223372036854775808, 9223372036854775807, -9223372036854775808
+             )",
+        );
+        assert_eq!(vec![0, i64::min_value()], values);
+        let values: Vec<i64> = column_values(
+            &db,
+            "SELECT value FROM generate_series(-9223372036854775808, 9223372036854775807) LIMIT 2",
+        );
+        assert_eq!(vec![i64::min_value(), i64::min_value() + 1], values);
+        let values: Vec<i64> = column_values(
+            &db,
+            "SELECT value FROM generate_series(-9223372036854775808, 9223372036854775807)
+             ORDER BY value DESC LIMIT 2",
+        );
+        assert_eq!(vec![i64::max_value(), i64::max_value() - 1], values);
+        // An ORDER BY on another column is left to SQLite.
+        let values: Vec<i64> = column_values(
+            &db,
+            "SELECT value FROM generate_series(1, 3) ORDER BY -value",
+        );
+        assert_eq!(vec![3, 2, 1], values);
+        // No sorting step is needed.
+        let mut stmt = db
+            .prepare(
+                "EXPLAIN QUERY PLAN SELECT value FROM generate_series(1, 3) ORDER BY value DESC",
+            )
             .unwrap();
-        assert_eq!("2016-02-23", s);
-        let t: NaiveDate = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+        let plan: Vec<String> = stmt
+            .query_map(NO_PARAMS, |r| r.get(3))
+            .unwrap()
+            .collect::<Result<_>>()
             .unwrap();
-        assert_eq!(date, t);
+        assert!(plan.iter().all(|step| !step.contains("ORDER BY")));
     }
 
     #[wasm_bindgen_test]
-    fn test_naive_time() {
-        let db = checked_memory_handlexxx();
-        let time = NaiveTime::from_hms(23, 56, 4);
-        db.execute("INSERT INTO foo (t) VALUES (?)", &[&time])
-            .unwrap();
+    fn test_series_real_and_date() {
+        let db = Connection::open_in_memory().unwrap();
+        series::load_module(&db).unwrap();
 
-        let s: String = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!("23:56:04", s);
-        let v: NaiveTime = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+        let values: Vec<f64> =
+            column_values(&db, "SELECT value FROM generate_series_real(0, 0.3, 0.1)");
+        assert_eq!(4, values.len());
+        assert!((values[3] - 0.3).abs() < 1e-9);
+        let values: Vec<f64> =
+            column_values(&db, "SELECT value FROM generate_series_real(1, 2, -0.5)");
+        assert_eq!(vec![2.0, 1.5, 1.0], values);
+        assert!(db
+            .query_row(
+                "SELECT value FROM generate_series_real(0, 1, 0)",
+                NO_PARAMS,
+                |r| r.get::<_, f64>(0)
+            )
+            .is_err());
+
+        let values: Vec<String> = column_values(
+            &db,
+            "SELECT value FROM generate_series_date('2020-01-30', '2020-02-02')",
+        );
+        assert_eq!(
+            vec!["2020-01-30", "2020-01-31", "2020-02-01", "2020-02-02"],
+            values
+        );
+        let values: Vec<String> = column_values(
+            &db,
+            "SELECT value FROM generate_series_date('2019-11-30', '2020-03-31', '+1 month')",
+        );
+        // As date('2019-11-30', '+3 months') is 2020-03-01.
+        assert_eq!(
+            vec![
+                "2019-11-30",
+                "2019-12-30",
+                "2020-01-30",
+                "2020-03-01",
+                "2020-03-30"
+            ],
+            values
+        );
+        let values: Vec<String> = column_values(
+            &db,
+            "SELECT value FROM generate_series_date('2020-01-01', '2020-01-01 18:00', '-6 hours')",
+        );
+        assert_eq!(
+            vec![
+                "2020-01-01 18:00:00",
+                "2020-01-01 12:00:00",
+                "2020-01-01 06:00:00",
+                "2020-01-01 00:00:00"
+            ],
+            values
+        );
+        let count: i64 = db
+            .query_row(
+                "SELECT count(*) FROM generate_series_date('2020-01-01', '2020-12-31', '+7 days')",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
             .unwrap();
-        assert_eq!(time, v);
+        assert_eq!(53, count);
+        // `stop` before `start`
+        for step in &["+1 day", "-1 day", "+1 month", "-1 year"] {
+            let count: i64 = db
+                .query_row(
+                    "SELECT count(*) FROM generate_series_date('2020-01-10', '2020-01-01', ?)",
+                    &[step],
+                    |r| r.get(0),
+                )
+                .unwrap();
+            assert_eq!(0, count);
+        }
     }
 
     #[wasm_bindgen_test]
-    fn test_naive_date_time() {
-        let db = checked_memory_handlexxx();
-        let date = NaiveDate::from_ymd(2016, 2, 23);
-        let time = NaiveTime::from_hms(23, 56, 4);
-        let dt = NaiveDateTime::new(date, time);
-
-        db.execute("INSERT INTO foo (t) VALUES (?)", &[&dt])
-            .unwrap();
+    fn test_fts5_tokenizers() {
+        use rusqlite::fts5;
 
-        let s: String = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!("2016-02-23T23:56:04", s);
-        let v: NaiveDateTime = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(dt, v);
+        let db = Connection::open_in_memory().unwrap();
+        fts5::load_tokenizers(&db).unwrap();
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE words USING fts5(body, tokenize = 'unicode_words');
+             INSERT INTO words VALUES ('Ünïcode Straße'), ('東京都に住む'), ('plain text');
+             CREATE VIRTUAL TABLE grams USING fts5(body, tokenize = 'ngram');
+             INSERT INTO grams VALUES ('Transliteration'), ('literal'), ('east');
+             CREATE VIRTUAL TABLE bigrams USING fts5(body, tokenize = 'ngram 2');
+             INSERT INTO bigrams VALUES ('東京都に住む'), ('京都');",
+        )
+        .unwrap();
 
-        db.execute("UPDATE foo set b = datetime(t)", NO_PARAMS)
-            .unwrap(); // "YYYY-MM-DD HH:MM:SS"
-        let hms: NaiveDateTime = db
-            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(dt, hms);
+        assert_eq!(
+            vec![1],
+            column_values::<i64>(&db, "SELECT rowid FROM words WHERE words MATCH 'ÜNÏCODE'")
+        );
+        assert_eq!(
+            vec![2],
+            column_values::<i64>(&db, "SELECT rowid FROM words WHERE words MATCH '東京'")
+        );
+        let none: Vec<i64> = column_values(&db, "SELECT rowid FROM words WHERE words MATCH '京東'");
+        assert!(none.is_empty());
+        assert_eq!(
+            vec![1, 2],
+            column_values::<i64>(
+                &db,
+                "SELECT rowid FROM grams WHERE grams MATCH 'LITER' ORDER BY rowid"
+            )
+        );
+        assert_eq!(
+            vec![1, 2],
+            column_values::<i64>(
+                &db,
+                "SELECT rowid FROM bigrams WHERE bigrams MATCH '京都' ORDER BY rowid"
+            )
+        );
+        assert!(db
+            .execute_batch("CREATE VIRTUAL TABLE bad USING fts5(body, tokenize = 'ngram x')")
+            .is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_date_time_utc() {
-        let db = checked_memory_handlexxx();
-        let date = NaiveDate::from_ymd(2016, 2, 23);
-        let time = NaiveTime::from_hms_milli(23, 56, 4, 789);
-        let dt = NaiveDateTime::new(date, time);
-        let utc = Utc.from_utc_datetime(&dt);
+    fn test_fts5_auxiliary_functions() {
+        let db = Connection::open_in_memory().unwrap();
+        // Surround the matched tokens of a column with the given strings.
+        db.create_fts5_function("mark", |ctx| {
+            let column = ctx.get::<i64>(0)? as usize;
+            let (open, close) = (ctx.get::<String>(1)?, ctx.get::<String>(2)?);
+            let mut hits = Vec::new();
+            for i in 0..ctx.inst_count()? {
+                let inst = ctx.inst(i)?;
+                if inst.column == column {
+                    hits.extend(inst.offset..inst.offset + ctx.phrase_size(inst.phrase));
+                }
+            }
+            let text = ctx.column_text(column)?;
+            let (mut marked, mut last, mut token) = (String::new(), 0, 0);
+            ctx.tokenize(text, |_, range| {
+                if hits.contains(&token) {
+                    marked.push_str(&text[last..range.start]);
+                    marked.push_str(&open);
+                    marked.push_str(&text[range.clone()]);
+                    marked.push_str(&close);
+                    last = range.end;
+                }
+                token += 1;
+                Ok(())
+            })?;
+            marked.push_str(&text[last..]);
+            Ok(marked)
+        })
+        .unwrap();
+        db.create_fts5_function("stats", |ctx| {
+            let mut documents = 0;
+            ctx.query_phrase(0, |_| {
+                documents += 1;
+                Ok(())
+            })?;
+            Ok(format!(
+                "{}/{} {} {}",
+                documents,
+                ctx.row_count()?,
+                ctx.column_size(None)?,
+                ctx.phrase_positions(0)?.len()
+            ))
+        })
+        .unwrap();
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE docs USING fts5(title, body);
+             INSERT INTO docs VALUES ('Rust', 'Rust is fast and rust is safe');
+             INSERT INTO docs VALUES ('C', 'C is fast');
+             INSERT INTO docs VALUES ('Go', 'Go is simple');",
+        )
+        .unwrap();
 
-        db.execute("INSERT INTO foo (t) VALUES (?)", &[&utc])
+        let mut stmt = db
+            .prepare(
+                "SELECT mark(docs, 1, '[', ']'), stats(docs) FROM docs \
+                 WHERE docs MATCH ? ORDER BY rowid",
+            )
             .unwrap();
-
-        let s: String = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+        let rows: Vec<(String, String)> = stmt
+            .query_map(&["fast"], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
             .unwrap();
-        assert_eq!("2016-02-23T23:56:04.789+00:00", s);
+        assert_eq!(
+            vec![
+                (
+                    "Rust is [fast] and rust is safe".to_owned(),
+                    "2/3 8 1".to_owned()
+                ),
+                ("C is [fast]".to_owned(), "2/3 4 1".to_owned()),
+            ],
+            rows
+        );
+        let marked: String = stmt.query_row(&["rust"], |r| r.get(0)).unwrap();
+        assert_eq!("[Rust] is fast and [rust] is safe", marked);
+    }
 
-        let v1: DateTime<Utc> = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(utc, v1);
+    use rusqlite::vtab::vector;
 
-        let v2: DateTime<Utc> = db
-            .query_row("SELECT '2016-02-23 23:56:04.789'", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(utc, v2);
+    // The rowid and distance of a row of a nearest-neighbour query.
+    fn rowid_distance(row: &Row<'_>) -> Result<(i64, f64)> {
+        Ok((row.get(0)?, row.get(1)?))
+    }
 
-        let v3: DateTime<Utc> = db
-            .query_row("SELECT '2016-02-23 23:56:04'", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(utc - chrono::Duration::milliseconds(789), v3);
+    #[wasm_bindgen_test]
+    fn test_vector_functions() {
+        let db = Connection::open_in_memory().unwrap();
+        vector::load_module(&db).unwrap();
 
-        let v4: DateTime<Utc> = db
-            .query_row("SELECT '2016-02-23 23:56:04.789+00:00'", NO_PARAMS, |r| {
-                r.get(0)
-            })
+        let blob: Vec<u8> = db
+            .query_row("SELECT vector('[1, 2.5, -3]')", NO_PARAMS, |r| r.get(0))
             .unwrap();
-        assert_eq!(utc, v4);
+        assert_eq!(Some(vec![1.0, 2.5, -3.0]), vector::from_blob(&blob));
+        let json: String = db
+            .query_row("SELECT vector_json(?)", &[&blob], |r| r.get(0))
+            .unwrap();
+        assert_eq!("[1,2.5,-3]", json);
+
+        let distance =
+            |sql: &str| -> f64 { db.query_row(sql, NO_PARAMS, |r| r.get(0)).unwrap() };
+        let orthogonal = distance("SELECT vector_distance_cosine('[1, 0]', '[0, 1]')");
+        assert!((orthogonal - 1.0).abs() < 1e-6);
+        let parallel = distance("SELECT vector_distance_cosine('[1, 1]', '[2, 2]')");
+        assert!(parallel.abs() < 1e-6);
+        let l2 = distance("SELECT vector_distance_l2('[0, 0]', '[3, 4]')");
+        assert!((l2 - 5.0).abs() < 1e-6);
+        let dot = distance("SELECT vector_dot('[1, 2, 3, 4, 5]', '[5, 4, 3, 2, 1]')");
+        assert!((dot - 35.0).abs() < 1e-6);
+
+        assert!(db
+            .query_row("SELECT vector_dot('[1, 2]', '[1, 2, 3]')", NO_PARAMS, |r| {
+                r.get::<_, f64>(0)
+            })
+            .is_err());
+        assert!(db
+            .query_row("SELECT vector('not a vector')", NO_PARAMS, |r| {
+                r.get::<_, Vec<u8>>(0)
+            })
+            .is_err());
     }
 
     #[wasm_bindgen_test]
-    fn test_date_time_local() {
-        let db = checked_memory_handlexxx();
-        let date = NaiveDate::from_ymd(2016, 2, 23);
-        let time = NaiveTime::from_hms_milli(23, 56, 4, 789);
-        let dt = NaiveDateTime::new(date, time);
-        let local = Local.from_local_datetime(&dt).single().unwrap();
+    fn test_vector_simd_matches_scalar() {
+        let db = Connection::open_in_memory().unwrap();
+        vector::load_module(&db).unwrap();
+        // Lengths around multiples of 4 exercise both the SIMD lanes and the tail.
+        for len in 1..=13 {
+            let a: Vec<f32> = (0..len).map(|i| i as f32 * 0.5 - 1.25).collect();
+            let b: Vec<f32> = (0..len).map(|i| 3.0 - i as f32 * 0.75).collect();
+            let dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+            let l2: f32 = a.iter().zip(&b).map(|(x, y)| (x - y) * (x - y)).sum();
+            let (a, b) = (vector::to_blob(&a), vector::to_blob(&b));
+            let simd = |sql: &str| -> f64 {
+                db.query_row(sql, &[&a, &b], |r| r.get(0)).unwrap()
+            };
+            assert!((simd("SELECT vector_dot(?, ?)") - f64::from(dot)).abs() < 1e-4, "{}", len);
+            let distance = simd("SELECT vector_distance_l2(?, ?)");
+            assert!((distance - f64::from(l2.sqrt())).abs() < 1e-4, "{}", len);
+        }
+    }
 
-        db.execute("INSERT INTO foo (t) VALUES (?)", &[&local])
-            .unwrap();
+    #[wasm_bindgen_test]
+    fn test_vector_module() {
+        let db = Connection::open_in_memory().unwrap();
+        vector::load_module(&db).unwrap();
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE v USING vector(dims=2, metric=l2);
+             INSERT INTO v(rowid, vector) VALUES
+               (1, '[0, 0]'), (2, '[1, 0]'), (3, '[5, 5]'), (4, '[0, 2]');
+             INSERT INTO v(vector) VALUES (vector('[10, 10]'));",
+        )
+        .unwrap();
 
-        // Stored string should be in UTC
-        let s: String = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert!(s.ends_with("+00:00"));
+        let nearest = query_rows(
+            &db,
+            "SELECT rowid, distance FROM v WHERE vector MATCH '[0.9, 0.1]' AND k = 3",
+            rowid_distance,
+        );
+        let ids: Vec<i64> = nearest.iter().map(|&(id, _)| id).collect();
+        assert_eq!(vec![2, 1, 4], ids);
+        assert!((nearest[0].1 - 0.1414).abs() < 1e-3);
+        // Without k, every row is returned, nearest first.
+        let all = query_rows(
+            &db,
+            "SELECT rowid, distance FROM v WHERE vector MATCH '[9, 9]'",
+            rowid_distance,
+        );
+        let ids: Vec<i64> = all.iter().map(|&(id, _)| id).collect();
+        assert_eq!(vec![5, 3, 4, 2, 1], ids);
+        let ordered = query_rows(
+            &db,
+            "SELECT rowid, distance FROM v WHERE vector MATCH '[9, 9]' AND k = 2 ORDER BY distance",
+            rowid_distance,
+        );
+        assert_eq!(all[..2].to_vec(), ordered);
 
-        let v: DateTime<Local> = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
+        db.execute_batch(
+            "UPDATE v SET vector = '[1, 1]' WHERE rowid = 5;
+             DELETE FROM v WHERE rowid = 2;",
+        )
+        .unwrap();
+        let ids: Vec<i64> = query_rows(
+            &db,
+            "SELECT rowid, distance FROM v WHERE vector MATCH '[0.9, 0.2]' AND k = 2",
+            rowid_distance,
+        )
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+        assert_eq!(vec![5, 1], ids);
+        let json: String = db
+            .query_row(
+                "SELECT vector_json(vector) FROM v WHERE rowid = 5",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
             .unwrap();
-        assert_eq!(local, v);
+        assert_eq!("[1,1]", json);
+
+        assert!(db
+            .execute("INSERT INTO v(vector) VALUES ('[1, 2, 3]')", NO_PARAMS)
+            .is_err());
+        assert!(db
+            .execute_batch("CREATE VIRTUAL TABLE w USING vector(metric=l2)")
+            .is_err());
+        db.execute_batch("DROP TABLE v").unwrap();
+        let shadows: i64 = db
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE name LIKE 'v\\_%' ESCAPE '\\'",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(0, shadows);
     }
 
     #[wasm_bindgen_test]
-    fn test_sqlite_functions() {
-        let db = checked_memory_handlexxx();
-        let result: Result<NaiveTime> =
-            db.query_row("SELECT CURRENT_TIME", NO_PARAMS, |r| r.get(0));
-        assert!(result.is_ok());
-        let result: Result<NaiveDate> =
-            db.query_row("SELECT CURRENT_DATE", NO_PARAMS, |r| r.get(0));
-        assert!(result.is_ok());
-        let result: Result<NaiveDateTime> =
-            db.query_row("SELECT CURRENT_TIMESTAMP", NO_PARAMS, |r| r.get(0));
-        assert!(result.is_ok());
-        let result: Result<DateTime<Utc>> =
-            db.query_row("SELECT CURRENT_TIMESTAMP", NO_PARAMS, |r| r.get(0));
-        assert!(result.is_ok());
+    fn test_vector_ivf_index() {
+        let db = Connection::open_in_memory().unwrap();
+        vector::load_module(&db).unwrap();
+        db.execute_batch("CREATE VIRTUAL TABLE v USING vector(dims=2, metric=cosine, lists=2)")
+            .unwrap();
+        {
+            let mut insert = db
+                .prepare("INSERT INTO v(rowid, vector) VALUES (?, ?)")
+                .unwrap();
+            // Two clusters, around the x and y axes.
+            for i in 0..20 {
+                let x = 1.0 + (i % 5) as f32 / 10.0;
+                let v = if i < 10 { [x, 0.1] } else { [0.1, x] };
+                insert
+                    .execute(&[&(i as i64) as &dyn ToSql, &vector::to_blob(&v)])
+                    .unwrap();
+            }
+        }
+        db.execute("INSERT INTO v(command) VALUES ('train')", NO_PARAMS)
+            .unwrap();
+        let lists: i64 = db
+            .query_row(
+                "SELECT count(DISTINCT list) FROM v_vectors WHERE list IS NOT NULL",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, lists);
+
+        // A single probe only searches the cluster of the query.
+        let nearest = query_rows(
+            &db,
+            "SELECT rowid, distance FROM v WHERE vector MATCH '[0, 1]'",
+            rowid_distance,
+        );
+        assert_eq!(10, nearest.len());
+        assert!(nearest.iter().all(|&(id, _)| id >= 10));
+        let probed = query_rows(
+            &db,
+            "SELECT rowid, distance FROM v WHERE vector MATCH '[0, 1]' AND probes = 2",
+            rowid_distance,
+        );
+        assert_eq!(20, probed.len());
+        assert_eq!(nearest[..], probed[..10]);
+
+        // Vectors inserted after training join their nearest cluster.
+        db.execute(
+            "INSERT INTO v(rowid, vector) VALUES (100, '[0.2, 3]')",
+            NO_PARAMS,
+        )
+        .unwrap();
+        let nearest = query_rows(
+            &db,
+            "SELECT rowid, distance FROM v WHERE vector MATCH '[0, 1]' AND k = 1",
+            rowid_distance,
+        );
+        assert_eq!(100, nearest[0].0);
+        assert!(db
+            .execute("INSERT INTO v(command) VALUES ('optimize')", NO_PARAMS)
+            .is_err());
     }
 
-    fn checked_memory_handle_from_sql() -> Connection {
-        Connection::open_in_memory().unwrap()
+    #[wasm_bindgen_test]
+    fn test_query_vector_params() {
+        use crate::database::Database;
+        use js_sys::{Array, Float32Array};
+
+        let db = Database::new().unwrap();
+        db.exec(
+            "CREATE VIRTUAL TABLE v USING vector(dims=3);
+             INSERT INTO v(rowid, vector) VALUES (1, '[1, 0, 0]'), (2, '[0, 1, 0]');",
+        )
+        .unwrap();
+        let query = Float32Array::from(&[0.1f32, 0.9, 0.0][..]);
+        let rows = db
+            .query(
+                "SELECT rowid FROM v WHERE vector MATCH ? AND k = 1",
+                Array::of1(&query.into()),
+            )
+            .unwrap();
+        assert_eq!(1, rows.length());
+        let rowid = js_sys::Reflect::get(&rows.get(0), &"rowid".into()).unwrap();
+        assert_eq!(Some(2.0), rowid.as_f64());
     }
 
     #[wasm_bindgen_test]
-    fn test_integral_ranges() {
-        let db = checked_memory_handle_from_sql();
+    fn test_rtree_query_functions() {
+        use rusqlite::rtree::{self, Within};
 
-        fn check_ranges<T>(db: &Connection, out_of_range: &[i64], in_range: &[i64])
-        where
-            T: Into<i64> + FromSql + ::std::fmt::Debug,
+        let db = Connection::open_in_memory().unwrap();
+        rtree::load_functions(&db).unwrap();
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE places USING rtree(id, min_lon, max_lon, min_lat, max_lat);
+             INSERT INTO places VALUES
+               (1, 2.2945, 2.2945, 48.8584, 48.8584),  -- Eiffel Tower
+               (2, 2.3499, 2.3499, 48.8530, 48.8530),  -- Notre-Dame
+               (3, 2.3376, 2.3376, 48.8606, 48.8606),  -- Louvre
+               (4, -0.1276, -0.1276, 51.5072, 51.5072); -- London",
+        )
+        .unwrap();
         {
-            for n in out_of_range {
-                let err = db
-                    .query_row("SELECT ?", &[n], |r| r.get::<_, T>(0))
-                    .unwrap_err();
-                match err {
-                    Error::IntegralValueOutOfRange(_, value) => assert_eq!(*n, value),
-                    _ => panic!("unexpected error: {}", err),
-                }
-            }
-            for n in in_range {
-                assert_eq!(
-                    *n,
-                    db.query_row("SELECT ?", &[n], |r| r.get::<_, T>(0))
-                        .unwrap()
-                        .into()
-                );
+            // A grid of points, for the tree to have inner nodes.
+            let mut insert = db
+                .prepare("INSERT INTO places VALUES (?1, ?2, ?2, ?3, ?3)")
+                .unwrap();
+            for i in 0..2000 {
+                let lon = 10.0 + (i % 50) as f64 / 10.0;
+                let lat = 40.0 + (i / 50) as f64 / 10.0;
+                insert
+                    .execute(&[&(100 + i) as &dyn ToSql, &lon, &lat])
+                    .unwrap();
             }
         }
 
-        check_ranges::<i8>(&db, &[-129, 128], &[-128, 0, 1, 127]);
-        check_ranges::<i16>(&db, &[-32769, 32768], &[-32768, -1, 0, 1, 32767]);
-        check_ranges::<i32>(
+        // Nearest first.
+        assert_eq!(
+            vec![1, 3],
+            column_values::<i64>(
+                &db,
+                "SELECT id FROM places WHERE id MATCH within_radius(48.8584, 2.2945, 4000)"
+            )
+        );
+        assert_eq!(
+            vec![3, 2, 1],
+            column_values::<i64>(
+                &db,
+                "SELECT id FROM places WHERE id MATCH within_radius(48.8606, 2.3376, 10000)"
+            )
+        );
+        let grid = column_values::<i64>(
             &db,
-            &[-2_147_483_649, 2_147_483_648],
-            &[-2_147_483_648, -1, 0, 1, 2_147_483_647],
+            "SELECT id FROM places WHERE id MATCH within_radius(40.0, 10.0, 12000)",
         );
-        check_ranges::<u8>(&db, &[-2, -1, 256], &[0, 1, 255]);
-        check_ranges::<u16>(&db, &[-2, -1, 65536], &[0, 1, 65535]);
-        check_ranges::<u32>(&db, &[-2, -1, 4_294_967_296], &[0, 1, 4_294_967_295]);
-    }
+        // 0.1 degree of latitude is about 11.1 km, of longitude at 40° about 8.5 km.
+        assert_eq!(vec![100, 101, 150], grid);
 
-    extern crate serde_json;
-
-    fn checked_memory_handle_serde_json() -> Connection {
-        let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo (t TEXT, b BLOB)")
+        // A triangle around Paris.
+        assert_eq!(
+            vec![1, 2, 3],
+            column_values::<i64>(
+                &db,
+                "SELECT id FROM places WHERE id MATCH within_polygon(2.0, 48.7, 2.6, 48.7, 2.3, 49.0) ORDER BY id"
+            )
+        );
+        // The lower left half of a square of the grid.
+        let count: i64 = db
+            .query_row(
+                "SELECT count(*) FROM places WHERE id MATCH within_polygon(9.95, 39.95, 11.0, 39.95, 9.95, 41.0)",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
             .unwrap();
-        db
+        assert_eq!(55, count);
+        let mut stmt = db
+            .prepare("SELECT id FROM places WHERE id MATCH within_polygon(1, 2, 3, 4)")
+            .unwrap();
+        let mut rows = stmt.query(NO_PARAMS).unwrap();
+        let err = rows.next().unwrap_err();
+        assert!(err.to_string().contains("at least 3 vertices"), "{}", err);
+
+        // A custom function: entries whose rowid is in a range, testing
+        // every node of the tree.
+        db.create_rtree_query_function("id_between", |info| {
+            let (low, high) = (info.params()[0] as i64, info.params()[1] as i64);
+            if info.is_entry() && (info.rowid() < low || info.rowid() > high) {
+                info.set_within(Within::Not);
+            } else {
+                info.set_within(Within::Partly);
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            vec![2, 3, 4],
+            column_values::<i64>(
+                &db,
+                "SELECT id FROM places WHERE id MATCH id_between(2, 4) ORDER BY id"
+            )
+        );
     }
 
     #[wasm_bindgen_test]
-    fn test_json_value() {
-        let db = checked_memory_handle_serde_json();
+    fn test_rtree_radius_near_poles_and_antimeridian() {
+        use rusqlite::rtree;
 
-        let json = r#"{"foo": 13, "bar": "baz"}"#;
-        let data: serde_json::Value = serde_json::from_str(json).unwrap();
-        db.execute(
-            "INSERT INTO foo (t, b) VALUES (?, ?)",
-            &[&data as &dyn ToSql, &json.as_bytes()],
+        let db = Connection::open_in_memory().unwrap();
+        rtree::load_functions(&db).unwrap();
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE places USING rtree(id, min_lon, max_lon, min_lat, max_lat)",
         )
         .unwrap();
+        // Grids around the north pole, and either side of the antimeridian,
+        // for the tree to have inner nodes there.
+        let mut points = Vec::new();
+        for i in 0..2000 {
+            let lon = -180.0 + (i % 50) as f64 * 7.2;
+            points.push((lon, 85.0 + (i / 50) as f64 * 0.12));
+        }
+        for i in 0..2000 {
+            let lon = match i % 100 {
+                j if j < 50 => -179.95 + j as f64 * 0.1,
+                j => 175.05 + (j - 50) as f64 * 0.1,
+            };
+            points.push((lon, -1.0 + (i / 100) as f64 * 0.1));
+        }
+        {
+            let mut insert = db
+                .prepare("INSERT INTO places VALUES (?1, ?2, ?2, ?3, ?3)")
+                .unwrap();
+            for (id, &(lon, lat)) in points.iter().enumerate() {
+                insert
+                    .execute(&[&(id as i64) as &dyn ToSql, &lon, &lat])
+                    .unwrap();
+            }
+        }
+        let haversine = |lat1: f64, lon1: f64, lat2: f64, lon2: f64| -> f64 {
+            let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+            let a = ((phi2 - phi1) / 2.0).sin().powi(2)
+                + phi1.cos() * phi2.cos() * ((lon2 - lon1).to_radians() / 2.0).sin().powi(2);
+            2.0 * 6_371_008.8 * a.sqrt().min(1.0).asin()
+        };
 
-        let t: serde_json::Value = db
-            .query_row("SELECT t FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(data, t);
-        let b: serde_json::Value = db
-            .query_row("SELECT b FROM foo", NO_PARAMS, |r| r.get(0))
-            .unwrap();
-        assert_eq!(data, b);
+        for &(lat, lon, radius) in &[(88.0, 0.0, 250_000.0), (0.0, 179.99, 20_000.0)] {
+            let sql = format!(
+                "SELECT id FROM places WHERE id MATCH within_radius({}, {}, {})",
+                lat, lon, radius
+            );
+            let mut found: Vec<i64> = column_values(&db, &sql);
+            found.sort();
+            let expected: Vec<i64> = points
+                .iter()
+                .enumerate()
+                .filter(|&(_, &(p_lon, p_lat))| haversine(lat, lon, p_lat, p_lon) <= radius)
+                .map(|(id, _)| id as i64)
+                .collect();
+            assert!(!expected.is_empty());
+            assert_eq!(expected, found, "{}", sql);
+        }
     }
 
-    fn is_to_sql<T: ToSql>() {}
-
     #[wasm_bindgen_test]
-    fn test_integral_types() {
-        is_to_sql::<i8>();
-        is_to_sql::<i16>();
-        is_to_sql::<i32>();
-        is_to_sql::<i64>();
-        is_to_sql::<u8>();
-        is_to_sql::<u16>();
-        is_to_sql::<u32>();
+    fn test_geopoly() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE zones USING geopoly(name);
+             INSERT INTO zones(_shape, name) VALUES
+               ('[[0,0],[10,0],[10,10],[0,10],[0,0]]', 'square'),
+               ('[[20,20],[30,20],[25,30],[20,20]]', 'triangle');",
+        )
+        .unwrap();
+        let name: String = db
+            .query_row(
+                "SELECT name FROM zones WHERE geopoly_contains_point(_shape, 25, 22)",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!("triangle", name);
+        let area: f64 = db
+            .query_row(
+                "SELECT geopoly_area(_shape) FROM zones WHERE name = 'square'",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert!((area - 100.0).abs() < 1e-9);
+        let overlapping: i64 = db
+            .query_row(
+                "SELECT count(*) FROM zones WHERE geopoly_overlap(_shape, '[[5,5],[22,5],[22,22],[5,22],[5,5]]')",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, overlapping);
     }
 
     #[wasm_bindgen_test]
-    fn test_cow_str() {
-        use std::borrow::Cow;
-        let s = "str";
-        let cow = Cow::Borrowed(s);
-        let r = cow.to_sql();
-        assert!(r.is_ok());
-        let cow = Cow::Owned::<str>(String::from(s));
-        let r = cow.to_sql();
-        assert!(r.is_ok());
+    fn test_js_functions() {
+        use crate::database::Database;
+        use js_sys::{Function, Object, Reflect};
+        use wasm_bindgen::JsValue;
+
+        let db = Database::new().unwrap();
+        let conn = db.connection();
+        let options = Object::new();
+        Reflect::set(&options, &"deterministic".into(), &true.into()).unwrap();
+        db.create_function(
+            "js_add",
+            Function::new_with_args("a, b", "return a + b"),
+            options.into(),
+        )
+        .unwrap();
+        db.create_function(
+            "js_upper",
+            Function::new_with_args("s", "return s === null ? null : s.toUpperCase()"),
+            JsValue::UNDEFINED,
+        )
+        .unwrap();
+        let varargs = Object::new();
+        Reflect::set(&varargs, &"varargs".into(), &true.into()).unwrap();
+        db.create_function(
+            "js_count",
+            Function::new_no_args("return arguments.length"),
+            varargs.into(),
+        )
+        .unwrap();
+        db.create_function(
+            "js_fail",
+            Function::new_no_args("throw new Error('no luck')"),
+            JsValue::UNDEFINED,
+        )
+        .unwrap();
+        db.create_function(
+            "js_object",
+            Function::new_no_args("return {}"),
+            JsValue::UNDEFINED,
+        )
+        .unwrap();
+
+        let sum: f64 = conn
+            .query_row("SELECT js_add(1, 2.5)", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(3.5, sum);
+        let upper: Option<String> = conn
+            .query_row("SELECT js_upper('abc')", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(Some("ABC".to_owned()), upper);
+        let upper: Option<String> = conn
+            .query_row("SELECT js_upper(NULL)", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(None, upper);
+        let count: i64 = conn
+            .query_row("SELECT js_count(1, 'a', x'00', NULL)", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(4, count);
+        // The number of arguments is the length of the JS function.
+        assert!(conn.prepare("SELECT js_add(1)").is_err());
+
+        let err = conn
+            .query_row("SELECT js_fail()", NO_PARAMS, |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert!(err.to_string().contains("no luck"), "{}", err);
+        let err = conn
+            .query_row("SELECT js_object()", NO_PARAMS, |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert!(err.to_string().contains("js_object() returned"), "{}", err);
     }
 
-    #[cfg(feature = "i128_blob")]
     #[wasm_bindgen_test]
-    fn test_i128() {
-        use crate::{Connection, NO_PARAMS};
-        use std::i128;
-        let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo (i128 BLOB, desc TEXT)")
-            .unwrap();
-        db.execute(
-            "
-            INSERT INTO foo(i128, desc) VALUES
-                (?, 'zero'),
-                (?, 'neg one'), (?, 'neg two'),
-                (?, 'pos one'), (?, 'pos two'),
-                (?, 'min'), (?, 'max')",
-            &[0i128, -1i128, -2i128, 1i128, 2i128, i128::MIN, i128::MAX],
+    fn test_js_aggregates() {
+        use crate::database::Database;
+        use js_sys::{Function, Object, Reflect};
+        use wasm_bindgen::JsValue;
+
+        let db = Database::new().unwrap();
+        let conn = db.connection();
+        conn.execute_batch(
+            "CREATE TABLE t(x INTEGER);
+             INSERT INTO t VALUES (1), (2), (3), (4);",
         )
         .unwrap();
+        let define = |name: &str, fields: &[(&str, JsValue)]| {
+            let definition = Object::new();
+            for &(key, ref value) in fields {
+                Reflect::set(&definition, &key.into(), value).unwrap();
+            }
+            db.create_aggregate(name, definition.into())
+        };
 
-        let mut stmt = db
-            .prepare("SELECT i128, desc FROM foo ORDER BY i128 ASC")
-            .unwrap();
+        define(
+            "js_product",
+            &[
+                ("init", 1.into()),
+                ("step", Function::new_with_args("acc, x", "return acc * x").into()),
+            ],
+        )
+        .unwrap();
+        // The state is mutated in place by `step`, and turned into the
+        // result by `final`.
+        define(
+            "js_join",
+            &[
+                ("init", Function::new_no_args("return []").into()),
+                ("step", Function::new_with_args("acc, x", "acc.push(x)").into()),
+                ("final", Function::new_with_args("acc", "return acc.join('-')").into()),
+            ],
+        )
+        .unwrap();
+        define(
+            "js_sum",
+            &[
+                ("init", 0.into()),
+                ("step", Function::new_with_args("acc, x", "return acc + x").into()),
+                ("inverse", Function::new_with_args("acc, x", "return acc - x").into()),
+            ],
+        )
+        .unwrap();
+        define(
+            "js_broken",
+            &[(
+                "step",
+                Function::new_with_args("acc, x", "throw new Error('bad step ' + x)").into(),
+            )],
+        )
+        .unwrap();
+        assert!(define("js_nostep", &[("init", 0.into())]).is_err());
 
-        let res = stmt
-            .query_map(NO_PARAMS, |row| {
-                Ok((row.get::<_, i128>(0)?, row.get::<_, String>(1)?))
+        let product: i64 = conn
+            .query_row("SELECT js_product(x) FROM t", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(24, product);
+        // Without rows, the result is computed from the initial state.
+        let product: i64 = conn
+            .query_row("SELECT js_product(x) FROM t WHERE x > 10", NO_PARAMS, |r| {
+                r.get(0)
             })
+            .unwrap();
+        assert_eq!(1, product);
+        let joined: String = conn
+            .query_row("SELECT js_join(x) FROM t", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("1-2-3-4", joined);
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT js_sum(x) OVER (ORDER BY x ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) FROM t",
+            )
+            .unwrap();
+        let sums: Vec<i64> = stmt
+            .query_map(NO_PARAMS, |r| r.get(0))
             .unwrap()
-            .collect::<Result<Vec<_>, _>>()
+            .collect::<Result<_>>()
             .unwrap();
+        assert_eq!(vec![1, 3, 5, 7], sums);
 
+        let err = conn
+            .query_row("SELECT js_broken(x) FROM t", NO_PARAMS, |r| {
+                r.get::<_, i64>(0)
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("bad step 1"), "{}", err);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_builtin_functions() {
+        use rusqlite::functions::builtin;
+
+        let db = Connection::open_in_memory().unwrap();
+        builtin::load_functions(&db).unwrap();
+        let text = |sql: &str| -> Option<String> {
+            db.query_row(sql, NO_PARAMS, |r| r.get(0)).unwrap()
+        };
+        let real = |sql: &str| -> Option<f64> {
+            db.query_row(sql, NO_PARAMS, |r| r.get(0)).unwrap()
+        };
+
+        // Regular expressions
+        db.execute_batch(
+            "CREATE TABLE orders(ref TEXT);
+             INSERT INTO orders VALUES ('FR-12'), ('de-7'), ('US-345'), (NULL);",
+        )
+        .unwrap();
+        let count: i64 = db
+            .query_row(
+                "SELECT count(*) FROM orders WHERE ref REGEXP '^[A-Z]{2}-[0-9]+$'",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, count);
         assert_eq!(
-            res,
-            &[
-                (i128::MIN, "min".to_owned()),
-                (-2, "neg two".to_owned()),
-                (-1, "neg one".to_owned()),
-                (0, "zero".to_owned()),
-                (1, "pos one".to_owned()),
-                (2, "pos two".to_owned()),
-                (i128::MAX, "max".to_owned()),
-            ]
+            Some("X-X-X".to_owned()),
+            text("SELECT regexp_replace('a-b-c', '[a-z]', 'X')")
+        );
+        assert_eq!(
+            Some("2020/01/31".to_owned()),
+            text("SELECT regexp_replace('31.01.2020', '(\\d+)\\.(\\d+)\\.(\\d+)', '$3/$2/$1')")
+        );
+        assert_eq!(
+            Some("345".to_owned()),
+            text("SELECT regexp_capture('US-345', '[0-9]+')")
+        );
+        assert_eq!(
+            Some("US".to_owned()),
+            text("SELECT regexp_capture('US-345', '(?P<country>[A-Z]+)-', 'country')")
+        );
+        assert_eq!(
+            Some("345".to_owned()),
+            text("SELECT regexp_capture('US-345', '([A-Z]+)-([0-9]+)', 2)")
+        );
+        assert_eq!(None, text("SELECT regexp_capture('US-345', 'x')"));
+        assert!(db
+            .query_row("SELECT 123 REGEXP '^1'", NO_PARAMS, |r| r.get::<_, bool>(0))
+            .unwrap());
+        assert!(db
+            .query_row("SELECT regexp('(', 'a')", NO_PARAMS, |r| r.get::<_, bool>(0))
+            .is_err());
+
+        // Math
+        assert_eq!(Some(3.0), real("SELECT sqrt(9)"));
+        assert_eq!(None, real("SELECT sqrt(-1)"));
+        assert_eq!(Some(1024.0), real("SELECT pow(2, 10)"));
+        assert_eq!(Some(2.0), real("SELECT log(100)"));
+        assert_eq!(Some(3.0), real("SELECT log(2, 8)"));
+        assert_eq!(None, real("SELECT ln(0)"));
+        assert_eq!(Some(1.0), real("SELECT mod(7, 3)"));
+        assert_eq!(Some(180.0), real("SELECT degrees(pi())"));
+        assert!((real("SELECT sin(pi() / 2)").unwrap() - 1.0).abs() < 1e-12);
+        let angle = real("SELECT atan2(1, 1)").unwrap();
+        assert!((angle - std::f64::consts::FRAC_PI_4).abs() < 1e-12);
+        assert_eq!(None, real("SELECT cos(NULL)"));
+        assert_eq!(Some(2.0), real("SELECT sqrt('4')"));
+        let ceil: Value = db
+            .query_row("SELECT ceil(2.1)", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(Value::Real(3.0), ceil);
+        let floor: Value = db
+            .query_row("SELECT floor(-7)", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(Value::Integer(-7), floor);
+
+        // UUIDs
+        let uuid = text("SELECT uuid()").unwrap();
+        assert_eq!(36, uuid.len());
+        assert_eq!(Some('4'), uuid.chars().nth(14));
+        assert_ne!(Some(uuid.clone()), text("SELECT uuid()"));
+        let blob: Vec<u8> = db
+            .query_row("SELECT uuid_blob(?)", &[&uuid], |r| r.get(0))
+            .unwrap();
+        assert_eq!(16, blob.len());
+        let round_trip: String = db
+            .query_row("SELECT uuid_str(?)", &[&blob], |r| r.get(0))
+            .unwrap();
+        assert_eq!(uuid, round_trip);
+        assert_eq!(
+            Some("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8".to_owned()),
+            text("SELECT uuid_str('{A1A2A3A4B1B2C1C2D1D2D3D4D5D6D7D8}')")
+        );
+        assert_eq!(None, text("SELECT uuid_str('not a uuid')"));
+
+        // Strings
+        assert_eq!(Some("00042".to_owned()), text("SELECT lpad('42', 5, '0')"));
+        assert_eq!(Some("00042".to_owned()), text("SELECT lpad(42, 5, '0')"));
+        assert_eq!(Some("45".to_owned()), text("SELECT split_part(123.45, '.', 2)"));
+        assert_eq!(Some("2.0".to_owned()), text("SELECT unaccent(2.0)"));
+        assert_eq!(Some("ab   ".to_owned()), text("SELECT rpad('ab', 5)"));
+        assert_eq!(Some("héllo".to_owned()), text("SELECT rpad('héllo wörld', 5)"));
+        assert_eq!(Some("ab-=-".to_owned()), text("SELECT rpad('ab', 5, '-=')"));
+        assert_eq!(None, text("SELECT lpad(NULL, 5)"));
+        assert_eq!(Some("b".to_owned()), text("SELECT split_part('a,b,c', ',', 2)"));
+        assert_eq!(Some("c".to_owned()), text("SELECT split_part('a,b,c', ',', -1)"));
+        assert_eq!(Some("".to_owned()), text("SELECT split_part('a,b,c', ',', 4)"));
+        assert_eq!(
+            Some("Creme brulee a Lodz, Strasse".to_owned()),
+            text("SELECT unaccent('Crème brûlée à Łódź, Straße')")
         );
     }
 
-    #[cfg(feature = "uuid")]
     #[wasm_bindgen_test]
-    fn test_uuid() {
-        use crate::{params, Connection};
-        use uuid::Uuid;
+    fn test_stats_functions() {
+        use rusqlite::functions::stats;
 
         let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE foo (id BLOB CHECK(length(id) = 16), label TEXT);")
-            .unwrap();
+        stats::load_functions(&db).unwrap();
+        db.execute_batch(
+            "CREATE TABLE t(x, y, label);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10)
+             INSERT INTO t SELECT i, 2 * i + 1, substr('ab', 2 - i % 2, 1) FROM n;
+             INSERT INTO t VALUES (NULL, NULL, NULL), ('x', 'y', 'b');",
+        )
+        .unwrap();
+        let real = |sql: &str| -> Option<f64> {
+            db.query_row(sql, NO_PARAMS, |r| r.get(0)).unwrap()
+        };
 
-        let id = Uuid::new_v4();
+        // Percentiles
+        assert_eq!(Some(5.5), real("SELECT median(x) FROM t"));
+        assert_eq!(Some(1.0), real("SELECT percentile(x, 0) FROM t"));
+        assert_eq!(Some(10.0), real("SELECT percentile(x, 100) FROM t"));
+        assert!((real("SELECT percentile(x, 90) FROM t").unwrap() - 9.1).abs() < 1e-9);
+        assert_eq!(Some(3.25), real("SELECT percentile_cont(x, 0.25) FROM t"));
+        assert_eq!(Some(3.0), real("SELECT percentile_disc(x, 0.25) FROM t"));
+        assert_eq!(None, real("SELECT median(x) FROM t WHERE x > 100"));
+        let fails = |sql: &str| {
+            db.query_row(sql, NO_PARAMS, |r| r.get::<_, Option<f64>>(0))
+                .is_err()
+        };
+        assert!(fails("SELECT percentile(x, 101) FROM t"));
+        assert!(fails("SELECT percentile_cont(x, x / 10.0) FROM t"));
+
+        // Variance and correlation
+        let var_samp = real("SELECT var_samp(x) FROM t").unwrap();
+        assert!((var_samp - 55.0 / 6.0).abs() < 1e-9);
+        assert_eq!(Some(var_samp), real("SELECT variance(x) FROM t"));
+        assert!((real("SELECT var_pop(x) FROM t").unwrap() - 8.25).abs() < 1e-9);
+        assert!((real("SELECT stddev_pop(x) FROM t").unwrap() - 8.25f64.sqrt()).abs() < 1e-9);
+        assert!((real("SELECT stddev(x) FROM t").unwrap() - var_samp.sqrt()).abs() < 1e-9);
+        assert_eq!(None, real("SELECT var_samp(x) FROM t WHERE x = 1"));
+        assert_eq!(Some(0.0), real("SELECT var_pop(x) FROM t WHERE x = 1"));
+        assert!((real("SELECT corr(y, x) FROM t").unwrap() - 1.0).abs() < 1e-9);
+        assert!((real("SELECT corr(-y, x) FROM t").unwrap() + 1.0).abs() < 1e-9);
+        assert_eq!(None, real("SELECT corr(y, 1) FROM t"));
+
+        // Mode, the smallest value on ties
+        let mode: Option<String> = db
+            .query_row("SELECT mode(label) FROM t", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(Some("b".to_owned()), mode);
+        let mode: Option<i64> = db
+            .query_row(
+                "SELECT mode(v) FROM (SELECT 3 AS v UNION ALL SELECT 2 UNION ALL SELECT 3.0
+                 UNION ALL SELECT 2 UNION ALL SELECT 'a' UNION ALL SELECT NULL)",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(Some(2), mode);
+        // Integers that a double cannot tell apart
+        let mode: Option<i64> = db
+            .query_row(
+                "SELECT mode(v) FROM (SELECT 9007199254740992 AS v
+                 UNION ALL SELECT 9007199254740993 UNION ALL SELECT 9007199254740993)",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(Some(9_007_199_254_740_993), mode);
 
-        db.execute(
-            "INSERT INTO foo (id, label) VALUES (?, ?)",
-            params![id, "target"],
+        // Approximate quantiles
+        db.execute_batch(
+            "CREATE TABLE big(x);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10000)
+             INSERT INTO big SELECT i FROM n;",
         )
         .unwrap();
-
+        let median = real("SELECT approx_median(x) FROM big").unwrap();
+        assert!((median - 5000.5).abs() < 50.0, "{}", median);
+        let p99 = real("SELECT approx_quantile(x, 0.99) FROM big").unwrap();
+        assert!((p99 - 9900.0).abs() < 25.0, "{}", p99);
+        assert!(real("SELECT approx_quantile(x, 0) FROM big").unwrap() < 20.0);
+        assert!(real("SELECT approx_quantile(x, 1) FROM big").unwrap() > 9980.0);
+        assert_eq!(Some(4.0), real("SELECT approx_median(x) FROM t WHERE x <= 7"));
+
+        // Window functions, over a sliding frame
         let mut stmt = db
-            .prepare("SELECT id, label FROM foo WHERE id = ?")
+            .prepare(
+                "SELECT median(x) OVER w, var_pop(x) OVER w, mode(label) OVER w,
+                        approx_median(x) OVER w
+                 FROM t WHERE x BETWEEN 1 AND 10
+                 WINDOW w AS (ORDER BY x ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING)
+                 ORDER BY x",
+            )
             .unwrap();
-
-        let mut rows = stmt.query(params![id]).unwrap();
-        let row = rows.next().unwrap().unwrap();
-
-        let found_id: Uuid = row.get_unwrap(0);
-        let found_label: String = row.get_unwrap(1);
-
-        assert_eq!(found_id, id);
-        assert_eq!(found_label, "target");
+        let rows: Vec<(f64, f64, String, f64)> = stmt
+            .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(10, rows.len());
+        assert_eq!((1.5, 0.25, "a".to_owned(), 1.5), rows[0]);
+        for (i, row) in rows.iter().enumerate().take(9).skip(1) {
+            let x = (i + 1) as f64;
+            assert_eq!(x, row.0);
+            assert!((row.1 - 2.0 / 3.0).abs() < 1e-9);
+            assert_eq!(x, row.3);
+        }
+        assert_eq!((9.5, 0.25, "a".to_owned(), 9.5), rows[9]);
     }
 
-    extern crate url;
+    #[wasm_bindgen_test]
+    fn test_builtin_collations() {
+        use rusqlite::collation::builtin;
 
-    fn checked_memory_handle_url() -> Connection {
         let db = Connection::open_in_memory().unwrap();
-        db.execute_batch("CREATE TABLE urls (i INTEGER, v TEXT)")
+        builtin::load_collations(&db).unwrap();
+        let names = |sql: &str| -> Vec<String> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let rows = stmt.query_map(NO_PARAMS, |r| r.get(0)).unwrap();
+            rows.collect::<Result<_>>().unwrap()
+        };
+        let is_true =
+            |sql: &str| -> bool { db.query_row(sql, NO_PARAMS, |r| r.get(0)).unwrap() };
+
+        assert!(is_true("SELECT 'Maße' = 'MASSE' COLLATE UNICODE_NOCASE"));
+        assert!(is_true("SELECT 'été' = 'ÉTÉ' COLLATE UNICODE_NOCASE"));
+        assert!(!is_true("SELECT 'été' = 'ÉTÉ' COLLATE NOCASE"));
+        assert!(is_true("SELECT 'Éa' < 'éB' COLLATE UNICODE_NOCASE"));
+        db.execute_batch("CREATE TABLE users(name TEXT UNIQUE COLLATE UNICODE_NOCASE)")
             .unwrap();
-        db
-    }
+        db.execute("INSERT INTO users VALUES ('Ærø')", NO_PARAMS).unwrap();
+        assert!(db.execute("INSERT INTO users VALUES ('ÆRØ')", NO_PARAMS).is_err());
 
-    fn get_url(db: &Connection, id: i64) -> Result<url::Url> {
-        db.query_row("SELECT v FROM urls WHERE i = ?", params![id], |r| r.get(0))
+        db.execute_batch(
+            "CREATE TABLE files(name TEXT);
+             INSERT INTO files VALUES ('file10.txt'), ('file2.txt'), ('File1.txt'),
+                                      ('file02.txt'), ('file1.txt'), ('file');",
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["File1.txt", "file", "file1.txt", "file02.txt", "file2.txt", "file10.txt"],
+            names("SELECT name FROM files ORDER BY name COLLATE NATURAL")
+        );
+        assert_eq!(
+            vec!["File1.txt", "file", "file02.txt", "file1.txt", "file10.txt", "file2.txt"],
+            names("SELECT name FROM files ORDER BY name")
+        );
+        assert!(is_true("SELECT 'v1.10.0' > 'v1.9.2' COLLATE NATURAL"));
+        assert!(is_true(
+            "SELECT 'a99999999999999999999' < 'a100000000000000000000' COLLATE NATURAL"
+        ));
     }
 
     #[wasm_bindgen_test]
-    fn test_sql_url() {
-        let db = &checked_memory_handle_url();
-
-        let url0 = url::Url::parse("http://www.example1.com").unwrap();
-        let url1 = url::Url::parse("http://www.example1.com/👌").unwrap();
-        let url2 = "http://www.example2.com/👌";
-
-        db.execute(
-            "INSERT INTO urls (i, v) VALUES (0, ?), (1, ?), (2, ?), (3, ?)",
-            // also insert a non-hex encoded url (which might be present if it was
-            // inserted separately)
-            params![url0, url1, url2, "illegal"],
+    fn test_locale_collations() {
+        use crate::database::Database;
+
+        let db = Database::new().unwrap();
+        let conn = db.connection();
+        conn.execute_batch(
+            "CREATE TABLE cities(name TEXT);
+             INSERT INTO cities VALUES ('Zürich'), ('Ähnlich'), ('apfel'), ('Bern');",
         )
         .unwrap();
+        let names = |collation: &str| -> Result<Vec<String>> {
+            let sql = format!("SELECT name FROM cities ORDER BY name COLLATE {}", collation);
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(NO_PARAMS, |r| r.get(0))?;
+            rows.collect()
+        };
+        assert_eq!(vec!["Bern", "Zürich", "apfel", "Ähnlich"], names("BINARY").unwrap());
+        assert_eq!(vec!["Ähnlich", "apfel", "Bern", "Zürich"], names("locale_de").unwrap());
+        assert_eq!(vec!["Ähnlich", "apfel", "Bern", "Zürich"], names("LOCALE_de_CH").unwrap());
+        assert_eq!(vec!["apfel", "Bern", "Zürich", "Ähnlich"], names("locale_sv").unwrap());
+        // An invalid tag
+        assert!(names("locale_x").is_err());
+        assert!(names("locale_").is_err());
+        // The Rust collations are loaded too.
+        assert!(names("UNICODE_NOCASE").is_ok());
+    }
 
-        assert_eq!(get_url(db, 0).unwrap(), url0);
+    #[wasm_bindgen_test]
+    fn test_schema_introspection() {
+        use rusqlite::schema::{Affinity, IndexOrigin};
 
-        assert_eq!(get_url(db, 1).unwrap(), url1);
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE authors(
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 name TEXT NOT NULL COLLATE NOCASE UNIQUE,
+                 born DATE,
+                 score DOUBLE DEFAULT 0.5,
+                 data
+             );
+             CREATE TABLE tags(a, b, PRIMARY KEY(a, b));
+             CREATE TABLE books(
+                 author_id INTEGER REFERENCES authors ON DELETE CASCADE,
+                 isbn VARCHAR(13),
+                 title TEXT,
+                 pages INT,
+                 sheets GENERATED ALWAYS AS (pages / 2),
+                 tag_a, tag_b,
+                 PRIMARY KEY(isbn, author_id),
+                 FOREIGN KEY(tag_a, tag_b) REFERENCES tags(a, b)
+             ) WITHOUT ROWID;
+             CREATE TABLE legacy(rowid TEXT PRIMARY KEY, value) WITHOUT ROWID;
+             CREATE INDEX books_title ON books(title DESC, lower(title)) WHERE pages > 0;
+             CREATE VIEW long_books AS SELECT title, pages FROM books WHERE pages > 500;
+             CREATE TRIGGER books_ai AFTER INSERT ON books BEGIN SELECT 1; END;
+             CREATE VIRTUAL TABLE docs USING fts5(body);",
+        )
+        .unwrap();
 
-        // Should successfully read it, even though it wasn't inserted as an
-        // escaped url.
-        let out_url2: url::Url = get_url(db, 2).unwrap();
-        assert_eq!(out_url2, url::Url::parse(url2).unwrap());
+        let tables: Vec<_> = db
+            .tables()
+            .unwrap()
+            .into_iter()
+            .filter(|t| !t.name.starts_with("docs_"))
+            .map(|t| (t.name, t.virtual_table, t.without_rowid))
+            .collect();
+        assert_eq!(
+            vec![
+                ("authors".to_owned(), false, false),
+                ("books".to_owned(), false, true),
+                ("docs".to_owned(), true, false),
+                // A column named rowid
+                ("legacy".to_owned(), false, true),
+                ("tags".to_owned(), false, false),
+            ],
+            tables
+        );
 
-        // Make sure the conversion error comes through correctly.
-        let err = get_url(db, 3).unwrap_err();
-        match err {
-            Error::FromSqlConversionFailure(_, _, e) => {
-                assert_eq!(
-                    *e.downcast::<ParseError>().unwrap(),
-                    ParseError::RelativeUrlWithoutBase,
-                );
-            }
-            e => {
-                panic!("Expected conversion failure, got {}", e);
-            }
-        }
-    }
+        let columns = db.columns("authors").unwrap();
+        assert_eq!(5, columns.len());
+        let id = &columns[0];
+        assert_eq!(
+            ("id", Some("INTEGER"), Affinity::Integer, Some(1), true),
+            (
+                id.name.as_str(),
+                id.decl_type.as_ref().map(String::as_str),
+                id.affinity,
+                id.primary_key,
+                id.auto_increment
+            )
+        );
+        assert!(columns[1].not_null);
+        assert_eq!("NOCASE", columns[1].collation);
+        assert_eq!(Affinity::Text, columns[1].affinity);
+        assert_eq!(Affinity::Numeric, columns[2].affinity);
+        assert_eq!(Affinity::Real, columns[3].affinity);
+        assert_eq!(Some("0.5".to_owned()), columns[3].default);
+        assert_eq!((None, Affinity::Blob), (columns[4].decl_type.clone(), columns[4].affinity));
+        assert!(columns.iter().skip(1).all(|c| c.primary_key.is_none() && !c.auto_increment));
+
+        let columns = db.columns("books").unwrap();
+        let pk: Vec<_> = columns
+            .iter()
+            .filter_map(|c| c.primary_key.map(|pk| (pk, c.name.as_str())))
+            .collect();
+        assert_eq!(vec![(2, "author_id"), (1, "isbn")], pk);
+        assert_eq!(Affinity::Text, columns[1].affinity);
+        assert_eq!(("sheets", true), (columns[4].name.as_str(), columns[4].generated));
+        assert_eq!(1, columns.iter().filter(|c| c.generated).count());
+
+        let indexes = db.indexes("books").unwrap();
+        assert_eq!(2, indexes.len());
+        let title = indexes.iter().find(|i| i.name == "books_title").unwrap();
+        assert_eq!(
+            (IndexOrigin::CreateIndex, false, true),
+            (title.origin, title.unique, title.partial)
+        );
+        assert_eq!(2, title.columns.len());
+        assert_eq!(Some("title".to_owned()), title.columns[0].name);
+        assert!(title.columns[0].descending);
+        assert_eq!((None, false), (title.columns[1].name.clone(), title.columns[1].descending));
+        let pk = indexes.iter().find(|i| i.origin == IndexOrigin::PrimaryKey).unwrap();
+        assert!(pk.unique);
+        let pk_columns: Vec<_> = pk.columns.iter().map(|c| c.name.clone().unwrap()).collect();
+        assert_eq!(vec!["isbn", "author_id"], pk_columns);
+        let indexes = db.indexes("authors").unwrap();
+        assert_eq!(1, indexes.len());
+        assert_eq!(IndexOrigin::Unique, indexes[0].origin);
+        assert_eq!("NOCASE", indexes[0].columns[0].collation);
+
+        let keys = db.foreign_keys("books").unwrap();
+        assert_eq!(2, keys.len());
+        assert_eq!("authors", keys[0].table);
+        assert_eq!(vec!["author_id"], keys[0].from);
+        assert_eq!(vec![None], keys[0].to);
+        assert_eq!("NO ACTION", keys[0].on_update);
+        assert_eq!("CASCADE", keys[0].on_delete);
+        assert_eq!("tags", keys[1].table);
+        assert_eq!(vec!["tag_a", "tag_b"], keys[1].from);
+        assert_eq!(vec![Some("a".to_owned()), Some("b".to_owned())], keys[1].to);
+        assert!(db.foreign_keys("authors").unwrap().is_empty());
+
+        let views = db.views().unwrap();
+        assert_eq!(1, views.len());
+        assert_eq!("long_books", views[0].name);
+        assert!(views[0].sql.starts_with("CREATE VIEW"));
+        let columns: Vec<_> = db
+            .columns("long_books")
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.name, c.affinity))
+            .collect();
+        assert_eq!(
+            vec![("title".to_owned(), Affinity::Text), ("pages".to_owned(), Affinity::Integer)],
+            columns
+        );
 
-    use rusqlite::vtab::{array, dequote, parse_boolean};
-    use std::rc::Rc;
+        let triggers = db.triggers().unwrap();
+        assert_eq!(1, triggers.len());
+        assert_eq!(("books_ai", "books"), (triggers[0].name.as_str(), triggers[0].table.as_str()));
 
-    #[wasm_bindgen_test]
-    fn test_dequote() {
-        assert_eq!("", dequote(""));
-        assert_eq!("'", dequote("'"));
-        assert_eq!("\"", dequote("\""));
-        assert_eq!("'\"", dequote("'\""));
-        assert_eq!("", dequote("''"));
-        assert_eq!("", dequote("\"\""));
-        assert_eq!("x", dequote("'x'"));
-        assert_eq!("x", dequote("\"x\""));
-        assert_eq!("x", dequote("x"));
+        assert!(db.columns("missing").is_err());
+        assert!(db.indexes("missing").is_err());
+        assert!(db.foreign_keys("missing").is_err());
+        // A documented quirk: "POINT" contains "INT".
+        assert_eq!(Affinity::Integer, Affinity::from_decl_type(Some("FLOATING POINT")));
+        assert_eq!(Affinity::Real, Affinity::from_decl_type(Some("float")));
     }
 
     #[wasm_bindgen_test]
-    fn test_parse_boolean() {
-        assert_eq!(None, parse_boolean(""));
-        assert_eq!(Some(true), parse_boolean("1"));
-        assert_eq!(Some(true), parse_boolean("yes"));
-        assert_eq!(Some(true), parse_boolean("on"));
-        assert_eq!(Some(true), parse_boolean("true"));
-        assert_eq!(Some(false), parse_boolean("0"));
-        assert_eq!(Some(false), parse_boolean("no"));
-        assert_eq!(Some(false), parse_boolean("off"));
-        assert_eq!(Some(false), parse_boolean("false"));
-    }
+    fn test_migrations() {
+        use rusqlite::migrations::{Migration, MigrationError, Migrations};
+
+        fn notes_migrations() -> Vec<Migration> {
+            vec![
+                Migration::sql("create notes", "CREATE TABLE notes(id INTEGER PRIMARY KEY, body);")
+                    .down_sql("DROP TABLE notes;"),
+                Migration::sql("add title", "ALTER TABLE notes ADD COLUMN title TEXT;")
+                    .down_sql(
+                        "CREATE TABLE old(id INTEGER PRIMARY KEY, body);
+                         INSERT INTO old SELECT id, body FROM notes;
+                         DROP TABLE notes;
+                         ALTER TABLE old RENAME TO notes;",
+                    ),
+                Migration::rust("title notes", |tx| {
+                    tx.execute("UPDATE notes SET title = upper(body)", NO_PARAMS)?;
+                    Ok(())
+                })
+                .down_rust(|tx| {
+                    tx.execute("UPDATE notes SET title = NULL", NO_PARAMS)?;
+                    Ok(())
+                }),
+            ]
+        }
 
-    #[wasm_bindgen_test]
-    fn test_array_module() {
         let db = Connection::open_in_memory().unwrap();
-        array::load_module(&db).unwrap();
-
-        let v = vec![1i64, 2, 3, 4];
-        let values = v.into_iter().map(Value::from).collect();
-        let ptr = Rc::new(values);
-        {
-            let mut stmt = db.prepare("SELECT value from rarray(?);").unwrap();
+        let user_version = || -> i64 {
+            db.query_row("PRAGMA user_version", NO_PARAMS, |r| r.get(0)).unwrap()
+        };
+        let migrations = Migrations::new(notes_migrations());
+        assert_eq!(3, migrations.latest());
+        assert_eq!(0, migrations.current_version(&db).unwrap());
+        migrations.to_version(&db, 1).unwrap();
+        assert_eq!(1, user_version());
+        db.execute("INSERT INTO notes(body) VALUES ('hello')", NO_PARAMS).unwrap();
+        migrations.to_latest(&db).unwrap();
+        assert_eq!(3, user_version());
+        let title: String = db
+            .query_row("SELECT title FROM notes", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("HELLO", title);
+        // Already at the latest version
+        migrations.to_latest(&db).unwrap();
+        assert_eq!(3, migrations.validate(&db).unwrap());
+
+        // Down migrations
+        migrations.to_version(&db, 1).unwrap();
+        assert_eq!(1, user_version());
+        assert_eq!(2, db.columns("notes").unwrap().len());
+        migrations.to_version(&db, 0).unwrap();
+        assert!(db.tables().unwrap().is_empty());
+        assert_eq!(
+            Err(Error::MigrationError(MigrationError::InvalidVersion {
+                version: 4,
+                latest: 3
+            })),
+            migrations.to_version(&db, 4)
+        );
 
-            let rows = stmt.query_map(&[&ptr], |row| row.get::<_, i64>(0)).unwrap();
-            assert_eq!(2, Rc::strong_count(&ptr));
-            let mut count = 0;
-            for (i, value) in rows.enumerate() {
-                assert_eq!(i as i64, value.unwrap() - 1);
-                count += 1;
-            }
-            assert_eq!(4, count);
-        }
-        assert_eq!(1, Rc::strong_count(&ptr));
-    }
-    /*
-        use rusqlite::vtab::csvtab;
+        // A database of a newer release
+        db.pragma_update(None, "user_version", &5).unwrap();
+        assert_eq!(
+            Err(Error::MigrationError(MigrationError::DatabaseTooNew {
+                version: 5,
+                latest: 3
+            })),
+            migrations.to_latest(&db)
+        );
+        db.pragma_update(None, "user_version", &0).unwrap();
 
-        #[wasm_bindgen_test]
-        #[ignore]
-        fn test_csv_module() {
-            let db = Connection::open_in_memory().unwrap();
-            csvtab::load_module(&db).unwrap();
-            db.execute_batch("CREATE VIRTUAL TABLE vtab USING csv(filename='test.csv', header=yes)")
-                .unwrap();
+        // A failed migration leaves the database at the previous version.
+        let mut failing = notes_migrations();
+        failing.push(Migration::sql(
+            "broken",
+            "CREATE TABLE tags(name); INSERT INTO missing VALUES (1);",
+        ));
+        assert!(Migrations::new(failing).to_latest(&db).is_err());
+        assert_eq!(3, user_version());
+        assert!(db.columns("tags").is_err());
 
-            {
-                let mut s = db.prepare("SELECT rowid, * FROM vtab").unwrap();
-                {
-                    let headers = s.column_names();
-                    assert_eq!(vec!["rowid", "colA", "colB", "colC"], headers);
-                }
+        // Irreversible migrations are detected before reverting any.
+        let mut irreversible = notes_migrations();
+        irreversible[1] = Migration::sql("add title", "ALTER TABLE notes ADD COLUMN title TEXT;");
+        assert_eq!(
+            Err(Error::MigrationError(MigrationError::Irreversible {
+                version: 2,
+                name: "add title".to_owned()
+            })),
+            Migrations::new(irreversible).to_version(&db, 0)
+        );
+        assert_eq!(3, user_version());
+    }
 
-                let ids: Result<Vec<i32>> = s
-                    .query(NO_PARAMS)
-                    .unwrap()
-                    .map(|row| row.get::<_, i32>(0))
-                    .collect();
-                let sum = ids.unwrap().iter().sum::<i32>();
-                assert_eq!(sum, 15);
-            }
-            db.execute_batch("DROP TABLE vtab").unwrap();
-        }
+    #[wasm_bindgen_test]
+    fn test_migrations_table() {
+        use rusqlite::migrations::{Migration, MigrationError, Migrations};
 
-        #[wasm_bindgen_test]
-        #[ignore]
-        fn test_csv_cursor() {
-            let db = Connection::open_in_memory().unwrap();
-            csvtab::load_module(&db).unwrap();
-            db.execute_batch("CREATE VIRTUAL TABLE vtab USING csv(filename='test.csv', header=yes)")
-                .unwrap();
+        let db = Connection::open_in_memory().unwrap();
+        let migrations = Migrations::new(vec![
+            Migration::sql("create users", "CREATE TABLE users(name TEXT);"),
+            Migration::rust("seed users", |tx| {
+                tx.execute("INSERT INTO users VALUES ('admin')", NO_PARAMS)?;
+                Ok(())
+            }),
+        ])
+        .with_table("schema_migrations");
+        assert_eq!(0, migrations.validate(&db).unwrap());
+        migrations.to_latest(&db).unwrap();
+        assert_eq!(2, migrations.current_version(&db).unwrap());
+        let user_version: i64 = db
+            .query_row("PRAGMA user_version", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, user_version);
+        let mut stmt = db
+            .prepare("SELECT version, name, checksum IS NOT NULL FROM schema_migrations")
+            .unwrap();
+        let applied: Vec<(i64, String, bool)> = stmt
+            .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (1, "create users".to_owned(), true),
+                (2, "seed users".to_owned(), false)
+            ],
+            applied
+        );
 
-            {
-                let mut s = db
-                    .prepare(
-                        "SELECT v1.rowid, v1.* FROM vtab v1 NATURAL JOIN vtab v2 WHERE \
-                         v1.rowid < v2.rowid",
-                    )
-                    .unwrap();
+        // The SQL of an applied migration changed.
+        let changed = Migrations::new(vec![
+            Migration::sql("create users", "CREATE TABLE users(name TEXT, email TEXT);"),
+            Migration::rust("seed users", |_| Ok(())),
+        ])
+        .with_table("schema_migrations");
+        assert_eq!(
+            Err(Error::MigrationError(MigrationError::ChecksumMismatch {
+                version: 1,
+                name: "create users".to_owned()
+            })),
+            changed.to_latest(&db)
+        );
+        let newer = Migrations::new(vec![Migration::sql(
+            "create users",
+            "CREATE TABLE users(name TEXT);",
+        )])
+        .with_table("schema_migrations");
+        assert!(newer.validate(&db).is_err());
+    }
 
-                let mut rows = s.query(NO_PARAMS).unwrap();
-                let row = rows.next().unwrap().unwrap();
-                assert_eq!(row.get_unwrap::<_, i32>(0), 2);
+    #[wasm_bindgen_test]
+    fn test_database_migrate() {
+        use crate::database::Database;
+        use js_sys::{Array, Object, Reflect};
+        use wasm_bindgen::JsValue;
+
+        let migration = |name: &str, up: &str, down: Option<&str>| -> JsValue {
+            let object = Object::new();
+            Reflect::set(&object, &"name".into(), &name.into()).unwrap();
+            Reflect::set(&object, &"up".into(), &up.into()).unwrap();
+            if let Some(down) = down {
+                Reflect::set(&object, &"down".into(), &down.into()).unwrap();
             }
-            db.execute_batch("DROP TABLE vtab").unwrap();
-        }
+            object.into()
+        };
+        let migrations = Array::of2(
+            &migration("create t", "CREATE TABLE t(x);", Some("DROP TABLE t;")),
+            &migration("fill t", "INSERT INTO t VALUES (1), (2);", Some("DELETE FROM t;")),
+        );
 
-    */
-    use rusqlite::vtab::series;
+        let db = Database::new().unwrap();
+        assert_eq!(2, db.migrate(migrations.clone(), JsValue::UNDEFINED).unwrap());
+        let count: i64 = db
+            .connection()
+            .query_row("SELECT count(*) FROM t", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(2, count);
+        let options = Object::new();
+        Reflect::set(&options, &"to".into(), &1.into()).unwrap();
+        assert_eq!(1, db.migrate(migrations.clone(), options.into()).unwrap());
+        let count: i64 = db
+            .connection()
+            .query_row("SELECT count(*) FROM t", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, count);
+        // Newer than the application
+        assert!(db.migrate(Array::new(), JsValue::UNDEFINED).is_err());
+        // Not SQL
+        assert!(db
+            .migrate(Array::of1(&Object::new().into()), JsValue::UNDEFINED)
+            .is_err());
+        // Not a version, rather than version 0
+        let versions = [JsValue::from(-1), JsValue::from(f64::NAN), JsValue::from(1.5), "1".into()];
+        for to in &versions {
+            Reflect::set(&options, &"to".into(), to).unwrap();
+            assert!(db.migrate(migrations.clone(), options.clone().into()).is_err());
+        }
+        // Still at version 1
+        assert!(db.connection().prepare("SELECT * FROM t").is_ok());
+    }
 
     #[wasm_bindgen_test]
-    fn test_series_module() {
-        let version = unsafe { ffi::sqlite3_libversion_number() };
-        if version < 3_008_012 {
-            return;
-        }
+    fn test_typed_pragmas() {
+        use rusqlite::pragma::get::{
+            self, ForeignKeyCheckRow, ForeignKeyListRow, IndexInfoRow, IndexOrigin,
+        };
+        use rusqlite::pragma::{
+            AutoVacuum, CacheSize, JournalMode, LockingMode, Pragma, Synchronous, TempStore,
+        };
 
         let db = Connection::open_in_memory().unwrap();
-        series::load_module(&db).unwrap();
+        assert_eq!(JournalMode::Memory, db.pragma_get(None, get::JournalMode).unwrap());
+        db.pragma_set(None, Pragma::JournalMode(JournalMode::Off))
+            .unwrap();
+        assert_eq!(JournalMode::Off, db.pragma_get(None, get::JournalMode).unwrap());
+        // An in-memory database has no write-ahead log.
+        assert!(db
+            .pragma_set(None, Pragma::JournalMode(JournalMode::Wal))
+            .is_err());
 
-        let mut s = db.prepare("SELECT * FROM generate_series(0,20,5)").unwrap();
+        db.pragma_set(None, Pragma::AutoVacuum(AutoVacuum::Full))
+            .unwrap();
+        assert_eq!(AutoVacuum::Full, db.pragma_get(None, get::AutoVacuum).unwrap());
+        db.pragma_set(None, Pragma::Synchronous(Synchronous::Normal))
+            .unwrap();
+        assert_eq!(Synchronous::Normal, db.pragma_get(None, get::Synchronous).unwrap());
+        db.pragma_set(None, Pragma::TempStore(TempStore::Memory))
+            .unwrap();
+        assert_eq!(TempStore::Memory, db.pragma_get(None, get::TempStore).unwrap());
+        db.pragma_set(None, Pragma::CacheSize(CacheSize::KiB(4096)))
+            .unwrap();
+        assert_eq!(CacheSize::KiB(4096), db.pragma_get(None, get::CacheSize).unwrap());
+        db.pragma_set(None, Pragma::CacheSize(CacheSize::Pages(500)))
+            .unwrap();
+        assert_eq!(CacheSize::Pages(500), db.pragma_get(None, get::CacheSize).unwrap());
+        db.pragma_set(None, Pragma::LockingMode(LockingMode::Exclusive))
+            .unwrap();
+        assert_eq!(LockingMode::Exclusive, db.pragma_get(None, get::LockingMode).unwrap());
+        db.pragma_set(None, Pragma::UserVersion(7)).unwrap();
+        assert_eq!(7, db.pragma_get(None, get::UserVersion).unwrap());
+        db.pragma_set(Some(DatabaseName::Main), Pragma::ApplicationId(0x1234))
+            .unwrap();
+        assert_eq!(
+            0x1234,
+            db.pragma_get(Some(DatabaseName::Main), get::ApplicationId)
+                .unwrap()
+        );
+        assert_eq!(4096, db.pragma_get(None, get::PageSize).unwrap());
+        assert_eq!("UTF-8", db.pragma_get(None, get::Encoding).unwrap());
+        let options = db.pragma_get(None, get::CompileOptions).unwrap();
+        assert!(options.iter().any(|o| o == "ENABLE_COLUMN_METADATA"));
 
-        let series = s.query_map(NO_PARAMS, |row| row.get::<_, i32>(0)).unwrap();
+        db.pragma_set(None, Pragma::ForeignKeys(true)).unwrap();
+        assert!(db.pragma_get(None, get::ForeignKeys).unwrap());
+        db.execute_batch(
+            "CREATE TABLE parent(id INTEGER PRIMARY KEY, name TEXT NOT NULL DEFAULT 'x');
+             CREATE TABLE child(id INTEGER PRIMARY KEY, parent_id REFERENCES parent(id));
+             CREATE INDEX child_parent ON child(parent_id);",
+        )
+        .unwrap();
+        assert!(db.pragma_get(None, get::PageCount).unwrap() > 1);
 
-        let mut expected = 0;
-        for value in series {
-            assert_eq!(expected, value.unwrap());
-            expected += 5;
-        }
+        let columns = db.pragma_get(None, get::TableInfo("parent")).unwrap();
+        assert_eq!(2, columns.len());
+        assert_eq!(
+            (Some("INTEGER".to_owned()), Some(1)),
+            (columns[0].decl_type.clone(), columns[0].primary_key)
+        );
+        assert_eq!(("name", true), (columns[1].name.as_str(), columns[1].not_null));
+        assert_eq!(Some("'x'".to_owned()), columns[1].default);
+        assert_eq!(None, db.pragma_get(None, get::TableInfo("child")).unwrap()[1].decl_type);
+
+        let indexes = db.pragma_get(None, get::IndexList("child")).unwrap();
+        assert_eq!(1, indexes.len());
+        assert_eq!(("child_parent", IndexOrigin::CreateIndex, false), (
+            indexes[0].name.as_str(),
+            indexes[0].origin,
+            indexes[0].unique
+        ));
+        assert_eq!(
+            vec![IndexInfoRow {
+                seqno: 0,
+                cid: 1,
+                name: Some("parent_id".to_owned()),
+                descending: false,
+                collation: None,
+                key: true,
+            }],
+            db.pragma_get(None, get::IndexInfo("child_parent")).unwrap()
+        );
+        let columns = db.pragma_get(None, get::IndexXInfo("child_parent")).unwrap();
+        assert_eq!(
+            (Some("BINARY".to_owned()), true),
+            (columns[0].collation.clone(), columns[0].key)
+        );
+        // The rowid follows the key.
+        assert_eq!((-1, false), (columns[1].cid, columns[1].key));
+        assert_eq!(
+            vec![ForeignKeyListRow {
+                id: 0,
+                seq: 0,
+                table: "parent".to_owned(),
+                from: "parent_id".to_owned(),
+                to: Some("id".to_owned()),
+                on_update: "NO ACTION".to_owned(),
+                on_delete: "NO ACTION".to_owned(),
+            }],
+            db.pragma_get(None, get::ForeignKeyList("child")).unwrap()
+        );
+
+        assert!(db
+            .execute("INSERT INTO child VALUES (1, 99)", NO_PARAMS)
+            .is_err());
+        db.pragma_set(None, Pragma::ForeignKeys(false)).unwrap();
+        db.execute("INSERT INTO child VALUES (1, 99)", NO_PARAMS)
+            .unwrap();
+        assert_eq!(
+            vec![ForeignKeyCheckRow {
+                table: "child".to_owned(),
+                rowid: Some(1),
+                parent: "parent".to_owned(),
+                fkid: 0,
+            }],
+            db.pragma_get(None, get::ForeignKeyCheck).unwrap()
+        );
+        assert!(db.pragma_get(None, get::IntegrityCheck).unwrap().is_empty());
+        assert!(db.pragma_get(None, get::QuickCheck).unwrap().is_empty());
+
+        db.pragma_set(None, Pragma::QueryOnly(true)).unwrap();
+        assert!(db.execute("DELETE FROM child", NO_PARAMS).is_err());
+        db.pragma_set(None, Pragma::QueryOnly(false)).unwrap();
+        assert_eq!(1, db.execute("DELETE FROM child", NO_PARAMS).unwrap());
     }
 
     fn checked_memory_handle_time() -> Connection {
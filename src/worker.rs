@@ -0,0 +1,412 @@
+//! Run a database in a Web Worker, or a node `worker_threads` worker, and
+//! talk to it with `postMessage`.
+//!
+//! Every request is an object `{ id, op, ...args }` and gets exactly one
+//! response, `{ id, result }` on success or `{ id, error }` with the error
+//! message on failure:
+//!
+//! | `op`       | arguments          | `result`                                  |
+//! |------------|--------------------|-------------------------------------------|
+//! | `open`     | `data?`            | `undefined`                               |
+//! | `exec`     | `sql`, `params?`   | changed rows, or `undefined` w/o `params` |
+//! | `query`    | `sql`, `params?`   | array of row objects                      |
+//! | `prepare`  | `sql`, `params?`   | statement id                              |
+//! | `step`     | `stmt`             | row object, or `null` when exhausted      |
+//! | `finalize` | `stmt`             | `false` for an unknown statement          |
+//! | `serialize`|                    | `Uint8Array` of the database file         |
+//! | `close`    |                    | `undefined`                               |
+//!
+//! Blobs in rows and serialized databases are sent as transferred
+//! `ArrayBuffer`s rather than copied.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use rusqlite::functions::{builtin, stats};
+use rusqlite::vtab::{array, series, vector};
+use rusqlite::{rtree, Connection, DatabaseName};
+
+use crate::collations;
+use crate::database::{params_from_js, to_js_error, Cursor};
+
+fn get(object: &JsValue, key: &str) -> Result<JsValue, JsValue> {
+    Reflect::get(object, &JsValue::from_str(key))
+}
+
+fn set(object: &JsValue, key: &str, value: &JsValue) {
+    let _ = Reflect::set(object, &JsValue::from_str(key), value);
+}
+
+fn error_message(error: &JsValue) -> JsValue {
+    match error.dyn_ref::<js_sys::Error>() {
+        Some(e) => e.message().into(),
+        None => error.clone(),
+    }
+}
+
+// Call `handler` with the data of every message received on `port`, which is
+// either a node `MessagePort`/`Worker` or a DOM `Worker`/worker global scope.
+fn listen(
+    port: &JsValue,
+    mut handler: Box<dyn FnMut(JsValue)>,
+) -> Result<Closure<dyn FnMut(JsValue)>, JsValue> {
+    if let Some(on) = get(port, "on")?.dyn_ref::<Function>() {
+        let closure = Closure::wrap(handler);
+        on.call2(port, &JsValue::from_str("message"), closure.as_ref())?;
+        return Ok(closure);
+    }
+    let closure = Closure::wrap(Box::new(move |event: JsValue| {
+        if let Ok(data) = get(&event, "data") {
+            handler(data);
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+    let add_event_listener = get(port, "addEventListener")?.dyn_into::<Function>()?;
+    add_event_listener.call2(port, &JsValue::from_str("message"), closure.as_ref())?;
+    Ok(closure)
+}
+
+fn post(port: &JsValue, message: &JsValue, transfer: &Array) -> Result<(), JsValue> {
+    let post_message = get(port, "postMessage")?.dyn_into::<Function>()?;
+    post_message.call2(port, message, transfer)?;
+    Ok(())
+}
+
+fn optional_params(request: &JsValue) -> Result<Option<Array>, JsValue> {
+    let params = get(request, "params")?;
+    if params.is_undefined() || params.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(params.dyn_into::<Array>()?))
+    }
+}
+
+fn stmt_id(request: &JsValue) -> Result<u32, JsValue> {
+    get(request, "stmt")?
+        .as_f64()
+        .map(|id| id as u32)
+        .ok_or_else(|| js_sys::TypeError::new("statement id must be a number").into())
+}
+
+// Transfer the blobs of `row` rather than copy them.
+fn transfer_blobs(row: &Object, transfer: &Array) {
+    for value in Object::values(row).iter() {
+        if let Some(blob) = value.dyn_ref::<Uint8Array>() {
+            transfer.push(&blob.buffer());
+        }
+    }
+}
+
+/// The worker side of the protocol: owns the connection and the prepared
+/// statements.
+#[derive(Default)]
+pub struct Host {
+    // Declared first so the statements go before the connection.
+    statements: HashMap<u32, Cursor>,
+    conn: Option<Rc<Connection>>,
+    next_stmt: u32,
+}
+
+impl Host {
+    /// Handle one request, returning the response and the buffers to
+    /// transfer with it.
+    pub fn handle(&mut self, request: &JsValue) -> (JsValue, Array) {
+        let response: JsValue = Object::new().into();
+        set(
+            &response,
+            "id",
+            &get(request, "id").unwrap_or(JsValue::NULL),
+        );
+        let transfer = Array::new();
+        match self.dispatch(request, &transfer) {
+            Ok(result) => set(&response, "result", &result),
+            Err(e) => {
+                transfer.set_length(0);
+                set(&response, "error", &error_message(&e));
+            }
+        }
+        (response, transfer)
+    }
+
+    fn conn(&self) -> Result<&Rc<Connection>, JsValue> {
+        self.conn
+            .as_ref()
+            .ok_or_else(|| js_sys::Error::new("database is not open").into())
+    }
+
+    // Prepare the `sql` of `request` and bind its `params`.
+    fn cursor(&self, request: &JsValue) -> Result<Cursor, JsValue> {
+        let sql = get(request, "sql")?.as_string().unwrap_or_default();
+        let params = match optional_params(request)? {
+            Some(params) => params_from_js(&params)?,
+            None => Vec::new(),
+        };
+        Cursor::new(self.conn()?, &sql, &params)
+    }
+
+    fn dispatch(&mut self, request: &JsValue, transfer: &Array) -> Result<JsValue, JsValue> {
+        let op = get(request, "op")?.as_string().unwrap_or_default();
+        match op.as_str() {
+            "open" => {
+                self.close()?;
+                let conn = Connection::open_in_memory().map_err(to_js_error)?;
+                array::load_module(&conn).map_err(to_js_error)?;
+                series::load_module(&conn).map_err(to_js_error)?;
+                vector::load_module(&conn).map_err(to_js_error)?;
+                rtree::load_functions(&conn).map_err(to_js_error)?;
+                builtin::load_functions(&conn).map_err(to_js_error)?;
+                stats::load_functions(&conn).map_err(to_js_error)?;
+                collations::load_collations(&conn).map_err(to_js_error)?;
+                let data = get(request, "data")?;
+                if !data.is_undefined() && !data.is_null() {
+                    let bytes = Uint8Array::new(&data).to_vec();
+                    conn.deserialize(DatabaseName::Main, &bytes, false)
+                        .map_err(to_js_error)?;
+                }
+                self.conn = Some(Rc::new(conn));
+                Ok(JsValue::UNDEFINED)
+            }
+            "exec" => {
+                let sql = get(request, "sql")?.as_string().unwrap_or_default();
+                let conn = self.conn()?;
+                match optional_params(request)? {
+                    Some(params) => {
+                        let params = params_from_js(&params)?;
+                        let changes = conn.execute(&sql, &params).map_err(to_js_error)?;
+                        Ok(JsValue::from_f64(changes as f64))
+                    }
+                    None => {
+                        conn.execute_batch(&sql).map_err(to_js_error)?;
+                        Ok(JsValue::UNDEFINED)
+                    }
+                }
+            }
+            "query" => {
+                let mut cursor = self.cursor(request)?;
+                let rows = Array::new();
+                while let Some(row) = cursor.step()? {
+                    transfer_blobs(&row, transfer);
+                    rows.push(&row);
+                }
+                Ok(rows.into())
+            }
+            "prepare" => {
+                let cursor = self.cursor(request)?;
+                let id = self.next_stmt;
+                self.next_stmt = self.next_stmt.wrapping_add(1);
+                self.statements.insert(id, cursor);
+                Ok(JsValue::from(id))
+            }
+            "step" => {
+                let id = stmt_id(request)?;
+                let cursor = self
+                    .statements
+                    .get_mut(&id)
+                    .ok_or_else(|| js_sys::Error::new("unknown statement"))?;
+                match cursor.step()? {
+                    Some(row) => {
+                        transfer_blobs(&row, transfer);
+                        Ok(row.into())
+                    }
+                    None => Ok(JsValue::NULL),
+                }
+            }
+            "finalize" => {
+                let id = stmt_id(request)?;
+                Ok(JsValue::from(self.statements.remove(&id).is_some()))
+            }
+            "serialize" => {
+                let bytes = self
+                    .conn()?
+                    .serialize(DatabaseName::Main)
+                    .map_err(to_js_error)?;
+                let data = Uint8Array::from(&bytes[..]);
+                transfer.push(&data.buffer());
+                Ok(data.into())
+            }
+            "close" => {
+                self.close()?;
+                Ok(JsValue::UNDEFINED)
+            }
+            _ => Err(js_sys::Error::new(&format!("unknown op {:?}", op)).into()),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), JsValue> {
+        self.statements.clear();
+        match self.conn.take().map(Rc::try_unwrap) {
+            Some(Ok(conn)) => conn.close().map_err(|(_, e)| to_js_error(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Serve requests from `port` for the rest of the worker's life. Pass `self`
+/// in a Web Worker, or `require('worker_threads').parentPort` in node.
+#[wasm_bindgen(js_name = startWorker)]
+pub fn start_worker(port: JsValue) -> Result<(), JsValue> {
+    let host = RefCell::new(Host::default());
+    let reply = port.clone();
+    let listener = listen(
+        &port,
+        Box::new(move |request| {
+            let (response, transfer) = host.borrow_mut().handle(&request);
+            let _ = post(&reply, &response, &transfer);
+        }),
+    )?;
+    listener.forget();
+    Ok(())
+}
+
+type Pending = Rc<RefCell<HashMap<u32, (Function, Function)>>>;
+
+struct Client {
+    port: JsValue,
+    next_id: Cell<u32>,
+    pending: Pending,
+    _listener: Closure<dyn FnMut(JsValue)>,
+}
+
+impl Client {
+    fn request(&self, op: &str, args: &[(&str, &JsValue)]) -> JsFuture {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+        let message: JsValue = Object::new().into();
+        set(&message, "id", &JsValue::from(id));
+        set(&message, "op", &JsValue::from_str(op));
+        for &(key, value) in args {
+            set(&message, key, value);
+        }
+        let pending = Rc::clone(&self.pending);
+        let port = &self.port;
+        JsFuture::from(Promise::new(
+            &mut |resolve, reject| match post(port, &message, &Array::new()) {
+                Ok(()) => {
+                    pending.borrow_mut().insert(id, (resolve, reject));
+                }
+                Err(e) => {
+                    let _ = reject.call1(&JsValue::NULL, &e);
+                }
+            },
+        ))
+    }
+}
+
+/// The main thread side of the protocol: the `Database` API, with every call
+/// returning a `Promise`, for a database served by `startWorker`.
+#[wasm_bindgen]
+pub struct WorkerDatabase {
+    client: Rc<Client>,
+}
+
+impl WorkerDatabase {
+    fn call(&self, op: &str, args: &[(&str, &JsValue)]) -> Promise {
+        future_to_promise(self.client.request(op, args))
+    }
+}
+
+#[wasm_bindgen]
+impl WorkerDatabase {
+    /// Talk to the worker behind `port`: a DOM or node `Worker`, or a
+    /// `MessagePort` connected to one.
+    #[wasm_bindgen(constructor)]
+    pub fn new(port: JsValue) -> Result<WorkerDatabase, JsValue> {
+        let pending: Pending = Rc::new(RefCell::new(HashMap::new()));
+        let responses = Rc::clone(&pending);
+        let listener = listen(
+            &port,
+            Box::new(move |response| {
+                let id = match get(&response, "id").ok().and_then(|id| id.as_f64()) {
+                    Some(id) => id as u32,
+                    None => return,
+                };
+                let callbacks = responses.borrow_mut().remove(&id);
+                if let Some((resolve, reject)) = callbacks {
+                    let error = get(&response, "error").unwrap_or(JsValue::UNDEFINED);
+                    let _ = if error.is_undefined() {
+                        let result = get(&response, "result").unwrap_or(JsValue::UNDEFINED);
+                        resolve.call1(&JsValue::NULL, &result)
+                    } else {
+                        let message = error.as_string().unwrap_or_default();
+                        reject.call1(&JsValue::NULL, &js_sys::Error::new(&message))
+                    };
+                }
+            }),
+        )?;
+        Ok(WorkerDatabase {
+            client: Rc::new(Client {
+                port,
+                next_id: Cell::new(0),
+                pending,
+                _listener: listener,
+            }),
+        })
+    }
+
+    /// Open a new in-memory database in the worker, optionally loaded from
+    /// the bytes returned by `serialize`. Any open database is closed.
+    pub fn open(&self, data: Option<Uint8Array>) -> Promise {
+        match data {
+            Some(data) => self.call("open", &[("data", &data.into())]),
+            None => self.call("open", &[]),
+        }
+    }
+
+    /// Run one or more SQL statements that take no parameters.
+    pub fn exec(&self, sql: &str) -> Promise {
+        self.call("exec", &[("sql", &JsValue::from_str(sql))])
+    }
+
+    /// Run a single statement with positional parameters, resolving to the
+    /// number of changed rows.
+    pub fn run(&self, sql: &str, params: Array) -> Promise {
+        self.call(
+            "exec",
+            &[("sql", &JsValue::from_str(sql)), ("params", &params.into())],
+        )
+    }
+
+    /// Run a query with positional parameters, resolving to an array of row
+    /// objects keyed by column name. The rows come in a single message; use
+    /// `prepare` and `step` to read them one at a time.
+    pub fn query(&self, sql: &str, params: Array) -> Promise {
+        self.call(
+            "query",
+            &[("sql", &JsValue::from_str(sql)), ("params", &params.into())],
+        )
+    }
+
+    /// Prepare `sql` and bind `params`, resolving to a statement id.
+    pub fn prepare(&self, sql: &str, params: Option<Array>) -> Promise {
+        let sql = JsValue::from_str(sql);
+        match params {
+            Some(params) => self.call("prepare", &[("sql", &sql), ("params", &params.into())]),
+            None => self.call("prepare", &[("sql", &sql)]),
+        }
+    }
+
+    /// Resolve to the next row of a statement, or `null` once exhausted.
+    pub fn step(&self, stmt: u32) -> Promise {
+        self.call("step", &[("stmt", &JsValue::from(stmt))])
+    }
+
+    /// Release a statement.
+    pub fn finalize(&self, stmt: u32) -> Promise {
+        self.call("finalize", &[("stmt", &JsValue::from(stmt))])
+    }
+
+    /// Resolve to the content of the database as a `Uint8Array`.
+    pub fn serialize(&self) -> Promise {
+        self.call("serialize", &[])
+    }
+
+    /// Close the database in the worker.
+    pub fn close(&self) -> Promise {
+        self.call("close", &[])
+    }
+}
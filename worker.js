@@ -0,0 +1,3 @@
+const { parentPort } = require('worker_threads');
+const sqlite = require('./dist-nodejs/c_to_rust_sqlite_wasm.js');
+sqlite.startWorker(parentPort);